@@ -0,0 +1,9 @@
+//! ---------------------------------------------------------------------------
+//! File       : arch.rs
+//! Module     : arch
+//! Author     : DiTurr
+//! Description: RISC-V architecture interfaces.
+//! ---------------------------------------------------------------------------
+pub mod decode;
+pub mod pmp;
+pub mod semihosting;
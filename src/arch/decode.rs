@@ -0,0 +1,1061 @@
+//! ---------------------------------------------------------------------------
+//! File       : decode.rs
+//! Module     : arch::decode
+//! Author     : DiTurr
+//! Description:
+//! Decoder and disassembler for RV64IMAFDC, with Zicsr and Zifencei (plus the
+//! privileged `mret`, `sret`, `wfi` and `sfence.vma`). [`decode`] turns the
+//! bits of an instruction into an [`Instruction`]; compressed instructions
+//! decode to the base instruction they expand to ([`length`] tells them
+//! apart). `Display` disassembles it with the GNU assembler syntax and its
+//! usual aliases (`li`, `mv`, `ret`, `csrw`, ...); branch and jump targets are
+//! relative to the instruction, e.g. `beqz a0, .+8`.
+//!
+//! The trap handler uses it to emulate misaligned accesses and to show the
+//! faulting instruction, the GDB stub to single-step.
+//!
+//! ## Example
+//! ```rust
+//! let insn = decode::decode(unsafe { decode::fetch(frame.mepc) });
+//! log_error!("{:#x}: {}", frame.mepc, insn); // 0x80000124: csrw satp, t0
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::fmt;
+use core::ptr::read_volatile;
+
+/// ABI names of the integer registers.
+pub const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// ABI names of the floating-point registers.
+const FREG_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+/// Names of the CSRs the kernel uses or reports.
+const CSR_NAMES: [(u16, &str); 33] = [
+    (0x001, "fflags"),   (0x002, "frm"),      (0x003, "fcsr"),
+    (0x100, "sstatus"),  (0x104, "sie"),      (0x105, "stvec"),    (0x140, "sscratch"),
+    (0x141, "sepc"),     (0x142, "scause"),   (0x143, "stval"),    (0x144, "sip"),
+    (0x180, "satp"),
+    (0x300, "mstatus"),  (0x301, "misa"),     (0x302, "medeleg"),  (0x303, "mideleg"),
+    (0x304, "mie"),      (0x305, "mtvec"),    (0x306, "mcounteren"),
+    (0x340, "mscratch"), (0x341, "mepc"),     (0x342, "mcause"),   (0x343, "mtval"),
+    (0x344, "mip"),      (0x3a0, "pmpcfg0"),  (0x3a2, "pmpcfg2"),  (0x3b0, "pmpaddr0"),
+    (0xb00, "mcycle"),   (0xb02, "minstret"),
+    (0xc00, "cycle"),    (0xc01, "time"),     (0xc02, "instret"),
+    (0xf14, "mhartid"),
+];
+
+// Major opcodes (bits 6:0) of the 32-bit instructions.
+const OP_LOAD: u32     = 0x03;
+const OP_LOAD_FP: u32  = 0x07;
+const OP_MISC_MEM: u32 = 0x0f;
+const OP_IMM: u32      = 0x13;
+const OP_AUIPC: u32    = 0x17;
+const OP_IMM_32: u32   = 0x1b;
+const OP_STORE: u32    = 0x23;
+const OP_STORE_FP: u32 = 0x27;
+const OP_AMO: u32      = 0x2f;
+const OP: u32          = 0x33;
+const OP_LUI: u32      = 0x37;
+const OP_32: u32       = 0x3b;
+const OP_MADD: u32     = 0x43;
+const OP_MSUB: u32     = 0x47;
+const OP_NMSUB: u32    = 0x4b;
+const OP_NMADD: u32    = 0x4f;
+const OP_FP: u32       = 0x53;
+const OP_BRANCH: u32   = 0x63;
+const OP_JALR: u32     = 0x67;
+const OP_JAL: u32      = 0x6f;
+const OP_SYSTEM: u32   = 0x73;
+
+/// Dynamic rounding mode (`frm`), not shown.
+const RM_DYNAMIC: u8 = 7;
+
+/// An integer register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reg(pub u8);
+
+/// A floating-point register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FReg(pub u8);
+
+const ZERO: Reg = Reg(0);
+const RA: Reg = Reg(1);
+const SP: Reg = Reg(2);
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REG_NAMES[self.0 as usize & 0x1f])
+    }
+}
+
+impl fmt::Display for FReg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(FREG_NAMES[self.0 as usize & 0x1f])
+    }
+}
+
+/// An integer load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadOp {
+    Lb,
+    Lh,
+    Lw,
+    Ld,
+    Lbu,
+    Lhu,
+    Lwu,
+}
+
+impl LoadOp {
+    /// Returns the size of the access in bytes.
+    pub fn width(self) -> usize {
+        match self {
+            LoadOp::Lb | LoadOp::Lbu => 1,
+            LoadOp::Lh | LoadOp::Lhu => 2,
+            LoadOp::Lw | LoadOp::Lwu => 4,
+            LoadOp::Ld => 8,
+        }
+    }
+
+    /// Returns whether the loaded value is sign-extended.
+    pub fn signed(self) -> bool {
+        matches!(self, LoadOp::Lb | LoadOp::Lh | LoadOp::Lw | LoadOp::Ld)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LoadOp::Lb => "lb",
+            LoadOp::Lh => "lh",
+            LoadOp::Lw => "lw",
+            LoadOp::Ld => "ld",
+            LoadOp::Lbu => "lbu",
+            LoadOp::Lhu => "lhu",
+            LoadOp::Lwu => "lwu",
+        }
+    }
+}
+
+/// An integer store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreOp {
+    Sb,
+    Sh,
+    Sw,
+    Sd,
+}
+
+impl StoreOp {
+    /// Returns the size of the access in bytes.
+    pub fn width(self) -> usize {
+        match self {
+            StoreOp::Sb => 1,
+            StoreOp::Sh => 2,
+            StoreOp::Sw => 4,
+            StoreOp::Sd => 8,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            StoreOp::Sb => "sb",
+            StoreOp::Sh => "sh",
+            StoreOp::Sw => "sw",
+            StoreOp::Sd => "sd",
+        }
+    }
+}
+
+/// A conditional branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchOp {
+    Beq,
+    Bne,
+    Blt,
+    Bge,
+    Bltu,
+    Bgeu,
+}
+
+impl BranchOp {
+    fn name(self) -> &'static str {
+        match self {
+            BranchOp::Beq => "beq",
+            BranchOp::Bne => "bne",
+            BranchOp::Blt => "blt",
+            BranchOp::Bge => "bge",
+            BranchOp::Bltu => "bltu",
+            BranchOp::Bgeu => "bgeu",
+        }
+    }
+}
+
+/// An integer operation (I and M extensions), on registers or with an
+/// immediate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+}
+
+impl AluOp {
+    /// Returns the mnemonic of the register (`imm == false`) or immediate
+    /// form, on 64 or 32 (`word`) bits.
+    fn name(self, imm: bool, word: bool) -> &'static str {
+        match (self, imm, word) {
+            (AluOp::Add, false, false) => "add",
+            (AluOp::Add, false, true) => "addw",
+            (AluOp::Add, true, false) => "addi",
+            (AluOp::Add, true, true) => "addiw",
+            (AluOp::Sub, _, false) => "sub",
+            (AluOp::Sub, _, true) => "subw",
+            (AluOp::Sll, false, false) => "sll",
+            (AluOp::Sll, false, true) => "sllw",
+            (AluOp::Sll, true, false) => "slli",
+            (AluOp::Sll, true, true) => "slliw",
+            (AluOp::Srl, false, false) => "srl",
+            (AluOp::Srl, false, true) => "srlw",
+            (AluOp::Srl, true, false) => "srli",
+            (AluOp::Srl, true, true) => "srliw",
+            (AluOp::Sra, false, false) => "sra",
+            (AluOp::Sra, false, true) => "sraw",
+            (AluOp::Sra, true, false) => "srai",
+            (AluOp::Sra, true, true) => "sraiw",
+            (AluOp::Slt, false, _) => "slt",
+            (AluOp::Slt, true, _) => "slti",
+            (AluOp::Sltu, false, _) => "sltu",
+            (AluOp::Sltu, true, _) => "sltiu",
+            (AluOp::Xor, false, _) => "xor",
+            (AluOp::Xor, true, _) => "xori",
+            (AluOp::Or, false, _) => "or",
+            (AluOp::Or, true, _) => "ori",
+            (AluOp::And, false, _) => "and",
+            (AluOp::And, true, _) => "andi",
+            (AluOp::Mul, _, false) => "mul",
+            (AluOp::Mul, _, true) => "mulw",
+            (AluOp::Mulh, _, _) => "mulh",
+            (AluOp::Mulhsu, _, _) => "mulhsu",
+            (AluOp::Mulhu, _, _) => "mulhu",
+            (AluOp::Div, _, false) => "div",
+            (AluOp::Div, _, true) => "divw",
+            (AluOp::Divu, _, false) => "divu",
+            (AluOp::Divu, _, true) => "divuw",
+            (AluOp::Rem, _, false) => "rem",
+            (AluOp::Rem, _, true) => "remw",
+            (AluOp::Remu, _, false) => "remu",
+            (AluOp::Remu, _, true) => "remuw",
+        }
+    }
+}
+
+/// A CSR access (Zicsr): read and write, set or clear bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsrOp {
+    Rw,
+    Rs,
+    Rc,
+}
+
+/// An atomic memory operation (A extension).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmoOp {
+    Lr,
+    Sc,
+    Swap,
+    Add,
+    Xor,
+    And,
+    Or,
+    Min,
+    Max,
+    Minu,
+    Maxu,
+}
+
+impl AmoOp {
+    fn name(self) -> &'static str {
+        match self {
+            AmoOp::Lr => "lr",
+            AmoOp::Sc => "sc",
+            AmoOp::Swap => "amoswap",
+            AmoOp::Add => "amoadd",
+            AmoOp::Xor => "amoxor",
+            AmoOp::And => "amoand",
+            AmoOp::Or => "amoor",
+            AmoOp::Min => "amomin",
+            AmoOp::Max => "amomax",
+            AmoOp::Minu => "amominu",
+            AmoOp::Maxu => "amomaxu",
+        }
+    }
+}
+
+/// A floating-point format: single (F) or double (D) precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpFmt {
+    S,
+    D,
+}
+
+impl FpFmt {
+    fn suffix(self) -> &'static str {
+        match self {
+            FpFmt::S => "s",
+            FpFmt::D => "d",
+        }
+    }
+}
+
+/// An integer type converted from or to a floating-point value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntType {
+    W,
+    Wu,
+    L,
+    Lu,
+}
+
+impl IntType {
+    fn suffix(self) -> &'static str {
+        match self {
+            IntType::W => "w",
+            IntType::Wu => "wu",
+            IntType::L => "l",
+            IntType::Lu => "lu",
+        }
+    }
+}
+
+/// A fused multiply-add.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FusedOp {
+    Madd,
+    Msub,
+    Nmsub,
+    Nmadd,
+}
+
+impl FusedOp {
+    fn name(self) -> &'static str {
+        match self {
+            FusedOp::Madd => "fmadd",
+            FusedOp::Msub => "fmsub",
+            FusedOp::Nmsub => "fnmsub",
+            FusedOp::Nmadd => "fnmadd",
+        }
+    }
+}
+
+/// A floating-point operation (F and D extensions, except loads, stores and
+/// fused multiply-adds).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Sqrt,
+    Sgnj,
+    Sgnjn,
+    Sgnjx,
+    Min,
+    Max,
+    /// Converts to the format of the instruction from the other one.
+    CvtFmt,
+    Eq,
+    Lt,
+    Le,
+    Class,
+    /// Moves the bits to an integer register (`fmv.x.w`, `fmv.x.d`).
+    MvToInt,
+    /// Moves the bits from an integer register (`fmv.w.x`, `fmv.d.x`).
+    MvFromInt,
+    CvtToInt(IntType),
+    CvtFromInt(IntType),
+}
+
+/// A decoded instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Lui { rd: Reg, imm: i64 },
+    Auipc { rd: Reg, imm: i64 },
+    Jal { rd: Reg, offset: i64 },
+    Jalr { rd: Reg, rs1: Reg, offset: i64 },
+    Branch { op: BranchOp, rs1: Reg, rs2: Reg, offset: i64 },
+    Load { op: LoadOp, rd: Reg, rs1: Reg, offset: i64 },
+    Store { op: StoreOp, rs1: Reg, rs2: Reg, offset: i64 },
+    /// Integer operation with an immediate, on 32 bits if `word`.
+    OpImm { op: AluOp, word: bool, rd: Reg, rs1: Reg, imm: i64 },
+    /// Integer operation on registers, on 32 bits if `word`.
+    Op { op: AluOp, word: bool, rd: Reg, rs1: Reg, rs2: Reg },
+    /// Memory ordering: `pred` and `succ` are sets of `iorw` bits.
+    Fence { pred: u8, succ: u8 },
+    FenceI,
+    Ecall,
+    Ebreak,
+    Mret,
+    Sret,
+    Wfi,
+    SfenceVma { rs1: Reg, rs2: Reg },
+    /// CSR access, from register `src` or, with `imm`, of the value `src`.
+    Csr { op: CsrOp, imm: bool, rd: Reg, csr: u16, src: u8 },
+    /// Atomic memory operation, on 64 bits if `double`.
+    Amo { op: AmoOp, double: bool, aq: bool, rl: bool, rd: Reg, rs1: Reg, rs2: Reg },
+    FpLoad { fmt: FpFmt, rd: FReg, rs1: Reg, offset: i64 },
+    FpStore { fmt: FpFmt, rs1: Reg, rs2: FReg, offset: i64 },
+    FpFused { op: FusedOp, fmt: FpFmt, rd: FReg, rs1: FReg, rs2: FReg, rs3: FReg, rm: u8 },
+    /// Floating-point operation; `rd` and `rs1` are integer registers for
+    /// some operations (see [`FpOp`]).
+    Fp { op: FpOp, fmt: FpFmt, rd: u8, rs1: u8, rs2: u8, rm: u8 },
+    /// Not a valid RV64GC instruction.
+    Unknown(u32),
+}
+
+/// Returns the size in bytes of the instruction starting with `bits`: 2 if
+/// compressed, else 4.
+pub fn length(bits: u32) -> usize {
+    if bits & 0b11 == 0b11 { 4 } else { 2 }
+}
+
+/// Reads the instruction at `pc`, which is only 2-byte aligned: the upper
+/// half is only read for a 32-bit instruction.
+///
+/// # Safety
+/// `pc` must be the address of an instruction.
+pub unsafe fn fetch(pc: usize) -> u32 {
+    let low = unsafe { read_volatile(pc as *const u16) } as u32;
+    if length(low) == 2 {
+        return low;
+    }
+    low | (unsafe { read_volatile((pc + 2) as *const u16) } as u32) << 16
+}
+
+/// Decodes the instruction starting with `bits`; the upper half is ignored
+/// for a compressed instruction.
+pub fn decode(bits: u32) -> Instruction {
+    let decoded = match length(bits) {
+        4 => decode_32(bits),
+        _ => decode_16(bits as u16),
+    };
+    decoded.unwrap_or(Instruction::Unknown(bits))
+}
+
+/// Returns bits `hi`:`lo` of `bits`.
+fn field(bits: u32, hi: u32, lo: u32) -> u32 {
+    (bits >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// Sign-extends the low `width` bits of `value`.
+fn sign_extend(value: u32, width: u32) -> i64 {
+    let shift = 64 - width;
+    ((value as i64) << shift) >> shift
+}
+
+// Immediates of the 32-bit formats.
+fn imm_i(bits: u32) -> i64 {
+    sign_extend(field(bits, 31, 20), 12)
+}
+
+fn imm_s(bits: u32) -> i64 {
+    sign_extend(field(bits, 31, 25) << 5 | field(bits, 11, 7), 12)
+}
+
+fn imm_b(bits: u32) -> i64 {
+    let imm = field(bits, 31, 31) << 12 | field(bits, 7, 7) << 11 | field(bits, 30, 25) << 5 | field(bits, 11, 8) << 1;
+    sign_extend(imm, 13)
+}
+
+fn imm_u(bits: u32) -> i64 {
+    (bits & 0xffff_f000) as i32 as i64
+}
+
+fn imm_j(bits: u32) -> i64 {
+    let imm = field(bits, 31, 31) << 20 | field(bits, 19, 12) << 12 | field(bits, 20, 20) << 11 | field(bits, 30, 21) << 1;
+    sign_extend(imm, 21)
+}
+
+fn decode_32(bits: u32) -> Option<Instruction> {
+    let rd = Reg(field(bits, 11, 7) as u8);
+    let rs1 = Reg(field(bits, 19, 15) as u8);
+    let rs2 = Reg(field(bits, 24, 20) as u8);
+    let funct3 = field(bits, 14, 12);
+    let funct7 = field(bits, 31, 25);
+    let insn = match field(bits, 6, 0) {
+        OP_LUI => Instruction::Lui { rd, imm: imm_u(bits) },
+        OP_AUIPC => Instruction::Auipc { rd, imm: imm_u(bits) },
+        OP_JAL => Instruction::Jal { rd, offset: imm_j(bits) },
+        OP_JALR if funct3 == 0 => Instruction::Jalr { rd, rs1, offset: imm_i(bits) },
+        OP_BRANCH => {
+            let op = match funct3 {
+                0 => BranchOp::Beq,
+                1 => BranchOp::Bne,
+                4 => BranchOp::Blt,
+                5 => BranchOp::Bge,
+                6 => BranchOp::Bltu,
+                7 => BranchOp::Bgeu,
+                _ => return None,
+            };
+            Instruction::Branch { op, rs1, rs2, offset: imm_b(bits) }
+        }
+        OP_LOAD => {
+            let op = match funct3 {
+                0 => LoadOp::Lb,
+                1 => LoadOp::Lh,
+                2 => LoadOp::Lw,
+                3 => LoadOp::Ld,
+                4 => LoadOp::Lbu,
+                5 => LoadOp::Lhu,
+                6 => LoadOp::Lwu,
+                _ => return None,
+            };
+            Instruction::Load { op, rd, rs1, offset: imm_i(bits) }
+        }
+        OP_STORE => {
+            let op = match funct3 {
+                0 => StoreOp::Sb,
+                1 => StoreOp::Sh,
+                2 => StoreOp::Sw,
+                3 => StoreOp::Sd,
+                _ => return None,
+            };
+            Instruction::Store { op, rs1, rs2, offset: imm_s(bits) }
+        }
+        opcode @ (OP_IMM | OP_IMM_32) => decode_op_imm(bits, opcode == OP_IMM_32, rd, rs1)?,
+        opcode @ (OP | OP_32) => {
+            let word = opcode == OP_32;
+            let op = match (funct7, funct3) {
+                (0x00, 0) => AluOp::Add,
+                (0x20, 0) => AluOp::Sub,
+                (0x00, 1) => AluOp::Sll,
+                (0x00, 5) => AluOp::Srl,
+                (0x20, 5) => AluOp::Sra,
+                (0x01, 0) => AluOp::Mul,
+                (0x01, 4) => AluOp::Div,
+                (0x01, 5) => AluOp::Divu,
+                (0x01, 6) => AluOp::Rem,
+                (0x01, 7) => AluOp::Remu,
+                _ if word => return None,
+                (0x00, 2) => AluOp::Slt,
+                (0x00, 3) => AluOp::Sltu,
+                (0x00, 4) => AluOp::Xor,
+                (0x00, 6) => AluOp::Or,
+                (0x00, 7) => AluOp::And,
+                (0x01, 1) => AluOp::Mulh,
+                (0x01, 2) => AluOp::Mulhsu,
+                (0x01, 3) => AluOp::Mulhu,
+                _ => return None,
+            };
+            Instruction::Op { op, word, rd, rs1, rs2 }
+        }
+        OP_MISC_MEM => match funct3 {
+            0 => Instruction::Fence { pred: field(bits, 27, 24) as u8, succ: field(bits, 23, 20) as u8 },
+            1 => Instruction::FenceI,
+            _ => return None,
+        },
+        OP_SYSTEM => decode_system(bits, rd, rs1, rs2, funct3, funct7)?,
+        OP_AMO => {
+            let double = match funct3 {
+                2 => false,
+                3 => true,
+                _ => return None,
+            };
+            let op = match field(bits, 31, 27) {
+                0b00010 if rs2 == ZERO => AmoOp::Lr,
+                0b00011 => AmoOp::Sc,
+                0b00001 => AmoOp::Swap,
+                0b00000 => AmoOp::Add,
+                0b00100 => AmoOp::Xor,
+                0b01100 => AmoOp::And,
+                0b01000 => AmoOp::Or,
+                0b10000 => AmoOp::Min,
+                0b10100 => AmoOp::Max,
+                0b11000 => AmoOp::Minu,
+                0b11100 => AmoOp::Maxu,
+                _ => return None,
+            };
+            let (aq, rl) = (field(bits, 26, 26) != 0, field(bits, 25, 25) != 0);
+            Instruction::Amo { op, double, aq, rl, rd, rs1, rs2 }
+        }
+        OP_LOAD_FP => Instruction::FpLoad { fmt: fp_width(funct3)?, rd: FReg(rd.0), rs1, offset: imm_i(bits) },
+        OP_STORE_FP => Instruction::FpStore { fmt: fp_width(funct3)?, rs1, rs2: FReg(rs2.0), offset: imm_s(bits) },
+        opcode @ (OP_MADD | OP_MSUB | OP_NMSUB | OP_NMADD) => {
+            let op = match opcode {
+                OP_MADD => FusedOp::Madd,
+                OP_MSUB => FusedOp::Msub,
+                OP_NMSUB => FusedOp::Nmsub,
+                _ => FusedOp::Nmadd,
+            };
+            let fmt = fp_fmt(field(bits, 26, 25))?;
+            let rs3 = FReg(field(bits, 31, 27) as u8);
+            Instruction::FpFused { op, fmt, rd: FReg(rd.0), rs1: FReg(rs1.0), rs2: FReg(rs2.0), rs3, rm: funct3 as u8 }
+        }
+        OP_FP => decode_fp(funct7, funct3, rd.0, rs1.0, rs2.0)?,
+        _ => return None,
+    };
+    Some(insn)
+}
+
+/// Decodes `OP-IMM` (or `OP-IMM-32` if `word`).
+fn decode_op_imm(bits: u32, word: bool, rd: Reg, rs1: Reg) -> Option<Instruction> {
+    let imm = imm_i(bits);
+    // Shift amounts are 6 bits wide, 5 for the word shifts.
+    let (shamt, funct) = match word {
+        false => (field(bits, 25, 20) as i64, field(bits, 31, 26) << 1),
+        true => (field(bits, 24, 20) as i64, field(bits, 31, 25)),
+    };
+    let (op, imm) = match (field(bits, 14, 12), funct) {
+        (0, _) => (AluOp::Add, imm),
+        (1, 0x00) => (AluOp::Sll, shamt),
+        (5, 0x00) => (AluOp::Srl, shamt),
+        (5, 0x20) => (AluOp::Sra, shamt),
+        _ if word => return None,
+        (2, _) => (AluOp::Slt, imm),
+        (3, _) => (AluOp::Sltu, imm),
+        (4, _) => (AluOp::Xor, imm),
+        (6, _) => (AluOp::Or, imm),
+        (7, _) => (AluOp::And, imm),
+        _ => return None,
+    };
+    Some(Instruction::OpImm { op, word, rd, rs1, imm })
+}
+
+/// Decodes `SYSTEM`: environment calls, privileged instructions and CSRs.
+fn decode_system(bits: u32, rd: Reg, rs1: Reg, rs2: Reg, funct3: u32, funct7: u32) -> Option<Instruction> {
+    let insn = match (funct3, bits) {
+        (0, 0x0000_0073) => Instruction::Ecall,
+        (0, 0x0010_0073) => Instruction::Ebreak,
+        (0, 0x3020_0073) => Instruction::Mret,
+        (0, 0x1020_0073) => Instruction::Sret,
+        (0, 0x1050_0073) => Instruction::Wfi,
+        (0, _) if funct7 == 0x09 && rd == ZERO => Instruction::SfenceVma { rs1, rs2 },
+        (1 | 2 | 3 | 5 | 6 | 7, _) => {
+            let op = match funct3 & 0b11 {
+                1 => CsrOp::Rw,
+                2 => CsrOp::Rs,
+                _ => CsrOp::Rc,
+            };
+            Instruction::Csr { op, imm: funct3 & 0b100 != 0, rd, csr: field(bits, 31, 20) as u16, src: rs1.0 }
+        }
+        _ => return None,
+    };
+    Some(insn)
+}
+
+/// Returns the format of a floating-point load or store, from its width.
+fn fp_width(funct3: u32) -> Option<FpFmt> {
+    match funct3 {
+        2 => Some(FpFmt::S),
+        3 => Some(FpFmt::D),
+        _ => None,
+    }
+}
+
+/// Returns the format of a floating-point operation, from its `fmt` field.
+fn fp_fmt(fmt: u32) -> Option<FpFmt> {
+    match fmt {
+        0 => Some(FpFmt::S),
+        1 => Some(FpFmt::D),
+        _ => None,
+    }
+}
+
+/// Decodes `OP-FP`.
+fn decode_fp(funct7: u32, funct3: u32, rd: u8, rs1: u8, rs2: u8) -> Option<Instruction> {
+    let fmt = fp_fmt(funct7 & 0b11)?;
+    let int_type = match rs2 {
+        0 => IntType::W,
+        1 => IntType::Wu,
+        2 => IntType::L,
+        3 => IntType::Lu,
+        _ => IntType::W,
+    };
+    let op = match (funct7 >> 2, funct3, rs2) {
+        (0x00, _, _) => FpOp::Add,
+        (0x01, _, _) => FpOp::Sub,
+        (0x02, _, _) => FpOp::Mul,
+        (0x03, _, _) => FpOp::Div,
+        (0x0b, _, 0) => FpOp::Sqrt,
+        (0x04, 0, _) => FpOp::Sgnj,
+        (0x04, 1, _) => FpOp::Sgnjn,
+        (0x04, 2, _) => FpOp::Sgnjx,
+        (0x05, 0, _) => FpOp::Min,
+        (0x05, 1, _) => FpOp::Max,
+        // fcvt.s.d converts from D (1), fcvt.d.s from S (0).
+        (0x08, _, 1) if fmt == FpFmt::S => FpOp::CvtFmt,
+        (0x08, _, 0) if fmt == FpFmt::D => FpOp::CvtFmt,
+        (0x14, 2, _) => FpOp::Eq,
+        (0x14, 1, _) => FpOp::Lt,
+        (0x14, 0, _) => FpOp::Le,
+        (0x1c, 0, 0) => FpOp::MvToInt,
+        (0x1c, 1, 0) => FpOp::Class,
+        (0x1e, 0, 0) => FpOp::MvFromInt,
+        (0x18, _, 0..=3) => FpOp::CvtToInt(int_type),
+        (0x1a, _, 0..=3) => FpOp::CvtFromInt(int_type),
+        _ => return None,
+    };
+    Some(Instruction::Fp { op, fmt, rd, rs1, rs2, rm: funct3 as u8 })
+}
+
+/// Decodes a compressed instruction into the one it expands to.
+fn decode_16(bits: u16) -> Option<Instruction> {
+    let bits = bits as u32;
+    // Full registers (bits 11:7 and 6:2), and x8-x15 (bits 9:7 and 4:2).
+    let (rd, rs2) = (Reg(field(bits, 11, 7) as u8), Reg(field(bits, 6, 2) as u8));
+    let (rd_short, rs2_short) = (Reg(field(bits, 9, 7) as u8 + 8), Reg(field(bits, 4, 2) as u8 + 8));
+    // Offsets of the loads and stores, scaled by 4 (`w`) or 8 (`d`).
+    let offset_w = (field(bits, 12, 10) << 3 | field(bits, 6, 6) << 2 | field(bits, 5, 5) << 6) as i64;
+    let offset_d = (field(bits, 12, 10) << 3 | field(bits, 6, 5) << 6) as i64;
+    let offset_lwsp = (field(bits, 12, 12) << 5 | field(bits, 6, 4) << 2 | field(bits, 3, 2) << 6) as i64;
+    let offset_ldsp = (field(bits, 12, 12) << 5 | field(bits, 6, 5) << 3 | field(bits, 4, 2) << 6) as i64;
+    let offset_swsp = (field(bits, 12, 9) << 2 | field(bits, 8, 7) << 6) as i64;
+    let offset_sdsp = (field(bits, 12, 10) << 3 | field(bits, 9, 7) << 6) as i64;
+    // 6-bit immediate of the arithmetic.
+    let imm = sign_extend(field(bits, 12, 12) << 5 | field(bits, 6, 2), 6);
+    let shamt = (field(bits, 12, 12) << 5 | field(bits, 6, 2)) as i64;
+    let op_imm = |op, word, rd, rs1, imm| Some(Instruction::OpImm { op, word, rd, rs1, imm });
+    let op = |op, word, rd, rs1, rs2| Some(Instruction::Op { op, word, rd, rs1, rs2 });
+    match (field(bits, 1, 0), field(bits, 15, 13)) {
+        // Quadrant 0.
+        (0b00, 0b000) => {
+            let imm = field(bits, 12, 11) << 4 | field(bits, 10, 7) << 6 | field(bits, 6, 6) << 2 | field(bits, 5, 5) << 3;
+            if imm == 0 {
+                return None;
+            }
+            op_imm(AluOp::Add, false, rs2_short, SP, imm as i64)
+        }
+        (0b00, 0b001) => Some(Instruction::FpLoad { fmt: FpFmt::D, rd: FReg(rs2_short.0), rs1: rd_short, offset: offset_d }),
+        (0b00, 0b010) => Some(Instruction::Load { op: LoadOp::Lw, rd: rs2_short, rs1: rd_short, offset: offset_w }),
+        (0b00, 0b011) => Some(Instruction::Load { op: LoadOp::Ld, rd: rs2_short, rs1: rd_short, offset: offset_d }),
+        (0b00, 0b101) => Some(Instruction::FpStore { fmt: FpFmt::D, rs1: rd_short, rs2: FReg(rs2_short.0), offset: offset_d }),
+        (0b00, 0b110) => Some(Instruction::Store { op: StoreOp::Sw, rs1: rd_short, rs2: rs2_short, offset: offset_w }),
+        (0b00, 0b111) => Some(Instruction::Store { op: StoreOp::Sd, rs1: rd_short, rs2: rs2_short, offset: offset_d }),
+        // Quadrant 1.
+        (0b01, 0b000) => op_imm(AluOp::Add, false, rd, rd, imm),
+        (0b01, 0b001) if rd != ZERO => op_imm(AluOp::Add, true, rd, rd, imm),
+        (0b01, 0b010) => op_imm(AluOp::Add, false, rd, ZERO, imm),
+        (0b01, 0b011) if rd == SP => {
+            let imm = field(bits, 12, 12) << 9 | field(bits, 6, 6) << 4 | field(bits, 5, 5) << 6 | field(bits, 4, 3) << 7 | field(bits, 2, 2) << 5;
+            match sign_extend(imm, 10) {
+                0 => None,
+                imm => op_imm(AluOp::Add, false, SP, SP, imm),
+            }
+        }
+        (0b01, 0b011) => match imm {
+            0 => None,
+            imm => Some(Instruction::Lui { rd, imm: imm << 12 }),
+        },
+        (0b01, 0b100) => match (field(bits, 11, 10), field(bits, 12, 12), field(bits, 6, 5)) {
+            (0b00, _, _) => op_imm(AluOp::Srl, false, rd_short, rd_short, shamt),
+            (0b01, _, _) => op_imm(AluOp::Sra, false, rd_short, rd_short, shamt),
+            (0b10, _, _) => op_imm(AluOp::And, false, rd_short, rd_short, imm),
+            (_, 0, 0b00) => op(AluOp::Sub, false, rd_short, rd_short, rs2_short),
+            (_, 0, 0b01) => op(AluOp::Xor, false, rd_short, rd_short, rs2_short),
+            (_, 0, 0b10) => op(AluOp::Or, false, rd_short, rd_short, rs2_short),
+            (_, 0, _) => op(AluOp::And, false, rd_short, rd_short, rs2_short),
+            (_, _, 0b00) => op(AluOp::Sub, true, rd_short, rd_short, rs2_short),
+            (_, _, 0b01) => op(AluOp::Add, true, rd_short, rd_short, rs2_short),
+            _ => None,
+        },
+        (0b01, 0b101) => {
+            let offset = field(bits, 12, 12) << 11 | field(bits, 11, 11) << 4 | field(bits, 10, 9) << 8 | field(bits, 8, 8) << 10
+                | field(bits, 7, 7) << 6 | field(bits, 6, 6) << 7 | field(bits, 5, 3) << 1 | field(bits, 2, 2) << 5;
+            Some(Instruction::Jal { rd: ZERO, offset: sign_extend(offset, 12) })
+        }
+        (0b01, funct3 @ (0b110 | 0b111)) => {
+            let offset = field(bits, 12, 12) << 8 | field(bits, 11, 10) << 3 | field(bits, 6, 5) << 6 | field(bits, 4, 3) << 1
+                | field(bits, 2, 2) << 5;
+            let op = if funct3 == 0b110 { BranchOp::Beq } else { BranchOp::Bne };
+            Some(Instruction::Branch { op, rs1: rd_short, rs2: ZERO, offset: sign_extend(offset, 9) })
+        }
+        // Quadrant 2.
+        (0b10, 0b000) => op_imm(AluOp::Sll, false, rd, rd, shamt),
+        (0b10, 0b001) => Some(Instruction::FpLoad { fmt: FpFmt::D, rd: FReg(rd.0), rs1: SP, offset: offset_ldsp }),
+        (0b10, 0b010) if rd != ZERO => Some(Instruction::Load { op: LoadOp::Lw, rd, rs1: SP, offset: offset_lwsp }),
+        (0b10, 0b011) if rd != ZERO => Some(Instruction::Load { op: LoadOp::Ld, rd, rs1: SP, offset: offset_ldsp }),
+        (0b10, 0b100) => match (field(bits, 12, 12), rd, rs2) {
+            (0, ZERO, _) => None,
+            (0, rs1, ZERO) => Some(Instruction::Jalr { rd: ZERO, rs1, offset: 0 }),
+            (0, rd, rs2) => op(AluOp::Add, false, rd, ZERO, rs2),
+            (_, ZERO, ZERO) => Some(Instruction::Ebreak),
+            (_, rs1, ZERO) => Some(Instruction::Jalr { rd: RA, rs1, offset: 0 }),
+            (_, rd, rs2) => op(AluOp::Add, false, rd, rd, rs2),
+        },
+        (0b10, 0b101) => Some(Instruction::FpStore { fmt: FpFmt::D, rs1: SP, rs2: FReg(rs2.0), offset: offset_sdsp }),
+        (0b10, 0b110) => Some(Instruction::Store { op: StoreOp::Sw, rs1: SP, rs2, offset: offset_swsp }),
+        (0b10, 0b111) => Some(Instruction::Store { op: StoreOp::Sd, rs1: SP, rs2, offset: offset_sdsp }),
+        _ => None,
+    }
+}
+
+/// A pc-relative target, e.g. `.+8`.
+struct Target(i64);
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ".{:+}", self.0)
+    }
+}
+
+/// A CSR, by name if known.
+struct CsrName(u16);
+
+impl fmt::Display for CsrName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match CSR_NAMES.iter().find(|(csr, _)| *csr == self.0) {
+            Some((_, name)) => f.write_str(name),
+            None => write!(f, "{:#x}", self.0),
+        }
+    }
+}
+
+/// A set of `iorw` bits of a fence.
+struct FenceSet(u8);
+
+impl fmt::Display for FenceSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (bit, name) in [(8, "i"), (4, "o"), (2, "r"), (1, "w")] {
+            if self.0 & bit != 0 {
+                f.write_str(name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A rounding mode, shown after the operands unless dynamic.
+struct RoundingMode(u8);
+
+impl fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.0 {
+            RM_DYNAMIC => return Ok(()),
+            0 => "rne",
+            1 => "rtz",
+            2 => "rdn",
+            3 => "rup",
+            4 => "rmm",
+            _ => "?",
+        };
+        write!(f, ", {}", name)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Lui { rd, imm } => write!(f, "lui {}, {:#x}", rd, (imm >> 12) & 0xfffff),
+            Instruction::Auipc { rd, imm } => write!(f, "auipc {}, {:#x}", rd, (imm >> 12) & 0xfffff),
+            Instruction::Jal { rd: ZERO, offset } => write!(f, "j {}", Target(offset)),
+            Instruction::Jal { rd: RA, offset } => write!(f, "jal {}", Target(offset)),
+            Instruction::Jal { rd, offset } => write!(f, "jal {}, {}", rd, Target(offset)),
+            Instruction::Jalr { rd: ZERO, rs1: RA, offset: 0 } => write!(f, "ret"),
+            Instruction::Jalr { rd: ZERO, rs1, offset: 0 } => write!(f, "jr {}", rs1),
+            Instruction::Jalr { rd: RA, rs1, offset: 0 } => write!(f, "jalr {}", rs1),
+            Instruction::Jalr { rd, rs1, offset } => write!(f, "jalr {}, {}({})", rd, offset, rs1),
+            Instruction::Branch { op: op @ (BranchOp::Beq | BranchOp::Bne), rs1, rs2: ZERO, offset } => {
+                write!(f, "{}z {}, {}", op.name(), rs1, Target(offset))
+            }
+            Instruction::Branch { op, rs1, rs2, offset } => write!(f, "{} {}, {}, {}", op.name(), rs1, rs2, Target(offset)),
+            Instruction::Load { op, rd, rs1, offset } => write!(f, "{} {}, {}({})", op.name(), rd, offset, rs1),
+            Instruction::Store { op, rs1, rs2, offset } => write!(f, "{} {}, {}({})", op.name(), rs2, offset, rs1),
+            Instruction::OpImm { op: AluOp::Add, word: false, rd: ZERO, rs1: ZERO, imm: 0 } => write!(f, "nop"),
+            Instruction::OpImm { op: AluOp::Add, word: false, rd, rs1: ZERO, imm } => write!(f, "li {}, {}", rd, imm),
+            Instruction::OpImm { op: AluOp::Add, word: false, rd, rs1, imm: 0 } => write!(f, "mv {}, {}", rd, rs1),
+            Instruction::OpImm { op: AluOp::Add, word: true, rd, rs1, imm: 0 } => write!(f, "sext.w {}, {}", rd, rs1),
+            Instruction::OpImm { op: AluOp::Xor, rd, rs1, imm: -1, .. } => write!(f, "not {}, {}", rd, rs1),
+            Instruction::OpImm { op, word, rd, rs1, imm } => write!(f, "{} {}, {}, {}", op.name(true, word), rd, rs1, imm),
+            Instruction::Op { op: AluOp::Add, word: false, rd, rs1: ZERO, rs2 } => write!(f, "mv {}, {}", rd, rs2),
+            Instruction::Op { op: AluOp::Sub, word: false, rd, rs1: ZERO, rs2 } => write!(f, "neg {}, {}", rd, rs2),
+            Instruction::Op { op, word, rd, rs1, rs2 } => write!(f, "{} {}, {}, {}", op.name(false, word), rd, rs1, rs2),
+            Instruction::Fence { pred: 0xf, succ: 0xf } => write!(f, "fence"),
+            Instruction::Fence { pred, succ } => write!(f, "fence {}, {}", FenceSet(pred), FenceSet(succ)),
+            Instruction::FenceI => write!(f, "fence.i"),
+            Instruction::Ecall => write!(f, "ecall"),
+            Instruction::Ebreak => write!(f, "ebreak"),
+            Instruction::Mret => write!(f, "mret"),
+            Instruction::Sret => write!(f, "sret"),
+            Instruction::Wfi => write!(f, "wfi"),
+            Instruction::SfenceVma { rs1, rs2 } => write!(f, "sfence.vma {}, {}", rs1, rs2),
+            Instruction::Csr { op, imm, rd, csr, src } => fmt_csr(f, op, imm, rd, CsrName(csr), src),
+            Instruction::Amo { op, double, aq, rl, rd, rs1, rs2 } => {
+                let width = if double { "d" } else { "w" };
+                let order = match (aq, rl) {
+                    (false, false) => "",
+                    (true, false) => ".aq",
+                    (false, true) => ".rl",
+                    (true, true) => ".aqrl",
+                };
+                match op {
+                    AmoOp::Lr => write!(f, "lr.{}{} {}, ({})", width, order, rd, rs1),
+                    _ => write!(f, "{}.{}{} {}, {}, ({})", op.name(), width, order, rd, rs2, rs1),
+                }
+            }
+            Instruction::FpLoad { fmt, rd, rs1, offset } => {
+                let name = if fmt == FpFmt::S { "flw" } else { "fld" };
+                write!(f, "{} {}, {}({})", name, rd, offset, rs1)
+            }
+            Instruction::FpStore { fmt, rs1, rs2, offset } => {
+                let name = if fmt == FpFmt::S { "fsw" } else { "fsd" };
+                write!(f, "{} {}, {}({})", name, rs2, offset, rs1)
+            }
+            Instruction::FpFused { op, fmt, rd, rs1, rs2, rs3, rm } => {
+                write!(f, "{}.{} {}, {}, {}, {}{}", op.name(), fmt.suffix(), rd, rs1, rs2, rs3, RoundingMode(rm))
+            }
+            Instruction::Fp { op, fmt, rd, rs1, rs2, rm } => fmt_fp(f, op, fmt, rd, rs1, rs2, rm),
+            Instruction::Unknown(bits) if length(bits) == 2 => write!(f, ".half {:#06x}", bits & 0xffff),
+            Instruction::Unknown(bits) => write!(f, ".word {:#010x}", bits),
+        }
+    }
+}
+
+/// Disassembles a CSR access, with the `csrr`/`csrw`/`csrs`/`csrc` aliases.
+fn fmt_csr(f: &mut fmt::Formatter, op: CsrOp, imm: bool, rd: Reg, csr: CsrName, src: u8) -> fmt::Result {
+    let name = match op {
+        CsrOp::Rw => "w",
+        CsrOp::Rs => "s",
+        CsrOp::Rc => "c",
+    };
+    match (op, imm, rd, src) {
+        (CsrOp::Rs, false, rd, 0) => write!(f, "csrr {}, {}", rd, csr),
+        (_, true, ZERO, src) => write!(f, "csr{}i {}, {}", name, csr, src),
+        (_, false, ZERO, src) => write!(f, "csr{} {}, {}", name, csr, Reg(src)),
+        (_, true, rd, src) => write!(f, "csrr{}i {}, {}, {}", name, rd, csr, src),
+        (_, false, rd, src) => write!(f, "csrr{} {}, {}, {}", name, rd, csr, Reg(src)),
+    }
+}
+
+/// Disassembles an `OP-FP` instruction, with the `fmv`/`fneg`/`fabs` aliases.
+fn fmt_fp(f: &mut fmt::Formatter, op: FpOp, fmt: FpFmt, rd: u8, rs1: u8, rs2: u8, rm: u8) -> fmt::Result {
+    let (s, x) = (fmt.suffix(), if fmt == FpFmt::S { "w" } else { "d" });
+    let (frd, frs1, frs2) = (FReg(rd), FReg(rs1), FReg(rs2));
+    let rm = RoundingMode(rm);
+    match op {
+        FpOp::Add => write!(f, "fadd.{} {}, {}, {}{}", s, frd, frs1, frs2, rm),
+        FpOp::Sub => write!(f, "fsub.{} {}, {}, {}{}", s, frd, frs1, frs2, rm),
+        FpOp::Mul => write!(f, "fmul.{} {}, {}, {}{}", s, frd, frs1, frs2, rm),
+        FpOp::Div => write!(f, "fdiv.{} {}, {}, {}{}", s, frd, frs1, frs2, rm),
+        FpOp::Sqrt => write!(f, "fsqrt.{} {}, {}{}", s, frd, frs1, rm),
+        FpOp::Sgnj if rs1 == rs2 => write!(f, "fmv.{} {}, {}", s, frd, frs1),
+        FpOp::Sgnjn if rs1 == rs2 => write!(f, "fneg.{} {}, {}", s, frd, frs1),
+        FpOp::Sgnjx if rs1 == rs2 => write!(f, "fabs.{} {}, {}", s, frd, frs1),
+        FpOp::Sgnj => write!(f, "fsgnj.{} {}, {}, {}", s, frd, frs1, frs2),
+        FpOp::Sgnjn => write!(f, "fsgnjn.{} {}, {}, {}", s, frd, frs1, frs2),
+        FpOp::Sgnjx => write!(f, "fsgnjx.{} {}, {}, {}", s, frd, frs1, frs2),
+        FpOp::Min => write!(f, "fmin.{} {}, {}, {}", s, frd, frs1, frs2),
+        FpOp::Max => write!(f, "fmax.{} {}, {}, {}", s, frd, frs1, frs2),
+        FpOp::CvtFmt => {
+            let from = if fmt == FpFmt::S { "d" } else { "s" };
+            write!(f, "fcvt.{}.{} {}, {}{}", s, from, frd, frs1, rm)
+        }
+        FpOp::Eq => write!(f, "feq.{} {}, {}, {}", s, Reg(rd), frs1, frs2),
+        FpOp::Lt => write!(f, "flt.{} {}, {}, {}", s, Reg(rd), frs1, frs2),
+        FpOp::Le => write!(f, "fle.{} {}, {}, {}", s, Reg(rd), frs1, frs2),
+        FpOp::Class => write!(f, "fclass.{} {}, {}", s, Reg(rd), frs1),
+        FpOp::MvToInt => write!(f, "fmv.x.{} {}, {}", x, Reg(rd), frs1),
+        FpOp::MvFromInt => write!(f, "fmv.{}.x {}, {}", x, frd, Reg(rs1)),
+        FpOp::CvtToInt(int) => write!(f, "fcvt.{}.{} {}, {}{}", int.suffix(), s, Reg(rd), frs1, rm),
+        FpOp::CvtFromInt(int) => write!(f, "fcvt.{}.{} {}, {}{}", s, int.suffix(), frd, Reg(rs1), rm),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    /// Formats `insn` into `buf`.
+    struct Text {
+        buf: [u8; 48],
+        len: usize,
+    }
+
+    impl Write for Text {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.buf.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    fn disassemble(bits: u32) -> Text {
+        let mut text = Text { buf: [0; 48], len: 0 };
+        write!(text, "{}", decode(bits)).unwrap();
+        text
+    }
+
+    fn check(cases: &[(u32, &str)]) {
+        for &(bits, expected) in cases {
+            let text = disassemble(bits);
+            let text = core::str::from_utf8(&text.buf[..text.len]).unwrap();
+            assert_eq!(text, expected, "{:#x}", bits);
+        }
+    }
+
+    #[test_case]
+    fn base_instructions_disassemble() {
+        check(&[
+            (0x1802_9073, "csrw satp, t0"),
+            (0x3410_2573, "csrr a0, mepc"),
+            (0x3004_6073, "csrsi mstatus, 8"),
+            (0x0000_0013, "nop"),
+            (0xfe01_0113, "addi sp, sp, -32"),
+            (0x0000_8067, "ret"),
+            (0xff1f_f0ef, "jal .-16"),
+            (0x0005_0463, "beqz a0, .+8"),
+            (0x1234_5537, "lui a0, 0x12345"),
+            (0x0005_a503, "lw a0, 0(a1)"),
+            (0x00b1_31a3, "sd a1, 3(sp)"),
+            (0x02b5_0533, "mul a0, a0, a1"),
+            (0x4035_551b, "sraiw a0, a0, 3"),
+            (0x0ff0_000f, "fence"),
+            (0x0000_100f, "fence.i"),
+            (0x3020_0073, "mret"),
+            (0x1050_0073, "wfi"),
+            (0x0eb6_252f, "amoswap.w.aqrl a0, a1, (a2)"),
+            (0x1005_b52f, "lr.d a0, (a1)"),
+            (0x0081_3507, "fld fa0, 8(sp)"),
+            (0x02b5_7553, "fadd.d fa0, fa0, fa1"),
+            (0xc205_1553, "fcvt.w.d a0, fa0, rtz"),
+            (0xffff_ffff, ".word 0xffffffff"),
+        ]);
+    }
+
+    #[test_case]
+    fn compressed_instructions_expand() {
+        check(&[
+            (0x4188, "lw a0, 0(a1)"),
+            (0xe406, "sd ra, 8(sp)"),
+            (0x1141, "addi sp, sp, -16"),
+            (0x852e, "mv a0, a1"),
+            (0x8082, "ret"),
+            (0x9002, "ebreak"),
+            (0x0001, "nop"),
+            (0x2188, "fld fa0, 0(a1)"),
+            (0x0000, ".half 0x0000"),
+        ]);
+        assert_eq!((length(0x4188), length(0x0005_a503)), (2, 4));
+    }
+}
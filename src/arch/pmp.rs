@@ -0,0 +1,600 @@
+//! ---------------------------------------------------------------------------
+//! File       : pmp.rs
+//! Module     : arch::pmp
+//! Author     : DiTurr
+//! Description:
+//! Physical Memory Protection: per-hart entries granting read, write and
+//! execute permissions on address ranges. Each entry is an address register
+//! (`pmpaddrN`, the address divided by 4) and a configuration byte (byte
+//! `N % 8` of `pmpcfg(N / 8 * 2)` on RV64) selecting how the address is
+//! matched:
+//! - TOR: `[pmpaddr(N-1), pmpaddrN)` (from 0 for entry 0);
+//! - NA4: the 4 bytes at `pmpaddrN`;
+//! - NAPOT: a naturally aligned power-of-two range of 8 bytes or more, its
+//!   size encoded in the trailing ones of `pmpaddrN`.
+//!
+//! The lowest-numbered matching entry decides. Unlocked entries only bind S
+//! and U modes (M-mode keeps full access); locked entries also bind M-mode and
+//! cannot change until reset. An S/U access no entry matches fails, an M-mode
+//! one succeeds.
+//!
+//! [`Builder`] encodes regions (picking NA4, NAPOT or TOR) and programs them;
+//! [`init`] protects the kernel image: the stack guard regions (locked),
+//! then text `r-x`, read-only data `r--`, data, stacks and heap `rw-`, locked
+//! with `pmp=lock` (which then enforces W^X on the kernel itself). Access faults are explained with [`Fault`], and
+//! [`dump`] (the monitor's `pmp`) lists the entries.
+//!
+//! ## Example
+//! ```rust
+//! let mut builder = Builder::new();
+//! builder.add(Region::new(0x8000_0000, 0x8002_0000, R | X).locked())?;
+//! builder.add(Region::new(0x1000_0000, 0x1000_0100, R | W))?;
+//! builder.apply()?;
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::fmt::{self, Write};
+use core::ptr::addr_of;
+
+use crate::cmdline;
+use crate::monitor::{self, Command, CommandError};
+use crate::registers::pmpaddr;
+use crate::registers::pmpcfg::{PMPCFG0, PMPCFG2};
+use crate::stack;
+use crate::traps::traps::Trap;
+use crate::{log_info, log_warn};
+
+/// Number of entries (QEMU implements 16).
+pub const ENTRIES: usize = 16;
+
+// Permission bits of a configuration byte.
+pub const R: u8 = 1 << 0;
+pub const W: u8 = 1 << 1;
+pub const X: u8 = 1 << 2;
+
+// Other fields of a configuration byte.
+const A_SHIFT: u8 = 3;
+const A_MASK: u8  = 0b11 << A_SHIFT;
+const L: u8       = 1 << 7;
+
+/// Implemented bits of `pmpaddrN` on RV64 (physical address bits 55:2).
+const ADDR_MASK: usize = (1 << 54) - 1;
+
+unsafe extern "C" {
+    static _text_start: u8;
+    static _text_end: u8;
+    static _data_start: u8;
+    static _memory_end: u8;
+}
+
+/// Reads `pmpcfgN`; only the even registers exist on RV64.
+fn read_pmpcfg(n: usize) -> usize {
+    match n {
+        0 => PMPCFG0::read(),
+        2 => PMPCFG2::read(),
+        _ => 0,
+    }
+}
+
+/// Writes `pmpcfgN`; only the even registers exist on RV64.
+fn write_pmpcfg(n: usize, value: usize) {
+    match n {
+        0 => PMPCFG0::write(value),
+        2 => PMPCFG2::write(value),
+        _ => {}
+    }
+}
+
+/// Reads `pmpaddrN`.
+pub fn read_pmpaddr(n: usize) -> usize {
+    match n {
+        0 => pmpaddr::PMPADDR0::read(),
+        1 => pmpaddr::PMPADDR1::read(),
+        2 => pmpaddr::PMPADDR2::read(),
+        3 => pmpaddr::PMPADDR3::read(),
+        4 => pmpaddr::PMPADDR4::read(),
+        5 => pmpaddr::PMPADDR5::read(),
+        6 => pmpaddr::PMPADDR6::read(),
+        7 => pmpaddr::PMPADDR7::read(),
+        8 => pmpaddr::PMPADDR8::read(),
+        9 => pmpaddr::PMPADDR9::read(),
+        10 => pmpaddr::PMPADDR10::read(),
+        11 => pmpaddr::PMPADDR11::read(),
+        12 => pmpaddr::PMPADDR12::read(),
+        13 => pmpaddr::PMPADDR13::read(),
+        14 => pmpaddr::PMPADDR14::read(),
+        15 => pmpaddr::PMPADDR15::read(),
+        _ => 0,
+    }
+}
+
+/// Writes `pmpaddrN`.
+fn write_pmpaddr(n: usize, value: usize) {
+    match n {
+        0 => pmpaddr::PMPADDR0::write(value),
+        1 => pmpaddr::PMPADDR1::write(value),
+        2 => pmpaddr::PMPADDR2::write(value),
+        3 => pmpaddr::PMPADDR3::write(value),
+        4 => pmpaddr::PMPADDR4::write(value),
+        5 => pmpaddr::PMPADDR5::write(value),
+        6 => pmpaddr::PMPADDR6::write(value),
+        7 => pmpaddr::PMPADDR7::write(value),
+        8 => pmpaddr::PMPADDR8::write(value),
+        9 => pmpaddr::PMPADDR9::write(value),
+        10 => pmpaddr::PMPADDR10::write(value),
+        11 => pmpaddr::PMPADDR11::write(value),
+        12 => pmpaddr::PMPADDR12::write(value),
+        13 => pmpaddr::PMPADDR13::write(value),
+        14 => pmpaddr::PMPADDR14::write(value),
+        15 => pmpaddr::PMPADDR15::write(value),
+        _ => {}
+    }
+}
+
+/// Reads the configuration of entry `index`.
+pub fn read_config(index: usize) -> Config {
+    Config((read_pmpcfg(index / 8 * 2) >> (index % 8 * 8)) as u8)
+}
+
+/// How an entry matches addresses (the `A` field).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Matching {
+    /// Disabled.
+    Off = 0,
+    /// Top of range.
+    Tor = 1,
+    /// Naturally aligned 4-byte region.
+    Na4 = 2,
+    /// Naturally aligned power-of-two region.
+    Napot = 3,
+}
+
+impl Matching {
+    /// Returns the name of the mode, e.g. `NAPOT`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Matching::Off => "OFF",
+            Matching::Tor => "TOR",
+            Matching::Na4 => "NA4",
+            Matching::Napot => "NAPOT",
+        }
+    }
+}
+
+/// Kind of memory access, to check permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    /// Returns the access an access fault `trap` reports.
+    pub fn of(trap: Trap) -> Option<Access> {
+        match trap {
+            Trap::LoadAccessFault => Some(Access::Read),
+            Trap::StoreAccessFault => Some(Access::Write),
+            Trap::InstructionAccessFault => Some(Access::Execute),
+            _ => None,
+        }
+    }
+
+    /// Returns the permission bit the access needs.
+    fn permission(self) -> u8 {
+        match self {
+            Access::Read => R,
+            Access::Write => W,
+            Access::Execute => X,
+        }
+    }
+}
+
+/// The configuration byte of an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config(pub u8);
+
+impl Config {
+    /// Returns a configuration.
+    ///
+    /// # Arguments
+    /// * `permissions` - `R`, `W` and `X` bits.
+    pub const fn new(matching: Matching, permissions: u8, locked: bool) -> Config {
+        Config((matching as u8) << A_SHIFT | (permissions & (R | W | X)) | if locked { L } else { 0 })
+    }
+
+    /// Returns how the entry matches addresses.
+    pub fn matching(self) -> Matching {
+        match (self.0 & A_MASK) >> A_SHIFT {
+            1 => Matching::Tor,
+            2 => Matching::Na4,
+            3 => Matching::Napot,
+            _ => Matching::Off,
+        }
+    }
+
+    /// Returns the `R`, `W` and `X` bits.
+    pub fn permissions(self) -> u8 {
+        self.0 & (R | W | X)
+    }
+
+    /// Returns whether the entry is locked (binds M-mode, read-only until reset).
+    pub fn locked(self) -> bool {
+        self.0 & L != 0
+    }
+
+    /// Returns whether the entry grants `access`.
+    pub fn permits(self, access: Access) -> bool {
+        self.0 & access.permission() != 0
+    }
+}
+
+impl fmt::Display for Config {
+    /// Formats the permissions and the lock, e.g. `r-x L`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (bit, name) in [(R, 'r'), (W, 'w'), (X, 'x')] {
+            f.write_char(if self.permissions() & bit != 0 { name } else { '-' })?;
+        }
+        f.write_str(if self.locked() { " L" } else { "" })
+    }
+}
+
+/// Decodes the range `[start, end)` an entry matches.
+///
+/// # Arguments
+/// * `previous` - `pmpaddr` of the entry before (0 for entry 0), the bottom of a TOR range.
+///
+/// # Returns
+/// `None` if the entry is off or its range is empty.
+pub fn decode(config: Config, addr: usize, previous: usize) -> Option<(usize, usize)> {
+    let addr = addr & ADDR_MASK;
+    let (start, end) = match config.matching() {
+        Matching::Off => return None,
+        Matching::Tor => ((previous & ADDR_MASK) << 2, addr << 2),
+        Matching::Na4 => (addr << 2, (addr << 2) + 4),
+        Matching::Napot => {
+            let ones = addr.trailing_ones();
+            let size = 1usize << (ones + 3);
+            ((addr & !((1 << ones) - 1)) << 2, ((addr & !((1 << ones) - 1)) << 2) + size)
+        }
+    };
+    (start < end).then_some((start, end))
+}
+
+/// A programmed entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub index: usize,
+    pub config: Config,
+    /// First address matched.
+    pub start: usize,
+    /// First address past the range.
+    pub end: usize,
+}
+
+impl fmt::Display for Entry {
+    /// Formats the entry, e.g. `pmp1  TOR   0x80000000-0x80030000 r-x L`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pmp{:<2} {:<5} {:#010x}-{:#010x} {}",
+            self.index, self.config.matching().name(), self.start, self.end, self.config
+        )
+    }
+}
+
+/// Returns the active entries of this hart, by priority.
+pub fn entries() -> impl Iterator<Item = Entry> {
+    (0..ENTRIES).filter_map(|index| {
+        let previous = if index == 0 { 0 } else { read_pmpaddr(index - 1) };
+        let config = read_config(index);
+        let (start, end) = decode(config, read_pmpaddr(index), previous)?;
+        Some(Entry { index, config, start, end })
+    })
+}
+
+/// Returns the entry deciding accesses to `addr`.
+pub fn find(addr: usize) -> Option<Entry> {
+    entries().find(|entry| (entry.start..entry.end).contains(&addr))
+}
+
+/// Returns whether M-mode may access `[addr, addr + len)`: a locked entry
+/// matching either end may forbid it.
+pub fn machine_permits(addr: usize, len: usize, access: Access) -> bool {
+    let last = addr.saturating_add(len.max(1) - 1);
+    [addr, last].into_iter().all(|addr| match find(addr) {
+        Some(entry) if entry.config.locked() => entry.config.permits(access),
+        _ => true,
+    })
+}
+
+/// Why a region cannot be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PmpError {
+    /// The region is empty or not 4-byte aligned.
+    Invalid,
+    /// No entry left.
+    Full,
+    /// An entry is locked: the configuration cannot change until reset.
+    Locked,
+}
+
+/// A range `[start, end)` with its permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    /// `R`, `W` and `X` bits.
+    pub permissions: u8,
+    pub locked: bool,
+}
+
+impl Region {
+    /// Returns an unlocked region.
+    pub const fn new(start: usize, end: usize, permissions: u8) -> Region {
+        Region { start, end, permissions, locked: false }
+    }
+
+    /// Returns the region, locked.
+    pub const fn locked(self) -> Region {
+        Region { locked: true, ..self }
+    }
+}
+
+/// Encodes regions into entries, by priority, then programs them.
+pub struct Builder {
+    /// Configuration and `pmpaddr` of the entries used.
+    entries: [(Config, usize); ENTRIES],
+    count: usize,
+}
+
+impl Builder {
+    /// Returns a builder with no entry used.
+    pub const fn new() -> Builder {
+        Builder { entries: [(Config(0), 0); ENTRIES], count: 0 }
+    }
+
+    /// Appends an entry.
+    fn push(&mut self, config: Config, addr: usize) -> Result<(), PmpError> {
+        let slot = self.entries.get_mut(self.count).ok_or(PmpError::Full)?;
+        *slot = (config, addr);
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Adds `region` after the previous ones (which take precedence where
+    /// they overlap). A 4-byte region uses NA4, a naturally aligned
+    /// power-of-two region NAPOT, anything else TOR, which takes a second
+    /// (disabled) entry for its bottom unless the previous entry ends there.
+    ///
+    /// # Returns
+    /// `Invalid` for an empty or misaligned region, `Full` if the entries run out.
+    pub fn add(&mut self, region: Region) -> Result<(), PmpError> {
+        let Region { start, end, permissions, locked } = region;
+        if start >= end || !start.is_multiple_of(4) || !end.is_multiple_of(4) {
+            return Err(PmpError::Invalid);
+        }
+        let size = end - start;
+        if size == 4 {
+            return self.push(Config::new(Matching::Na4, permissions, locked), start >> 2);
+        }
+        if size.is_power_of_two() && start.is_multiple_of(size) {
+            return self.push(Config::new(Matching::Napot, permissions, locked), (start | (size / 2 - 1)) >> 2);
+        }
+        let bottom = match self.count {
+            0 => 0,
+            count => self.entries[count - 1].1,
+        };
+        if bottom != start >> 2 {
+            if self.count + 2 > ENTRIES {
+                return Err(PmpError::Full);
+            }
+            // A locked TOR entry also locks the entry below it.
+            self.push(Config::new(Matching::Off, 0, locked), start >> 2)?;
+        }
+        self.push(Config::new(Matching::Tor, permissions, locked), end >> 2)
+    }
+
+    /// Programs the entries on this hart, disabling the unused ones.
+    ///
+    /// # Returns
+    /// `Locked` if an entry is locked already (nothing is changed).
+    pub fn apply(&self) -> Result<(), PmpError> {
+        if (0..ENTRIES).any(|index| read_config(index).locked()) {
+            return Err(PmpError::Locked);
+        }
+        // Disable every entry while the addresses change.
+        write_pmpcfg(0, 0);
+        write_pmpcfg(2, 0);
+        let mut configs = [0usize; 2];
+        for (index, &(config, addr)) in self.entries.iter().enumerate() {
+            write_pmpaddr(index, if index < self.count { addr } else { 0 });
+            configs[index / 8] |= (config.0 as usize) << (index % 8 * 8);
+        }
+        write_pmpcfg(0, configs[0]);
+        write_pmpcfg(2, configs[1]);
+        Ok(())
+    }
+}
+
+/// Protects the kernel image on the boot hart: the guard regions of the
+/// stacks (see `stack`) inaccessible, text `r-x`, read-only data `r--`, the
+/// rest of RAM (data, bss, stacks, heap) `rw-`; locked with `pmp=lock`,
+/// skipped with `pmp=off`. Must run after `stack::init`.
+pub fn init() {
+    let locked = match cmdline::get("pmp") {
+        Some("off") => return,
+        Some("lock") => true,
+        None => false,
+        Some(other) => {
+            log_warn!("pmp: unknown mode '{}', not locking.", other);
+            false
+        }
+    };
+    let text = addr_of!(_text_start) as usize;
+    let rodata = addr_of!(_text_end) as usize;
+    let data = addr_of!(_data_start) as usize;
+    let end = addr_of!(_memory_end) as usize;
+    let mut builder = Builder::new();
+    // Guard regions come first (they take precedence) and are always locked,
+    // to catch kernel stack overflows.
+    let guards = stack::guards().map(|guard| Region::new(guard.start, guard.end, 0).locked());
+    let image = [Region::new(text, rodata, R | X), Region::new(rodata, data, R), Region::new(data, end, R | W)];
+    let image = image.into_iter().map(|region| if locked { region.locked() } else { region });
+    let result = guards.chain(image).try_for_each(|region| builder.add(region)).and_then(|()| builder.apply());
+    match result {
+        Ok(()) => log_info!("pmp: kernel image protected{}.", if locked { " (locked)" } else { "" }),
+        Err(err) => log_warn!("pmp: cannot protect the kernel image: {:?}", err),
+    }
+    if let Err(err) = monitor::register(&PMP) {
+        log_warn!("pmp: cannot register the monitor command: {:?}", err);
+    }
+}
+
+/// Writes the active entries of this hart to `out`, one per line.
+pub fn dump(out: &mut dyn Write) {
+    let mut any = false;
+    for entry in entries() {
+        let _ = writeln!(out, "{}", entry);
+        any = true;
+    }
+    if !any {
+        let _ = writeln!(out, "no PMP entry");
+    }
+}
+
+static PMP: Command = Command { name: "pmp", args: "", help: "list the PMP entries", run: pmp };
+
+fn pmp(args: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    dump(out);
+    Ok(())
+}
+
+/// An access fault, explained by the PMP entry covering the address.
+pub struct Fault {
+    pub access: Access,
+    pub addr: usize,
+}
+
+impl fmt::Display for Fault {
+    /// Formats e.g. `write to 0x80001000 denied by pmp1  TOR ... r-x L`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verb = match self.access {
+            Access::Read => "read from",
+            Access::Write => "write to",
+            Access::Execute => "execution at",
+        };
+        write!(f, "{} {:#x}", verb, self.addr)?;
+        match find(self.addr) {
+            Some(entry) if entry.config.locked() && !entry.config.permits(self.access) => {
+                write!(f, " denied by {}", entry)
+            }
+            // M-mode is allowed: the bus rejected the access.
+            Some(entry) => write!(f, " allowed by {} (nothing at that address?)", entry),
+            None => f.write_str(", no PMP entry (nothing at that address?)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Formats `value` into a buffer.
+    struct Text {
+        text: [u8; 96],
+        len: usize,
+    }
+
+    impl Text {
+        fn of(value: impl fmt::Display) -> Text {
+            let mut text = Text { text: [0; 96], len: 0 };
+            let _ = write!(text, "{}", value);
+            text
+        }
+
+        fn as_str(&self) -> &str {
+            core::str::from_utf8(&self.text[..self.len]).unwrap()
+        }
+    }
+
+    impl Write for Text {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.text[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+            self.len += s.len();
+            Ok(())
+        }
+    }
+
+    /// Decodes the entries of `builder`.
+    fn ranges(builder: &Builder) -> impl Iterator<Item = (Matching, usize, usize)> + '_ {
+        builder.entries[..builder.count].iter().enumerate().filter_map(|(index, &(config, addr))| {
+            let previous = if index == 0 { 0 } else { builder.entries[index - 1].1 };
+            decode(config, addr, previous).map(|(start, end)| (config.matching(), start, end))
+        })
+    }
+
+    #[test_case]
+    fn regions_pick_their_encoding() {
+        let mut builder = Builder::new();
+        builder.add(Region::new(0x1000_0000, 0x1000_0004, R)).unwrap();
+        builder.add(Region::new(0x8000_0000, 0x8000_1000, R | X)).unwrap();
+        builder.add(Region::new(0x8000_1000, 0x8000_3000, R)).unwrap();
+        builder.add(Region::new(0x8000_3000, 0x8000_5000, R | W)).unwrap();
+        builder.add(Region::new(0x9000_0000, 0x9000_0010, R)).unwrap();
+        // NA4, NAPOT, then TOR regions from the top of the NAPOT one (an extra bottom entry).
+        assert_eq!(builder.count, 6);
+        assert_eq!(builder.entries[1], (Config::new(Matching::Napot, R | X, false), 0x2000_01ff));
+        assert_eq!(builder.entries[2].0.matching(), Matching::Off);
+        let expected = [
+            (Matching::Na4, 0x1000_0000, 0x1000_0004),
+            (Matching::Napot, 0x8000_0000, 0x8000_1000),
+            (Matching::Tor, 0x8000_1000, 0x8000_3000),
+            (Matching::Tor, 0x8000_3000, 0x8000_5000),
+            (Matching::Napot, 0x9000_0000, 0x9000_0010),
+        ];
+        assert!(ranges(&builder).eq(expected));
+    }
+
+    #[test_case]
+    fn invalid_regions_are_rejected() {
+        let mut builder = Builder::new();
+        assert_eq!(builder.add(Region::new(0x1000, 0x1000, R)), Err(PmpError::Invalid));
+        assert_eq!(builder.add(Region::new(0x1002, 0x2000, R)), Err(PmpError::Invalid));
+        for index in 0..ENTRIES {
+            builder.add(Region::new(index * 8, index * 8 + 4, R)).unwrap();
+        }
+        assert_eq!(builder.add(Region::new(0x1000, 0x1004, R)), Err(PmpError::Full));
+    }
+
+    #[test_case]
+    fn configs_format() {
+        let config = Config::new(Matching::Tor, R | X, true);
+        assert_eq!((config.matching(), config.permissions(), config.locked()), (Matching::Tor, R | X, true));
+        assert!(config.permits(Access::Execute) && !config.permits(Access::Write));
+        let entry = Entry { index: 1, config, start: 0x8000_0000, end: 0x8003_0000 };
+        assert_eq!(Text::of(entry).as_str(), "pmp1  TOR   0x80000000-0x80030000 r-x L");
+    }
+
+    // Programs the mock CSR file: the kernel's own entries must stay.
+    #[cfg(not(target_os = "none"))]
+    #[test_case]
+    fn applied_entries_read_back() {
+        let mut builder = Builder::new();
+        builder.add(Region::new(0x8000_0000, 0x8003_0000, R | X).locked()).unwrap();
+        builder.add(Region::new(0x8003_0000, 0x8800_0000, R | W).locked()).unwrap();
+        builder.apply().unwrap();
+        assert_eq!(entries().count(), 2);
+        assert_eq!(find(0x8000_1000).map(|entry| entry.index), Some(1));
+        assert!(!machine_permits(0x8002_fffc, 8, Access::Write));
+        assert!(machine_permits(0x8003_0000, 8, Access::Write));
+        assert!(machine_permits(0x1000_0000, 1, Access::Write));
+        let fault = Fault { access: Access::Write, addr: 0x8000_1000 };
+        assert_eq!(Text::of(fault).as_str(), "write to 0x80001000 denied by pmp1  TOR   0x80000000-0x80030000 r-x L");
+        assert_eq!(builder.apply(), Err(PmpError::Locked));
+    }
+}
@@ -0,0 +1,342 @@
+//! ---------------------------------------------------------------------------
+//! File       : semihosting.rs
+//! Module     : arch::semihosting
+//! Author     : DiTurr
+//! Description:
+//! RISC-V semihosting: requests served by the host (QEMU started with
+//! `-semihosting`, or a debugger), for test automation. A request is the
+//! operation number in `a0` and the address of its parameter block in `a1`,
+//! issued with the sequence
+//! ```text
+//! slli zero, zero, 0x1f
+//! ebreak
+//! srai zero, zero, 7
+//! ```
+//! whose surrounding no-ops tell the host that the `ebreak` is a request; the
+//! result comes back in `a0`.
+//!
+//! Without a host, the `ebreak` traps to the kernel like any other:
+//! [`handle_trap`] recognizes the sequence, fails the request and marks
+//! semihosting unavailable. [`init`] probes this way, so that nothing else is
+//! issued when there is no host.
+//!
+//! Supported operations: console output (`SYS_WRITE0`, also as a log sink
+//! named `semihosting`), host files ([`File`], used by the test log and the
+//! monitor's `hostcat` and `hostsave`), the elapsed time (`SYS_CLOCK`) and exit with a status
+//! (`SYS_EXIT_EXTENDED`, also used by `power=semihosting`).
+//!
+//! ## Example
+//! ```rust
+//! // qemu ... -semihosting
+//! semihosting::init();
+//! let file = File::open("results.txt", Mode::Write)?;
+//! file.write(b"ok\n")?;
+//! semihosting::exit(0);
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use crate::logger::sink::LogSink;
+use crate::monitor::commands::parse_number;
+use crate::monitor::{self, Command, CommandError};
+use crate::syscalls::errno::Errno;
+use crate::syscalls::user_slice;
+use crate::traps::trap_frame::{reg, TrapFrame};
+use crate::{log_info, log_warn};
+
+// Operation numbers.
+const SYS_OPEN: usize          = 0x01;
+const SYS_CLOSE: usize         = 0x02;
+const SYS_WRITE0: usize        = 0x04;
+const SYS_WRITE: usize         = 0x05;
+const SYS_READ: usize          = 0x06;
+const SYS_CLOCK: usize         = 0x10;
+const SYS_ERRNO: usize         = 0x13;
+const SYS_EXIT_EXTENDED: usize = 0x20;
+
+/// `SYS_EXIT_EXTENDED` reason: the program exited normally.
+const ADP_STOPPED_APPLICATION_EXIT: usize = 0x2_0026;
+
+// Instructions around the `ebreak` of a request.
+const SLLI_ZERO_0X1F: u32 = 0x01f0_1013;
+const SRAI_ZERO_7: u32    = 0x4070_5013;
+
+/// Size in bytes of the (uncompressed) `ebreak`.
+const EBREAK_SIZE: usize = 4;
+
+/// Longest path passed to [`File::open`].
+const PATH_MAX: usize = 255;
+
+/// A host serves the requests.
+static AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Issues request `op` with parameter `param`.
+///
+/// # Returns
+/// The result in `a0`; `usize::MAX` (-1) for most failures.
+fn call(op: usize, param: usize) -> usize {
+    #[cfg(target_os = "none")]
+    {
+        let ret: usize;
+        // SAFETY: The host (or `handle_trap`) only accesses the parameter block
+        // and the memory it points to, all of which the caller owns.
+        unsafe {
+            core::arch::asm!(
+                ".option push",
+                ".option norvc",
+                // The sequence must not cross a page boundary.
+                ".balign 16",
+                "slli zero, zero, 0x1f",
+                "ebreak",
+                "srai zero, zero, 7",
+                ".option pop",
+                inlateout("a0") op => ret,
+                in("a1") param,
+                options(nostack),
+            );
+        }
+        ret
+    }
+    #[cfg(not(target_os = "none"))]
+    {
+        let _ = (op, param);
+        usize::MAX
+    }
+}
+
+/// Issues request `op` if a host is there.
+///
+/// # Returns
+/// `ENODEV` without a host.
+fn request(op: usize, param: usize) -> Result<usize, Errno> {
+    if !available() {
+        return Err(Errno::ENODEV);
+    }
+    Ok(call(op, param))
+}
+
+/// Returns the error of the last failed request, as a kernel error number.
+fn host_errno() -> Errno {
+    match call(SYS_ERRNO, 0) {
+        2 => Errno::ENOENT,
+        17 => Errno::EEXIST,
+        28 => Errno::ENOSPC,
+        36 => Errno::ENAMETOOLONG,
+        _ => Errno::EIO,
+    }
+}
+
+/// Probes for a host. Must run once the trap vector is installed.
+pub fn init() {
+    AVAILABLE.store(true, Ordering::Relaxed);
+    call(SYS_ERRNO, 0);
+    if !available() {
+        return;
+    }
+    log_info!("semihosting: available.");
+    for command in [&HOSTCAT, &HOSTSAVE] {
+        if let Err(err) = monitor::register(command) {
+            log_warn!("semihosting: cannot register '{}': {:?}", command.name, err);
+        }
+    }
+}
+
+/// Returns whether a host serves semihosting requests.
+pub fn available() -> bool {
+    AVAILABLE.load(Ordering::Relaxed)
+}
+
+/// Fails a request that trapped because no host served it, from the
+/// `Breakpoint` trap.
+///
+/// # Returns
+/// `false` if the `ebreak` is not part of a request.
+pub fn handle_trap(frame: &mut TrapFrame) -> bool {
+    let Some(start) = frame.mepc.checked_sub(4) else {
+        return false;
+    };
+    let Ok(code) = user_slice(start, 12) else {
+        return false;
+    };
+    let word = |offset: usize| u32::from_le_bytes([code[offset], code[offset + 1], code[offset + 2], code[offset + 3]]);
+    if word(0) != SLLI_ZERO_0X1F || word(8) != SRAI_ZERO_7 {
+        return false;
+    }
+    AVAILABLE.store(false, Ordering::Relaxed);
+    frame.regs[reg::A0] = usize::MAX;
+    frame.mepc += EBREAK_SIZE;
+    true
+}
+
+/// Writes `s` to the host console.
+pub fn write_str(s: &str) {
+    if !available() {
+        return;
+    }
+    // `SYS_WRITE0` takes a NUL-terminated string.
+    let mut buf = [0u8; 128];
+    for chunk in s.as_bytes().chunks(buf.len() - 1) {
+        buf[..chunk.len()].copy_from_slice(chunk);
+        buf[chunk.len()] = 0;
+        call(SYS_WRITE0, buf.as_ptr() as usize);
+    }
+}
+
+/// Returns the time elapsed since the program started, as seen by the host
+/// (with a 10 ms resolution).
+pub fn clock() -> Option<Duration> {
+    match request(SYS_CLOCK, 0) {
+        Ok(centiseconds) if centiseconds != usize::MAX => Some(Duration::from_millis(10 * centiseconds as u64)),
+        _ => None,
+    }
+}
+
+/// Ends the program: the host exits with status `code`.
+///
+/// Returns only if there is no host.
+pub fn exit(code: u32) {
+    let block = [ADP_STOPPED_APPLICATION_EXIT, code as usize];
+    let _ = request(SYS_EXIT_EXTENDED, block.as_ptr() as usize);
+}
+
+/// How to open a host file (binary modes of `fopen`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Mode {
+    /// `rb`
+    Read = 1,
+    /// `wb`: created or truncated.
+    Write = 5,
+}
+
+/// An open host file; closed when dropped.
+pub struct File(usize);
+
+impl File {
+    /// Opens the host file `path` (relative to the directory QEMU runs in),
+    /// or the host console with `:tt`.
+    pub fn open(path: &str, mode: Mode) -> Result<File, Errno> {
+        if path.len() > PATH_MAX {
+            return Err(Errno::ENAMETOOLONG);
+        }
+        let mut name = [0u8; PATH_MAX + 1];
+        name[..path.len()].copy_from_slice(path.as_bytes());
+        let block = [name.as_ptr() as usize, mode as usize, path.len()];
+        match request(SYS_OPEN, block.as_ptr() as usize)? {
+            usize::MAX => Err(host_errno()),
+            handle => Ok(File(handle)),
+        }
+    }
+
+    /// Reads up to `buf.len()` bytes.
+    ///
+    /// # Returns
+    /// The number of bytes read, 0 at the end of the file.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let block = [self.0, buf.as_mut_ptr() as usize, buf.len()];
+        // The host returns the number of bytes *not* read.
+        match request(SYS_READ, block.as_ptr() as usize)? {
+            left if left <= buf.len() => Ok(buf.len() - left),
+            _ => Err(host_errno()),
+        }
+    }
+
+    /// Writes `buf`.
+    ///
+    /// # Returns
+    /// The number of bytes written.
+    pub fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        let block = [self.0, buf.as_ptr() as usize, buf.len()];
+        // The host returns the number of bytes *not* written.
+        match request(SYS_WRITE, block.as_ptr() as usize)? {
+            0 => Ok(buf.len()),
+            left if left < buf.len() => Ok(buf.len() - left),
+            _ => Err(host_errno()),
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let block = [self.0];
+        let _ = request(SYS_CLOSE, block.as_ptr() as usize);
+    }
+}
+
+static HOSTCAT: Command = Command { name: "hostcat", args: "<path>", help: "print a host file", run: hostcat };
+
+fn hostcat(args: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let [path] = args else {
+        return Err(CommandError::Usage);
+    };
+    let file = File::open(path, Mode::Read).map_err(|_| CommandError::Failed("cannot open the file"))?;
+    let mut buf = [0u8; 256];
+    loop {
+        let count = file.read(&mut buf).map_err(|_| CommandError::Failed("read error"))?;
+        if count == 0 {
+            return Ok(());
+        }
+        for chunk in buf[..count].utf8_chunks() {
+            let _ = out.write_str(chunk.valid());
+            if !chunk.invalid().is_empty() {
+                let _ = out.write_char(char::REPLACEMENT_CHARACTER);
+            }
+        }
+    }
+}
+
+static HOSTSAVE: Command = Command {
+    name: "hostsave",
+    args: "<path> <addr> <len>",
+    help: "save memory to a host file",
+    run: hostsave,
+};
+
+fn hostsave(args: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let [path, addr, len] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (addr, len) = (parse_number(addr)?, parse_number(len)?);
+    let memory = user_slice(addr, len).map_err(|_| CommandError::Failed("not in RAM"))?;
+    let file = File::open(path, Mode::Write).map_err(|_| CommandError::Failed("cannot create the file"))?;
+    let written = file.write(memory).map_err(|_| CommandError::Failed("write error"))?;
+    let _ = writeln!(out, "{} bytes written", written);
+    Ok(())
+}
+
+/// The host console as a log sink.
+pub struct Console;
+
+/// The semihosting log sink (`log=semihosting`).
+pub static CONSOLE: Console = Console;
+
+impl LogSink for Console {
+    fn name(&self) -> &'static str {
+        "semihosting"
+    }
+
+    fn write_str(&self, s: &str) {
+        write_str(s);
+    }
+}
+
+// Requests go through the trap vector: test kernel only.
+#[cfg(all(test, target_os = "none"))]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn requests_fail_without_a_host() {
+        if available() {
+            assert!(clock().is_some());
+        } else {
+            // Issued anyway: the trap handler fails it and the kernel goes on.
+            assert_eq!(call(SYS_CLOCK, 0), usize::MAX);
+            assert!(!available());
+            assert_eq!(File::open(":tt", Mode::Write).err(), Some(Errno::ENODEV));
+        }
+    }
+}
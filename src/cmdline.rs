@@ -0,0 +1,48 @@
+//! ---------------------------------------------------------------------------
+//! File       : cmdline.rs
+//! Module     : cmdline
+//! Author     : DiTurr
+//! Description:
+//! The kernel command line, taken from the `bootargs` property of the device
+//! tree's `/chosen` node (QEMU fills it from `-append`). It is a list of
+//! whitespace-separated `key=value` options.
+//!
+//! ## Example
+//! ```rust
+//! // qemu ... -append "log=hvc1 console=hvc0"
+//! cmdline::init();
+//! let console = cmdline::get("console").unwrap_or("ttyS0");
+//! ```
+//! ---------------------------------------------------------------------------
+
+use crate::fdt;
+use crate::sync::spinlock::SpinLock;
+
+/// The command line; empty until [`init`] runs or if there are no boot arguments.
+static CMDLINE: SpinLock<&'static str> = SpinLock::new("");
+
+/// Loads the command line from the device tree.
+pub fn init() {
+    let bootargs = fdt::get()
+        .and_then(|fdt| fdt.find_node("/chosen"))
+        .and_then(|chosen| chosen.property_str("bootargs"));
+    *CMDLINE.lock() = bootargs.unwrap_or("");
+}
+
+/// Returns the whole command line.
+pub fn as_str() -> &'static str {
+    *CMDLINE.lock()
+}
+
+/// Returns the value of option `key`, e.g. `get("log")` is `Some("hvc1")` for
+/// `log=hvc1`. A bare `key` yields `Some("")`; if the option is repeated, the
+/// last occurrence wins.
+pub fn get(key: &str) -> Option<&'static str> {
+    as_str()
+        .split_whitespace()
+        .filter_map(|option| match option.split_once('=') {
+            Some((name, value)) => (name == key).then_some(value),
+            None => (option == key).then_some(""),
+        })
+        .next_back()
+}
@@ -0,0 +1,106 @@
+//! ---------------------------------------------------------------------------
+//! File       : console.rs
+//! Module     : console
+//! Author     : DiTurr
+//! Description:
+//! The system console: the interactive terminal behind `/dev/console` and file
+//! descriptors 0-2, chosen at boot with `console=<dev>` on the kernel command
+//! line. Where kernel logs go is chosen separately (see `logger::sink`).
+//!
+//! Terminal devices are `ttyS0` (the 16550 UART, the default) and `hvc0`-
+//! `hvc2` (virtio console ports). All of them are also available under `/dev`.
+//!
+//! ## Example
+//! ```rust
+//! // qemu ... -append "console=hvc0"
+//! console::init();
+//! ```
+//! ---------------------------------------------------------------------------
+
+use crate::cmdline;
+use crate::fs::{devfs, CharDevice};
+use crate::logger::sink::LogSink;
+use crate::peripherals::uart::UART;
+use crate::peripherals::virtio::console::{HVC, VIRTIO_CONSOLE};
+use crate::peripherals::virtio::{self, DeviceType};
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+use crate::syscalls::fd;
+use crate::{log_info, log_warn};
+
+/// The `/dev/console` device, forwarding to the selected terminal.
+pub struct Console;
+
+/// The kernel's console instance.
+pub static CONSOLE: Console = Console;
+
+/// The terminal selected with `console=`.
+static TERMINAL: SpinLock<&'static dyn CharDevice> = SpinLock::new(&UART);
+
+/// Returns the terminal device named `name`.
+fn find_terminal(name: &str) -> Option<&'static dyn CharDevice> {
+    if name == UART.name() {
+        return Some(&UART);
+    }
+    let port = HVC.iter().find(|port| port.name() == name)?;
+    Some(port)
+}
+
+/// Probes the virtio console, registers the terminal devices and applies the
+/// `console=` selection.
+pub fn init() {
+    match virtio::find_device(DeviceType::Console) {
+        Some(transport) => {
+            if let Err(err) = VIRTIO_CONSOLE.init(transport) {
+                log_warn!("virtio-console initialization failed: {:?}", err);
+            }
+        }
+        None => {
+            log_info!("No virtio-console device found.");
+        }
+    }
+    let mut registered = devfs::register(UART.name(), &UART);
+    for (index, port) in HVC.iter().enumerate() {
+        if let Some((true, console)) = VIRTIO_CONSOLE.port_info(index) {
+            log_info!("virtio-console: {} ({}).", port.name(), if console { "console" } else { "serial port" });
+            registered = registered.and(devfs::register(port.name(), port));
+        }
+    }
+    registered = registered.and(devfs::register("console", &CONSOLE));
+    if let Err(err) = registered {
+        log_warn!("console: cannot register terminal devices: {:?}", err);
+    }
+    let console = cmdline::get("console").unwrap_or(UART.name());
+    match find_terminal(console) {
+        Some(device) => *TERMINAL.lock() = device,
+        None => {
+            log_warn!("console: unknown console device '{}'.", console);
+        }
+    }
+    fd::open_std_streams(&CONSOLE);
+    log_info!("console: console on {}.", console);
+}
+
+/// Returns the terminal selected with `console=`, for readers that must not
+/// block (the `/dev/console` device waits for input).
+pub fn terminal() -> &'static dyn CharDevice {
+    *TERMINAL.lock()
+}
+
+impl CharDevice for Console {
+    /// Waits for input on the terminal and returns what is available.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let terminal = *TERMINAL.lock();
+        loop {
+            match terminal.read(buf) {
+                Err(Errno::EAGAIN) => core::hint::spin_loop(),
+                result => return result,
+            }
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        let terminal = *TERMINAL.lock();
+        terminal.write(buf)
+    }
+}
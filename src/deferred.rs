@@ -0,0 +1,43 @@
+//! ---------------------------------------------------------------------------
+//! File       : deferred.rs
+//! Module     : deferred
+//! Author     : DiTurr
+//! Description:
+//! Deferred interrupt work ("bottom halves"): an interrupt handler does the
+//! urgent part with interrupts disabled, and defers the rest to
+//! - a softirq ([`softirq`]): a per-hart vector raised by the handler, run
+//!   when the outermost interrupt handler returns, with interrupts enabled;
+//! - a tasklet ([`tasklet`]): a function scheduled by the handler, run by the
+//!   tasklet softirq on the same hart, once however often it was scheduled;
+//! - a work item ([`workqueue`]): a function run by a kernel worker thread,
+//!   which may take locks and yield (softirqs and tasklets must not take a
+//!   lock the interrupted code may hold).
+//!
+//! The receive path of the network uses all three: the virtio-net interrupt
+//! schedules a tasklet that reclaims the transmitted buffers and raises the
+//! `NetRx` softirq, which queues the processing of the received frames on the
+//! system work queue.
+//! ---------------------------------------------------------------------------
+pub mod softirq;
+pub mod tasklet;
+pub mod workqueue;
+
+use crate::{log_error, thread};
+
+/// Opens the tasklet softirq and starts the worker thread of the system work
+/// queue. Runs after `thread::init`.
+pub fn init() {
+    if let Err(err) = softirq::open(softirq::Softirq::Tasklet, tasklet::action) {
+        log_error!("deferred: cannot open the tasklet softirq: {:?}", err);
+    }
+    if let Err(err) = workqueue::SYSTEM.start() {
+        log_error!("deferred: cannot start the system work queue: {:?}", err);
+    }
+}
+
+/// Runs the softirqs left pending by interrupt handlers, then lets the worker
+/// threads run. Called from the main loop.
+pub fn poll() {
+    softirq::run();
+    thread::yield_now();
+}
@@ -0,0 +1,149 @@
+//! ---------------------------------------------------------------------------
+//! File       : softirq.rs
+//! Module     : deferred::softirq
+//! Author     : DiTurr
+//! Description:
+//! Softirqs: a fixed set of vectors, each with a handler ([`open`]) and a
+//! pending bit per hart. An interrupt handler [`raise`]s a vector; when the
+//! outermost interrupt handler of the hart returns, `traps::interrupts` runs
+//! the pending vectors ([`run`]), lowest first, with interrupts enabled. An
+//! interrupt taken meanwhile may raise vectors again: they run in the same
+//! pass, up to `MAX_RESTART` rounds, then are left to the main loop.
+//!
+//! Handlers run in interrupt context, on the interrupted stack: they must not
+//! take a lock the interrupted code may hold (see `deferred::workqueue`).
+//! ---------------------------------------------------------------------------
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use crate::registers::mhartid::MHARTID;
+use crate::stack::MAX_HARTS;
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+use crate::traps::interrupts;
+
+/// Softirq vectors, by priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Softirq {
+    /// Received network frames.
+    NetRx   = 0,
+    /// Scheduled tasklets (see `deferred::tasklet`).
+    Tasklet = 1,
+}
+
+impl Softirq {
+    /// All vectors, by priority.
+    pub const ALL: [Softirq; 2] = [Softirq::NetRx, Softirq::Tasklet];
+
+    /// Returns the name of the vector, e.g. `net-rx`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Softirq::NetRx => "net-rx",
+            Softirq::Tasklet => "tasklet",
+        }
+    }
+}
+
+/// Number of vectors.
+const VECTORS: usize = Softirq::ALL.len();
+
+/// Rounds of [`run`] before leaving the vectors raised again to the main loop.
+const MAX_RESTART: usize = 10;
+
+/// The handler of each vector.
+type Handlers = [Option<fn()>; VECTORS];
+
+static HANDLERS: SpinLock<Handlers> = SpinLock::new([None; VECTORS]);
+
+/// Raised vectors, per hart: bit `n` for vector `n`.
+static PENDING: [AtomicU32; MAX_HARTS] = [const { AtomicU32::new(0) }; MAX_HARTS];
+
+/// Set while [`run`] runs on the hart.
+static ACTIVE: [AtomicBool; MAX_HARTS] = [const { AtomicBool::new(false) }; MAX_HARTS];
+
+/// Handler runs, per hart and vector.
+static COUNTS: [[AtomicUsize; VECTORS]; MAX_HARTS] = [const { [const { AtomicUsize::new(0) }; VECTORS] }; MAX_HARTS];
+
+fn hart() -> usize {
+    MHARTID::read()
+}
+
+/// Sets the handler of `vector`.
+///
+/// # Returns
+/// `EEXIST` if the vector has a handler.
+pub fn open(vector: Softirq, handler: fn()) -> Result<(), Errno> {
+    // The handlers are looked up in interrupt context.
+    interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        if handlers[vector as usize].is_some() {
+            return Err(Errno::EEXIST);
+        }
+        handlers[vector as usize] = Some(handler);
+        Ok(())
+    })
+}
+
+/// Marks `vector` pending on this hart.
+pub fn raise(vector: Softirq) {
+    PENDING[hart()].fetch_or(1 << vector as usize, Ordering::Relaxed);
+}
+
+/// Returns whether a vector is pending on this hart.
+pub fn pending() -> bool {
+    PENDING[hart()].load(Ordering::Relaxed) != 0
+}
+
+/// Runs the pending vectors of this hart, unless they are already running on
+/// it (this is an interrupt taken meanwhile). Interrupts must be enabled.
+pub fn run() {
+    let hart = hart();
+    if ACTIVE[hart].swap(true, Ordering::Acquire) {
+        return;
+    }
+    let handlers = interrupts::without_interrupts(|| *HANDLERS.lock());
+    for _ in 0..MAX_RESTART {
+        let pending = PENDING[hart].swap(0, Ordering::Relaxed);
+        if pending == 0 {
+            break;
+        }
+        for vector in Softirq::ALL.into_iter().filter(|&vector| pending & (1 << vector as usize) != 0) {
+            if let Some(handler) = handlers[vector as usize] {
+                COUNTS[hart][vector as usize].fetch_add(1, Ordering::Relaxed);
+                handler();
+            }
+        }
+    }
+    ACTIVE[hart].store(false, Ordering::Release);
+}
+
+/// Returns the number of times `vector` ran on `hart`.
+pub fn count(hart: usize, vector: Softirq) -> usize {
+    COUNTS.get(hart).map_or(0, |counts| counts[vector as usize].load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn raised_vectors_run_once() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        fn count_run() {
+            RUNS.fetch_add(1, Ordering::Relaxed);
+        }
+        // `NetRx` may already be opened by the network stack (test kernel):
+        // then its handler runs instead of this one.
+        let opened = open(Softirq::NetRx, count_run).is_ok();
+        assert_eq!(open(Softirq::NetRx, count_run), Err(Errno::EEXIST));
+        let (runs, before) = (RUNS.load(Ordering::Relaxed), count(hart(), Softirq::NetRx));
+        raise(Softirq::NetRx);
+        raise(Softirq::NetRx);
+        assert!(pending());
+        run();
+        assert!(!pending());
+        assert_eq!(count(hart(), Softirq::NetRx), before + 1);
+        assert_eq!(RUNS.load(Ordering::Relaxed), runs + usize::from(opened));
+    }
+}
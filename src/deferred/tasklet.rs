@@ -0,0 +1,173 @@
+//! ---------------------------------------------------------------------------
+//! File       : tasklet.rs
+//! Module     : deferred::tasklet
+//! Author     : DiTurr
+//! Description:
+//! Tasklets: functions an interrupt handler [`Tasklet::schedule`]s, run by
+//! the `Tasklet` softirq of the hart, in the order they were scheduled. A
+//! tasklet scheduled again before it ran runs once; scheduled while it runs,
+//! it runs again afterwards. It never runs on two harts at once.
+//!
+//! Tasklets are statics, chained in a per-hart list through their `next`
+//! field: there is no allocation, and scheduling is lock-free.
+//!
+//! ## Example
+//! ```rust
+//! static RECLAIM: Tasklet = Tasklet::new("reclaim", reclaim);
+//!
+//! fn interrupt(irq: u32) {
+//!     RECLAIM.schedule();
+//! }
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+
+use super::softirq::{self, Softirq};
+use crate::log_trace;
+use crate::registers::mhartid::MHARTID;
+use crate::stack::MAX_HARTS;
+
+/// Tasklets run by the softirq before it checks for new ones.
+const BATCH: usize = 32;
+
+// Tasklet state bits.
+const SCHEDULED: u8 = 1 << 0;
+const RUNNING: u8   = 1 << 1;
+
+/// Scheduled tasklets, per hart, most recent first.
+static LISTS: [AtomicPtr<Tasklet>; MAX_HARTS] = [const { AtomicPtr::new(null_mut()) }; MAX_HARTS];
+
+/// A function deferred by an interrupt handler.
+pub struct Tasklet {
+    pub name: &'static str,
+    func: fn(),
+    state: AtomicU8,
+    /// Next tasklet in the list of the hart.
+    next: AtomicPtr<Tasklet>,
+}
+
+impl Tasklet {
+    /// Creates a tasklet running `func`.
+    pub const fn new(name: &'static str, func: fn()) -> Self {
+        Tasklet { name, func, state: AtomicU8::new(0), next: AtomicPtr::new(null_mut()) }
+    }
+
+    /// Schedules the tasklet on this hart.
+    ///
+    /// # Returns
+    /// `false` if it was already scheduled.
+    pub fn schedule(&'static self) -> bool {
+        if self.state.fetch_or(SCHEDULED, Ordering::AcqRel) & SCHEDULED != 0 {
+            return false;
+        }
+        self.push();
+        true
+    }
+
+    /// Adds the tasklet to the list of this hart and raises the softirq.
+    fn push(&'static self) {
+        let list = &LISTS[MHARTID::read()];
+        let mut head = list.load(Ordering::Relaxed);
+        loop {
+            self.next.store(head, Ordering::Relaxed);
+            let this = self as *const Tasklet as *mut Tasklet;
+            match list.compare_exchange_weak(head, this, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        softirq::raise(Softirq::Tasklet);
+    }
+}
+
+/// Runs the tasklets scheduled on this hart: the `Tasklet` softirq.
+pub fn action() {
+    // The list is most recent first: reverse it.
+    let mut node = LISTS[MHARTID::read()].swap(null_mut(), Ordering::Acquire);
+    let mut oldest = null_mut();
+    while !node.is_null() {
+        // SAFETY: Only `&'static Tasklet`s are pushed.
+        let tasklet: &'static Tasklet = unsafe { &*node };
+        node = tasklet.next.swap(oldest, Ordering::Relaxed);
+        oldest = tasklet as *const Tasklet as *mut Tasklet;
+    }
+    let mut taken: [Option<&'static Tasklet>; BATCH] = [None; BATCH];
+    let mut count = 0;
+    node = oldest;
+    while !node.is_null() {
+        // SAFETY: As above.
+        let tasklet: &'static Tasklet = unsafe { &*node };
+        node = tasklet.next.load(Ordering::Relaxed);
+        if count == BATCH {
+            // Run the newer ones later, in order.
+            tasklet.push();
+            continue;
+        }
+        taken[count] = Some(tasklet);
+        count += 1;
+    }
+    for tasklet in taken[..count].iter().flatten() {
+        if tasklet.state.fetch_or(RUNNING, Ordering::Acquire) & RUNNING != 0 {
+            // Running on another hart: try again later.
+            tasklet.push();
+            continue;
+        }
+        tasklet.state.fetch_and(!SCHEDULED, Ordering::AcqRel);
+        log_trace!("tasklet: running {}", tasklet.name);
+        (tasklet.func)();
+        tasklet.state.fetch_and(!RUNNING, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicUsize;
+
+    use crate::traps::interrupts;
+
+    #[test_case]
+    fn tasklets_run_once_in_order() {
+        static ORDER: AtomicUsize = AtomicUsize::new(0);
+        fn first() {
+            assert_eq!(ORDER.fetch_add(1, Ordering::Relaxed) % 2, 0);
+        }
+        fn second() {
+            assert_eq!(ORDER.fetch_add(1, Ordering::Relaxed) % 2, 1);
+        }
+        static FIRST: Tasklet = Tasklet::new("first", first);
+        static SECOND: Tasklet = Tasklet::new("second", second);
+        let before = ORDER.load(Ordering::Relaxed);
+        assert!(FIRST.schedule());
+        assert!(!FIRST.schedule());
+        assert!(SECOND.schedule());
+        action();
+        assert_eq!(ORDER.load(Ordering::Relaxed), before + 2);
+        assert_eq!(FIRST.state.load(Ordering::Relaxed), 0);
+    }
+
+    #[test_case]
+    fn batches_run_oldest_first() {
+        /// Tasklets run so far.
+        static RAN: AtomicUsize = AtomicUsize::new(0);
+        static TASKLETS: [Tasklet; BATCH + 4] = [const { Tasklet::new("batch", check_order) }; BATCH + 4];
+        // A tasklet is no longer scheduled once started: those are the oldest.
+        fn check_order() {
+            let ran = RAN.fetch_add(1, Ordering::Relaxed);
+            let started = TASKLETS.iter().take_while(|t| t.state.load(Ordering::Relaxed) & SCHEDULED == 0).count();
+            assert_eq!(started, ran + 1);
+        }
+        // The softirq must not run them behind the test's back.
+        interrupts::without_interrupts(|| {
+            for tasklet in TASKLETS.iter() {
+                assert!(tasklet.schedule());
+            }
+            action();
+            assert_eq!(RAN.load(Ordering::Relaxed), BATCH);
+            action();
+            assert_eq!(RAN.load(Ordering::Relaxed), BATCH + 4);
+        });
+    }
+}
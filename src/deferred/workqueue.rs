@@ -0,0 +1,223 @@
+//! ---------------------------------------------------------------------------
+//! File       : workqueue.rs
+//! Module     : deferred::workqueue
+//! Author     : DiTurr
+//! Description:
+//! Work queues: [`Work`] items queued from any context (interrupt handlers,
+//! softirqs, tasklets, threads) and run, in order, by the worker thread of
+//! the queue (see `thread`). Work items run in thread context with
+//! interrupts enabled: unlike softirqs and tasklets, they may take locks
+//! (threads switch only where they yield), and may yield, though not while
+//! holding a lock.
+//!
+//! [`SYSTEM`] is the queue for general use; its worker thread is `kworker`.
+//!
+//! ## Example
+//! ```rust
+//! static RX: Work = Work::new("net-rx", receive);
+//!
+//! workqueue::SYSTEM.queue(&RX);
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use crate::log_trace;
+use crate::syscalls::errno::Errno;
+use crate::thread::{self, ThreadId};
+
+/// Work items run by a worker before it checks for new ones.
+const BATCH: usize = 32;
+
+/// The system work queue.
+pub static SYSTEM: WorkQueue = WorkQueue::new("kworker", system_worker);
+
+fn system_worker() -> ! {
+    SYSTEM.work()
+}
+
+/// A function to run in thread context.
+pub struct Work {
+    pub name: &'static str,
+    func: fn(),
+    /// Queued and not started yet.
+    pending: AtomicBool,
+    /// Next item in the queue.
+    next: AtomicPtr<Work>,
+}
+
+impl Work {
+    /// Creates a work item running `func`.
+    pub const fn new(name: &'static str, func: fn()) -> Self {
+        Work { name, func, pending: AtomicBool::new(false), next: AtomicPtr::new(null_mut()) }
+    }
+}
+
+/// A queue of work items, run by a worker thread.
+pub struct WorkQueue {
+    /// Name of the worker thread.
+    pub name: &'static str,
+    /// Entry of the worker thread, which calls [`WorkQueue::work`].
+    worker: fn() -> !,
+    /// Queued items, most recent first.
+    head: AtomicPtr<Work>,
+    /// Worker thread (`usize::MAX` until [`WorkQueue::start`]).
+    thread: AtomicUsize,
+    /// Items run.
+    done: AtomicUsize,
+}
+
+impl WorkQueue {
+    /// Creates a queue whose worker thread `name` runs `worker`.
+    pub const fn new(name: &'static str, worker: fn() -> !) -> Self {
+        WorkQueue {
+            name,
+            worker,
+            head: AtomicPtr::new(null_mut()),
+            thread: AtomicUsize::new(usize::MAX),
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts the worker thread.
+    ///
+    /// # Returns
+    /// The error of `thread::spawn`.
+    pub fn start(&self) -> Result<(), Errno> {
+        let ThreadId(index) = thread::spawn(self.name, self.worker)?;
+        self.thread.store(index, Ordering::Release);
+        Ok(())
+    }
+
+    /// Queues `work` and wakes the worker up.
+    ///
+    /// # Returns
+    /// `false` if it was already queued (it runs once).
+    pub fn queue(&self, work: &'static Work) -> bool {
+        if work.pending.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            work.next.store(head, Ordering::Relaxed);
+            let this = work as *const Work as *mut Work;
+            match self.head.compare_exchange_weak(head, this, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        match self.thread.load(Ordering::Acquire) {
+            usize::MAX => {}
+            index => thread::unpark(ThreadId(index)),
+        }
+        true
+    }
+
+    /// Returns the number of work items run.
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// Runs the queued items, oldest first.
+    ///
+    /// # Returns
+    /// The number of items run.
+    fn run_pending(&self) -> usize {
+        // The queue is most recent first: reverse it.
+        let mut node = self.head.swap(null_mut(), Ordering::Acquire);
+        let mut oldest = null_mut();
+        while !node.is_null() {
+            // SAFETY: Only `&'static Work`s are queued.
+            let work: &'static Work = unsafe { &*node };
+            node = work.next.swap(oldest, Ordering::Relaxed);
+            oldest = work as *const Work as *mut Work;
+        }
+        let mut taken: [Option<&'static Work>; BATCH] = [None; BATCH];
+        let mut count = 0;
+        node = oldest;
+        while !node.is_null() {
+            // SAFETY: As above.
+            let work: &'static Work = unsafe { &*node };
+            node = work.next.load(Ordering::Relaxed);
+            if count == BATCH {
+                // Put the newer ones back, in order, for the next batch.
+                work.pending.store(false, Ordering::Relaxed);
+                self.queue(work);
+                continue;
+            }
+            taken[count] = Some(work);
+            count += 1;
+        }
+        for work in taken[..count].iter().flatten() {
+            // Queued again from now on, it runs again.
+            work.pending.store(false, Ordering::Release);
+            log_trace!("{}: running {}", self.name, work.name);
+            (work.func)();
+            self.done.fetch_add(1, Ordering::Relaxed);
+        }
+        count
+    }
+
+    /// The worker thread: runs the queued items, parks when there are none.
+    pub fn work(&self) -> ! {
+        loop {
+            if self.run_pending() == 0 {
+                thread::park();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn work_runs_once_in_order() {
+        static ORDER: AtomicUsize = AtomicUsize::new(0);
+        fn first() {
+            assert_eq!(ORDER.fetch_add(1, Ordering::Relaxed), 0);
+        }
+        fn second() {
+            assert_eq!(ORDER.fetch_add(1, Ordering::Relaxed), 1);
+        }
+        fn never() -> ! {
+            unreachable!()
+        }
+        static FIRST: Work = Work::new("first", first);
+        static SECOND: Work = Work::new("second", second);
+        // Without a worker thread, run by hand.
+        static QUEUE: WorkQueue = WorkQueue::new("test", never);
+        assert!(QUEUE.queue(&FIRST));
+        assert!(!QUEUE.queue(&FIRST));
+        assert!(QUEUE.queue(&SECOND));
+        assert_eq!(QUEUE.run_pending(), 2);
+        assert_eq!((ORDER.load(Ordering::Relaxed), QUEUE.done()), (2, 2));
+        assert_eq!(QUEUE.run_pending(), 0);
+    }
+
+    #[test_case]
+    fn batches_run_oldest_first() {
+        /// Items run so far.
+        static RAN: AtomicUsize = AtomicUsize::new(0);
+        static WORKS: [Work; BATCH + 4] = [const { Work::new("batch", check_order) }; BATCH + 4];
+        // An item is no longer pending once started: those are the oldest.
+        fn check_order() {
+            let ran = RAN.fetch_add(1, Ordering::Relaxed);
+            let started = WORKS.iter().take_while(|work| !work.pending.load(Ordering::Relaxed)).count();
+            assert_eq!(started, ran + 1);
+        }
+        fn never() -> ! {
+            unreachable!()
+        }
+        static QUEUE: WorkQueue = WorkQueue::new("test", never);
+        for work in WORKS.iter() {
+            assert!(QUEUE.queue(work));
+        }
+        assert_eq!(QUEUE.run_pending(), BATCH);
+        assert_eq!(QUEUE.run_pending(), 4);
+        assert_eq!(RAN.load(Ordering::Relaxed), BATCH + 4);
+        assert_eq!(QUEUE.run_pending(), 0);
+    }
+}
@@ -0,0 +1,435 @@
+//! ---------------------------------------------------------------------------
+//! File       : fdt.rs
+//! Module     : fdt
+//! Author     : DiTurr
+//! Description:
+//! Read-only parser for the flattened device tree (FDT, devicetree spec 0.4,
+//! chapter 5) that QEMU passes to the kernel in `a1`. The blob is used in place:
+//! nodes and properties are returned as slices into it.
+//!
+//! ## Example
+//! ```rust
+//! let fdt = unsafe { Fdt::from_addr(dtb) }.ok_or("no device tree")?;
+//! if let Some(chosen) = fdt.find_node("/chosen") {
+//!     let bootargs = chosen.property_str("bootargs");
+//! }
+//! ```
+//! ---------------------------------------------------------------------------
+
+use crate::sync::spinlock::SpinLock;
+
+/// Magic number at the start of every blob.
+const MAGIC: u32 = 0xd00d_feed;
+
+/// Size of the blob header.
+const HEADER_LEN: usize = 40;
+
+// Structure block tokens.
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32   = 2;
+const FDT_PROP: u32       = 3;
+const FDT_NOP: u32        = 4;
+
+/// `#address-cells` of a node without the property (devicetree spec, 2.3.5).
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+
+/// The device tree the kernel was booted with, once [`init`] found one.
+static FDT: SpinLock<Option<Fdt>> = SpinLock::new(None);
+
+/// A validated flattened device tree blob.
+#[derive(Clone, Copy)]
+pub struct Fdt {
+    /// The structure block.
+    structs: &'static [u8],
+    /// The strings block (property names).
+    strings: &'static [u8],
+}
+
+/// A node of the tree.
+#[derive(Clone, Copy)]
+pub struct Node {
+    fdt: Fdt,
+    /// Unit name, e.g. `uart@10000000`.
+    name: &'static str,
+    /// Offset of the first token after the node name.
+    body: usize,
+}
+
+/// Reads a big-endian `u32` at `offset`, if in bounds.
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Rounds `offset` up to the 4-byte token alignment.
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// Reads a NUL-terminated string at `offset`.
+fn cstr(data: &'static [u8], offset: usize) -> Option<&'static str> {
+    let rest = data.get(offset..)?;
+    let len = rest.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&rest[..len]).ok()
+}
+
+impl Fdt {
+    /// Validates the blob at `addr`.
+    ///
+    /// # Safety
+    /// `addr` must be 0 or point to memory that stays mapped and unmodified for
+    /// the lifetime of the kernel.
+    ///
+    /// # Returns
+    /// `None` if `addr` is 0 or does not hold a device tree.
+    pub unsafe fn from_addr(addr: usize) -> Option<Fdt> {
+        if addr == 0 || !addr.is_multiple_of(4) {
+            return None;
+        }
+        let header = unsafe { core::slice::from_raw_parts(addr as *const u8, HEADER_LEN) };
+        if be32(header, 0)? != MAGIC {
+            return None;
+        }
+        let total = be32(header, 4)? as usize;
+        let data = unsafe { core::slice::from_raw_parts(addr as *const u8, total) };
+        let struct_off = be32(header, 8)? as usize;
+        let strings_off = be32(header, 12)? as usize;
+        let strings_len = be32(header, 32)? as usize;
+        let struct_len = be32(header, 36)? as usize;
+        Some(Fdt {
+            structs: data.get(struct_off..struct_off + struct_len)?,
+            strings: data.get(strings_off..strings_off + strings_len)?,
+        })
+    }
+
+    /// Reads the token at `offset`, skipping NOPs.
+    ///
+    /// # Returns
+    /// The token and the offset of its payload.
+    fn token(&self, mut offset: usize) -> Option<(u32, usize)> {
+        loop {
+            let token = be32(self.structs, offset)?;
+            offset += 4;
+            if token != FDT_NOP {
+                return Some((token, offset));
+            }
+        }
+    }
+
+    /// Skips the properties and children of the node whose body starts at
+    /// `offset`.
+    ///
+    /// # Returns
+    /// The offset just past the node's `FDT_END_NODE` token.
+    fn skip_node(&self, mut offset: usize) -> Option<usize> {
+        let mut depth = 1;
+        while depth > 0 {
+            let (token, payload) = self.token(offset)?;
+            offset = match token {
+                FDT_BEGIN_NODE => {
+                    depth += 1;
+                    let name = cstr(self.structs, payload)?;
+                    align4(payload + name.len() + 1)
+                }
+                FDT_END_NODE => {
+                    depth -= 1;
+                    payload
+                }
+                FDT_PROP => align4(payload + 8 + be32(self.structs, payload)? as usize),
+                _ => return None,
+            };
+        }
+        Some(offset)
+    }
+
+    /// Returns the root node.
+    pub fn root(&self) -> Option<Node> {
+        let (token, payload) = self.token(0)?;
+        if token != FDT_BEGIN_NODE {
+            return None;
+        }
+        let name = cstr(self.structs, payload)?;
+        Some(Node { fdt: *self, name, body: align4(payload + name.len() + 1) })
+    }
+
+    /// Finds a node by absolute path, e.g. `/chosen` or `/soc/rtc@101000`.
+    ///
+    /// Path components without a unit address match any unit address, so
+    /// `/cpus/cpu` finds the first CPU node.
+    pub fn find_node(&self, path: &str) -> Option<Node> {
+        let mut node = self.root()?;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node.children().find(|child| {
+                child.name == component
+                    || (!component.contains('@')
+                        && child.name.split('@').next() == Some(component))
+            })?;
+        }
+        Some(node)
+    }
+
+    /// Finds the first node, in depth-first order, for which `matches` holds.
+    ///
+    /// # Returns
+    /// The node and the `#address-cells` of its parent, needed to decode its
+    /// `reg` property (see [`Node::reg_address`]).
+    pub fn find(&self, matches: impl Fn(&Node) -> bool) -> Option<(Node, u32)> {
+        fn search(node: Node, matches: &dyn Fn(&Node) -> bool) -> Option<(Node, u32)> {
+            let cells = node.property_u32("#address-cells").unwrap_or(DEFAULT_ADDRESS_CELLS);
+            node.children().find_map(|child| {
+                if matches(&child) { Some((child, cells)) } else { search(child, matches) }
+            })
+        }
+        search(self.root()?, &matches)
+    }
+
+    /// Finds the first node compatible with `compatible`.
+    pub fn find_compatible(&self, compatible: &str) -> Option<(Node, u32)> {
+        self.find(|node| node.is_compatible(compatible))
+    }
+
+    /// Finds the node with the given `phandle` (the value other nodes use to
+    /// refer to it).
+    pub fn find_phandle(&self, phandle: u32) -> Option<(Node, u32)> {
+        self.find(|node| node.property_u32("phandle") == Some(phandle))
+    }
+}
+
+impl Node {
+    /// Iterates over `(name, value)` pairs of the node's properties.
+    pub fn properties(&self) -> impl Iterator<Item = (&'static str, &'static [u8])> + use<> {
+        let fdt = self.fdt;
+        let mut offset = self.body;
+        core::iter::from_fn(move || {
+            let (token, payload) = fdt.token(offset)?;
+            if token != FDT_PROP {
+                return None;
+            }
+            let len = be32(fdt.structs, payload)? as usize;
+            let name = cstr(fdt.strings, be32(fdt.structs, payload + 4)? as usize)?;
+            let value = fdt.structs.get(payload + 8..payload + 8 + len)?;
+            offset = align4(payload + 8 + len);
+            Some((name, value))
+        })
+    }
+
+    /// Returns the raw value of property `name`.
+    pub fn property(&self, name: &str) -> Option<&'static [u8]> {
+        self.properties().find(|(prop, _)| *prop == name).map(|(_, value)| value)
+    }
+
+    /// Returns property `name` as a string (without its terminating NUL).
+    pub fn property_str(&self, name: &str) -> Option<&'static str> {
+        let value = self.property(name)?;
+        let value = value.strip_suffix(&[0]).unwrap_or(value);
+        core::str::from_utf8(value).ok()
+    }
+
+    /// Returns property `name` as a single cell.
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        match *self.property(name)? {
+            [a, b, c, d] => Some(u32::from_be_bytes([a, b, c, d])),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if `compatible` is one of the strings of the node's
+    /// `compatible` property.
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible")
+            .is_some_and(|list| list.split(|&b| b == 0).any(|name| name == compatible.as_bytes()))
+    }
+
+    /// Returns the first address of the node's `reg` property.
+    ///
+    /// # Arguments
+    /// * `address_cells` - The `#address-cells` of the parent node (1 or 2).
+    pub fn reg_address(&self, address_cells: u32) -> Option<usize> {
+        let reg = self.property("reg")?;
+        match (address_cells, reg) {
+            (1, [a, b, c, d, ..]) => Some(u32::from_be_bytes([*a, *b, *c, *d]) as usize),
+            (2, [a, b, c, d, e, f, g, h, ..]) => Some(u64::from_be_bytes([*a, *b, *c, *d, *e, *f, *g, *h]) as usize),
+            _ => None,
+        }
+    }
+
+    /// Iterates over the direct children of the node.
+    pub fn children(&self) -> impl Iterator<Item = Node> + use<> {
+        let fdt = self.fdt;
+        // Skip the properties to reach the first child.
+        let mut offset = Some(self.body);
+        while let Some((FDT_PROP, payload)) = offset.and_then(|o| fdt.token(o)) {
+            offset = be32(fdt.structs, payload).map(|len| align4(payload + 8 + len as usize));
+        }
+        core::iter::from_fn(move || {
+            let (token, payload) = fdt.token(offset?)?;
+            if token != FDT_BEGIN_NODE {
+                return None;
+            }
+            let name = cstr(fdt.structs, payload)?;
+            let body = align4(payload + name.len() + 1);
+            offset = fdt.skip_node(body);
+            Some(Node { fdt, name, body })
+        })
+    }
+}
+
+/// Records the device tree passed by the boot loader.
+///
+/// # Safety
+/// See [`Fdt::from_addr`].
+///
+/// # Returns
+/// `true` if a valid device tree was found at `addr`.
+pub unsafe fn init(addr: usize) -> bool {
+    let fdt = unsafe { Fdt::from_addr(addr) };
+    *FDT.lock() = fdt;
+    fdt.is_some()
+}
+
+/// Returns the device tree the kernel was booted with.
+pub fn get() -> Option<Fdt> {
+    *FDT.lock()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a blob in memory, like `dtc` would.
+    struct Builder {
+        structs: [u8; 1024],
+        structs_len: usize,
+        strings: [u8; 256],
+        strings_len: usize,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            Builder { structs: [0; 1024], structs_len: 0, strings: [0; 256], strings_len: 0 }
+        }
+
+        fn push(&mut self, bytes: &[u8]) {
+            self.structs[self.structs_len..self.structs_len + bytes.len()].copy_from_slice(bytes);
+            self.structs_len = align4(self.structs_len + bytes.len());
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.push(&FDT_BEGIN_NODE.to_be_bytes());
+            self.push(name.as_bytes());
+            if name.len().is_multiple_of(4) {
+                // The NUL terminator needs a word of its own.
+                self.push(&[0]);
+            }
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.push(&FDT_END_NODE.to_be_bytes());
+            self
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let offset = self.strings_len;
+            self.strings[offset..offset + name.len()].copy_from_slice(name.as_bytes());
+            self.strings_len += name.len() + 1;
+            self.push(&FDT_PROP.to_be_bytes());
+            self.push(&(value.len() as u32).to_be_bytes());
+            self.push(&(offset as u32).to_be_bytes());
+            self.push(value);
+            self
+        }
+
+        fn prop_u32(&mut self, name: &str, value: u32) -> &mut Self {
+            self.prop(name, &value.to_be_bytes())
+        }
+
+        /// Writes the blob (header, structure block, strings block) into `blob`.
+        fn finish(&mut self, blob: &mut [u32; 512]) -> usize {
+            self.push(&9u32.to_be_bytes()); // FDT_END
+            let struct_off = HEADER_LEN + 16; // after an empty reservation map
+            let strings_off = struct_off + self.structs_len;
+            let total = strings_off + self.strings_len;
+            let header = [MAGIC, total as u32, struct_off as u32, strings_off as u32, HEADER_LEN as u32, 17, 16, 0,
+                          self.strings_len as u32, self.structs_len as u32];
+            let bytes = unsafe { core::slice::from_raw_parts_mut(blob.as_mut_ptr() as *mut u8, 2048) };
+            bytes.fill(0);
+            for (i, word) in header.iter().enumerate() {
+                bytes[4 * i..4 * i + 4].copy_from_slice(&word.to_be_bytes());
+            }
+            bytes[struct_off..strings_off].copy_from_slice(&self.structs[..self.structs_len]);
+            bytes[strings_off..total].copy_from_slice(&self.strings[..self.strings_len]);
+            blob.as_ptr() as usize
+        }
+    }
+
+    /// A tree shaped like the one of the QEMU `virt` machine.
+    fn virt() -> Fdt {
+        static mut BLOB: [u32; 512] = [0; 512];
+        let mut b = Builder::new();
+        b.begin("").prop_u32("#address-cells", 2).prop_u32("#size-cells", 2);
+        b.begin("chosen").prop("bootargs", b"console=hvc0\0").end();
+        b.begin("cpus").prop_u32("#address-cells", 1).prop_u32("timebase-frequency", 10_000_000);
+        b.begin("cpu@0").prop_u32("reg", 0).end();
+        b.end();
+        b.begin("soc");
+        b.begin("test@100000")
+            .prop("compatible", b"sifive,test1\0sifive,test0\0syscon\0")
+            .prop("reg", &[0, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0])
+            .prop_u32("phandle", 4)
+            .end();
+        b.begin("poweroff").prop("compatible", b"syscon-poweroff\0").prop_u32("regmap", 4).end();
+        b.begin("rtc@101000").prop("compatible", b"google,goldfish-rtc\0").end();
+        b.end().end();
+        // SAFETY: Each test builds the same blob; nothing else uses it.
+        let addr = b.finish(unsafe { &mut *core::ptr::addr_of_mut!(BLOB) });
+        unsafe { Fdt::from_addr(addr) }.expect("invalid blob")
+    }
+
+    #[test_case]
+    fn rejects_bad_blobs() {
+        let blob = [0u32; 16];
+        assert!(unsafe { Fdt::from_addr(0) }.is_none());
+        assert!(unsafe { Fdt::from_addr(blob.as_ptr() as usize) }.is_none());
+    }
+
+    #[test_case]
+    fn finds_nodes_by_path() {
+        let fdt = virt();
+        let chosen = fdt.find_node("/chosen").unwrap();
+        assert_eq!(chosen.property_str("bootargs"), Some("console=hvc0"));
+        // Without unit address, any unit address matches.
+        assert_eq!(fdt.find_node("/cpus/cpu").unwrap().property_u32("reg"), Some(0));
+        assert!(fdt.find_node("/soc/rtc@101000").is_some());
+        assert!(fdt.find_node("/soc/rtc@101001").is_none());
+        assert_eq!(fdt.find_node("/soc").unwrap().children().count(), 3);
+    }
+
+    #[test_case]
+    fn matches_whole_compatible_strings() {
+        let fdt = virt();
+        let (test, cells) = fdt.find_compatible("sifive,test0").unwrap();
+        assert_eq!(cells, 2);
+        assert_eq!(test.reg_address(cells), Some(0x10_0000));
+        assert!(test.is_compatible("syscon"));
+        assert!(!test.is_compatible("sifive,test"));
+        assert!(fdt.find_compatible("ns16550a").is_none());
+    }
+
+    #[test_case]
+    fn follows_phandles() {
+        let fdt = virt();
+        let (poweroff, _) = fdt.find_compatible("syscon-poweroff").unwrap();
+        let (regmap, _) = fdt.find_phandle(poweroff.property_u32("regmap").unwrap()).unwrap();
+        assert!(regmap.is_compatible("sifive,test0"));
+    }
+
+    #[test_case]
+    fn decodes_one_cell_addresses() {
+        let fdt = virt();
+        let (cpu, cells) = fdt.find(|node| node.property_u32("reg") == Some(0)).unwrap();
+        assert_eq!(cells, 1);
+        assert_eq!(cpu.reg_address(cells), Some(0));
+        assert_eq!(cpu.reg_address(2), None);
+    }
+}
@@ -0,0 +1,35 @@
+//! ---------------------------------------------------------------------------
+//! File       : fs.rs
+//! Module     : fs
+//! Author     : DiTurr
+//! Description:
+//! File system layer. There is no storage yet: `devfs` exposes kernel
+//! character devices under `/dev`, and `procfs` read-only files generated by
+//! the kernel (e.g. `/proc/interrupts`) under `/proc`.
+//!
+//! ## Example
+//! ```rust
+//! devfs::register("urandom", &URANDOM)?;
+//! let device = devfs::lookup("/dev/urandom").ok_or(Errno::ENOENT)?;
+//! device.read(&mut buf)?;
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod devfs;
+pub mod procfs;
+
+use crate::syscalls::errno::Errno;
+
+/// A byte-stream device reachable through a file descriptor.
+pub trait CharDevice: Sync {
+    /// Reads up to `buf.len()` bytes from the device.
+    ///
+    /// # Returns
+    /// The number of bytes read.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno>;
+
+    /// Writes up to `buf.len()` bytes to the device.
+    ///
+    /// # Returns
+    /// The number of bytes consumed.
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno>;
+}
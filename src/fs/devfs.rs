@@ -0,0 +1,51 @@
+//! ---------------------------------------------------------------------------
+//! File       : devfs.rs
+//! Module     : fs::devfs
+//! Author     : DiTurr
+//! Description:
+//! The device file system: a flat, fixed-size table of named character devices
+//! looked up by their `/dev/<name>` path.
+//! ---------------------------------------------------------------------------
+
+use super::CharDevice;
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+
+/// Maximum number of registered devices.
+const MAX_DEVICES: usize = 8;
+
+/// Mount point of the device file system.
+const PREFIX: &str = "/dev/";
+
+/// A registered device.
+#[derive(Clone, Copy)]
+struct Entry {
+    name: &'static str,
+    device: &'static dyn CharDevice,
+}
+
+static DEVICES: SpinLock<[Option<Entry>; MAX_DEVICES]> = SpinLock::new([None; MAX_DEVICES]);
+
+/// Makes `device` available as `/dev/<name>`.
+///
+/// # Returns
+/// `EEXIST` if the name is taken, `ENOSPC` if the table is full.
+pub fn register(name: &'static str, device: &'static dyn CharDevice) -> Result<(), Errno> {
+    let mut devices = DEVICES.lock();
+    if devices.iter().flatten().any(|entry| entry.name == name) {
+        return Err(Errno::EEXIST);
+    }
+    let slot = devices.iter_mut().find(|slot| slot.is_none()).ok_or(Errno::ENOSPC)?;
+    *slot = Some(Entry { name, device });
+    Ok(())
+}
+
+/// Finds the device behind `path` (e.g. `/dev/urandom`).
+pub fn lookup(path: &str) -> Option<&'static dyn CharDevice> {
+    find(path.strip_prefix(PREFIX)?)
+}
+
+/// Finds the device registered as `name` (e.g. `hvc2`).
+pub fn find(name: &str) -> Option<&'static dyn CharDevice> {
+    DEVICES.lock().iter().flatten().find(|entry| entry.name == name).map(|entry| entry.device)
+}
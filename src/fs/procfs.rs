@@ -0,0 +1,122 @@
+//! ---------------------------------------------------------------------------
+//! File       : procfs.rs
+//! Module     : fs::procfs
+//! Author     : DiTurr
+//! Description:
+//! The process file system: a flat, fixed-size table of read-only files under
+//! `/proc`, whose text is generated by the kernel each time they are read.
+//!
+//! Descriptors carry no state, so all readers of a file share a single read
+//! position: reads return the text from there, then 0 (end of file) once, and
+//! the next read starts over with fresh values.
+//! ---------------------------------------------------------------------------
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::CharDevice;
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+
+/// Maximum number of registered files.
+const MAX_FILES: usize = 8;
+
+/// Mount point of the process file system.
+const PREFIX: &str = "/proc/";
+
+/// A file whose text is written by a function.
+pub struct ProcFile {
+    pub name: &'static str,
+    /// Writes the whole text of the file.
+    show: fn(&mut dyn Write),
+    /// Offset of the next read.
+    pos: AtomicUsize,
+}
+
+impl ProcFile {
+    /// Creates `/proc/<name>`, whose text `show` writes.
+    pub const fn new(name: &'static str, show: fn(&mut dyn Write)) -> Self {
+        ProcFile { name, show, pos: AtomicUsize::new(0) }
+    }
+}
+
+/// Copies the part of the text from `skip` on into `buf`.
+struct Window<'a> {
+    buf: &'a mut [u8],
+    skip: usize,
+    len: usize,
+}
+
+impl Write for Window<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        let skipped = bytes.len().min(self.skip);
+        self.skip -= skipped;
+        let bytes = &bytes[skipped..];
+        let count = bytes.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + count].copy_from_slice(&bytes[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+impl CharDevice for ProcFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let pos = self.pos.load(Ordering::Relaxed);
+        let mut window = Window { buf, skip: pos, len: 0 };
+        (self.show)(&mut window);
+        // End of file: the next read starts over.
+        let next = if window.len == 0 { 0 } else { pos + window.len };
+        self.pos.store(next, Ordering::Relaxed);
+        Ok(window.len)
+    }
+
+    fn write(&self, _: &[u8]) -> Result<usize, Errno> {
+        Err(Errno::EBADF)
+    }
+}
+
+static FILES: SpinLock<[Option<&'static ProcFile>; MAX_FILES]> = SpinLock::new([None; MAX_FILES]);
+
+/// Makes `file` available as `/proc/<name>`.
+///
+/// # Returns
+/// `EEXIST` if the name is taken, `ENOSPC` if the table is full.
+pub fn register(file: &'static ProcFile) -> Result<(), Errno> {
+    let mut files = FILES.lock();
+    if files.iter().flatten().any(|entry| entry.name == file.name) {
+        return Err(Errno::EEXIST);
+    }
+    let slot = files.iter_mut().find(|slot| slot.is_none()).ok_or(Errno::ENOSPC)?;
+    *slot = Some(file);
+    Ok(())
+}
+
+/// Finds the file behind `path` (e.g. `/proc/interrupts`).
+pub fn lookup(path: &str) -> Option<&'static dyn CharDevice> {
+    let name = path.strip_prefix(PREFIX)?;
+    let file = FILES.lock().iter().flatten().copied().find(|file| file.name == name)?;
+    Some(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn reads_resume_then_start_over() {
+        fn show(out: &mut dyn Write) {
+            let _ = write!(out, "0123");
+            let _ = writeln!(out, "456789");
+        }
+        static FILE: ProcFile = ProcFile::new("test", show);
+        let mut buf = [0u8; 6];
+        assert_eq!(FILE.read(&mut buf), Ok(6));
+        assert_eq!(&buf, b"012345");
+        assert_eq!(FILE.read(&mut buf), Ok(5));
+        assert_eq!(&buf[..5], b"6789\n");
+        assert_eq!(FILE.read(&mut buf), Ok(0));
+        assert_eq!(FILE.read(&mut buf), Ok(6));
+        assert_eq!(FILE.write(b"x"), Err(Errno::EBADF));
+    }
+}
@@ -0,0 +1,417 @@
+//! ---------------------------------------------------------------------------
+//! File       : gdbstub.rs
+//! Module     : gdbstub
+//! Author     : DiTurr
+//! Description:
+//! A GDB remote serial protocol stub, reached over the terminal named by
+//! `gdb=<dev>` on the kernel command line (`hvc2`, the virtio serial port the
+//! Makefile exposes on TCP port 4556, or `ttyS0`). Unlike QEMU's own `-s`
+//! stub, it runs inside the kernel, so it also works on hardware.
+//!
+//! The kernel stops when it executes an `ebreak`: a breakpoint set by GDB,
+//! [`breakpoint`] (called at boot with `gdbwait`), or the end of a step. The
+//! `Breakpoint` trap then enters the stub, which serves GDB with the trap
+//! frame of the stopped hart until GDB resumes it:
+//! - registers (`g`, `G`, `p`, `P`): `x1`-`x31` and `pc` (`mepc`); `sp` is
+//!   read-only, the trap frame lies on the stack it points to;
+//! - memory (`m`, `M`), limited to RAM;
+//! - software breakpoints (`Z0`, `z0`, see `breakpoints`);
+//! - continue (`c`) and single-step (`s`, by instruction decoding, see `step`);
+//! - threads: one per hart (thread ID = hart ID + 1). The other harts stay
+//!   parked at boot, so only the stopped hart has registers.
+//!
+//! While the kernel runs, the main loop calls [`poll`]: Ctrl-C, or a packet
+//! from a GDB connecting, stops it. Breakpoints must not be set in code the
+//! stub itself relies on (the terminal driver, locks held around it).
+//!
+//! ## Example
+//! ```rust
+//! // qemu ... -append "gdb=hvc2 gdbwait"
+//! // gdb target/elf/rustos.elf -ex "target remote localhost:4556"
+//! gdbstub::init();
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod breakpoints;
+pub mod packet;
+pub mod step;
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::cmdline;
+use crate::fdt;
+use crate::fs::{devfs, CharDevice};
+use crate::registers::mhartid::MHARTID;
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::{user_slice, user_slice_mut};
+use crate::traps::trap_frame::TrapFrame;
+use crate::{log_info, log_warn};
+use packet::{decode_hex, parse_hex, parse_register, Connection, Reply, PACKET_LEN};
+
+/// Index of `pc` in GDB's register numbering, after `x0`-`x31`.
+const PC: usize = 32;
+
+/// Index of `sp`.
+const SP: usize = 2;
+
+/// Description of the registers sent by `g`, served to GDB as `target.xml`.
+const TARGET_XML: &str = concat!(
+    r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd"><target version="1.0">"#,
+    r#"<architecture>riscv:rv64</architecture><feature name="org.gnu.gdb.riscv.cpu">"#,
+    r#"<reg name="zero" bitsize="64" type="int"/><reg name="ra" bitsize="64" type="code_ptr"/>"#,
+    r#"<reg name="sp" bitsize="64" type="data_ptr"/><reg name="gp" bitsize="64" type="data_ptr"/>"#,
+    r#"<reg name="tp" bitsize="64" type="data_ptr"/><reg name="t0" bitsize="64" type="int"/>"#,
+    r#"<reg name="t1" bitsize="64" type="int"/><reg name="t2" bitsize="64" type="int"/>"#,
+    r#"<reg name="fp" bitsize="64" type="data_ptr"/><reg name="s1" bitsize="64" type="int"/>"#,
+    r#"<reg name="a0" bitsize="64" type="int"/><reg name="a1" bitsize="64" type="int"/>"#,
+    r#"<reg name="a2" bitsize="64" type="int"/><reg name="a3" bitsize="64" type="int"/>"#,
+    r#"<reg name="a4" bitsize="64" type="int"/><reg name="a5" bitsize="64" type="int"/>"#,
+    r#"<reg name="a6" bitsize="64" type="int"/><reg name="a7" bitsize="64" type="int"/>"#,
+    r#"<reg name="s2" bitsize="64" type="int"/><reg name="s3" bitsize="64" type="int"/>"#,
+    r#"<reg name="s4" bitsize="64" type="int"/><reg name="s5" bitsize="64" type="int"/>"#,
+    r#"<reg name="s6" bitsize="64" type="int"/><reg name="s7" bitsize="64" type="int"/>"#,
+    r#"<reg name="s8" bitsize="64" type="int"/><reg name="s9" bitsize="64" type="int"/>"#,
+    r#"<reg name="s10" bitsize="64" type="int"/><reg name="s11" bitsize="64" type="int"/>"#,
+    r#"<reg name="t3" bitsize="64" type="int"/><reg name="t4" bitsize="64" type="int"/>"#,
+    r#"<reg name="t5" bitsize="64" type="int"/><reg name="t6" bitsize="64" type="int"/>"#,
+    r#"<reg name="pc" bitsize="64" type="code_ptr"/></feature></target>"#,
+);
+
+/// The terminal GDB is connected to, once [`init`] found it.
+static TRANSPORT: SpinLock<Option<&'static dyn CharDevice>> = SpinLock::new(None);
+
+/// Number of harts, from the device tree.
+static HARTS: AtomicUsize = AtomicUsize::new(1);
+
+/// GDB is attached: it expects a stop reply when the kernel stops.
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+
+/// Packets are acknowledged (until `QStartNoAckMode`).
+static ACK: AtomicBool = AtomicBool::new(true);
+
+/// [`poll`] consumed the `$` starting a packet.
+static PACKET_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Counts the harts described in the device tree.
+fn count_harts() -> usize {
+    fdt::get()
+        .and_then(|fdt| fdt.find_node("/cpus"))
+        .map(|cpus| cpus.children().filter(|cpu| cpu.property_str("device_type") == Some("cpu")).count())
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Applies the `gdb=<dev>` and `gdbwait` command line options. Must run after
+/// the terminals are registered.
+pub fn init() {
+    let Some(name) = cmdline::get("gdb") else {
+        return;
+    };
+    let Some(device) = devfs::find(name) else {
+        log_warn!("gdb: unknown device '{}'.", name);
+        return;
+    };
+    HARTS.store(count_harts(), Ordering::Relaxed);
+    *TRANSPORT.lock() = Some(device);
+    log_info!("gdb: remote stub on {}.", name);
+    if cmdline::get("gdbwait").is_some() {
+        log_info!("gdb: waiting for the debugger.");
+        breakpoint();
+    }
+}
+
+/// Stops in the debugger, if one is configured.
+pub fn breakpoint() {
+    if TRANSPORT.lock().is_some() {
+        #[cfg(target_os = "none")]
+        unsafe {
+            core::arch::asm!("ebreak")
+        };
+    }
+}
+
+/// Checks for GDB wanting to stop the running kernel: Ctrl-C or, from a GDB
+/// connecting, the start of a packet. Called from the main loop.
+pub fn poll() {
+    let Some(device) = *TRANSPORT.lock() else {
+        return;
+    };
+    let mut byte = [0];
+    match device.read(&mut byte) {
+        Ok(1) if byte[0] == 0x03 => breakpoint(),
+        Ok(1) if byte[0] == b'$' => {
+            PACKET_STARTED.store(true, Ordering::Relaxed);
+            breakpoint();
+        }
+        _ => {}
+    }
+}
+
+/// Serves GDB on a `Breakpoint` trap.
+///
+/// # Returns
+/// `false` if no debugger is configured: the trap is not handled.
+pub fn handle_trap(frame: &mut TrapFrame) -> bool {
+    let Some(device) = *TRANSPORT.lock() else {
+        return false;
+    };
+    let stepped = breakpoints::remove_step().is_some();
+    let mut session = Session { connection: Connection::new(device, ACK.load(Ordering::Relaxed)), frame, stepped };
+    if ATTACHED.load(Ordering::Relaxed) {
+        session.send_stop_reply();
+    }
+    session.serve();
+    ACK.store(session.connection.ack(), Ordering::Relaxed);
+    // Resuming at a compiled-in `ebreak` would stop again right away: skip it.
+    if let Some(len) = breakpoints::ebreak_len(session.frame.mepc) {
+        session.frame.mepc += len;
+    }
+    true
+}
+
+/// A stop of the kernel, served until GDB resumes it.
+struct Session<'a> {
+    connection: Connection,
+    frame: &'a mut TrapFrame,
+    /// The stop ends a step, rather than hitting a breakpoint.
+    stepped: bool,
+}
+
+/// What to do after a packet.
+enum Action {
+    /// Keep serving packets.
+    Stay,
+    /// Resume the kernel.
+    Resume,
+}
+
+impl Session<'_> {
+    /// Returns the thread ID of the stopped hart.
+    fn thread(&self) -> usize {
+        MHARTID::read() + 1
+    }
+
+    fn send_stop_reply(&self) {
+        let mut reply = Reply::new();
+        let _ = write!(reply, "T05thread:{:x};", self.thread());
+        if !self.stepped {
+            reply.push(b"swbreak:;");
+        }
+        self.connection.send(reply.as_bytes());
+    }
+
+    /// Serves packets until GDB resumes the kernel.
+    fn serve(&mut self) {
+        let mut buf = [0u8; PACKET_LEN];
+        loop {
+            let packet = self.connection.receive(&mut buf, PACKET_STARTED.swap(false, Ordering::Relaxed));
+            ATTACHED.store(true, Ordering::Relaxed);
+            let mut reply = Reply::new();
+            let action = self.command(packet, &mut reply);
+            if let Action::Resume = action {
+                return;
+            }
+            self.connection.send(reply.as_bytes());
+            if packet == b"QStartNoAckMode" {
+                self.connection.disable_ack();
+            }
+        }
+    }
+
+    /// Returns register `n` in GDB's numbering.
+    fn register(&self, n: usize) -> Option<usize> {
+        match n {
+            0 => Some(0),
+            PC => Some(self.frame.mepc),
+            1..PC => Some(self.frame.regs[n]),
+            _ => None,
+        }
+    }
+
+    /// Sets register `n`; `x0` and `sp` cannot be changed.
+    fn set_register(&mut self, n: usize, value: usize) -> bool {
+        match n {
+            PC => self.frame.mepc = value,
+            0 | SP => {}
+            1..PC => self.frame.regs[n] = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Handles one packet, leaving the answer in `reply`.
+    fn command(&mut self, packet: &[u8], reply: &mut Reply) -> Action {
+        let (&kind, args) = packet.split_first().unwrap_or((&0, &[]));
+        let result = match kind {
+            b'?' => {
+                let _ = write!(reply, "T05thread:{:x};", self.thread());
+                Ok(())
+            }
+            b'g' => {
+                (0..=PC).filter_map(|n| self.register(n)).for_each(|value| reply.push_register(value));
+                Ok(())
+            }
+            b'G' => self.write_registers(args),
+            b'p' => parse_hex(args).and_then(|n| self.register(n)).map(|value| reply.push_register(value)).ok_or(()),
+            b'P' => {
+                let (n, value) = split(args, b'=');
+                match (parse_hex(n), parse_register(value)) {
+                    (Some(n), Some(value)) if self.set_register(n, value) => reply.push(b"OK"),
+                    _ => return self.error(reply),
+                }
+                Ok(())
+            }
+            b'm' => self.read_memory(args, reply),
+            b'M' => self.write_memory(args).map(|()| reply.push(b"OK")),
+            b'Z' | b'z' => {
+                let mut fields = args.split(|&b| b == b',');
+                match (fields.next(), fields.next().and_then(parse_hex)) {
+                    (Some(b"0"), Some(addr)) => {
+                        let result = if kind == b'Z' { breakpoints::insert(addr) } else { breakpoints::remove(addr) };
+                        result.map(|()| reply.push(b"OK")).map_err(|_| ())
+                    }
+                    // Hardware breakpoints and watchpoints are not supported.
+                    _ => Ok(()),
+                }
+            }
+            b'c' | b's' => {
+                if let Some(addr) = parse_hex(args) {
+                    self.frame.mepc = addr;
+                }
+                if kind == b's' && self.step().is_err() {
+                    return self.error(reply);
+                }
+                return Action::Resume;
+            }
+            b'D' => {
+                breakpoints::remove_all();
+                ATTACHED.store(false, Ordering::Relaxed);
+                self.connection.send(b"OK");
+                return Action::Resume;
+            }
+            b'k' => {
+                breakpoints::remove_all();
+                ATTACHED.store(false, Ordering::Relaxed);
+                return Action::Resume;
+            }
+            b'H' => {
+                reply.push(b"OK");
+                Ok(())
+            }
+            b'T' => self.thread_alive(args).map(|()| reply.push(b"OK")),
+            b'q' | b'Q' => self.query(packet, reply),
+            // Unsupported: an empty reply.
+            _ => Ok(()),
+        };
+        match result {
+            Ok(()) => Action::Stay,
+            Err(()) => self.error(reply),
+        }
+    }
+
+    fn error(&self, reply: &mut Reply) -> Action {
+        *reply = Reply::new();
+        reply.push(b"E01");
+        Action::Stay
+    }
+
+    /// `G`: sets every register.
+    fn write_registers(&mut self, args: &[u8]) -> Result<(), ()> {
+        let width = 2 * size_of::<usize>();
+        for (n, value) in args.chunks(width).enumerate().take(PC + 1) {
+            self.set_register(n, parse_register(value).ok_or(())?);
+        }
+        Ok(())
+    }
+
+    /// `m<addr>,<len>`: reads memory.
+    fn read_memory(&self, args: &[u8], reply: &mut Reply) -> Result<(), ()> {
+        let (addr, len) = split(args, b',');
+        let (addr, len) = (parse_hex(addr).ok_or(())?, parse_hex(len).ok_or(())?);
+        let memory = user_slice(addr, len.min(PACKET_LEN / 2)).map_err(|_| ())?;
+        reply.push_hex(memory);
+        Ok(())
+    }
+
+    /// `M<addr>,<len>:<data>`: writes memory.
+    fn write_memory(&self, args: &[u8]) -> Result<(), ()> {
+        let (range, data) = split(args, b':');
+        let (addr, len) = split(range, b',');
+        let (addr, len) = (parse_hex(addr).ok_or(())?, parse_hex(len).ok_or(())?);
+        let memory = user_slice_mut(addr, len).map_err(|_| ())?;
+        match decode_hex(data, memory) {
+            Some(count) if count == len => {}
+            _ => return Err(()),
+        }
+        // The data may be code.
+        breakpoints::sync_icache();
+        Ok(())
+    }
+
+    /// Prepares a single step: stops again after the current instruction.
+    fn step(&mut self) -> Result<(), ()> {
+        let pc = self.frame.mepc;
+        let insn = breakpoints::read_instruction(pc).map_err(|_| ())?;
+        let next = step::next_pc(insn, pc, |n| self.register(n).unwrap_or(0));
+        breakpoints::insert_step(next).map_err(|_| ())
+    }
+
+    /// `T<thread>`: checks that a thread exists.
+    fn thread_alive(&self, args: &[u8]) -> Result<(), ()> {
+        match parse_hex(args) {
+            Some(thread) if (1..=HARTS.load(Ordering::Relaxed)).contains(&thread) => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    /// General queries (`q`) and settings (`Q`).
+    fn query(&mut self, packet: &[u8], reply: &mut Reply) -> Result<(), ()> {
+        let (name, args) = split(packet, b':');
+        match name {
+            b"qSupported" => {
+                let _ = write!(reply, "PacketSize={:x};qXfer:features:read+;swbreak+;QStartNoAckMode+", PACKET_LEN);
+            }
+            b"QStartNoAckMode" => reply.push(b"OK"),
+            b"qAttached" => reply.push(b"1"),
+            b"qC" => {
+                let _ = write!(reply, "QC{:x}", self.thread());
+            }
+            b"qfThreadInfo" => {
+                reply.push(b"m");
+                for thread in 1..=HARTS.load(Ordering::Relaxed) {
+                    let _ = write!(reply, "{}{:x}", if thread > 1 { "," } else { "" }, thread);
+                }
+            }
+            b"qsThreadInfo" => reply.push(b"l"),
+            b"qXfer" => {
+                // features:read:target.xml:<offset>,<length>
+                let args = args.strip_prefix(b"features:read:target.xml:").ok_or(())?;
+                let (offset, len) = split(args, b',');
+                let (offset, len) = (parse_hex(offset).ok_or(())?, parse_hex(len).ok_or(())?);
+                let xml = TARGET_XML.as_bytes();
+                let chunk = &xml[offset.min(xml.len())..];
+                let chunk = &chunk[..chunk.len().min(len).min(PACKET_LEN - 1)];
+                reply.push(if offset + chunk.len() < xml.len() { b"m" } else { b"l" });
+                reply.push(chunk);
+            }
+            _ => {
+                if let Some(thread) = name.strip_prefix(b"qThreadExtraInfo,") {
+                    let thread = parse_hex(thread).ok_or(())?;
+                    let mut info = Reply::new();
+                    let state = if thread == self.thread() { "stopped" } else { "parked" };
+                    let _ = write!(info, "hart {} ({})", thread - 1, state);
+                    reply.push_hex(info.as_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits `text` at the first `separator`.
+fn split(text: &[u8], separator: u8) -> (&[u8], &[u8]) {
+    match text.iter().position(|&b| b == separator) {
+        Some(index) => (&text[..index], &text[index + 1..]),
+        None => (text, &[]),
+    }
+}
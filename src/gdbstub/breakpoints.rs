@@ -0,0 +1,173 @@
+//! ---------------------------------------------------------------------------
+//! File       : breakpoints.rs
+//! Module     : gdbstub::breakpoints
+//! Author     : DiTurr
+//! Description:
+//! Software breakpoints: the instruction at the breakpoint is replaced by an
+//! `ebreak` (`c.ebreak` over a compressed instruction) and put back when the
+//! breakpoint is removed. Besides the breakpoints set by GDB (`Z0`), one
+//! temporary breakpoint implements single-stepping (see `step`).
+//! ---------------------------------------------------------------------------
+
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::arch::decode;
+use crate::arch::pmp::{self, Access};
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+use crate::syscalls::user_slice;
+
+/// Maximum number of breakpoints set by GDB.
+const MAX_BREAKPOINTS: usize = 16;
+
+/// The `ebreak` and `c.ebreak` instructions.
+const EBREAK: u32   = 0x0010_0073;
+const C_EBREAK: u16 = 0x9002;
+
+/// A breakpoint and the instruction it replaced.
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    addr: usize,
+    /// The replaced instruction (its low 16 bits if compressed).
+    original: u32,
+    /// Length of the replaced instruction: 2 or 4.
+    len: usize,
+}
+
+/// The breakpoints set by GDB, and the temporary one of a step.
+struct Breakpoints {
+    set: [Option<Breakpoint>; MAX_BREAKPOINTS],
+    step: Option<Breakpoint>,
+}
+
+static BREAKPOINTS: SpinLock<Breakpoints> = SpinLock::new(Breakpoints { set: [None; MAX_BREAKPOINTS], step: None });
+
+/// Makes instruction fetches see the code just written.
+pub fn sync_icache() {
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!("fence.i")
+    };
+}
+
+/// Reads the instruction at `addr` (its low 16 bits if compressed).
+///
+/// # Returns
+/// `EFAULT` if `addr` is not in RAM or not 2-byte aligned.
+pub fn read_instruction(addr: usize) -> Result<u32, Errno> {
+    if !addr.is_multiple_of(2) {
+        return Err(Errno::EFAULT);
+    }
+    user_slice(addr, 4)?;
+    // Read by parcels: a 32-bit instruction may be only 2-byte aligned.
+    let low = unsafe { read_volatile(addr as *const u16) };
+    if decode::length(low as u32) == 2 {
+        return Ok(low as u32);
+    }
+    let high = unsafe { read_volatile((addr + 2) as *const u16) };
+    Ok((high as u32) << 16 | low as u32)
+}
+
+/// Writes the instruction `insn` of `len` bytes at `addr`.
+fn write_instruction(addr: usize, insn: u32, len: usize) {
+    unsafe {
+        write_volatile(addr as *mut u16, insn as u16);
+        if len == 4 {
+            write_volatile((addr + 2) as *mut u16, (insn >> 16) as u16);
+        }
+    }
+    sync_icache();
+}
+
+/// Replaces the instruction at `addr` with a breakpoint.
+fn plant(addr: usize) -> Result<Breakpoint, Errno> {
+    let original = read_instruction(addr)?;
+    let len = decode::length(original);
+    // Locked text (`pmp=lock`) cannot take breakpoints.
+    if !pmp::machine_permits(addr, len, Access::Write) {
+        return Err(Errno::EFAULT);
+    }
+    write_instruction(addr, if len == 2 { C_EBREAK as u32 } else { EBREAK }, len);
+    Ok(Breakpoint { addr, original, len })
+}
+
+/// Sets a breakpoint at `addr`; setting it again is not an error.
+///
+/// # Returns
+/// `EFAULT` for an invalid address, `ENOSPC` if the table is full.
+pub fn insert(addr: usize) -> Result<(), Errno> {
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints.set.iter().flatten().any(|bp| bp.addr == addr) {
+        return Ok(());
+    }
+    let slot = breakpoints.set.iter().position(Option::is_none).ok_or(Errno::ENOSPC)?;
+    // Under the step breakpoint, the original instruction is the saved one.
+    let breakpoint = match breakpoints.step {
+        Some(step) if step.addr == addr => step,
+        _ => plant(addr)?,
+    };
+    breakpoints.set[slot] = Some(breakpoint);
+    Ok(())
+}
+
+/// Removes the breakpoint at `addr`.
+///
+/// # Returns
+/// `ENOENT` if there is none.
+pub fn remove(addr: usize) -> Result<(), Errno> {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let slot = breakpoints.set.iter_mut().find(|slot| slot.is_some_and(|bp| bp.addr == addr)).ok_or(Errno::ENOENT)?;
+    let breakpoint = slot.take().unwrap();
+    if breakpoints.step.is_none_or(|step| step.addr != addr) {
+        write_instruction(addr, breakpoint.original, breakpoint.len);
+    }
+    Ok(())
+}
+
+/// Sets the temporary breakpoint ending a step at `addr`.
+pub fn insert_step(addr: usize) -> Result<(), Errno> {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let existing = breakpoints.set.iter().flatten().find(|bp| bp.addr == addr).copied();
+    breakpoints.step = Some(match existing {
+        Some(breakpoint) => breakpoint,
+        None => plant(addr)?,
+    });
+    Ok(())
+}
+
+/// Removes the temporary breakpoint of a step.
+///
+/// # Returns
+/// Its address, if one was set.
+pub fn remove_step() -> Option<usize> {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let step = breakpoints.step.take()?;
+    if !breakpoints.set.iter().flatten().any(|bp| bp.addr == step.addr) {
+        write_instruction(step.addr, step.original, step.len);
+    }
+    Some(step.addr)
+}
+
+/// Removes every breakpoint (GDB detached).
+pub fn remove_all() {
+    remove_step();
+    let mut breakpoints = BREAKPOINTS.lock();
+    for breakpoint in breakpoints.set.iter_mut().filter_map(Option::take) {
+        write_instruction(breakpoint.addr, breakpoint.original, breakpoint.len);
+    }
+}
+
+/// Returns the length of the compiled-in `ebreak` at `addr`, or `None` if
+/// there is none (breakpoints planted here do not count: resuming at one runs
+/// the original instruction once it is removed).
+pub fn ebreak_len(addr: usize) -> Option<usize> {
+    let breakpoints = BREAKPOINTS.lock();
+    if breakpoints.set.iter().chain(core::iter::once(&breakpoints.step)).flatten().any(|bp| bp.addr == addr) {
+        return None;
+    }
+    match read_instruction(addr).ok()? {
+        EBREAK => Some(4),
+        insn if insn == C_EBREAK as u32 => Some(2),
+        _ => None,
+    }
+}
@@ -0,0 +1,239 @@
+//! ---------------------------------------------------------------------------
+//! File       : packet.rs
+//! Module     : gdbstub::packet
+//! Author     : DiTurr
+//! Description:
+//! Framing of the GDB remote serial protocol: packets are `$<data>#<checksum>`,
+//! the checksum being the sum of the data bytes modulo 256 in two hex digits.
+//! Each packet is acknowledged with `+` (or `-` to ask for it again) until GDB
+//! switches acknowledgments off with `QStartNoAckMode`.
+//! ---------------------------------------------------------------------------
+
+use core::fmt;
+
+use crate::fs::CharDevice;
+
+/// Maximum packet size, advertised to GDB in `qSupported`.
+pub const PACKET_LEN: usize = 1024;
+
+/// Returns the value of hex digit `digit`.
+fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parses a big-endian hex number, as used for addresses and lengths.
+pub fn parse_hex(text: &[u8]) -> Option<usize> {
+    if text.is_empty() || text.len() > 2 * size_of::<usize>() {
+        return None;
+    }
+    text.iter().try_fold(0, |value, &digit| Some(value << 4 | hex_digit(digit)? as usize))
+}
+
+/// Decodes hex byte pairs from `text` into `out`.
+///
+/// # Returns
+/// The number of bytes decoded, or `None` if `text` is malformed or too long.
+pub fn decode_hex(text: &[u8], out: &mut [u8]) -> Option<usize> {
+    if !text.len().is_multiple_of(2) || text.len() / 2 > out.len() {
+        return None;
+    }
+    for (byte, pair) in out.iter_mut().zip(text.chunks_exact(2)) {
+        *byte = hex_digit(pair[0])? << 4 | hex_digit(pair[1])?;
+    }
+    Some(text.len() / 2)
+}
+
+/// Parses a register value: target (little-endian) byte order.
+pub fn parse_register(text: &[u8]) -> Option<usize> {
+    let mut bytes = [0u8; size_of::<usize>()];
+    match decode_hex(text, &mut bytes)? {
+        len if len == bytes.len() => Some(usize::from_le_bytes(bytes)),
+        _ => None,
+    }
+}
+
+/// The data of a packet being built.
+pub struct Reply {
+    data: [u8; PACKET_LEN],
+    len: usize,
+}
+
+impl Reply {
+    pub fn new() -> Self {
+        Reply { data: [0; PACKET_LEN], len: 0 }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Appends `bytes`, dropping what does not fit.
+    pub fn push(&mut self, bytes: &[u8]) {
+        let count = bytes.len().min(PACKET_LEN - self.len);
+        self.data[self.len..self.len + count].copy_from_slice(&bytes[..count]);
+        self.len += count;
+    }
+
+    /// Appends `bytes` as hex pairs.
+    pub fn push_hex(&mut self, bytes: &[u8]) {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        for &byte in bytes {
+            self.push(&[DIGITS[(byte >> 4) as usize], DIGITS[(byte & 0xf) as usize]]);
+        }
+    }
+
+    /// Appends a register value, in target (little-endian) byte order.
+    pub fn push_register(&mut self, value: usize) {
+        self.push_hex(&value.to_le_bytes());
+    }
+}
+
+impl fmt::Write for Reply {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// A connection to GDB over a character device.
+pub struct Connection {
+    device: &'static dyn CharDevice,
+    /// Packets are acknowledged.
+    ack: bool,
+}
+
+impl Connection {
+    pub fn new(device: &'static dyn CharDevice, ack: bool) -> Self {
+        Connection { device, ack }
+    }
+
+    /// Switches acknowledgments off (`QStartNoAckMode`).
+    pub fn disable_ack(&mut self) {
+        self.ack = false;
+    }
+
+    pub fn ack(&self) -> bool {
+        self.ack
+    }
+
+    /// Waits for a byte from GDB.
+    fn getb(&self) -> u8 {
+        let mut byte = [0];
+        loop {
+            match self.device.read(&mut byte) {
+                Ok(1) => return byte[0],
+                _ => core::hint::spin_loop(),
+            }
+        }
+    }
+
+    /// Sends `data` to GDB, giving up if the device fails.
+    fn put(&self, mut data: &[u8]) {
+        while !data.is_empty() {
+            match self.device.write(data) {
+                Ok(count) if count > 0 => data = &data[count..],
+                _ => return,
+            }
+        }
+    }
+
+    /// Waits for the next valid packet.
+    ///
+    /// # Arguments
+    /// * `buf` - Receives the data of the packet.
+    /// * `started` - The `$` starting the packet was already read.
+    ///
+    /// # Returns
+    /// The data of the packet.
+    pub fn receive<'a>(&self, buf: &'a mut [u8; PACKET_LEN], mut started: bool) -> &'a [u8] {
+        loop {
+            // Skip anything before the packet (acknowledgments, interrupts).
+            while !started && self.getb() != b'$' {}
+            started = false;
+            let mut len = 0;
+            let mut sum = 0u8;
+            let mut overflow = false;
+            loop {
+                let byte = self.getb();
+                if byte == b'#' {
+                    break;
+                }
+                sum = sum.wrapping_add(byte);
+                match buf.get_mut(len) {
+                    Some(slot) => *slot = byte,
+                    None => overflow = true,
+                }
+                len += 1;
+            }
+            let checksum = [self.getb(), self.getb()];
+            if !self.ack {
+                return &buf[..len.min(PACKET_LEN)];
+            }
+            if !overflow && parse_hex(&checksum) == Some(sum as usize) {
+                self.put(b"+");
+                return &buf[..len];
+            }
+            self.put(b"-");
+        }
+    }
+
+    /// Sends a packet, again until GDB acknowledges it.
+    pub fn send(&self, data: &[u8]) {
+        let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        let mut trailer = Reply::new();
+        trailer.push(b"#");
+        trailer.push_hex(&[sum]);
+        loop {
+            self.put(b"$");
+            self.put(data);
+            self.put(trailer.as_bytes());
+            if !self.ack {
+                return;
+            }
+            loop {
+                match self.getb() {
+                    b'+' => return,
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn parses_hex_numbers() {
+        assert_eq!(parse_hex(b"80001a2c"), Some(0x8000_1a2c));
+        assert_eq!(parse_hex(b"FF"), Some(0xff));
+        assert_eq!(parse_hex(b""), None);
+        assert_eq!(parse_hex(b"12g"), None);
+        assert_eq!(parse_hex(b"11112222333344445"), None);
+    }
+
+    #[test_case]
+    fn registers_are_little_endian() {
+        let mut reply = Reply::new();
+        reply.push_register(0x8000_1234);
+        assert_eq!(reply.as_bytes(), b"3412008000000000");
+        assert_eq!(parse_register(reply.as_bytes()), Some(0x8000_1234));
+        assert_eq!(parse_register(b"34120080"), None);
+    }
+
+    #[test_case]
+    fn decodes_hex_bytes() {
+        let mut out = [0u8; 4];
+        assert_eq!(decode_hex(b"deadbeef", &mut out), Some(4));
+        assert_eq!(out, [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(decode_hex(b"abc", &mut out), None);
+        assert_eq!(decode_hex(b"0011223344", &mut out), None);
+    }
+}
@@ -0,0 +1,91 @@
+//! ---------------------------------------------------------------------------
+//! File       : step.rs
+//! Module     : gdbstub::step
+//! Author     : DiTurr
+//! Description:
+//! Single-stepping support: RISC-V has no hardware single-step in M-mode, so
+//! the stub decodes the instruction about to run, computes the address of the
+//! next one from the registers (branch conditions included) and stops there
+//! with a temporary breakpoint.
+//!
+//! The instruction is decoded by `arch::decode`: control flow changes are
+//! `jal`, `jalr` and the conditional branches, compressed forms included
+//! (`c.j`, `c.jr`, `c.jalr`, `c.beqz`, `c.bnez`); every other instruction
+//! falls through. Instructions that trap (`ecall`) resume after themselves,
+//! which also falls through.
+//! ---------------------------------------------------------------------------
+
+use crate::arch::decode::{self, BranchOp, Instruction};
+
+/// Returns the address of the instruction executed after `insn`.
+///
+/// # Arguments
+/// * `insn` - The instruction at `pc` (the low 16 bits only for a compressed one).
+/// * `pc` - Its address.
+/// * `reg` - Returns the value of general purpose register `n` (`x0` reads 0).
+pub fn next_pc(insn: u32, pc: usize, reg: impl Fn(usize) -> usize) -> usize {
+    let target = |offset: i64| pc.wrapping_add_signed(offset as isize);
+    let next = pc + decode::length(insn);
+    match decode::decode(insn) {
+        Instruction::Jal { offset, .. } => target(offset),
+        Instruction::Jalr { rs1, offset, .. } => reg(rs1.0 as usize).wrapping_add_signed(offset as isize) & !1,
+        Instruction::Branch { op, rs1, rs2, offset } => {
+            let (rs1, rs2) = (reg(rs1.0 as usize), reg(rs2.0 as usize));
+            let taken = match op {
+                BranchOp::Beq => rs1 == rs2,
+                BranchOp::Bne => rs1 != rs2,
+                BranchOp::Blt => (rs1 as isize) < (rs2 as isize),
+                BranchOp::Bge => (rs1 as isize) >= (rs2 as isize),
+                BranchOp::Bltu => rs1 < rs2,
+                BranchOp::Bgeu => rs1 >= rs2,
+            };
+            if taken { target(offset) } else { next }
+        }
+        _ => next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PC: usize = 0x8000_1000;
+
+    /// Registers: `xn` holds `n`, except `a0` (-1) and `a1` (1).
+    fn reg(n: usize) -> usize {
+        match n {
+            10 => usize::MAX,
+            11 => 1,
+            n => n,
+        }
+    }
+
+    #[test_case]
+    fn jumps() {
+        assert_eq!(next_pc(0x0080_006f, PC, reg), PC + 8); // jal x0, 8
+        assert_eq!(next_pc(0xffdf_f0ef, PC, reg), PC - 4); // jal ra, -4
+        assert_eq!(next_pc(0x7fe0_006f, PC, reg), PC + 0x7fe); // jal x0, 0x7fe
+        assert_eq!(next_pc(0x8000_006f, PC, reg), PC - 0x10_0000); // jal x0, -1M
+        assert_eq!(next_pc(0x0000_8067, PC, reg), 0); // jalr x0, 0(ra): ra is 1
+        assert_eq!(next_pc(0xffe2_80e7, PC, reg), 2); // jalr ra, -2(t0): bit 0 cleared
+    }
+
+    #[test_case]
+    fn branches() {
+        assert_eq!(next_pc(0x0000_0863, PC, reg), PC + 16); // beq x0, x0, 16
+        assert_eq!(next_pc(0x0000_1863, PC, reg), PC + 4); // bne x0, x0, 16
+        assert_eq!(next_pc(0xfeb5_40e3, PC, reg), PC - 32); // blt a0, a1, -32: -1 < 1
+        assert_eq!(next_pc(0xfeb5_60e3, PC, reg), PC + 4); // bltu a0, a1, -32
+    }
+
+    #[test_case]
+    fn compressed() {
+        assert_eq!(next_pc(0xbffd, PC, reg), PC - 2); // c.j -2
+        assert_eq!(next_pc(0xaffd, PC, reg), PC + 0x7fe); // c.j 0x7fe
+        assert_eq!(next_pc(0x8082, PC, reg), 0); // c.jr ra
+        assert_eq!(next_pc(0xd101, PC, reg), PC + 2); // c.beqz a0, -256
+        assert_eq!(next_pc(0xe529, PC, reg), PC + 0x4a); // c.bnez a0, 0x4a
+        assert_eq!(next_pc(0x9002, PC, reg), PC + 2); // c.ebreak
+        assert_eq!(next_pc(0x852e, PC, reg), PC + 2); // c.mv a0, a1
+    }
+}
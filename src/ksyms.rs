@@ -0,0 +1,111 @@
+//! ---------------------------------------------------------------------------
+//! File       : ksyms.rs
+//! Module     : ksyms
+//! Author     : DiTurr
+//! Description:
+//! The kernel symbol table, used to show code addresses as `kmain+0x24`.
+//!
+//! The table lives in the `.ksymtab` section (see `lds/virt.lds`). The Makefile
+//! links the kernel twice: the first image, with an empty table, is passed to
+//! `tools/ksymtab.sh`, which extracts its functions (demangled names, sorted
+//! by address) into an assembly file; the second link adds the assembled
+//! table. The section is placed after the code, so both images have the same
+//! function addresses. Without a table (e.g. when linked by other means),
+//! addresses are shown raw.
+//!
+//! ## Example
+//! ```rust
+//! log_info!("handler at {}", ksyms::symbolize(handler as usize));
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::fmt;
+use core::ptr::addr_of;
+
+// Boundaries defined by the linker script.
+unsafe extern "C" {
+    static _text_start: u8;
+    static _text_end: u8;
+    static _ksymtab_start: u8;
+    static _ksymtab_end: u8;
+}
+
+/// One function of the table.
+#[repr(C)]
+struct Entry {
+    /// Start address of the function.
+    addr: u64,
+    /// Name, as an offset and a length in the string block.
+    name_offset: u32,
+    name_len: u32,
+}
+
+/// Returns the entries and the string block of the table, if there is one.
+fn table() -> Option<(&'static [Entry], &'static [u8])> {
+    let start = addr_of!(_ksymtab_start) as usize;
+    let end = addr_of!(_ksymtab_end) as usize;
+    let size = end.checked_sub(start)?;
+    if size < 8 {
+        return None;
+    }
+    // SAFETY: The section is 8-byte aligned and starts with the symbol count.
+    let count = unsafe { *(start as *const u64) } as usize;
+    let entries_len = count.checked_mul(size_of::<Entry>())?;
+    let strings_len = size.checked_sub(8 + entries_len)?;
+    // SAFETY: Both parts lie within the section, which is never written.
+    unsafe {
+        Some((
+            core::slice::from_raw_parts((start + 8) as *const Entry, count),
+            core::slice::from_raw_parts((start + 8 + entries_len) as *const u8, strings_len),
+        ))
+    }
+}
+
+/// Finds the function containing `addr`.
+///
+/// # Returns
+/// The name of the function and the offset of `addr` in it, or `None` if
+/// `addr` is not in the kernel code or there is no symbol table.
+pub fn lookup(addr: usize) -> Option<(&'static str, usize)> {
+    let text = addr_of!(_text_start) as usize..addr_of!(_text_end) as usize;
+    if !text.contains(&addr) {
+        return None;
+    }
+    let (entries, strings) = table()?;
+    let index = entries.partition_point(|entry| entry.addr as usize <= addr).checked_sub(1)?;
+    let entry = &entries[index];
+    let offset = entry.name_offset as usize;
+    let name = strings.get(offset..offset + entry.name_len as usize)?;
+    Some((core::str::from_utf8(name).ok()?, addr - entry.addr as usize))
+}
+
+/// A code address shown with its symbol, e.g. `0x0000000080001234 kmain+0x24`.
+pub struct Symbolized {
+    addr: usize,
+    /// Address looked up in the table; differs from `addr` for return addresses.
+    lookup: usize,
+}
+
+/// Shows the code address `addr` with its symbol.
+pub fn symbolize(addr: usize) -> Symbolized {
+    Symbolized { addr, lookup: addr }
+}
+
+/// Shows the return address `ra` with its symbol. The call instruction
+/// precedes `ra`, which may already be the start of the next function when
+/// the call does not return.
+// Only the panic report (kernel builds) walks return addresses.
+#[cfg_attr(not(target_os = "none"), allow(dead_code))]
+pub fn symbolize_return(ra: usize) -> Symbolized {
+    Symbolized { addr: ra, lookup: ra.wrapping_sub(1) }
+}
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#018x}", self.addr)?;
+        if let Some((name, offset)) = lookup(self.lookup) {
+            write!(f, " {}+{:#x}", name, offset + (self.addr - self.lookup))?;
+        }
+        Ok(())
+    }
+}
@@ -4,4 +4,11 @@
 //! Author     : DiTurr
 //! Description: Logger handlers and utilities.
 //! ---------------------------------------------------------------------------
+#[cfg(feature = "log")]
+pub mod facade;
+pub mod filter;
+pub mod kmsg;
+pub mod level;
+#[allow(clippy::module_inception)]
 pub mod logger;
+pub mod sink;
@@ -0,0 +1,52 @@
+//! ---------------------------------------------------------------------------
+//! File       : facade.rs
+//! Module     : logger::facade
+//! Author     : DiTurr
+//! Description:
+//! Backend for the `log` crate facade (cargo feature `log`), so that `no_std`
+//! dependencies using `log::info!` and friends write through the kernel logger.
+//! The record target (usually the module path) is matched by the runtime filter
+//! and prefixes the message.
+//! ---------------------------------------------------------------------------
+
+use super::filter;
+use super::level::{Level, STATIC_MAX_LEVEL};
+use super::logger;
+
+/// The `log::Log` implementation forwarding to [`logger::log`].
+struct KernelLogger;
+
+static KERNEL_LOGGER: KernelLogger = KernelLogger;
+
+/// Maps a `log` crate level to the kernel's.
+fn level(level: log::Level) -> Level {
+    match level {
+        log::Level::Error => Level::Error,
+        log::Level::Warn => Level::Warn,
+        log::Level::Info => Level::Info,
+        log::Level::Debug => Level::Debug,
+        log::Level::Trace => Level::Trace,
+    }
+}
+
+impl log::Log for KernelLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        let level = level(metadata.level());
+        level <= STATIC_MAX_LEVEL && filter::enabled(level, metadata.target())
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            logger::log(level(record.level()), record.target(), *record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the kernel logger as the `log` crate backend.
+pub fn init() {
+    if log::set_logger(&KERNEL_LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Trace);
+    }
+}
@@ -0,0 +1,180 @@
+//! ---------------------------------------------------------------------------
+//! File       : filter.rs
+//! Module     : logger::filter
+//! Author     : DiTurr
+//! Description:
+//! Runtime log filtering: a global threshold plus per-module overrides. Both are
+//! set at boot from the `loglevel=` command line option, a comma-separated list
+//! of directives:
+//! - `<level>` sets the global threshold (default `info`);
+//! - `<module>=<level>` overrides it for a module and its children.
+//!
+//! A module directive matches a record if it is a prefix of the record's module
+//! path (without the crate name) or one of its components, so `virtio` matches
+//! `peripherals::virtio::net`. When several directives match, the longest one
+//! wins.
+//!
+//! ## Example
+//! ```rust
+//! // qemu ... -append "loglevel=warn,virtio=trace,net::tcp=debug"
+//! filter::init();
+//! if filter::enabled(Level::Debug, module_path!()) { ... }
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::level::Level;
+use crate::cmdline;
+use crate::sync::spinlock::SpinLock;
+use crate::log_warn;
+
+/// Maximum number of per-module overrides.
+const MAX_DIRECTIVES: usize = 8;
+
+/// The global threshold, stored as `Level as u8`.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// A per-module override.
+#[derive(Clone, Copy)]
+struct Directive {
+    module: &'static str,
+    level: Level,
+}
+
+static DIRECTIVES: SpinLock<[Option<Directive>; MAX_DIRECTIVES]> = SpinLock::new([None; MAX_DIRECTIVES]);
+
+/// Errors reported when changing the filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterError {
+    /// All override slots are in use.
+    Full,
+}
+
+/// Returns the global threshold.
+pub fn max_level() -> Level {
+    Level::from_u8(MAX_LEVEL.load(Ordering::Relaxed)).unwrap_or(Level::Info)
+}
+
+/// Sets the global threshold.
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Sets the threshold of `module` (and its children), replacing any previous
+/// override for the same module.
+pub fn set_module_level(module: &'static str, level: Level) -> Result<(), FilterError> {
+    let mut directives = DIRECTIVES.lock();
+    if let Some(directive) = directives.iter_mut().flatten().find(|d| d.module == module) {
+        directive.level = level;
+        return Ok(());
+    }
+    let slot = directives.iter_mut().find(|slot| slot.is_none()).ok_or(FilterError::Full)?;
+    *slot = Some(Directive { module, level });
+    Ok(())
+}
+
+/// Removes the kernel's crate name from `module_path`: records of the kernel
+/// are matched (and printed) as e.g. `net::tcp`. Targets of other crates, as
+/// passed through the `log` facade, are kept whole.
+pub fn strip_crate(module_path: &str) -> &str {
+    match module_path.strip_prefix(env!("CARGO_CRATE_NAME")) {
+        Some("") => "",
+        Some(rest) => rest.strip_prefix("::").unwrap_or(module_path),
+        None => module_path,
+    }
+}
+
+/// Returns `true` if `directive` applies to the module path `path`.
+fn matches(directive: &str, path: &str) -> bool {
+    match path.strip_prefix(directive) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => path.split("::").any(|component| component == directive),
+    }
+}
+
+/// Returns the level of the longest directive of `directives` matching the
+/// module path `path`, if any.
+fn module_level(directives: &[Option<Directive>], path: &str) -> Option<Level> {
+    directives
+        .iter()
+        .flatten()
+        .filter(|d| matches(d.module, path))
+        .max_by_key(|d| d.module.len())
+        .map(|d| d.level)
+}
+
+/// Returns `true` if a record of `level` from `module_path` must be logged.
+///
+/// # Arguments
+/// * `module_path` - The full module path, as given by `module_path!()`.
+pub fn enabled(level: Level, module_path: &str) -> bool {
+    let path = strip_crate(module_path);
+    // A trap handler may log while the overrides are being changed: fall
+    // back to the global threshold then.
+    let threshold = DIRECTIVES
+        .try_lock()
+        .and_then(|directives| module_level(&*directives, path))
+        .unwrap_or_else(max_level);
+    level <= threshold
+}
+
+/// Applies the `loglevel=` command line option.
+pub fn init() {
+    let Some(spec) = cmdline::get("loglevel") else {
+        return;
+    };
+    for directive in spec.split(',').filter(|d| !d.is_empty()) {
+        let result = match directive.split_once('=') {
+            None => Level::parse(directive).map(|level| {
+                set_max_level(level);
+                Ok(())
+            }),
+            Some((module, level)) => Level::parse(level).map(|level| set_module_level(module, level)),
+        };
+        match result {
+            Some(Ok(())) => {}
+            Some(Err(err)) => {
+                log_warn!("log: cannot apply '{}': {:?}", directive, err);
+            }
+            None => {
+                log_warn!("log: invalid level directive '{}'.", directive);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn strips_the_kernel_crate() {
+        assert_eq!(strip_crate("rustos::net::tcp"), "net::tcp");
+        assert_eq!(strip_crate("rustos"), "");
+        assert_eq!(strip_crate("rustosx::net"), "rustosx::net");
+        assert_eq!(strip_crate("smoltcp::iface"), "smoltcp::iface");
+    }
+
+    #[test_case]
+    fn directive_matches_prefix_or_component() {
+        assert!(matches("net", "net::tcp"));
+        assert!(matches("net::tcp", "net::tcp"));
+        assert!(matches("tcp", "net::tcp"));
+        assert!(!matches("net::tc", "net::tcp"));
+        assert!(!matches("ne", "net::tcp"));
+        assert!(!matches("udp", "net::tcp"));
+    }
+
+    #[test_case]
+    fn longest_directive_wins() {
+        let directives = [
+            Some(Directive { module: "net", level: Level::Error }),
+            Some(Directive { module: "net::tcp", level: Level::Trace }),
+            None,
+        ];
+        assert_eq!(module_level(&directives, "net::udp"), Some(Level::Error));
+        assert_eq!(module_level(&directives, "net::tcp::timer"), Some(Level::Trace));
+        assert_eq!(module_level(&directives, "fs::procfs"), None);
+    }
+}
@@ -0,0 +1,343 @@
+//! ---------------------------------------------------------------------------
+//! File       : kmsg.rs
+//! Module     : logger::kmsg
+//! Author     : DiTurr
+//! Description:
+//! The kernel message buffer: a fixed-size ring keeping the most recent log
+//! records, whatever the log sinks are, so that they can be read back after they
+//! scrolled by or when no terminal was attached. Every record gets a sequence
+//! number and keeps its timestamp, level and hart.
+//!
+//! The ring is lock-free, so that records logged by a trap handler interrupting
+//! a writer or a reader are never lost nor block. Each slot is a small seqlock:
+//! - a writer takes the next sequence number with an atomic increment, marks
+//!   the slot `seq % SLOTS` as being written, fills it and publishes it;
+//! - a reader copies a slot and keeps the copy only if the slot held the
+//!   wanted record, completely written, before and after the copy.
+//!
+//! The buffer is read by the panic handler and the monitor's `dmesg` ([`dump`]),
+//! the `syslog` system call, `/dev/kmsg` and the status page, in one of the
+//! [`Format`]s.
+//!
+//! ## Example
+//! ```rust
+//! let mut seq = kmsg::first_seq();
+//! while let Some(entry) = kmsg::read(seq) {
+//!     uart_println!("{}", entry);
+//!     seq = entry.seq + 1;
+//! }
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod device;
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{fence, AtomicU16, AtomicU64, AtomicU8, Ordering};
+
+use super::level::Level;
+use crate::fs::devfs;
+use crate::log_warn;
+
+/// Number of records kept.
+pub const SLOTS: usize = 64;
+
+/// Maximum length of the text of a record; longer texts are truncated.
+pub const TEXT_LEN: usize = 232;
+
+/// Maximum length of a formatted record, in any format.
+const LINE_LEN: usize = TEXT_LEN + 64;
+
+/// `Slot::state` bit set while the slot is being written.
+const WRITING: u64 = 1;
+
+/// One record of the ring.
+struct Slot {
+    /// `(seq + 1) << 1` for the record held, plus [`WRITING`] while it is being
+    /// written; 0 if the slot was never used.
+    state: AtomicU64,
+    time_us: AtomicU64,
+    level: AtomicU8,
+    hart: AtomicU8,
+    len: AtomicU16,
+    text: [AtomicU8; TEXT_LEN],
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            state: AtomicU64::new(0),
+            time_us: AtomicU64::new(0),
+            level: AtomicU8::new(0),
+            hart: AtomicU8::new(0),
+            len: AtomicU16::new(0),
+            text: [const { AtomicU8::new(0) }; TEXT_LEN],
+        }
+    }
+}
+
+/// The ring: record `seq` lives in slot `seq % SLOTS`.
+static RING: [Slot; SLOTS] = [const { Slot::new() }; SLOTS];
+
+/// Sequence number of the next record.
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A record copied out of the ring.
+pub struct Entry {
+    /// Sequence number, counting every record logged since boot.
+    pub seq: u64,
+    /// Time since boot, in microseconds.
+    pub time_us: u64,
+    pub level: Level,
+    /// Hart that logged the record.
+    pub hart: u8,
+    text: [u8; TEXT_LEN],
+    len: usize,
+}
+
+impl Entry {
+    /// Returns the text of the record (module and message).
+    pub fn text(&self) -> &str {
+        core::str::from_utf8(&self.text[..self.len]).unwrap_or("")
+    }
+}
+
+/// `dmesg`-style rendering, e.g. `[INF] [    1.532000] hart0 net: link up`.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} [{:5}.{:06}] hart{} {}",
+            self.level.tag(),
+            self.time_us / 1_000_000,
+            self.time_us % 1_000_000,
+            self.hart,
+            self.text()
+        )
+    }
+}
+
+/// The ways records are rendered for readers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `[INF] [    1.532000] hart0 net: link up`, as shown by `dmesg`.
+    Dmesg,
+    /// `<6>[    1.532000] net: link up`, as returned by `syslog`.
+    Syslog,
+    /// `6,42,1532000,-;net: link up`, as read from `/dev/kmsg`.
+    Kmsg,
+}
+
+/// Appends `entry` to `out` in `format`, with a final newline.
+fn format_entry(out: &mut impl Write, entry: &Entry, format: Format) -> fmt::Result {
+    let (secs, micros) = (entry.time_us / 1_000_000, entry.time_us % 1_000_000);
+    let priority = entry.level.syslog_priority();
+    match format {
+        Format::Dmesg => writeln!(out, "{}", entry),
+        Format::Syslog => writeln!(out, "<{}>[{:5}.{:06}] {}", priority, secs, micros, entry.text()),
+        Format::Kmsg => writeln!(out, "{},{},{},-;{}", priority, entry.seq, entry.time_us, entry.text()),
+    }
+}
+
+/// A formatted record.
+struct Line {
+    data: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl Line {
+    fn new(entry: &Entry, format: Format) -> Line {
+        let mut line = Line { data: [0; LINE_LEN], len: 0 };
+        let _ = format_entry(&mut line, entry, format);
+        line
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(LINE_LEN - self.len);
+        self.data[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Appends a record to the ring, overwriting the oldest one.
+///
+/// The text is truncated to [`TEXT_LEN`] bytes on a character boundary.
+///
+/// # Arguments
+/// * `level` - The level of the record.
+/// * `time_us` - The time since boot, in microseconds.
+/// * `hart` - The hart logging the record.
+/// * `text` - The record, without level, timestamp or final newline.
+pub fn store(level: Level, time_us: u64, hart: usize, text: &str) {
+    let seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+    let slot = &RING[seq as usize % SLOTS];
+    let state = (seq + 1) << 1;
+    // Readers seeing any of the new fields must also see the slot being written.
+    slot.state.store(state | WRITING, Ordering::Relaxed);
+    fence(Ordering::Release);
+    let mut len = text.len().min(TEXT_LEN);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    for (dst, &src) in slot.text.iter().zip(&text.as_bytes()[..len]) {
+        dst.store(src, Ordering::Relaxed);
+    }
+    slot.len.store(len as u16, Ordering::Relaxed);
+    slot.time_us.store(time_us, Ordering::Relaxed);
+    slot.level.store(level as u8, Ordering::Relaxed);
+    slot.hart.store(hart as u8, Ordering::Relaxed);
+    // Publish the record.
+    slot.state.store(state, Ordering::Release);
+}
+
+/// Returns the sequence number the next record will get.
+pub fn next_seq() -> u64 {
+    NEXT_SEQ.load(Ordering::Relaxed)
+}
+
+/// Returns the sequence number of the oldest record still in the ring.
+pub fn first_seq() -> u64 {
+    next_seq().saturating_sub(SLOTS as u64)
+}
+
+/// Copies record `seq` out of the ring.
+///
+/// # Returns
+/// `None` if the record was overwritten, is being written or does not exist.
+fn load(seq: u64) -> Option<Entry> {
+    let slot = &RING[seq as usize % SLOTS];
+    let state = (seq + 1) << 1;
+    if slot.state.load(Ordering::Acquire) != state {
+        return None;
+    }
+    let mut entry = Entry {
+        seq,
+        time_us: slot.time_us.load(Ordering::Relaxed),
+        level: Level::from_u8(slot.level.load(Ordering::Relaxed))?,
+        hart: slot.hart.load(Ordering::Relaxed),
+        text: [0; TEXT_LEN],
+        len: (slot.len.load(Ordering::Relaxed) as usize).min(TEXT_LEN),
+    };
+    for (dst, src) in entry.text.iter_mut().zip(&slot.text) {
+        *dst = src.load(Ordering::Relaxed);
+    }
+    // Keep the copy only if no writer reused the slot meanwhile.
+    fence(Ordering::Acquire);
+    (slot.state.load(Ordering::Relaxed) == state).then_some(entry)
+}
+
+/// Returns the oldest record still in the ring whose sequence number is at
+/// least `seq`, or `None` if there is none yet.
+pub fn read(seq: u64) -> Option<Entry> {
+    (seq.max(first_seq())..next_seq()).find_map(load)
+}
+
+/// Formats records into `buf`, starting at `*seq`, and advances `*seq` past
+/// them. Only whole records are copied, except when the first one alone does
+/// not fit: it is then truncated.
+///
+/// # Returns
+/// The number of bytes written.
+pub fn read_lines(seq: &mut u64, buf: &mut [u8], format: Format) -> usize {
+    let mut count = 0;
+    if buf.is_empty() {
+        return 0;
+    }
+    while let Some(entry) = read(*seq) {
+        let line = Line::new(&entry, format);
+        let room = buf.len() - count;
+        if line.len > room && count > 0 {
+            break;
+        }
+        let len = line.len.min(room);
+        buf[count..count + len].copy_from_slice(&line.as_bytes()[..len]);
+        count += len;
+        *seq = entry.seq + 1;
+        if count == buf.len() {
+            break;
+        }
+    }
+    count
+}
+
+/// Returns the number of bytes the records from `seq` onwards take in `format`.
+pub fn pending_len(mut seq: u64, format: Format) -> usize {
+    let mut len = 0;
+    while let Some(entry) = read(seq) {
+        len += Line::new(&entry, format).len;
+        seq = entry.seq + 1;
+    }
+    len
+}
+
+/// Formats the most recent records from `seq` onwards that fit into `buf`.
+///
+/// # Returns
+/// The number of bytes written.
+pub fn read_tail(seq: u64, buf: &mut [u8], format: Format) -> usize {
+    // Walk back from the newest record until the buffer is full.
+    let mut start = next_seq();
+    let mut len = 0;
+    while start > seq.max(first_seq()) {
+        if let Some(entry) = load(start - 1) {
+            let line_len = Line::new(&entry, format).len;
+            if len + line_len > buf.len() {
+                break;
+            }
+            len += line_len;
+        }
+        start -= 1;
+    }
+    read_lines(&mut start, buf, format)
+}
+
+/// Writes every record in the ring, in `dmesg` format, to `out`.
+///
+/// Used by the panic handler: this takes no lock.
+pub fn dump(mut out: impl FnMut(&str)) {
+    let mut seq = first_seq();
+    while let Some(entry) = read(seq) {
+        let line = Line::new(&entry, Format::Dmesg);
+        out(core::str::from_utf8(line.as_bytes()).unwrap_or("?\n"));
+        seq = entry.seq + 1;
+    }
+}
+
+/// Registers `/dev/kmsg`.
+pub fn init() {
+    if let Err(err) = devfs::register("kmsg", &device::KMSG) {
+        log_warn!("kmsg: cannot register /dev/kmsg: {:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn stored_record_reads_back() {
+        let seq = next_seq();
+        store(Level::Warn, 1_500_000, 0, "kmsg: test record");
+        let entry = read(seq).expect("record not found");
+        assert_eq!(entry.seq, seq);
+        assert_eq!(entry.level, Level::Warn);
+        assert_eq!(entry.text(), "kmsg: test record");
+        let line = Line::new(&entry, Format::Syslog);
+        assert_eq!(line.as_bytes(), b"<4>[    1.500000] kmsg: test record\n");
+    }
+
+    #[test_case]
+    fn long_text_is_truncated_on_char_boundary() {
+        let seq = next_seq();
+        // 'é' takes two bytes: one byte more than the room left.
+        let mut text = [b'a'; TEXT_LEN + 1];
+        text[TEXT_LEN - 1..].copy_from_slice("é".as_bytes());
+        store(Level::Info, 0, 0, core::str::from_utf8(&text).unwrap());
+        assert_eq!(read(seq).expect("record not found").text().len(), TEXT_LEN - 1);
+    }
+}
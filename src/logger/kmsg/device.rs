@@ -0,0 +1,76 @@
+//! ---------------------------------------------------------------------------
+//! File       : device.rs
+//! Module     : logger::kmsg::device
+//! Author     : DiTurr
+//! Description:
+//! The `/dev/kmsg` character device, following the Linux interface:
+//! - each read returns one record, `priority,seq,time_us,-;text\n`, or fails
+//!   with `EAGAIN` once all records were read, and with `EPIPE` (once) if the
+//!   records to be read next were overwritten;
+//! - each write logs one record, with an optional `<priority>` prefix
+//!   (default: info).
+//!
+//! Descriptors carry no state, so all readers share a single read position.
+//! ---------------------------------------------------------------------------
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::Format;
+use crate::fs::CharDevice;
+use crate::logger::filter;
+use crate::logger::level::{Level, STATIC_MAX_LEVEL};
+use crate::logger::logger;
+use crate::syscalls::errno::Errno;
+
+/// Module path given to records written by programs.
+const USER_MODULE: &str = "user";
+
+/// The `/dev/kmsg` device.
+pub struct KmsgDevice {
+    /// Sequence number of the next record to read.
+    seq: AtomicU64,
+}
+
+/// The kernel's `/dev/kmsg` instance.
+pub static KMSG: KmsgDevice = KmsgDevice { seq: AtomicU64::new(0) };
+
+/// Splits the `<priority>` prefix off a record written to the device.
+fn parse_priority(text: &str) -> (Level, &str) {
+    let parsed = text
+        .strip_prefix('<')
+        .and_then(|rest| rest.split_once('>'))
+        .and_then(|(priority, rest)| {
+            // Only the severity is used; the facility bits are ignored.
+            let priority = priority.parse::<u32>().ok()?;
+            Some((Level::from_syslog_priority((priority & 7) as u8)?, rest))
+        });
+    parsed.unwrap_or((Level::Info, text))
+}
+
+impl CharDevice for KmsgDevice {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let first = super::first_seq();
+        if seq < first {
+            self.seq.store(first, Ordering::Relaxed);
+            return Err(Errno::EPIPE);
+        }
+        let entry = super::read(seq).ok_or(Errno::EAGAIN)?;
+        let line = super::Line::new(&entry, Format::Kmsg);
+        // A record is never split across reads.
+        let out = buf.get_mut(..line.len).ok_or(Errno::EINVAL)?;
+        out.copy_from_slice(line.as_bytes());
+        self.seq.store(entry.seq + 1, Ordering::Relaxed);
+        Ok(line.len)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        let text = core::str::from_utf8(buf).map_err(|_| Errno::EINVAL)?;
+        let (level, text) = parse_priority(text);
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        if level <= STATIC_MAX_LEVEL && filter::enabled(level, USER_MODULE) {
+            logger::log(level, USER_MODULE, format_args!("{}", text));
+        }
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,123 @@
+//! ---------------------------------------------------------------------------
+//! File       : level.rs
+//! Module     : logger::level
+//! Author     : DiTurr
+//! Description:
+//! Log levels and the compile-time maximum level. Records above
+//! [`STATIC_MAX_LEVEL`] are removed at compile time: the check in the logging
+//! macros compares two constants, so the optimizer drops the whole call.
+//! The maximum is selected with one of the cargo features `max_level_error`,
+//! `max_level_warn`, `max_level_info` or `max_level_debug` (the most
+//! restrictive wins); without any of them every level is compiled in.
+//! ---------------------------------------------------------------------------
+
+/// Severity of a log record, from the most to the least important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn  = 2,
+    Info  = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    /// Returns the tag printed in front of records, e.g. `[INF]`.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Level::Error => "[ERR]",
+            Level::Warn => "[WRN]",
+            Level::Info => "[INF]",
+            Level::Debug => "[DBG]",
+            Level::Trace => "[TRC]",
+        }
+    }
+
+    /// Converts a value produced by `level as u8` back into a level.
+    pub fn from_u8(value: u8) -> Option<Level> {
+        match value {
+            1 => Some(Level::Error),
+            2 => Some(Level::Warn),
+            3 => Some(Level::Info),
+            4 => Some(Level::Debug),
+            5 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// Returns the syslog priority of the level (`LOG_ERR` to `LOG_DEBUG`).
+    pub fn syslog_priority(self) -> u8 {
+        match self {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        }
+    }
+
+    /// Returns the level of syslog priority `priority` (0 to 7); the most
+    /// severe priorities are all errors.
+    pub fn from_syslog_priority(priority: u8) -> Option<Level> {
+        match priority {
+            0..=3 => Some(Level::Error),
+            4 => Some(Level::Warn),
+            5 | 6 => Some(Level::Info),
+            7 => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    /// Parses a level name (`error`, `warn`, `info`, `debug`, `trace`).
+    pub fn parse(name: &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// The most verbose level compiled into the kernel.
+pub const STATIC_MAX_LEVEL: Level = if cfg!(feature = "max_level_error") {
+    Level::Error
+} else if cfg!(feature = "max_level_warn") {
+    Level::Warn
+} else if cfg!(feature = "max_level_info") {
+    Level::Info
+} else if cfg!(feature = "max_level_debug") {
+    Level::Debug
+} else {
+    Level::Trace
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+    #[test_case]
+    fn levels_round_trip() {
+        for level in ALL {
+            assert_eq!(Level::from_u8(level as u8), Some(level));
+        }
+        for level in [Level::Error, Level::Warn, Level::Info, Level::Debug] {
+            assert_eq!(Level::from_syslog_priority(level.syslog_priority()), Some(level));
+        }
+        // syslog has no level below debug.
+        assert_eq!(Level::from_syslog_priority(Level::Trace.syslog_priority()), Some(Level::Debug));
+        assert_eq!(Level::from_u8(0), None);
+        assert_eq!(Level::from_u8(6), None);
+    }
+
+    #[test_case]
+    fn parses_names() {
+        assert_eq!(Level::parse("warn"), Some(Level::Warn));
+        assert_eq!(Level::parse("trace"), Some(Level::Trace));
+        assert_eq!(Level::parse("verbose"), None);
+    }
+}
@@ -3,15 +3,21 @@
 //! Module     : logger::logger
 //! Author     : DiTurr
 //! Description:
-//! This module provides logging macros (`log_info!`, `log_warn!`, `log_error!`) for use in
+//! This module provides logging macros (`log_error!`, `log_warn!`, `log_info!`, `log_debug!`,
+//! `log_trace!`) for use in
 //! a `no_std` embedded or OS environment. It avoids heap allocations and uses `core::fmt::Write`
-//! to print log messages directly through a UART device.
-//! Each log entry includes a timestamp derived from the hardware timer and is prefixed
-//! with a log level tag (e.g., `[INF]`, `[WRN]`, `[ERR]`).
+//! to format each log message once, then hands it to the registered log sinks (see `logger::sink`).
+//! Each log entry includes a timestamp (`[secs.micros]` since boot, or the wall-clock time with
+//! `logtime=wall` on the command line) and is prefixed
+//! with a log level tag (e.g., `[INF]`, `[WRN]`, `[ERR]`), the hart that logged it and the
+//! module it comes from.
 //!
 //! ## Features
-//! - Uses a constant CPU frequency to convert timer ticks into milliseconds.
-//! - Relies on a `UART` driver to send output directly to a serial port.
+//! - Timestamps come from the `time` subsystem (device-tree timebase and RTC).
+//! - Levels above `level::STATIC_MAX_LEVEL` are compiled out; the others are filtered at
+//!   runtime per module (see `logger::filter`) before anything is formatted.
+//! - Writes every record to all registered `LogSink`s (UART, virtio console, network) and keeps
+//!   it in the kernel message buffer (see `logger::kmsg`).
 //! - Exported macros can be used anywhere in the crate for structured logging.
 //!
 //! ## Example
@@ -19,61 +25,136 @@
 //! log_info!("Starting kernel...");
 //! log_warn!("Battery level is low: {}%", 18);
 //! log_error!("Unhandled exception: code={:#x}", code);
+//! log_trace!("virtq {}: used index {}", queue, index);
 //! ```
 //!
 //! ---------------------------------------------------------------------------
 
-/// The fixed CPU frequency in Hertz used to calculate wall-clock time
-/// from timer ticks. This value must match the actual hardware or
-/// emulated platform (e.g., QEMU) timer frequency.
-pub const CPU_FREQ: u64 = 10_000_000;
+use super::filter;
+use super::kmsg;
+use super::level::Level;
+use super::sink;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-/// Logs a message with a given level (e.g., "[INF]", "[WRN]") and timestamp.
+use crate::cmdline;
+use crate::registers::mhartid::MHARTID;
+use crate::time::{self, system::SystemTime};
+
+/// Whether records are stamped with the wall-clock time instead of the uptime.
+static WALL_CLOCK: AtomicBool = AtomicBool::new(false);
+
+/// Maximum length of a formatted log record; longer records are truncated.
+const RECORD_LEN: usize = 512;
+
+/// A log record formatted into a fixed buffer.
+struct Record {
+    data: [u8; RECORD_LEN],
+    len: usize,
+}
+
+impl core::fmt::Write for Record {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // Keep one byte for the final newline and never split a character.
+        let room = RECORD_LEN - 1 - self.len;
+        let mut count = s.len().min(room);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+        self.data[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Formats one log record and hands it to every registered sink.
+///
+/// This is the single writer behind all logging macros: the record is
+/// formatted once, on the stack, then written to the sinks as a whole line.
+///
+/// The caller is responsible for filtering: the logging macros only call this
+/// function for enabled records.
+///
+/// # Arguments
+/// * `level` - The level of the record.
+/// * `module` - The module the record comes from, as given by `module_path!()`.
+/// * `args` - The message, as produced by `format_args!`.
+pub fn log(level: Level, module: &str, args: core::fmt::Arguments) {
+    use core::fmt::Write;
+    let mut record = Record { data: [0; RECORD_LEN], len: 0 };
+    // Time since boot, from the monotonic clock.
+    let uptime = time::uptime();
+    let hart = MHARTID::read();
+    // Records of the kernel itself are shown without the crate name.
+    let module = filter::strip_crate(module);
+    // Print the log level, timestamp and hart prefix, then the module and message body.
+    let _ = write!(record, "{} ", level.tag());
+    let _ = if WALL_CLOCK.load(Ordering::Relaxed) {
+        write!(record, "[{}] ", SystemTime::now())
+    } else {
+        write!(record, "[{:5}.{:06}] ", uptime.as_secs(), uptime.subsec_micros())
+    };
+    let _ = write!(record, "hart{} ", hart);
+    let body = record.len;
+    if !module.is_empty() {
+        let _ = write!(record, "{}: ", module);
+    }
+    let _ = record.write_fmt(args);
+    record.data[record.len] = b'\n';
+    record.len += 1;
+    // Only whole characters were copied, so the record is valid UTF-8.
+    if let Ok(text) = core::str::from_utf8(&record.data[..record.len]) {
+        // The message buffer keeps the level, time and hart apart.
+        kmsg::store(level, uptime.as_micros() as u64, hart, &text[body..text.len() - 1]);
+        sink::write(text);
+    }
+}
+
+/// Applies the `logtime=` command line option: `wall` stamps records with the
+/// wall-clock time, `uptime` (the default) with the time since boot.
+pub fn init() {
+    match cmdline::get("logtime") {
+        None | Some("uptime") => {}
+        Some("wall") => WALL_CLOCK.store(true, Ordering::Relaxed),
+        Some(other) => crate::log_warn!("log: invalid time stamp '{}'.", other),
+    }
+}
+
+/// Logs a message with a given level and timestamp.
 ///
-/// This macro uses `core::fmt::Write` to format the message without heap allocation,
-/// and outputs via the UART peripheral. It includes the current time in milliseconds
-/// since boot, computed from the hardware timer register.
+/// The record is dropped without being formatted if its level is above
+/// [`STATIC_MAX_LEVEL`](super::level::STATIC_MAX_LEVEL) (at compile time) or
+/// disabled for the calling module by the runtime filter. Otherwise [`log`]
+/// formats it without heap allocation and writes it to every registered log
+/// sink. It includes the current time in microseconds since boot, computed
+/// from the hardware timer register.
 ///
 /// # Examples
 /// ```rust
-/// logln!("[INF]", "System initialized.");
-/// logln!("[ERR]", "Failed at step {}", 42);
+/// logln!(Level::Info, "System initialized.");
+/// logln!(Level::Error, "Failed at step {}", 42);
 /// ```
 #[macro_export]
 macro_rules! logln {
     ($level:expr, $($arg:tt)*) => {{
-        use core::fmt::Write;
-        // Writer wrapper that implements core::fmt::Write for UART output.
-        struct UartWriter;
-
-        impl core::fmt::Write for UartWriter {
-            fn write_str(&mut self, s: &str) -> core::fmt::Result {
-                // Send each string fragment via the UART driver.
-                $crate::peripherals::uart::UART.puts(s);
-                Ok(())
-            }
+        let level: $crate::logger::level::Level = $level;
+        if level <= $crate::logger::level::STATIC_MAX_LEVEL
+            && $crate::logger::filter::enabled(level, module_path!())
+        {
+            $crate::logger::logger::log(level, module_path!(), format_args!($($arg)*));
         }
-        // Read the current timer value (in ticks) from the TIME register.
-        let time: usize = $crate::registers::time::TIME::read();
-        // Convert to milliseconds using the CPU frequency.
-        let time_ms: u64 = (time as u64 * 1_000) / $crate::logger::logger::CPU_FREQ;
-        // Print the log level and timestamp prefix.
-        let _ = write!(UartWriter, "{} [{}] ", $level, time_ms);
-        // Print the formatted message body.
-        let _ = writeln!(UartWriter, $($arg)*);
     }};
 }
 
-/// Logs an info-level message (abbreviated as "[INF]").
+/// Logs an error-level message (abbreviated as "[ERR]").
 ///
 /// # Example
 /// ```rust
-/// log_info!("Kernel started");
+/// log_error!("Kernel panic: code {}", panic_code);
 /// ```
 #[macro_export]
-macro_rules! log_info {
+macro_rules! log_error {
     ($($arg:tt)*) => {
-        $crate::logln!("[INF]", $($arg)*);
+        $crate::logln!($crate::logger::level::Level::Error, $($arg)*)
     };
 }
 
@@ -86,19 +167,45 @@ macro_rules! log_info {
 #[macro_export]
 macro_rules! log_warn {
     ($($arg:tt)*) => {
-        $crate::logln!("[WRN]", $($arg)*);
+        $crate::logln!($crate::logger::level::Level::Warn, $($arg)*)
     };
 }
 
-/// Logs an error-level message (abbreviated as "[ERR]").
+/// Logs an info-level message (abbreviated as "[INF]").
 ///
 /// # Example
 /// ```rust
-/// log_error!("Kernel panic: code {}", panic_code);
+/// log_info!("Kernel started");
 /// ```
 #[macro_export]
-macro_rules! log_error {
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logln!($crate::logger::level::Level::Info, $($arg)*)
+    };
+}
+
+/// Logs a debug-level message (abbreviated as "[DBG]"), disabled by default.
+///
+/// # Example
+/// ```rust
+/// log_debug!("virtio-net: {} rx buffers posted", count);
+/// ```
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::logln!($crate::logger::level::Level::Debug, $($arg)*)
+    };
+}
+
+/// Logs a trace-level message (abbreviated as "[TRC]"), disabled by default.
+///
+/// # Example
+/// ```rust
+/// log_trace!("trap: mcause={:#x}", mcause);
+/// ```
+#[macro_export]
+macro_rules! log_trace {
     ($($arg:tt)*) => {
-        $crate::logln!("[ERR]", $($arg)*);
+        $crate::logln!($crate::logger::level::Level::Trace, $($arg)*)
     };
 }
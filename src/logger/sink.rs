@@ -0,0 +1,130 @@
+//! ---------------------------------------------------------------------------
+//! File       : sink.rs
+//! Module     : logger::sink
+//! Author     : DiTurr
+//! Description:
+//! Log sinks: the destinations log records are written to. Sinks are kept in a
+//! small registry and can be added and removed at runtime; every record is
+//! written to all of them. The UART is registered from the start so that early
+//! boot messages are not lost; records are also kept in the kernel message
+//! buffer (see `logger::kmsg`) whatever the sinks are.
+//!
+//! The set of sinks can be chosen at boot with `log=<sink>[,<sink>...]` on the
+//! kernel command line, using the sink names: `ttyS0`, `hvc0`, `hvc1`, `net`
+//! and `semihosting`.
+//!
+//! ## Example
+//! ```rust
+//! sink::add(&HVC[1])?;
+//! sink::remove("ttyS0");
+//! ```
+//! ---------------------------------------------------------------------------
+
+use crate::arch::semihosting;
+use crate::cmdline;
+use crate::net::netlog::NETLOG;
+use crate::peripherals::uart::UART;
+use crate::peripherals::virtio::console::HVC;
+use crate::sync::spinlock::SpinLock;
+use crate::log_warn;
+
+/// Maximum number of registered sinks.
+const MAX_SINKS: usize = 8;
+
+/// A destination for log records.
+pub trait LogSink: Sync {
+    /// Returns the name used to select the sink, e.g. `ttyS0`.
+    fn name(&self) -> &'static str;
+
+    /// Writes a fragment of a log record (usually a whole line).
+    ///
+    /// Called with the record already formatted: the sink must not block
+    /// indefinitely and must not log itself. Sinks may be called from a trap
+    /// handler while their driver is busy and should drop the output rather
+    /// than wait for it.
+    fn write_str(&self, s: &str);
+}
+
+/// Errors reported by [`add`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkError {
+    /// A sink with the same name is already registered.
+    AlreadyRegistered,
+    /// All sink slots are in use.
+    Full,
+}
+
+/// The registered sinks.
+static SINKS: SpinLock<[Option<&'static dyn LogSink>; MAX_SINKS]> = SpinLock::new({
+    let mut sinks: [Option<&'static dyn LogSink>; MAX_SINKS] = [None; MAX_SINKS];
+    sinks[0] = Some(&UART);
+    sinks
+});
+
+/// Registers `sink`; subsequent records are written to it too.
+pub fn add(sink: &'static dyn LogSink) -> Result<(), SinkError> {
+    let mut sinks = SINKS.lock();
+    if sinks.iter().flatten().any(|other| other.name() == sink.name()) {
+        return Err(SinkError::AlreadyRegistered);
+    }
+    let slot = sinks.iter_mut().find(|slot| slot.is_none()).ok_or(SinkError::Full)?;
+    *slot = Some(sink);
+    Ok(())
+}
+
+/// Unregisters the sink named `name`.
+///
+/// # Returns
+/// The removed sink, or `None` if no sink has that name.
+pub fn remove(name: &str) -> Option<&'static dyn LogSink> {
+    let mut sinks = SINKS.lock();
+    let slot = sinks.iter_mut().find(|slot| slot.is_some_and(|sink| sink.name() == name))?;
+    slot.take()
+}
+
+/// Writes a formatted record to every registered sink.
+///
+/// The registry is copied out so that no lock is held while the sinks run. If
+/// the registry is being modified (a record logged from a trap handler that
+/// interrupted [`add`] or [`remove`]), the record goes to the UART only.
+pub fn write(record: &str) {
+    let sinks = match SINKS.try_lock() {
+        Some(sinks) => *sinks,
+        None => {
+            UART.write_str(record);
+            return;
+        }
+    };
+    for sink in sinks.iter().flatten() {
+        sink.write_str(record);
+    }
+}
+
+/// Returns the sink named `name` among those the kernel provides.
+fn find(name: &str) -> Option<&'static dyn LogSink> {
+    let known: [&'static dyn LogSink; 5] = [&UART, &HVC[0], &HVC[1], &NETLOG, &semihosting::CONSOLE];
+    known.into_iter().find(|sink| sink.name() == name)
+}
+
+/// Applies the `log=` command line option: the listed sinks replace the UART.
+pub fn init() {
+    let Some(list) = cmdline::get("log") else {
+        return;
+    };
+    remove(UART.name());
+    for name in list.split(',').filter(|name| !name.is_empty()) {
+        match find(name).map(add) {
+            Some(Ok(())) | Some(Err(SinkError::AlreadyRegistered)) => {}
+            Some(Err(err)) => {
+                log_warn!("log: cannot add sink '{}': {:?}", name, err);
+            }
+            None => {
+                log_warn!("log: unknown sink '{}'.", name);
+            }
+        }
+    }
+    // Never end up without a sink.
+    if SINKS.lock().iter().all(Option::is_none) {
+        let _ = add(&UART);
+    }
+}
@@ -6,53 +6,169 @@
 //! Kernel entry point and panic handler.
 //! ---------------------------------------------------------------------------
 // We are not linking the Rust standard library (needed for bare-metal systems).
-#![no_std]
+// Host builds (`make test-host`) only run the unit tests and use it.
+#![cfg_attr(target_os = "none", no_std)]
 // We are not using the standard `main` entry point (replaced by `kmain` below).
-#![no_main]
+#![cfg_attr(target_os = "none", no_main)]
+// The `#[test_case]` functions run in the test kernel (`make test`) or on the
+// host (`make test-host`), see `testing`.
+#![cfg_attr(test, feature(custom_test_frameworks))]
+#![cfg_attr(test, test_runner(crate::testing::runner))]
+#![cfg_attr(all(test, target_os = "none"), reexport_test_harness_main = "test_main")]
 
 // Core panic handler trait (used to define custom panic behavior).
+#[cfg(target_os = "none")]
 use core::panic::PanicInfo;
 
 // Declare submodules used by the kernel.
+mod arch;         // RISC-V architecture interfaces (PMP, semihosting)
+mod cmdline;      // Kernel command line
+mod console;      // System console and log destination
+mod deferred;     // Deferred interrupt work (softirqs, tasklets, work queues)
+mod fdt;          // Flattened device tree parser
+mod fs;           // File systems (devfs)
+mod gdbstub;      // GDB remote serial protocol stub
+mod ksyms;        // Kernel symbol table
 mod logger;       // Logging infrastructure
-mod peripherals;  // Memory-mapped I/O (UART, etc.)
+mod monitor;      // Interactive debug shell
+mod net;          // IPv4 network stack
+mod panic;        // Panic report and shutdown policy
+mod peripherals;  // Memory-mapped I/O (UART, VirtIO, etc.)
+mod power;        // Poweroff and reboot
+mod random;       // Entropy pool and CSPRNG
 mod registers;    // Low-level register access (CSRs, etc.)
+mod stack;        // Kernel stacks and overflow detection
+mod sync;         // Synchronization primitives
+mod syscalls;     // System call interface
+#[cfg(test)]
+mod testing;      // In-kernel test framework
+mod thread;       // Cooperative kernel threads
+mod time;         // Monotonic and wall-clock time
 mod traps;        // Trap (interrupt/exception) handling
 
 // Import CSR abstraction for the machine exception program counter (MEPC).
 use registers::mepc::MEPC;
+use net::netlog::{LOG_HOST, LOG_PORT};
+use peripherals::virtio::{self, net::VIRTIO_NET, DeviceType};
+
+/// TCP port of the HTTP status page.
+const HTTP_PORT: u16 = 80;
 
 /// Kernel entry point called by the bootloader.
 /// This is the first Rust function executed after boot. It must never return,
 /// hence the return type `-> !`.
-#[no_mangle] // Ensure the symbol name remains exactly `kmain`
-pub unsafe extern "C" fn kmain() -> ! {
+///
+/// # Arguments
+/// * `hartid` - ID of the hart running the kernel (passed by QEMU in `a0`).
+/// * `dtb` - Address of the flattened device tree (passed by QEMU in `a1`).
+///
+/// # Safety
+/// Called once, by the boot code, on the boot stack; `dtb` is passed to
+/// `fdt::init` (see `Fdt::from_addr`).
+#[unsafe(no_mangle)] // Ensure the symbol name remains exactly `kmain`
+pub unsafe extern "C" fn kmain(hartid: usize, dtb: usize) -> ! {
+    // Find the stacks, so that the trap vector survives an overflow.
+    stack::init();
+    // The boot code becomes the `main` thread.
+    thread::init();
     // Read the address at which the kernel was loaded (via MEPC CSR).
     let mepc = MEPC::read();
-    log_info!("Kernel loaded at address {:#x}.", mepc);
-    // Trigger a machine-level trap to test the trap handling system.
-    unsafe { core::arch::asm!("ecall"); }
-    // Enter an infinite loop to prevent returning from `kmain`.
-    loop {}
+    log_info!("Kernel loaded at address {:#x} on hart {}.", mepc, hartid);
+    // Parse the device tree and the command line it carries.
+    if unsafe { fdt::init(dtb) } {
+        cmdline::init();
+        logger::filter::init();
+        logger::logger::init();
+        panic::init();
+        traps::misaligned::init();
+        log_info!("Device tree at {:#x}, command line '{}'.", dtb, cmdline::as_str());
+    } else {
+        log_warn!("No device tree found at {:#x}.", dtb);
+    }
+    // Calibrate the clocks before anything measures time.
+    time::init();
+    // Protect the kernel image with PMP entries (enforced on the kernel with `pmp=lock`).
+    arch::pmp::init();
+    // Probe for a semihosting host (a log sink and a power backend).
+    arch::semihosting::init();
+    // Take the machine interrupts (through the vector table with `vectored-traps`).
+    traps::interrupts::init();
+    // Publish the trap and interrupt statistics as /proc/interrupts.
+    traps::stats::init();
+    // Start the worker threads of the deferred interrupt work.
+    deferred::init();
+    // Find the poweroff and reboot registers (also used by the panic policy).
+    power::init();
+    // Bring up the terminals, then select the log sinks and the interactive console.
+    console::init();
+    logger::sink::init();
+    logger::kmsg::init();
+    // Attach the debugger, if `gdb=<dev>` names its terminal.
+    gdbstub::init();
+    // Start the debug shell (on the console terminal by default).
+    monitor::init();
+    // Let `log` crate users write through the kernel logger.
+    #[cfg(feature = "log")]
+    logger::facade::init();
+    // Seed the random number generator before anything needs it (e.g. TCP).
+    random::init();
+    // Bring up networking if QEMU provides a virtio-net device.
+    let status_server = init_network().and_then(|()| net::httpd::StatusServer::bind(HTTP_PORT).ok());
+    // The test kernel runs its tests on the initialized kernel, then powers off.
+    #[cfg(all(test, target_os = "none"))]
+    test_main();
+    // Keep servicing the network, the debugger, the monitor and the stack checks forever instead of returning from `kmain`.
+    loop {
+        gdbstub::poll();
+        monitor::poll();
+        stack::poll();
+        net::poll();
+        deferred::poll();
+        if let Some(server) = &status_server {
+            server.poll();
+        }
+    }
+}
+
+/// Initializes the virtio-net device and the network stack, then announces the
+/// kernel to the host with a UDP log datagram.
+///
+/// # Returns
+/// `Some(())` if the network is up.
+fn init_network() -> Option<()> {
+    let Some(transport) = virtio::find_device(DeviceType::Network) else {
+        log_warn!("No virtio-net device found, networking disabled.");
+        return None;
+    };
+    if let Err(err) = VIRTIO_NET.init(transport) {
+        log_error!("virtio-net initialization failed: {:?}", err);
+        return None;
+    }
+    if let Err(err) = net::init(&VIRTIO_NET) {
+        log_error!("Network initialization failed: {:?}", err);
+        return None;
+    }
+    // Resolve the host first so the datagram is not dropped waiting for ARP.
+    let sent = net::udp::UdpSocket::bind(0).and_then(|socket| {
+        net::arp::resolve(LOG_HOST, 1_000)?;
+        socket.send_to(b"rustos: kernel up\n", LOG_HOST, LOG_PORT)
+    });
+    if let Err(err) = sent {
+        log_warn!("Could not send UDP log datagram: {:?}", err);
+    }
+    Some(())
 }
 
+/// Host builds exist for the unit tests only (see `make test-host`).
+#[cfg(all(not(test), not(target_os = "none")))]
+fn main() {}
+
 /// Panic handler function for the kernel.
 /// This is called whenever a panic occurs. Since we’re in `#![no_std]` mode,
-/// we must define it manually. It never returns (`-> !`).
+/// we must define it manually. It never returns (`-> !`): the report and the
+/// shutdown policy live in the `panic` module.
+#[cfg(target_os = "none")]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    // If the panic has location information, log it.
-    // Otherwise, log a generic panic message.
-    if let Some(location) = info.location() {
-        log_error!(
-            "Kernel panic at line {}, file {}: {}",
-            location.line(),
-            location.file(),
-            info.message()
-        );
-    } else {
-        log_error!("Kernel panic without additional information.");
-    }
-    // Enter an infinite loop to prevent exiting after panic.
-    loop {}
+    panic::handle(info)
 }
@@ -0,0 +1,191 @@
+//! ---------------------------------------------------------------------------
+//! File       : monitor.rs
+//! Module     : monitor
+//! Author     : DiTurr
+//! Description:
+//! The kernel monitor: an interactive debug shell on a terminal, by default
+//! the system console (`ttyS0` unless `console=` says otherwise). It is
+//! polled from the main loop, so commands run in the kernel's normal context
+//! and can use every subsystem. `monitor=<dev>` moves it to another terminal,
+//! `monitor=off` disables it.
+//!
+//! Lines are edited with history (see `editor`), then split on whitespace
+//! into a command name and its arguments. The built-in commands (see
+//! `commands`, `help` lists them) inspect the hardware and the kernel state;
+//! subsystems add their own with [`register`].
+//!
+//! ## Example
+//! ```rust
+//! static NETSTAT: Command = Command {
+//!     name: "netstat",
+//!     args: "",
+//!     help: "list the sockets",
+//!     run: netstat,
+//! };
+//! monitor::register(&NETSTAT)?;
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod commands;
+pub mod editor;
+
+use core::fmt::{self, Write};
+
+use crate::cmdline;
+use crate::console;
+use crate::fs::{devfs, CharDevice};
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+use crate::{log_info, log_warn};
+use editor::Editor;
+
+/// Maximum number of registered commands.
+const MAX_COMMANDS: usize = 32;
+
+/// Maximum number of arguments of a command.
+const MAX_ARGS: usize = 8;
+
+/// A monitor command.
+pub struct Command {
+    /// Name typed to run the command.
+    pub name: &'static str,
+    /// Arguments, as shown by `help`, e.g. `<addr> [len]`.
+    pub args: &'static str,
+    /// One-line description.
+    pub help: &'static str,
+    /// Runs the command with its arguments (the name excluded), writing its
+    /// output to the terminal.
+    pub run: fn(args: &[&str], out: &mut dyn Write) -> Result<(), CommandError>,
+}
+
+/// Why a command failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    /// Wrong arguments: the usage is shown.
+    Usage,
+    /// The command could not be carried out.
+    Failed(&'static str),
+}
+
+/// The registered commands.
+static COMMANDS: SpinLock<[Option<&'static Command>; MAX_COMMANDS]> = SpinLock::new([None; MAX_COMMANDS]);
+
+/// The prompt.
+const PROMPT: &str = "rustos> ";
+
+/// A running monitor: its terminal and the line being edited.
+struct Monitor {
+    terminal: Terminal,
+    editor: Editor,
+}
+
+static MONITOR: SpinLock<Option<Monitor>> = SpinLock::new(None);
+
+/// A terminal, as a formatting target.
+struct Terminal(&'static dyn CharDevice);
+
+impl Write for Terminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            match self.0.write(bytes) {
+                Ok(0) | Err(_) => return Err(fmt::Error),
+                Ok(count) => bytes = &bytes[count..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds a command to the monitor.
+///
+/// # Returns
+/// `EEXIST` if the name is taken, `ENOSPC` if the table is full.
+pub fn register(command: &'static Command) -> Result<(), Errno> {
+    let mut commands = COMMANDS.lock();
+    if commands.iter().flatten().any(|entry| entry.name == command.name) {
+        return Err(Errno::EEXIST);
+    }
+    let slot = commands.iter_mut().find(|slot| slot.is_none()).ok_or(Errno::ENOSPC)?;
+    *slot = Some(command);
+    Ok(())
+}
+
+/// Returns the registered commands.
+pub fn commands() -> impl Iterator<Item = &'static Command> {
+    let commands = *COMMANDS.lock();
+    commands.into_iter().flatten()
+}
+
+/// Finds the command named `name`.
+fn find(name: &str) -> Option<&'static Command> {
+    commands().find(|command| command.name == name)
+}
+
+/// Runs the command line `line`, writing its output to `out`.
+pub fn execute(line: &str, out: &mut dyn Write) {
+    let mut args = [""; MAX_ARGS + 1];
+    let mut count = 0;
+    for (slot, word) in args.iter_mut().zip(line.split_whitespace()) {
+        *slot = word;
+        count += 1;
+    }
+    let Some((&name, args)) = args[..count].split_first() else {
+        return;
+    };
+    let Some(command) = find(name) else {
+        let _ = writeln!(out, "{}: unknown command (try 'help')", name);
+        return;
+    };
+    let result = if args.len() > MAX_ARGS { Err(CommandError::Usage) } else { (command.run)(args, out) };
+    match result {
+        Ok(()) => {}
+        Err(CommandError::Usage) => {
+            let _ = writeln!(out, "usage: {} {}", command.name, command.args);
+        }
+        Err(CommandError::Failed(reason)) => {
+            let _ = writeln!(out, "{}: {}", command.name, reason);
+        }
+    }
+}
+
+/// Registers the built-in commands and starts the monitor on its terminal.
+/// Must run after the terminals are registered.
+pub fn init() {
+    for command in commands::BUILTIN {
+        if let Err(err) = register(command) {
+            log_warn!("monitor: cannot register '{}': {:?}", command.name, err);
+        }
+    }
+    let device = match cmdline::get("monitor") {
+        Some("off") => return,
+        Some(name) => match devfs::find(name) {
+            Some(device) => device,
+            None => {
+                log_warn!("monitor: unknown device '{}'.", name);
+                return;
+            }
+        },
+        None => console::terminal(),
+    };
+    log_info!("monitor: type 'help' for the list of commands.");
+    let monitor = Monitor { terminal: Terminal(device), editor: Editor::new(PROMPT) };
+    monitor.editor.prompt(&mut Terminal(device));
+    *MONITOR.lock() = Some(monitor);
+}
+
+/// Handles the input received on the monitor terminal. Called from the main
+/// loop.
+pub fn poll() {
+    let mut monitor = MONITOR.lock();
+    let Some(Monitor { terminal, editor }) = monitor.as_mut() else {
+        return;
+    };
+    let mut buf = [0u8; 16];
+    let count = terminal.0.read(&mut buf).unwrap_or(0);
+    for &byte in &buf[..count] {
+        if let Some(line) = editor.feed(byte, terminal) {
+            execute(line.as_str(), terminal);
+            editor.prompt(terminal);
+        }
+    }
+}
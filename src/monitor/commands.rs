@@ -0,0 +1,449 @@
+//! ---------------------------------------------------------------------------
+//! File       : commands.rs
+//! Module     : monitor::commands
+//! Author     : DiTurr
+//! Description:
+//! The built-in monitor commands.
+//!
+//! `peek`, `poke` and `hexdump` access any address, MMIO registers included,
+//! with volatile accesses of the given width: an address where nothing is
+//! mapped raises an access fault, which is fatal like any other. `poke`
+//! refuses addresses a locked PMP entry makes read-only.
+//! ---------------------------------------------------------------------------
+
+use core::fmt::Write;
+use core::ptr::addr_of;
+
+use super::{commands, Command, CommandError};
+use crate::arch::pmp::{self, Access};
+use crate::arch::semihosting;
+use crate::deferred::softirq::{self, Softirq};
+use crate::deferred::workqueue;
+use crate::fdt;
+use crate::logger::kmsg;
+use crate::peripherals::plic;
+use crate::power;
+use crate::registers::{mcause::MCAUSE, mepc::MEPC, mhartid::MHARTID, mie::MIE, mip::MIP};
+use crate::registers::{mstatus::MSTATUS, mtval::MTVAL, time::TIME};
+use crate::stack;
+use crate::thread;
+use crate::time;
+use crate::traps::{interrupts, misaligned, stats};
+use crate::traps::traps::Trap;
+
+/// The commands registered by `monitor::init`.
+pub static BUILTIN: [&Command; 13] = [
+    &HELP, &CSR, &PEEK, &POKE, &HEXDUMP, &MEMINFO, &IRQSTAT, &TRAPSTAT, &PS, &DMESG, &UPTIME, &REBOOT, &POWEROFF,
+];
+
+/// Largest `hexdump`.
+const HEXDUMP_MAX: usize = 4096;
+
+/// Reads a CSR.
+type CsrRead = fn() -> usize;
+
+/// CSRs readable with `csr`.
+const CSRS: [(&str, CsrRead); 8] = [
+    ("mstatus", MSTATUS::read),
+    ("mie",     MIE::read),
+    ("mip",     MIP::read),
+    ("mepc",    MEPC::read),
+    ("mcause",  MCAUSE::read),
+    ("mtval",   MTVAL::read),
+    ("mhartid", MHARTID::read),
+    ("time",    TIME::read),
+];
+
+// Memory layout defined by the linker script.
+unsafe extern "C" {
+    static _text_start: u8;
+    static _text_end: u8;
+    static _rodata_start: u8;
+    static _rodata_end: u8;
+    static _ksymtab_start: u8;
+    static _ksymtab_end: u8;
+    static _data_start: u8;
+    static _data_end: u8;
+    static _bss_start: u8;
+    static _bss_end: u8;
+    static _stacks_start: u8;
+    static _stacks_end: u8;
+    static _memory_start: u8;
+    static _memory_end: u8;
+}
+
+/// Parses a number, in hexadecimal with a `0x` prefix, in decimal otherwise.
+pub fn parse_number(text: &str) -> Result<usize, CommandError> {
+    let result = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    result.map_err(|_| CommandError::Usage)
+}
+
+/// Parses an access width in bytes (1, 2, 4 or 8), 8 if not given.
+fn parse_width(text: Option<&&str>) -> Result<usize, CommandError> {
+    match text.map(|text| parse_number(text)).transpose()? {
+        None => Ok(8),
+        Some(width @ (1 | 2 | 4 | 8)) => Ok(width),
+        Some(_) => Err(CommandError::Usage),
+    }
+}
+
+/// Checks that `addr` is aligned for an access of `width` bytes.
+fn check_alignment(addr: usize, width: usize) -> Result<(), CommandError> {
+    if addr.is_multiple_of(width) { Ok(()) } else { Err(CommandError::Failed("misaligned address")) }
+}
+
+/// Writes one `hexdump` line: the address, up to 16 bytes in hexadecimal,
+/// then as ASCII.
+pub fn hexdump_line(out: &mut dyn Write, addr: usize, bytes: &[u8]) {
+    let _ = write!(out, "{:016x} ", addr);
+    for index in 0..16usize {
+        if index.is_multiple_of(8) {
+            let _ = out.write_char(' ');
+        }
+        let _ = match bytes.get(index) {
+            Some(byte) => write!(out, "{:02x} ", byte),
+            None => out.write_str("   "),
+        };
+    }
+    let _ = out.write_str(" |");
+    for &byte in bytes {
+        let _ = out.write_char(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+    }
+    let _ = out.write_str("|\n");
+}
+
+static HELP: Command = Command { name: "help", args: "", help: "list the commands", run: help };
+
+fn help(_: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    for command in commands() {
+        let pad = 28usize.saturating_sub(command.name.len() + 1 + command.args.len());
+        let _ = writeln!(out, "  {} {}{:pad$} {}", command.name, command.args, "", command.help);
+    }
+    Ok(())
+}
+
+static CSR: Command = Command { name: "csr", args: "[name]", help: "read a CSR (all of them without a name)", run: csr };
+
+fn csr(args: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    match args {
+        [] => {
+            for (name, read) in CSRS {
+                let _ = writeln!(out, "{:<8} 0x{:016x}", name, read());
+            }
+        }
+        [name] => {
+            let (_, read) = CSRS.iter().find(|(csr, _)| csr == name).ok_or(CommandError::Failed("unknown CSR"))?;
+            let _ = writeln!(out, "0x{:016x}", read());
+        }
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+static PEEK: Command = Command { name: "peek", args: "<addr> [1|2|4|8]", help: "read memory", run: peek };
+
+fn peek(args: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let [addr, rest @ ..] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (addr, width) = (parse_number(addr)?, parse_width(rest.first())?);
+    if rest.len() > 1 {
+        return Err(CommandError::Usage);
+    }
+    check_alignment(addr, width)?;
+    // SAFETY: Explicitly requested; see the module documentation.
+    let value = unsafe {
+        match width {
+            1 => core::ptr::read_volatile(addr as *const u8) as usize,
+            2 => core::ptr::read_volatile(addr as *const u16) as usize,
+            4 => core::ptr::read_volatile(addr as *const u32) as usize,
+            _ => core::ptr::read_volatile(addr as *const u64) as usize,
+        }
+    };
+    let _ = writeln!(out, "0x{:0width$x}", value, width = 2 * width);
+    Ok(())
+}
+
+static POKE: Command = Command { name: "poke", args: "<addr> <value> [1|2|4|8]", help: "write memory", run: poke };
+
+fn poke(args: &[&str], _: &mut dyn Write) -> Result<(), CommandError> {
+    let [addr, value, rest @ ..] = args else {
+        return Err(CommandError::Usage);
+    };
+    let (addr, value, width) = (parse_number(addr)?, parse_number(value)?, parse_width(rest.first())?);
+    if rest.len() > 1 || (width < 8 && value >> (8 * width) != 0) {
+        return Err(CommandError::Usage);
+    }
+    check_alignment(addr, width)?;
+    if !pmp::machine_permits(addr, width, Access::Write) {
+        return Err(CommandError::Failed("write-protected by PMP"));
+    }
+    // SAFETY: Explicitly requested; see the module documentation.
+    unsafe {
+        match width {
+            1 => core::ptr::write_volatile(addr as *mut u8, value as u8),
+            2 => core::ptr::write_volatile(addr as *mut u16, value as u16),
+            4 => core::ptr::write_volatile(addr as *mut u32, value as u32),
+            _ => core::ptr::write_volatile(addr as *mut u64, value as u64),
+        }
+    }
+    Ok(())
+}
+
+static HEXDUMP: Command = Command { name: "hexdump", args: "<addr> [len]", help: "dump memory (64 bytes by default)", run: hexdump };
+
+fn hexdump(args: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let (addr, len) = match args {
+        [addr] => (parse_number(addr)?, 64),
+        [addr, len] => (parse_number(addr)?, parse_number(len)?),
+        _ => return Err(CommandError::Usage),
+    };
+    if len > HEXDUMP_MAX {
+        return Err(CommandError::Failed("at most 4096 bytes"));
+    }
+    let mut line = [0u8; 16];
+    for start in (addr..addr.saturating_add(len)).step_by(16) {
+        let count = (addr + len - start).min(16);
+        for (index, byte) in line[..count].iter_mut().enumerate() {
+            // SAFETY: Explicitly requested; see the module documentation.
+            *byte = unsafe { core::ptr::read_volatile((start + index) as *const u8) };
+        }
+        hexdump_line(out, start, &line[..count]);
+    }
+    Ok(())
+}
+
+static MEMINFO: Command = Command { name: "meminfo", args: "", help: "show the memory layout", run: meminfo };
+
+fn meminfo(_: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let regions = [
+        ("text", addr_of!(_text_start), addr_of!(_text_end)),
+        ("rodata", addr_of!(_rodata_start), addr_of!(_rodata_end)),
+        ("ksymtab", addr_of!(_ksymtab_start), addr_of!(_ksymtab_end)),
+        ("data", addr_of!(_data_start), addr_of!(_data_end)),
+        ("bss", addr_of!(_bss_start), addr_of!(_bss_end)),
+        ("stacks", addr_of!(_stacks_start), addr_of!(_stacks_end)),
+        ("free", addr_of!(_stacks_end), addr_of!(_memory_end)),
+        ("ram", addr_of!(_memory_start), addr_of!(_memory_end)),
+    ];
+    for (name, start, end) in regions {
+        let (start, end) = (start as usize, end as usize);
+        let _ = writeln!(out, "{:<8} 0x{:08x}-0x{:08x} {:>8} KiB", name, start, end, (end - start) / 1024);
+    }
+    Ok(())
+}
+
+static IRQSTAT: Command = Command {
+    name: "irqstat",
+    args: "",
+    help: "show the interrupts, their handler times and nesting",
+    run: irqstat,
+};
+
+fn irqstat(_: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let hart = MHARTID::read();
+    let (enabled, pending) = (MIE::read(), MIP::read());
+    let _ = writeln!(
+        out,
+        "{:<30} {:>7} {:>7} {:>10} {:>10} {:>10} {:>10}",
+        "interrupt", "enabled", "pending", "count", "mean cyc", "max cyc", "max lat ns"
+    );
+    for trap in Trap::ALL.into_iter().filter(|&trap| (trap as isize) < 0) {
+        let bit = 1 << (trap as usize & 0x3f);
+        let (enabled, pending) = (enabled & bit != 0, pending & bit != 0);
+        let stats = stats::trap(hart, trap);
+        let _ = writeln!(
+            out,
+            "{:<30} {:>7} {:>7} {:>10} {:>10} {:>10} {:>10}",
+            trap.name(), enabled, pending, stats.count, stats.mean_cycles(), stats.max_cycles, stats.max_latency_ns
+        );
+    }
+    // Device lines, and how the handlers nest.
+    let _ = writeln!(
+        out,
+        "\n{:>4} {:<12} {:>8} {:<26} {:>10} {:>10} {:>10}",
+        "line", "handler", "priority", "nesting", "count", "mean cyc", "max lat ns"
+    );
+    for (irq, handler) in plic::handlers() {
+        let nesting = match (handler.is_preemptible(), handler.is_reentrant()) {
+            (false, _) => "never",
+            (true, true) => "preemptible",
+            (true, false) => "preemptible, non-reentrant",
+        };
+        let stats = stats::line(hart, irq);
+        let _ = writeln!(
+            out,
+            "{:>4} {:<12} {:>8} {:<26} {:>10} {:>10} {:>10}",
+            irq, handler.name, handler.priority, nesting, stats.count, stats.mean_cycles(), stats.max_latency_ns
+        );
+    }
+    let _ = writeln!(out, "spurious: {}", stats::spurious(hart));
+    if let Some(nesting) = interrupts::nesting(hart) {
+        let _ = writeln!(
+            out,
+            "\nhart{}: depth {}, max {} (limit {}), {} preemptions, {} at the limit",
+            hart, nesting.depth, nesting.max_depth, interrupts::nesting_limit(), nesting.preemptions, nesting.capped
+        );
+    }
+    // Work deferred by the handlers.
+    let _ = writeln!(out, "\n{:<12} {:>10}", "softirq", "runs");
+    for vector in Softirq::ALL {
+        let _ = writeln!(out, "{:<12} {:>10}", vector.name(), softirq::count(hart, vector));
+    }
+    Ok(())
+}
+
+static TRAPSTAT: Command = Command {
+    name: "trapstat",
+    args: "",
+    help: "count the exceptions taken and time their handlers",
+    run: trapstat,
+};
+
+fn trapstat(_: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let hart = MHARTID::read();
+    let _ = writeln!(out, "{:<30} {:>10} {:>10} {:>10}", "exception", "count", "mean cyc", "max cyc");
+    for trap in Trap::ALL.into_iter().filter(|&trap| (trap as isize) >= 0) {
+        let stats = stats::trap(hart, trap);
+        let _ = writeln!(out, "{:<30} {:>10} {:>10} {:>10}", trap.name(), stats.count, stats.mean_cycles(), stats.max_cycles);
+    }
+    let (loads, stores) = misaligned::emulated();
+    let _ = writeln!(out, "\nmisaligned ({}): {} loads, {} stores emulated", misaligned::policy(), loads, stores);
+    Ok(())
+}
+
+static PS: Command = Command { name: "ps", args: "", help: "list the harts, the threads and the stack usage", run: ps };
+
+fn ps(_: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let cpus = fdt::get().and_then(|fdt| fdt.find_node("/cpus")).ok_or(CommandError::Failed("no device tree"))?;
+    let current = MHARTID::read();
+    let _ = writeln!(out, "{:>4}  {:<10} state", "hart", "isa");
+    for cpu in cpus.children().filter(|cpu| cpu.property_str("device_type") == Some("cpu")) {
+        let hart = cpu.property_u32("reg").unwrap_or(0) as usize;
+        let state = match cpu.property_str("status") {
+            Some("disabled") => "disabled",
+            _ if hart == current => "running (monitor)",
+            _ => "parked",
+        };
+        let isa = cpu.property_str("riscv,isa").unwrap_or("?");
+        let _ = writeln!(out, "{:>4}  {:<10} {}", hart, isa, state);
+    }
+    // Kernel threads, switched where they yield.
+    let _ = writeln!(out, "\n{:<12} {:<12} {:<9} {:>8}", "thread", "stack", "state", "switches");
+    for thread in thread::threads() {
+        let _ = writeln!(out, "{:<12} {:<12} {:<9} {:>8}", thread.name, thread.stack, thread.state.name(), thread.switches);
+    }
+    let _ = writeln!(out, "system work queue: {} items run", workqueue::SYSTEM.done());
+    // High-water marks of the stacks.
+    let _ = writeln!(out, "\n{:<12} {:>8} {:>8} {:>4}", "stack", "used", "size", "max");
+    for stack in stack::stacks() {
+        if let Some(usage) = stack::stack_usage(stack.name) {
+            let _ = writeln!(out, "{:<12} {:>8} {:>8} {:>3}%", stack.name, usage.used, usage.size, usage.percent());
+        }
+    }
+    Ok(())
+}
+
+static DMESG: Command = Command { name: "dmesg", args: "", help: "show the kernel message buffer", run: dmesg };
+
+fn dmesg(_: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    kmsg::dump(|line| {
+        let _ = out.write_str(line);
+    });
+    Ok(())
+}
+
+static UPTIME: Command = Command { name: "uptime", args: "", help: "show the time since boot", run: uptime };
+
+fn uptime(_: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let uptime = time::uptime();
+    let _ = writeln!(out, "{}.{:06} s", uptime.as_secs(), uptime.subsec_micros());
+    if let Some(host) = semihosting::clock() {
+        let _ = writeln!(out, "{}.{:02} s on the host clock (semihosting)", host.as_secs(), host.subsec_millis() / 10);
+    }
+    Ok(())
+}
+
+static REBOOT: Command = Command { name: "reboot", args: "", help: "restart the machine", run: reboot };
+
+fn reboot(_: &[&str], _: &mut dyn Write) -> Result<(), CommandError> {
+    power::reboot()
+}
+
+static POWEROFF: Command = Command { name: "poweroff", args: "", help: "power the machine off", run: poweroff };
+
+fn poweroff(_: &[&str], _: &mut dyn Write) -> Result<(), CommandError> {
+    power::shutdown()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects the output of a command.
+    struct Output {
+        text: [u8; 256],
+        len: usize,
+    }
+
+    impl Write for Output {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            self.text[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+            self.len += s.len();
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn new() -> Self {
+            Output { text: [0; 256], len: 0 }
+        }
+
+        fn as_str(&self) -> &str {
+            core::str::from_utf8(&self.text[..self.len]).unwrap()
+        }
+    }
+
+    #[test_case]
+    fn numbers() {
+        assert_eq!(parse_number("0x80000000"), Ok(0x8000_0000));
+        assert_eq!(parse_number("0X1f"), Ok(0x1f));
+        assert_eq!(parse_number("42"), Ok(42));
+        assert_eq!(parse_number("0x"), Err(CommandError::Usage));
+        assert_eq!(parse_number("-1"), Err(CommandError::Usage));
+    }
+
+    #[test_case]
+    fn hexdump_lines() {
+        let mut out = Output::new();
+        hexdump_line(&mut out, 0x8000_0010, b"rustos\0\x01\x7f 012345");
+        assert_eq!(
+            out.as_str(),
+            "0000000080000010  72 75 73 74 6f 73 00 01  7f 20 30 31 32 33 34 35  |rustos... 012345|\n",
+        );
+        let mut out = Output::new();
+        hexdump_line(&mut out, 0x20, b"ab");
+        assert_eq!(out.as_str(), "0000000000000020  61 62                                             |ab|
+");
+    }
+
+    #[test_case]
+    fn memory_access() {
+        let mut words = [0u64; 2];
+        let mut addr = Output::new();
+        let _ = write!(addr, "{:#x}", words.as_mut_ptr() as usize);
+        let mut odd = Output::new();
+        let _ = write!(odd, "{:#x}", words.as_mut_ptr() as usize + 1);
+        let mut out = Output::new();
+        assert_eq!(poke(&[addr.as_str(), "0x1234", "2"], &mut out), Ok(()));
+        assert_eq!(poke(&[odd.as_str(), "1", "2"], &mut out), Err(CommandError::Failed("misaligned address")));
+        assert_eq!(poke(&[addr.as_str(), "0x10000", "2"], &mut out), Err(CommandError::Usage));
+        assert_eq!(poke(&[addr.as_str(), "1", "3"], &mut out), Err(CommandError::Usage));
+        assert_eq!(words[0], 0x1234);
+        assert_eq!(peek(&[addr.as_str(), "2"], &mut out), Ok(()));
+        assert_eq!(out.as_str(), "0x1234\n");
+    }
+}
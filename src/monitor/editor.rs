@@ -0,0 +1,305 @@
+//! ---------------------------------------------------------------------------
+//! File       : editor.rs
+//! Module     : monitor::editor
+//! Author     : DiTurr
+//! Description:
+//! The line editor of the monitor: it is fed the bytes received from the
+//! terminal one at a time, echoes them and returns the line once Enter is
+//! pressed. It understands the usual VT100/xterm keys:
+//! - Left/Right, Home/End (or Ctrl-A/Ctrl-E) and Delete move and edit within
+//!   the line; Backspace deletes before the cursor;
+//! - Up/Down browse the history of the last `HISTORY_LEN` lines;
+//! - Ctrl-U clears the line, Ctrl-C abandons it.
+//!
+//! ## Example
+//! ```rust
+//! let mut editor = Editor::new("rustos> ");
+//! if let Some(line) = editor.feed(byte, &mut out) {
+//!     run(line.as_str());
+//!     editor.prompt(&mut out);
+//! }
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::fmt::Write;
+
+/// Maximum length of a line.
+pub const LINE_LEN: usize = 128;
+
+/// Number of lines kept in the history.
+pub const HISTORY_LEN: usize = 16;
+
+// Control characters.
+const CTRL_A: u8    = 0x01;
+const CTRL_C: u8    = 0x03;
+const CTRL_E: u8    = 0x05;
+const BACKSPACE: u8 = 0x08;
+const CTRL_U: u8    = 0x15;
+const ESC: u8       = 0x1b;
+const DEL: u8       = 0x7f;
+
+/// A line of text.
+#[derive(Clone, Copy)]
+pub struct Line {
+    bytes: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl Line {
+    const EMPTY: Line = Line { bytes: [0; LINE_LEN], len: 0 };
+
+    /// Returns the text of the line (only printable ASCII is ever inserted).
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+/// Progress through an escape sequence.
+#[derive(Clone, Copy)]
+enum Escape {
+    None,
+    /// Got `ESC`.
+    Start,
+    /// Got `ESC [` or `ESC O`, then the numeric parameter so far.
+    Sequence(u8),
+}
+
+/// A line being edited, and the history.
+pub struct Editor {
+    prompt: &'static str,
+    line: Line,
+    cursor: usize,
+    /// Previous lines, the most recent at `(count - 1) % HISTORY_LEN`.
+    history: [Line; HISTORY_LEN],
+    count: usize,
+    /// History line shown (1 = most recent), 0 when editing a new line.
+    browse: usize,
+    /// The new line, kept while browsing the history.
+    draft: Line,
+    escape: Escape,
+    /// The previous byte was `\r`: a following `\n` is part of the same Enter.
+    after_cr: bool,
+}
+
+impl Editor {
+    pub const fn new(prompt: &'static str) -> Self {
+        Editor {
+            prompt,
+            line: Line::EMPTY,
+            cursor: 0,
+            history: [Line::EMPTY; HISTORY_LEN],
+            count: 0,
+            browse: 0,
+            draft: Line::EMPTY,
+            escape: Escape::None,
+            after_cr: false,
+        }
+    }
+
+    /// Writes the prompt and the line being edited.
+    pub fn prompt(&self, out: &mut dyn Write) {
+        let _ = write!(out, "{}{}", self.prompt, self.line.as_str());
+    }
+
+    /// Handles one byte of input.
+    ///
+    /// # Returns
+    /// The line, once Enter is pressed.
+    pub fn feed(&mut self, byte: u8, out: &mut dyn Write) -> Option<Line> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match self.escape {
+            Escape::Start => {
+                self.escape = if byte == b'[' || byte == b'O' { Escape::Sequence(0) } else { Escape::None };
+                return None;
+            }
+            Escape::Sequence(param) => {
+                self.escape = match byte {
+                    b'0'..=b'9' => Escape::Sequence(param.saturating_mul(10).saturating_add(byte - b'0')),
+                    b';' => Escape::Sequence(param),
+                    _ => {
+                        self.key(byte, param, out);
+                        Escape::None
+                    }
+                };
+                return None;
+            }
+            Escape::None => {}
+        }
+        match byte {
+            b'\n' if after_cr => {}
+            b'\r' | b'\n' => return Some(self.submit(out)),
+            ESC => self.escape = Escape::Start,
+            CTRL_A => self.move_to(0, out),
+            CTRL_E => self.move_to(self.line.len, out),
+            CTRL_C => {
+                let _ = out.write_str("^C\n");
+                self.reset();
+                self.prompt(out);
+            }
+            CTRL_U => {
+                self.line.len = 0;
+                self.cursor = 0;
+                self.redraw(out);
+            }
+            BACKSPACE | DEL if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.delete();
+                self.redraw(out);
+            }
+            b' '..=b'~' if self.line.len < LINE_LEN => self.insert(byte, out),
+            _ => {}
+        }
+        None
+    }
+
+    /// Handles the final byte of an escape sequence.
+    fn key(&mut self, byte: u8, param: u8, out: &mut dyn Write) {
+        match (byte, param) {
+            (b'A', _) => self.browse(self.browse + 1, out),
+            (b'B', _) if self.browse > 0 => self.browse(self.browse - 1, out),
+            (b'C', _) => self.move_to((self.cursor + 1).min(self.line.len), out),
+            (b'D', _) => self.move_to(self.cursor.saturating_sub(1), out),
+            (b'H', _) | (b'~', 1 | 7) => self.move_to(0, out),
+            (b'F', _) | (b'~', 4 | 8) => self.move_to(self.line.len, out),
+            (b'~', 3) if self.cursor < self.line.len => {
+                self.delete();
+                self.redraw(out);
+            }
+            _ => {}
+        }
+    }
+
+    fn insert(&mut self, byte: u8, out: &mut dyn Write) {
+        self.line.bytes.copy_within(self.cursor..self.line.len, self.cursor + 1);
+        self.line.bytes[self.cursor] = byte;
+        self.line.len += 1;
+        self.cursor += 1;
+        if self.cursor == self.line.len {
+            let _ = out.write_char(byte as char);
+        } else {
+            self.redraw(out);
+        }
+    }
+
+    /// Deletes the character under the cursor.
+    fn delete(&mut self) {
+        self.line.bytes.copy_within(self.cursor + 1..self.line.len, self.cursor);
+        self.line.len -= 1;
+    }
+
+    fn move_to(&mut self, cursor: usize, out: &mut dyn Write) {
+        self.cursor = cursor;
+        self.redraw(out);
+    }
+
+    /// Shows history line `index` (1 = most recent; 0 = the new line).
+    fn browse(&mut self, index: usize, out: &mut dyn Write) {
+        if index > self.count.min(HISTORY_LEN) {
+            return;
+        }
+        if self.browse == 0 {
+            self.draft = self.line;
+        }
+        self.browse = index;
+        self.line = match index {
+            0 => self.draft,
+            _ => self.history[(self.count - index) % HISTORY_LEN],
+        };
+        self.cursor = self.line.len;
+        self.redraw(out);
+    }
+
+    /// Redraws the line and places the cursor.
+    fn redraw(&self, out: &mut dyn Write) {
+        let _ = write!(out, "\r{}{}\x1b[K", self.prompt, self.line.as_str());
+        let back = self.line.len - self.cursor;
+        if back > 0 {
+            let _ = write!(out, "\x1b[{}D", back);
+        }
+    }
+
+    /// Ends the line: records it in the history and starts a new one.
+    fn submit(&mut self, out: &mut dyn Write) -> Line {
+        let _ = out.write_char('\n');
+        let line = self.line;
+        let last = &self.history[self.count.wrapping_sub(1) % HISTORY_LEN];
+        let repeated = self.count > 0 && last.as_str() == line.as_str();
+        if !line.as_str().trim().is_empty() && !repeated {
+            self.history[self.count % HISTORY_LEN] = line;
+            self.count += 1;
+        }
+        self.reset();
+        line
+    }
+
+    fn reset(&mut self) {
+        self.line.len = 0;
+        self.cursor = 0;
+        self.browse = 0;
+        self.escape = Escape::None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Terminal output, discarded.
+    struct Sink;
+
+    impl Write for Sink {
+        fn write_str(&mut self, _: &str) -> core::fmt::Result {
+            Ok(())
+        }
+    }
+
+    /// Feeds `input`, returning the last line completed.
+    fn type_in(editor: &mut Editor, input: &[u8]) -> Option<Line> {
+        input.iter().fold(None, |line, &byte| editor.feed(byte, &mut Sink).or(line))
+    }
+
+    #[test_case]
+    fn lines_end_with_enter() {
+        let mut editor = Editor::new("> ");
+        assert!(type_in(&mut editor, b"help").is_none());
+        assert_eq!(type_in(&mut editor, b"\r\n").unwrap().as_str(), "help");
+        // The `\n` of `\r\n` does not submit an empty line.
+        assert_eq!(type_in(&mut editor, b"x\n").unwrap().as_str(), "x");
+    }
+
+    #[test_case]
+    fn editing_keys() {
+        let mut editor = Editor::new("> ");
+        // Backspace, then insertion in the middle.
+        assert_eq!(type_in(&mut editor, b"pek\x7fk\x1b[D\x1b[De\r").unwrap().as_str(), "peek");
+        // Home, Delete, End.
+        assert_eq!(type_in(&mut editor, b"xcsr\x1b[H\x1b[3~\x1b[F mie\r").unwrap().as_str(), "csr mie");
+        // Ctrl-U clears the line; Ctrl-C abandons it.
+        assert_eq!(type_in(&mut editor, b"junk\x15ps\r").unwrap().as_str(), "ps");
+        assert_eq!(type_in(&mut editor, b"junk\x03ps\r").unwrap().as_str(), "ps");
+    }
+
+    #[test_case]
+    fn lines_are_bounded() {
+        let mut editor = Editor::new("> ");
+        let input = [b'a'; LINE_LEN + 10];
+        type_in(&mut editor, &input);
+        assert_eq!(type_in(&mut editor, b"\r").unwrap().as_str().len(), LINE_LEN);
+    }
+
+    #[test_case]
+    fn history() {
+        let mut editor = Editor::new("> ");
+        type_in(&mut editor, b"one\rtwo\rtwo\r\r");
+        // Repeated and empty lines are not recorded.
+        assert_eq!(type_in(&mut editor, b"\x1b[A\r").unwrap().as_str(), "two");
+        assert_eq!(type_in(&mut editor, b"\x1b[A\x1b[A\r").unwrap().as_str(), "one");
+        // Going past the oldest line stays on it; Down returns to the draft.
+        assert_eq!(type_in(&mut editor, b"two\x1b[A\x1b[A\x1b[A\x1b[A\r").unwrap().as_str(), "one");
+        assert_eq!(type_in(&mut editor, b"new\x1b[A\x1b[B\r").unwrap().as_str(), "new");
+        for n in 0..HISTORY_LEN + 4 {
+            type_in(&mut editor, if n.is_multiple_of(2) { b"a\r" } else { b"b\r" });
+        }
+        assert_eq!(type_in(&mut editor, b"\x1b[A\r").unwrap().as_str(), "b");
+    }
+}
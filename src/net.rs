@@ -0,0 +1,148 @@
+//! ---------------------------------------------------------------------------
+//! File       : net.rs
+//! Module     : net
+//! Author     : DiTurr
+//! Description:
+//! Minimal IPv4 network stack: Ethernet, ARP, IPv4, ICMP echo, UDP, TCP, a DHCP
+//! client and a BSD-like socket layer. The stack is polled, and uses only
+//! static memory; a device interrupt (the `NetRx` softirq) also has the
+//! received frames processed at once, by the system work queue.
+//!
+//! ## Example
+//! ```rust
+//! let config = net::init(&VIRTIO_NET)?;
+//! let socket = UdpSocket::bind(0)?;
+//! socket.send_to(b"hello\n", Ipv4Addr::new(10, 0, 2, 2), 6666)?;
+//! loop { net::poll(); }
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod arp;
+pub mod checksum;
+pub mod dhcp;
+pub mod ethernet;
+pub mod httpd;
+pub mod icmp;
+pub mod interface;
+pub mod ipv4;
+pub mod netlog;
+pub mod socket;
+pub mod tcp;
+pub mod udp;
+
+use crate::deferred::softirq::{self, Softirq};
+use crate::deferred::workqueue::{self, Work};
+use crate::random;
+use crate::time::instant::Instant;
+use crate::{log_info, log_warn};
+use interface::{Config, NetDevice};
+
+/// Time allowed for DHCP to configure the interface at boot, in milliseconds.
+const DHCP_TIMEOUT_MS: u64 = 2_000;
+
+/// Processes the received frames, in thread context.
+static RX_WORK: Work = Work::new("net-rx", receive);
+
+/// Errors reported by the network stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetError {
+    /// No network device is attached.
+    NoDevice,
+    /// The interface has no IPv4 address yet.
+    NotConfigured,
+    /// The next hop's MAC address is unknown; an ARP request was sent.
+    ArpPending,
+    /// The device refused the frame (queue full or link down).
+    TxFailed,
+    /// The payload does not fit in a single frame.
+    TooLarge,
+    /// The requested port is already bound.
+    AddrInUse,
+    /// All socket slots are in use.
+    NoSockets,
+    /// The operation did not complete in time.
+    Timeout,
+    /// The operation cannot complete yet; retry after polling the network.
+    WouldBlock,
+    /// The socket is not in a state allowing the operation.
+    InvalidState,
+    /// The socket is not connected.
+    NotConnected,
+    /// The peer refused the connection.
+    ConnectionRefused,
+    /// The peer reset the connection.
+    ConnectionReset,
+}
+
+/// Attaches `device` to the stack and configures its address.
+///
+/// DHCP is tried first; if no server answers within [`DHCP_TIMEOUT_MS`], the
+/// static QEMU user-mode networking defaults are used instead.
+///
+/// # Returns
+/// The configuration applied to the interface.
+pub fn init(device: &'static dyn NetDevice) -> Result<Config, NetError> {
+    interface::attach(device);
+    if let Err(err) = softirq::open(Softirq::NetRx, rx_action) {
+        log_warn!("net: cannot open the receive softirq: {:?}", err);
+    }
+    // The MAC address is not secret but differs between machines.
+    random::add_device_randomness(&device.mac().0);
+    log_info!("net: attached device with MAC {}.", device.mac());
+    let config = match dhcp::request(DHCP_TIMEOUT_MS) {
+        Ok(config) => config,
+        Err(err) => {
+            log_warn!("net: DHCP failed ({:?}), using static configuration.", err);
+            Config::QEMU_USER
+        }
+    };
+    interface::configure(config);
+    log_info!(
+        "net: address {} netmask {} gateway {}.",
+        config.address, config.netmask, config.gateway
+    );
+    Ok(config)
+}
+
+/// Processes received frames and runs the protocol timers.
+///
+/// Must be called regularly: the stack is driven entirely by polling.
+pub fn poll() {
+    interface::poll();
+    tcp::poll();
+    netlog::flush();
+}
+
+/// The `NetRx` softirq: frames are processed in thread context, where the
+/// stack may take its locks.
+fn rx_action() {
+    workqueue::SYSTEM.queue(&RX_WORK);
+}
+
+fn receive() {
+    interface::poll();
+}
+
+/// Returns the number of milliseconds elapsed since `start`.
+pub(crate) fn elapsed_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+/// Reads a big-endian `u16` at `offset`.
+pub(crate) fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// Reads a big-endian `u32` at `offset`.
+pub(crate) fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+/// Writes `value` as a big-endian `u16` at `offset`.
+pub(crate) fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Writes `value` as a big-endian `u32` at `offset`.
+pub(crate) fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
@@ -0,0 +1,157 @@
+//! ---------------------------------------------------------------------------
+//! File       : arp.rs
+//! Module     : net::arp
+//! Author     : DiTurr
+//! Description:
+//! Address Resolution Protocol (RFC 826) for IPv4 over Ethernet: a small cache
+//! of IPv4 to MAC mappings, answers to requests for our address, and outgoing
+//! requests for unknown neighbours.
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::ethernet::{MacAddr, ETHERTYPE_ARP, ETHERTYPE_IPV4};
+use super::{elapsed_ms, interface, read_u16, write_u16, NetError};
+use crate::time::instant::Instant;
+use crate::sync::spinlock::SpinLock;
+
+/// Length of an ARP packet for IPv4 over Ethernet.
+const PACKET_LEN: usize = 28;
+
+/// Hardware type: Ethernet.
+const HTYPE_ETHERNET: u16 = 1;
+
+/// Operation: request.
+const OP_REQUEST: u16 = 1;
+/// Operation: reply.
+const OP_REPLY: u16 = 2;
+
+/// Number of entries in the ARP cache.
+const CACHE_SIZE: usize = 16;
+
+/// A cached IPv4 to MAC mapping.
+#[derive(Clone, Copy)]
+struct Entry {
+    ip: Ipv4Addr,
+    mac: MacAddr,
+    /// Value of the cache clock at the last update, used for LRU eviction.
+    stamp: u32,
+}
+
+/// The ARP cache.
+struct Cache {
+    entries: [Option<Entry>; CACHE_SIZE],
+    clock: u32,
+}
+
+static CACHE: SpinLock<Cache> = SpinLock::new(Cache { entries: [None; CACHE_SIZE], clock: 0 });
+
+impl Cache {
+    fn lookup(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.entries.iter().flatten().find(|entry| entry.ip == ip).map(|entry| entry.mac)
+    }
+
+    /// Updates the mapping for `ip` if present.
+    ///
+    /// # Returns
+    /// `true` if an entry was updated.
+    fn update(&mut self, ip: Ipv4Addr, mac: MacAddr) -> bool {
+        self.clock = self.clock.wrapping_add(1);
+        let clock = self.clock;
+        match self.entries.iter_mut().flatten().find(|entry| entry.ip == ip) {
+            Some(entry) => {
+                entry.mac = mac;
+                entry.stamp = clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts or updates a mapping, evicting the least recently updated entry if full.
+    fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+        if self.update(ip, mac) {
+            return;
+        }
+        let clock = self.clock;
+        let slot = match self.entries.iter().position(Option::is_none) {
+            Some(free) => free,
+            None => (0..CACHE_SIZE)
+                .max_by_key(|&i| self.entries[i].map_or(0, |e| clock.wrapping_sub(e.stamp)))
+                .unwrap_or(0),
+        };
+        self.entries[slot] = Some(Entry { ip, mac, stamp: clock });
+    }
+}
+
+/// Returns the cached MAC address of `ip`.
+pub fn lookup(ip: Ipv4Addr) -> Option<MacAddr> {
+    CACHE.lock().lookup(ip)
+}
+
+/// Broadcasts a request for the MAC address of `ip`.
+pub fn request(ip: Ipv4Addr) -> Result<(), NetError> {
+    send(OP_REQUEST, MacAddr::BROADCAST, MacAddr::ZERO, ip)
+}
+
+/// Resolves `ip`, polling the interface until a reply arrives or `timeout_ms` expires.
+pub fn resolve(ip: Ipv4Addr, timeout_ms: u64) -> Result<MacAddr, NetError> {
+    if let Some(mac) = lookup(ip) {
+        return Ok(mac);
+    }
+    request(ip)?;
+    let start = Instant::now();
+    while elapsed_ms(start) < timeout_ms {
+        interface::poll();
+        if let Some(mac) = lookup(ip) {
+            return Ok(mac);
+        }
+    }
+    Err(NetError::Timeout)
+}
+
+/// Builds and sends an ARP packet.
+fn send(op: u16, eth_dst: MacAddr, target_mac: MacAddr, target_ip: Ipv4Addr) -> Result<(), NetError> {
+    let our_mac = interface::mac().ok_or(NetError::NoDevice)?;
+    let our_ip = interface::address();
+    interface::send_frame(eth_dst, ETHERTYPE_ARP, |p| {
+        write_u16(p, 0, HTYPE_ETHERNET);
+        write_u16(p, 2, ETHERTYPE_IPV4);
+        p[4] = 6;
+        p[5] = 4;
+        write_u16(p, 6, op);
+        p[8..14].copy_from_slice(&our_mac.0);
+        p[14..18].copy_from_slice(&our_ip.octets());
+        p[18..24].copy_from_slice(&target_mac.0);
+        p[24..28].copy_from_slice(&target_ip.octets());
+        PACKET_LEN
+    })
+}
+
+/// Handles a received ARP packet.
+pub fn handle(packet: &[u8]) {
+    if packet.len() < PACKET_LEN
+        || read_u16(packet, 0) != HTYPE_ETHERNET
+        || read_u16(packet, 2) != ETHERTYPE_IPV4
+        || packet[4] != 6
+        || packet[5] != 4
+    {
+        return;
+    }
+    let op = read_u16(packet, 6);
+    let sender_mac = MacAddr(packet[8..14].try_into().unwrap());
+    let sender_ip = Ipv4Addr::from(<[u8; 4]>::try_from(&packet[14..18]).unwrap());
+    let target_ip = Ipv4Addr::from(<[u8; 4]>::try_from(&packet[24..28]).unwrap());
+    let our_ip = interface::address();
+    // RFC 826 merge: refresh known senders, learn new ones that talk to us.
+    let for_us = !our_ip.is_unspecified() && target_ip == our_ip;
+    {
+        let mut cache = CACHE.lock();
+        if !cache.update(sender_ip, sender_mac) && for_us {
+            cache.insert(sender_ip, sender_mac);
+        }
+    }
+    if for_us && op == OP_REQUEST {
+        let _ = send(OP_REPLY, sender_mac, sender_mac, sender_ip);
+    }
+}
@@ -0,0 +1,71 @@
+//! ---------------------------------------------------------------------------
+//! File       : checksum.rs
+//! Module     : net::checksum
+//! Author     : DiTurr
+//! Description:
+//! Internet checksum (RFC 1071) used by IPv4, ICMP, UDP and TCP.
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+/// Adds `data` to a running one's complement sum.
+pub fn accumulate(mut sum: u32, data: &[u8]) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u32;
+    }
+    // An odd trailing byte is padded with zero on the right.
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    sum
+}
+
+/// Folds a running sum into the final 16-bit checksum.
+pub fn finish(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Computes the checksum of `data`.
+pub fn checksum(data: &[u8]) -> u16 {
+    finish(accumulate(0, data))
+}
+
+/// Starts a running sum with the IPv4 pseudo-header used by UDP and TCP.
+pub fn pseudo_header(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, length: usize) -> u32 {
+    let mut sum = accumulate(0, &src.octets());
+    sum = accumulate(sum, &dst.octets());
+    sum + protocol as u32 + length as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::XorShift;
+
+    #[test_case]
+    fn rfc1071_example() {
+        // RFC 1071, section 3: the sum of these bytes is 0xddf2.
+        assert_eq!(checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]), !0xddf2);
+    }
+
+    #[test_case]
+    fn odd_length_is_zero_padded() {
+        assert_eq!(checksum(&[0x12, 0x34, 0x56]), checksum(&[0x12, 0x34, 0x56, 0x00]));
+    }
+
+    #[test_case]
+    fn data_with_its_checksum_verifies() {
+        let mut rng = XorShift::new(1071);
+        for len in (2..256).step_by(2) {
+            let mut data = [0u8; 256];
+            rng.fill(&mut data[2..len]);
+            let sum = checksum(&data[..len]);
+            data[..2].copy_from_slice(&sum.to_be_bytes());
+            assert_eq!(checksum(&data[..len]), 0, "length {}", len);
+        }
+    }
+}
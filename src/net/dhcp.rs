@@ -0,0 +1,184 @@
+//! ---------------------------------------------------------------------------
+//! File       : dhcp.rs
+//! Module     : net::dhcp
+//! Author     : DiTurr
+//! Description:
+//! A minimal DHCP client (RFC 2131): DISCOVER, OFFER, REQUEST, ACK. The lease is
+//! acquired once at boot and never renewed, which is sufficient for QEMU
+//! user-mode networking.
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::interface::{self, Config};
+use super::udp::{self, UdpSocket};
+use super::{elapsed_ms, read_u32, write_u16, write_u32, NetError};
+use crate::random;
+use crate::time::instant::Instant;
+
+/// UDP port of DHCP servers.
+const SERVER_PORT: u16 = 67;
+/// UDP port of DHCP clients.
+const CLIENT_PORT: u16 = 68;
+
+/// Offset of the options field in a BOOTP message.
+const OPTIONS_OFFSET: usize = 240;
+/// Magic cookie at the start of the options field.
+const MAGIC_COOKIE: u32 = 0x6382_5363;
+
+/// How long to wait for each server answer before retransmitting, in milliseconds.
+const RETRANSMIT_MS: u64 = 500;
+
+// Option codes.
+const OPT_SUBNET_MASK: u8  = 1;
+const OPT_ROUTER: u8       = 3;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8    = 54;
+const OPT_PARAM_LIST: u8   = 55;
+const OPT_END: u8          = 255;
+
+// Message types.
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8    = 2;
+const DHCPREQUEST: u8  = 3;
+const DHCPACK: u8      = 5;
+const DHCPNAK: u8      = 6;
+
+/// The fields of a server reply the client cares about.
+struct Reply {
+    message_type: u8,
+    your_ip: Ipv4Addr,
+    server_id: Option<Ipv4Addr>,
+    netmask: Option<Ipv4Addr>,
+    router: Option<Ipv4Addr>,
+}
+
+/// Acquires an address lease, giving up after `timeout_ms` milliseconds.
+///
+/// # Returns
+/// The configuration offered by the server; the caller applies it.
+pub fn request(timeout_ms: u64) -> Result<Config, NetError> {
+    let mac = interface::mac().ok_or(NetError::NoDevice)?;
+    let socket = UdpSocket::bind(CLIENT_PORT)?;
+    let start = Instant::now();
+    // A random transaction ID avoids collisions with other clients and makes
+    // forged replies harder.
+    let xid = random::next_u32();
+    let offer = exchange(&socket, xid, mac.0, start, timeout_ms, DHCPDISCOVER, None, DHCPOFFER)?;
+    let server = offer.server_id.ok_or(NetError::Timeout)?;
+    let ack = exchange(&socket, xid, mac.0, start, timeout_ms, DHCPREQUEST, Some((offer.your_ip, server)), DHCPACK)?;
+    let netmask = ack.netmask.unwrap_or(Ipv4Addr::new(255, 255, 255, 0));
+    Ok(Config {
+        address: ack.your_ip,
+        netmask,
+        gateway: ack.router.unwrap_or(server),
+    })
+}
+
+/// Sends one message and waits for the expected answer, retransmitting periodically.
+#[allow(clippy::too_many_arguments)]
+fn exchange(
+    socket: &UdpSocket,
+    xid: u32,
+    mac: [u8; 6],
+    start: Instant,
+    timeout_ms: u64,
+    message_type: u8,
+    requested: Option<(Ipv4Addr, Ipv4Addr)>,
+    expected: u8,
+) -> Result<Reply, NetError> {
+    let mut buf = [0u8; udp::MAX_PAYLOAD];
+    while elapsed_ms(start) < timeout_ms {
+        send(xid, mac, message_type, requested)?;
+        let sent = Instant::now();
+        while elapsed_ms(sent) < RETRANSMIT_MS && elapsed_ms(start) < timeout_ms {
+            interface::poll();
+            let Some((len, _, _)) = socket.recv_from(&mut buf) else {
+                continue;
+            };
+            match parse(&buf[..len], xid) {
+                Some(reply) if reply.message_type == expected => return Ok(reply),
+                Some(reply) if reply.message_type == DHCPNAK => return Err(NetError::Timeout),
+                _ => {}
+            }
+        }
+    }
+    Err(NetError::Timeout)
+}
+
+/// Broadcasts a client message.
+fn send(xid: u32, mac: [u8; 6], message_type: u8, requested: Option<(Ipv4Addr, Ipv4Addr)>) -> Result<(), NetError> {
+    let mut msg = [0u8; 300];
+    msg[0] = 1; // op: BOOTREQUEST
+    msg[1] = 1; // htype: Ethernet
+    msg[2] = 6; // hlen
+    write_u32(&mut msg, 4, xid);
+    write_u16(&mut msg, 10, 0x8000); // flags: ask the server to broadcast its answer
+    msg[28..34].copy_from_slice(&mac);
+    write_u32(&mut msg, 236, MAGIC_COOKIE);
+    let mut options = OptionWriter { msg: &mut msg, offset: OPTIONS_OFFSET };
+    options.put(OPT_MESSAGE_TYPE, &[message_type]);
+    if let Some((address, server)) = requested {
+        options.put(OPT_REQUESTED_IP, &address.octets());
+        options.put(OPT_SERVER_ID, &server.octets());
+    }
+    options.put(OPT_PARAM_LIST, &[OPT_SUBNET_MASK, OPT_ROUTER]);
+    options.msg[options.offset] = OPT_END;
+    udp::send(Ipv4Addr::UNSPECIFIED, CLIENT_PORT, Ipv4Addr::BROADCAST, SERVER_PORT, &msg)
+}
+
+/// Appends type-length-value options to a message.
+struct OptionWriter<'a> {
+    msg: &'a mut [u8],
+    offset: usize,
+}
+
+impl OptionWriter<'_> {
+    fn put(&mut self, code: u8, value: &[u8]) {
+        self.msg[self.offset] = code;
+        self.msg[self.offset + 1] = value.len() as u8;
+        self.msg[self.offset + 2..self.offset + 2 + value.len()].copy_from_slice(value);
+        self.offset += 2 + value.len();
+    }
+}
+
+/// Parses a server reply belonging to transaction `xid`.
+fn parse(msg: &[u8], xid: u32) -> Option<Reply> {
+    if msg.len() < OPTIONS_OFFSET || msg[0] != 2 || read_u32(msg, 4) != xid || read_u32(msg, 236) != MAGIC_COOKIE {
+        return None;
+    }
+    let address = |bytes: &[u8]| -> Option<Ipv4Addr> {
+        Some(Ipv4Addr::from(<[u8; 4]>::try_from(bytes.get(..4)?).ok()?))
+    };
+    let mut reply = Reply {
+        message_type: 0,
+        your_ip: address(&msg[16..20])?,
+        server_id: None,
+        netmask: None,
+        router: None,
+    };
+    let mut offset = OPTIONS_OFFSET;
+    while offset < msg.len() {
+        let code = msg[offset];
+        match code {
+            0 => {
+                offset += 1;
+                continue;
+            }
+            OPT_END => break,
+            _ => {}
+        }
+        let len = *msg.get(offset + 1)? as usize;
+        let value = msg.get(offset + 2..offset + 2 + len)?;
+        match code {
+            OPT_MESSAGE_TYPE => reply.message_type = *value.first()?,
+            OPT_SERVER_ID => reply.server_id = address(value),
+            OPT_SUBNET_MASK => reply.netmask = address(value),
+            OPT_ROUTER => reply.router = address(value),
+            _ => {}
+        }
+        offset += 2 + len;
+    }
+    Some(reply)
+}
@@ -0,0 +1,71 @@
+//! ---------------------------------------------------------------------------
+//! File       : ethernet.rs
+//! Module     : net::ethernet
+//! Author     : DiTurr
+//! Description:
+//! Ethernet II framing and MAC addresses.
+//! ---------------------------------------------------------------------------
+
+use core::fmt;
+
+use super::{arp, ipv4, read_u16, write_u16};
+
+/// Length of the Ethernet II header.
+pub const HEADER_LEN: usize = 14;
+
+/// Maximum frame length (header + 1500 bytes MTU), without FCS.
+pub const MAX_FRAME_LEN: usize = HEADER_LEN + MTU;
+
+/// Maximum payload carried by one frame.
+pub const MTU: usize = 1500;
+
+/// EtherType for IPv4.
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+/// EtherType for ARP.
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+/// A 48-bit IEEE 802 MAC address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// The all-zero (unknown) address.
+    pub const ZERO: MacAddr = MacAddr([0; 6]);
+    /// The broadcast address.
+    pub const BROADCAST: MacAddr = MacAddr([0xff; 6]);
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", m[0], m[1], m[2], m[3], m[4], m[5])
+    }
+}
+
+/// Writes an Ethernet header at the start of `frame`.
+///
+/// # Returns
+/// The header length, i.e. the offset at which the payload starts.
+pub fn write_header(frame: &mut [u8], dst: MacAddr, src: MacAddr, ethertype: u16) -> usize {
+    frame[0..6].copy_from_slice(&dst.0);
+    frame[6..12].copy_from_slice(&src.0);
+    write_u16(frame, 12, ethertype);
+    HEADER_LEN
+}
+
+/// Dispatches a received frame to the protocol named by its EtherType.
+pub fn handle(frame: &[u8], our_mac: MacAddr) {
+    if frame.len() < HEADER_LEN {
+        return;
+    }
+    let dst = MacAddr(frame[0..6].try_into().unwrap());
+    if dst != our_mac && dst != MacAddr::BROADCAST {
+        return;
+    }
+    let payload = &frame[HEADER_LEN..];
+    match read_u16(frame, 12) {
+        ETHERTYPE_ARP => arp::handle(payload),
+        ETHERTYPE_IPV4 => ipv4::handle(payload),
+        _ => {}
+    }
+}
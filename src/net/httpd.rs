@@ -0,0 +1,91 @@
+//! ---------------------------------------------------------------------------
+//! File       : httpd.rs
+//! Module     : net::httpd
+//! Author     : DiTurr
+//! Description:
+//! A tiny HTTP/1.0 status page served by the kernel. Every accepted connection
+//! gets a plain-text page with the network configuration, uptime, wall-clock time and the tail of
+//! the log ring buffer, after which the connection is closed. The request itself
+//! is ignored.
+//!
+//! With `hostfwd=tcp::8080-:80` on the QEMU user network:
+//! ```bash
+//! curl http://localhost:8080/
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::fmt::Write;
+
+use super::interface;
+use super::tcp::TcpListener;
+use super::NetError;
+use crate::logger::kmsg::{self, Format};
+use crate::time::{self, system::SystemTime};
+
+/// Size of the buffer the response is formatted into.
+const RESPONSE_LEN: usize = 512;
+
+/// Amount of recent log output included in the page.
+const LOG_TAIL_LEN: usize = 1024;
+
+/// A listening status page server, serviced by [`StatusServer::poll`].
+pub struct StatusServer {
+    listener: TcpListener,
+}
+
+/// `core::fmt::Write` adapter over a fixed byte buffer.
+struct FixedBuf {
+    data: [u8; RESPONSE_LEN],
+    len: usize,
+}
+
+impl Write for FixedBuf {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > self.data.len() {
+            return Err(core::fmt::Error);
+        }
+        self.data[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl StatusServer {
+    /// Starts listening on `port`.
+    pub fn bind(port: u16) -> Result<StatusServer, NetError> {
+        Ok(StatusServer { listener: TcpListener::bind(port, 2)? })
+    }
+
+    /// Answers every pending connection. Never blocks.
+    pub fn poll(&self) {
+        while let Ok(stream) = self.listener.accept() {
+            let mut body = FixedBuf { data: [0; RESPONSE_LEN], len: 0 };
+            let uptime_s = time::uptime().as_secs();
+            let _ = writeln!(body, "rustos status");
+            if let Some(mac) = interface::mac() {
+                let _ = writeln!(body, "mac:     {}", mac);
+            }
+            if let Some(config) = interface::config() {
+                let _ = writeln!(body, "address: {}/{}", config.address, config.netmask);
+                let _ = writeln!(body, "gateway: {}", config.gateway);
+            }
+            let _ = writeln!(body, "uptime:  {} s", uptime_s);
+            let _ = writeln!(body, "time:    {} UTC", SystemTime::now());
+            let _ = writeln!(body, "\nrecent log:");
+            let mut log = [0u8; LOG_TAIL_LEN];
+            let log_len = kmsg::read_tail(0, &mut log, Format::Dmesg);
+            let mut response = FixedBuf { data: [0; RESPONSE_LEN], len: 0 };
+            let _ = write!(
+                response,
+                "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len + log_len
+            );
+            // All parts fit in the (empty) send buffer of a fresh connection.
+            let _ = stream.send(&response.data[..response.len]);
+            let _ = stream.send(&body.data[..body.len]);
+            let _ = stream.send(&log[..log_len]);
+            // Dropping the stream closes it after the data has been sent.
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! ---------------------------------------------------------------------------
+//! File       : icmp.rs
+//! Module     : net::icmp
+//! Author     : DiTurr
+//! Description:
+//! Internet Control Message Protocol (RFC 792). Only echo requests are handled:
+//! they are answered with an echo reply carrying the same identifier, sequence
+//! number and data, which is enough for `ping`.
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::{checksum, interface, ipv4, write_u16};
+
+/// ICMP type: echo reply.
+const TYPE_ECHO_REPLY: u8 = 0;
+/// ICMP type: echo request.
+const TYPE_ECHO_REQUEST: u8 = 8;
+
+/// Length of the ICMP echo header (type, code, checksum, identifier, sequence).
+const HEADER_LEN: usize = 8;
+
+/// Handles a received ICMP message.
+pub fn handle(src: Ipv4Addr, dst: Ipv4Addr, message: &[u8]) {
+    if message.len() < HEADER_LEN || checksum::checksum(message) != 0 {
+        return;
+    }
+    // Do not answer pings sent to broadcast addresses.
+    if message[0] != TYPE_ECHO_REQUEST || dst != interface::address() {
+        return;
+    }
+    let _ = ipv4::send(dst, src, ipv4::PROTO_ICMP, |p| {
+        p[..message.len()].copy_from_slice(message);
+        p[0] = TYPE_ECHO_REPLY;
+        p[1] = 0;
+        write_u16(p, 2, 0);
+        let sum = checksum::checksum(&p[..message.len()]);
+        write_u16(p, 2, sum);
+        message.len()
+    });
+}
@@ -0,0 +1,139 @@
+//! ---------------------------------------------------------------------------
+//! File       : interface.rs
+//! Module     : net::interface
+//! Author     : DiTurr
+//! Description:
+//! The single network interface of the kernel: the attached device, its MAC
+//! address and IPv4 configuration, frame transmission and the receive poll loop.
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::ethernet::{self, MacAddr, MAX_FRAME_LEN};
+use super::NetError;
+use crate::random;
+use crate::sync::spinlock::SpinLock;
+
+/// A network device able to send and receive raw Ethernet frames.
+pub trait NetDevice: Sync {
+    /// Returns the hardware address of the device.
+    fn mac(&self) -> MacAddr;
+
+    /// Queues `frame` for transmission.
+    ///
+    /// # Returns
+    /// `true` if the frame was accepted by the device.
+    fn transmit(&self, frame: &[u8]) -> bool;
+
+    /// Copies the next received frame into `buf`.
+    ///
+    /// # Returns
+    /// The frame length, or `None` if nothing was received.
+    fn receive(&self, buf: &mut [u8]) -> Option<usize>;
+}
+
+/// IPv4 configuration of the interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub address: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+}
+
+impl Config {
+    /// Default addresses handed out by QEMU user-mode networking (`-netdev user`).
+    pub const QEMU_USER: Config = Config {
+        address: Ipv4Addr::new(10, 0, 2, 15),
+        netmask: Ipv4Addr::new(255, 255, 255, 0),
+        gateway: Ipv4Addr::new(10, 0, 2, 2),
+    };
+
+    /// Returns `true` if `addr` is on the directly attached subnet.
+    pub fn is_local(&self, addr: Ipv4Addr) -> bool {
+        let mask = self.netmask.to_bits();
+        addr.to_bits() & mask == self.address.to_bits() & mask
+    }
+
+    /// Returns the directed broadcast address of the subnet.
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from_bits(self.address.to_bits() | !self.netmask.to_bits())
+    }
+}
+
+/// State of the attached interface.
+struct Interface {
+    device: &'static dyn NetDevice,
+    mac: MacAddr,
+    config: Option<Config>,
+}
+
+static INTERFACE: SpinLock<Option<Interface>> = SpinLock::new(None);
+
+/// Attaches `device` as the kernel's network interface (unconfigured).
+pub fn attach(device: &'static dyn NetDevice) {
+    *INTERFACE.lock() = Some(Interface { device, mac: device.mac(), config: None });
+}
+
+/// Applies an IPv4 configuration to the interface.
+pub fn configure(config: Config) {
+    if let Some(iface) = INTERFACE.lock().as_mut() {
+        iface.config = Some(config);
+    }
+}
+
+/// Returns the interface configuration, if an address has been assigned.
+pub fn config() -> Option<Config> {
+    INTERFACE.lock().as_ref().and_then(|iface| iface.config)
+}
+
+/// Returns the interface IPv4 address, or `0.0.0.0` if unconfigured.
+pub fn address() -> Ipv4Addr {
+    config().map_or(Ipv4Addr::UNSPECIFIED, |config| config.address)
+}
+
+/// Returns the interface MAC address, if a device is attached.
+pub fn mac() -> Option<MacAddr> {
+    INTERFACE.lock().as_ref().map(|iface| iface.mac)
+}
+
+/// Builds and sends an Ethernet frame.
+///
+/// `fill` writes the payload into the slice it is given and returns its length.
+pub fn send_frame(
+    dst: MacAddr,
+    ethertype: u16,
+    fill: impl FnOnce(&mut [u8]) -> usize,
+) -> Result<(), NetError> {
+    let (device, src) = {
+        let guard = INTERFACE.lock();
+        let iface = guard.as_ref().ok_or(NetError::NoDevice)?;
+        (iface.device, iface.mac)
+    };
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    let offset = ethernet::write_header(&mut frame, dst, src, ethertype);
+    let len = offset + fill(&mut frame[offset..]);
+    if device.transmit(&frame[..len]) {
+        Ok(())
+    } else {
+        Err(NetError::TxFailed)
+    }
+}
+
+/// Processes every frame currently waiting in the device.
+///
+/// # Returns
+/// The number of frames processed.
+pub fn poll() -> usize {
+    let Some((device, mac)) = INTERFACE.lock().as_ref().map(|iface| (iface.device, iface.mac)) else {
+        return 0;
+    };
+    let mut frame = [0u8; MAX_FRAME_LEN];
+    let mut count = 0;
+    while let Some(len) = device.receive(&mut frame) {
+        // Frame arrival times depend on the outside world.
+        random::add_timer_jitter();
+        ethernet::handle(&frame[..len], mac);
+        count += 1;
+    }
+    count
+}
@@ -0,0 +1,125 @@
+//! ---------------------------------------------------------------------------
+//! File       : ipv4.rs
+//! Module     : net::ipv4
+//! Author     : DiTurr
+//! Description:
+//! Internet Protocol version 4 (RFC 791): header validation, local delivery to
+//! ICMP, TCP and UDP, next-hop selection and datagram transmission. Fragmentation
+//! and IP options are not supported.
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use super::ethernet::{MacAddr, ETHERTYPE_IPV4, MTU};
+use super::{arp, checksum, icmp, interface, read_u16, tcp, udp, write_u16, NetError};
+
+/// Length of an IPv4 header without options.
+pub const HEADER_LEN: usize = 20;
+
+/// Largest payload that fits in one unfragmented datagram.
+pub const MAX_PAYLOAD: usize = MTU - HEADER_LEN;
+
+/// Protocol number of ICMP.
+pub const PROTO_ICMP: u8 = 1;
+/// Protocol number of TCP.
+pub const PROTO_TCP: u8 = 6;
+/// Protocol number of UDP.
+pub const PROTO_UDP: u8 = 17;
+
+/// Default time-to-live of outgoing datagrams.
+const DEFAULT_TTL: u8 = 64;
+
+/// Flags/fragment field mask of the "more fragments" bit and fragment offset.
+const FRAGMENT_MASK: u16 = 0x3fff;
+
+/// Identification field of the next outgoing datagram.
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+/// Handles a received IPv4 datagram.
+pub fn handle(packet: &[u8]) {
+    if packet.len() < HEADER_LEN || packet[0] >> 4 != 4 {
+        return;
+    }
+    let header_len = ((packet[0] & 0x0f) as usize) * 4;
+    let total_len = read_u16(packet, 2) as usize;
+    if header_len < HEADER_LEN || total_len < header_len || total_len > packet.len() {
+        return;
+    }
+    if checksum::checksum(&packet[..header_len]) != 0 {
+        return;
+    }
+    if read_u16(packet, 6) & FRAGMENT_MASK != 0 {
+        return;
+    }
+    let src = Ipv4Addr::from(<[u8; 4]>::try_from(&packet[12..16]).unwrap());
+    let dst = Ipv4Addr::from(<[u8; 4]>::try_from(&packet[16..20]).unwrap());
+    // Before an address is assigned (e.g. during DHCP) accept everything.
+    let accept = match interface::config() {
+        Some(config) => dst == config.address || dst == config.broadcast() || dst.is_broadcast(),
+        None => true,
+    };
+    if !accept {
+        return;
+    }
+    let payload = &packet[header_len..total_len];
+    match packet[9] {
+        PROTO_ICMP => icmp::handle(src, dst, payload),
+        PROTO_TCP => tcp::handle(src, dst, payload),
+        PROTO_UDP => udp::handle(src, dst, payload),
+        _ => {}
+    }
+}
+
+/// Returns the MAC address of the next hop towards `dst`.
+///
+/// Unknown neighbours trigger an ARP request and [`NetError::ArpPending`];
+/// the caller may retry once the reply has been processed.
+fn next_hop(dst: Ipv4Addr) -> Result<MacAddr, NetError> {
+    if dst.is_broadcast() {
+        return Ok(MacAddr::BROADCAST);
+    }
+    let config = interface::config().ok_or(NetError::NotConfigured)?;
+    if dst == config.broadcast() {
+        return Ok(MacAddr::BROADCAST);
+    }
+    let hop = if config.is_local(dst) { dst } else { config.gateway };
+    match arp::lookup(hop) {
+        Some(mac) => Ok(mac),
+        None => {
+            arp::request(hop)?;
+            Err(NetError::ArpPending)
+        }
+    }
+}
+
+/// Sends `payload` to `dst` as a datagram of the given `protocol`.
+///
+/// `fill` writes the payload into the slice it is given (positioned after the
+/// IPv4 header, at most [`MAX_PAYLOAD`] bytes) and returns its length; `src` is
+/// the source address to use.
+pub fn send(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    protocol: u8,
+    fill: impl FnOnce(&mut [u8]) -> usize,
+) -> Result<(), NetError> {
+    let mac = next_hop(dst)?;
+    interface::send_frame(mac, ETHERTYPE_IPV4, |p| {
+        let payload_len = fill(&mut p[HEADER_LEN..]);
+        let total_len = HEADER_LEN + payload_len;
+        p[0] = 0x45;
+        p[1] = 0;
+        write_u16(p, 2, total_len as u16);
+        write_u16(p, 4, NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        write_u16(p, 6, 0x4000); // Don't fragment.
+        p[8] = DEFAULT_TTL;
+        p[9] = protocol;
+        write_u16(p, 10, 0);
+        p[12..16].copy_from_slice(&src.octets());
+        p[16..20].copy_from_slice(&dst.octets());
+        let sum = checksum::checksum(&p[..HEADER_LEN]);
+        write_u16(p, 10, sum);
+        total_len
+    })
+}
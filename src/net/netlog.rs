@@ -0,0 +1,92 @@
+//! ---------------------------------------------------------------------------
+//! File       : netlog.rs
+//! Module     : net::netlog
+//! Author     : DiTurr
+//! Description:
+//! Network log sink: log records are sent as UDP datagrams to a collector on
+//! the host (`nc -ul 6666`). Records are only queued when logged, since the
+//! stack itself logs while holding its locks; the queue is sent from
+//! [`net::poll`](super::poll) once the interface is configured.
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::{interface, udp};
+use crate::logger::sink::LogSink;
+use crate::sync::spinlock::SpinLock;
+
+/// Host receiving the log datagrams (the QEMU user-mode gateway).
+pub const LOG_HOST: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+/// UDP port of the collector, also used as source port.
+pub const LOG_PORT: u16 = 6666;
+
+/// Capacity of the queue of records waiting to be sent.
+const QUEUE_LEN: usize = 2048;
+
+/// Bytes waiting to be sent.
+struct Queue {
+    data: [u8; QUEUE_LEN],
+    len: usize,
+}
+
+/// The network log sink.
+pub struct NetLog {
+    queue: SpinLock<Queue>,
+}
+
+/// The kernel's network log sink instance.
+pub static NETLOG: NetLog = NetLog {
+    queue: SpinLock::new(Queue { data: [0; QUEUE_LEN], len: 0 }),
+};
+
+impl LogSink for NetLog {
+    fn name(&self) -> &'static str {
+        "net"
+    }
+
+    fn write_str(&self, s: &str) {
+        let Some(mut queue) = self.queue.try_lock() else {
+            return;
+        };
+        // Records that do not fit are dropped whole.
+        let len = queue.len;
+        if len + s.len() > QUEUE_LEN {
+            return;
+        }
+        queue.data[len..len + s.len()].copy_from_slice(s.as_bytes());
+        queue.len += s.len();
+    }
+}
+
+/// Sends queued records, one datagram per batch of whole lines.
+///
+/// Stops at the first failure (e.g. ARP still pending) and retries on the next
+/// call; nothing is sent before the interface has an address.
+pub fn flush() {
+    let Some(address) = interface::config().map(|config| config.address) else {
+        return;
+    };
+    let mut datagram = [0u8; udp::MAX_PAYLOAD];
+    loop {
+        // Copy a batch out so that no lock is held while sending.
+        let len = {
+            let queue = NETLOG.queue.lock();
+            let mut len = queue.len.min(datagram.len());
+            // Prefer ending the datagram after a complete line.
+            if len < queue.len
+                && let Some(newline) = queue.data[..len].iter().rposition(|&b| b == b'\n')
+            {
+                len = newline + 1;
+            }
+            datagram[..len].copy_from_slice(&queue.data[..len]);
+            len
+        };
+        if len == 0 || udp::send(address, LOG_PORT, LOG_HOST, LOG_PORT, &datagram[..len]).is_err() {
+            return;
+        }
+        let mut queue = NETLOG.queue.lock();
+        let remaining = queue.len - len;
+        queue.data.copy_within(len..len + remaining, 0);
+        queue.len = remaining;
+    }
+}
@@ -0,0 +1,179 @@
+//! ---------------------------------------------------------------------------
+//! File       : socket.rs
+//! Module     : net::socket
+//! Author     : DiTurr
+//! Description:
+//! BSD-like sockets on top of the UDP and TCP implementations.
+//!
+//! A [`Socket`] starts unbound and becomes a UDP socket, a TCP listener or a
+//! TCP stream depending on the calls made on it, mirroring the usual
+//! `socket/bind/listen/accept/connect/send/recv` sequence. Operations block by
+//! polling the network unless the socket is non-blocking, in which case they
+//! report [`NetError::WouldBlock`].
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::tcp::{State, TcpListener, TcpStream};
+use super::udp::UdpSocket;
+use super::NetError;
+
+/// Socket types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketKind {
+    /// Reliable byte stream (TCP).
+    Stream,
+    /// Datagrams (UDP).
+    Datagram,
+}
+
+/// The protocol object behind a socket.
+enum Inner {
+    /// Created but not yet bound, listening or connected.
+    Unbound { kind: SocketKind, port: u16 },
+    Udp { socket: UdpSocket, peer: Option<(Ipv4Addr, u16)> },
+    Listener(TcpListener),
+    Stream(TcpStream),
+}
+
+/// A BSD-like socket.
+pub struct Socket {
+    inner: Inner,
+    nonblocking: bool,
+}
+
+impl Socket {
+    /// Creates an unbound socket of the given kind.
+    pub fn new(kind: SocketKind, nonblocking: bool) -> Socket {
+        Socket { inner: Inner::Unbound { kind, port: 0 }, nonblocking }
+    }
+
+    /// Repeats `op` while it would block, polling the network in between.
+    fn block_on<T>(&self, mut op: impl FnMut() -> Result<T, NetError>) -> Result<T, NetError> {
+        loop {
+            match op() {
+                Err(NetError::WouldBlock) if !self.nonblocking => super::poll(),
+                result => return result,
+            }
+        }
+    }
+
+    /// Assigns a local port (0 selects an ephemeral port).
+    pub fn bind(&mut self, port: u16) -> Result<(), NetError> {
+        match self.inner {
+            Inner::Unbound { kind: SocketKind::Datagram, .. } => {
+                self.inner = Inner::Udp { socket: UdpSocket::bind(port)?, peer: None };
+                Ok(())
+            }
+            // TCP ports are claimed by `listen`.
+            Inner::Unbound { kind: SocketKind::Stream, port: ref mut bound } => {
+                *bound = port;
+                Ok(())
+            }
+            _ => Err(NetError::InvalidState),
+        }
+    }
+
+    /// Starts listening for connections on the bound port.
+    pub fn listen(&mut self, backlog: usize) -> Result<(), NetError> {
+        match self.inner {
+            Inner::Unbound { kind: SocketKind::Stream, port } => {
+                self.inner = Inner::Listener(TcpListener::bind(port, backlog)?);
+                Ok(())
+            }
+            _ => Err(NetError::InvalidState),
+        }
+    }
+
+    /// Waits for an incoming connection.
+    ///
+    /// # Returns
+    /// A connected socket and the address of the peer.
+    pub fn accept(&self) -> Result<(Socket, (Ipv4Addr, u16)), NetError> {
+        let Inner::Listener(listener) = &self.inner else {
+            return Err(NetError::InvalidState);
+        };
+        let stream = self.block_on(|| listener.accept())?;
+        let peer = stream.peer();
+        Ok((Socket { inner: Inner::Stream(stream), nonblocking: self.nonblocking }, peer))
+    }
+
+    /// Connects to `addr:port`.
+    ///
+    /// For UDP this only sets the default destination; for TCP it performs the
+    /// handshake (a non-blocking socket returns [`NetError::WouldBlock`] while
+    /// the handshake is in progress).
+    pub fn connect(&mut self, addr: Ipv4Addr, port: u16) -> Result<(), NetError> {
+        match &mut self.inner {
+            Inner::Unbound { kind: SocketKind::Datagram, .. } => {
+                self.bind(0)?;
+                self.connect(addr, port)
+            }
+            Inner::Udp { peer, .. } => {
+                *peer = Some((addr, port));
+                Ok(())
+            }
+            Inner::Unbound { kind: SocketKind::Stream, .. } => {
+                self.inner = Inner::Stream(TcpStream::connect(addr, port)?);
+                self.wait_established()
+            }
+            Inner::Stream(_) => self.wait_established(),
+            Inner::Listener(_) => Err(NetError::InvalidState),
+        }
+    }
+
+    /// Waits for the TCP handshake of a stream socket to complete.
+    fn wait_established(&self) -> Result<(), NetError> {
+        let Inner::Stream(stream) = &self.inner else {
+            return Err(NetError::InvalidState);
+        };
+        self.block_on(|| match stream.state()? {
+            State::SynSent | State::SynReceived => Err(NetError::WouldBlock),
+            _ => Ok(()),
+        })
+    }
+
+    /// Sends `data`, to `dest` for an unconnected UDP socket.
+    ///
+    /// # Returns
+    /// The number of bytes sent or queued.
+    pub fn send_to(&mut self, data: &[u8], dest: Option<(Ipv4Addr, u16)>) -> Result<usize, NetError> {
+        if let Inner::Unbound { kind: SocketKind::Datagram, .. } = self.inner {
+            self.bind(0)?;
+        }
+        match &self.inner {
+            Inner::Udp { socket, peer } => {
+                let (addr, port) = dest.or(*peer).ok_or(NetError::NotConnected)?;
+                // The first datagram to a new neighbour waits for ARP resolution.
+                self.block_on(|| match socket.send_to(data, addr, port) {
+                    Err(NetError::ArpPending) => Err(NetError::WouldBlock),
+                    result => result,
+                })?;
+                Ok(data.len())
+            }
+            Inner::Stream(stream) => self.block_on(|| stream.send(data)),
+            _ => Err(NetError::NotConnected),
+        }
+    }
+
+    /// Receives data into `buf`.
+    ///
+    /// # Returns
+    /// The number of bytes received (0 at the end of a TCP stream) and the
+    /// sender's address.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, (Ipv4Addr, u16)), NetError> {
+        match &self.inner {
+            Inner::Udp { socket, .. } => self.block_on(|| {
+                socket
+                    .recv_from(buf)
+                    .map(|(len, addr, port)| (len, (addr, port)))
+                    .ok_or(NetError::WouldBlock)
+            }),
+            Inner::Stream(stream) => {
+                let len = self.block_on(|| stream.recv(buf))?;
+                Ok((len, stream.peer()))
+            }
+            _ => Err(NetError::NotConnected),
+        }
+    }
+}
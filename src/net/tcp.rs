@@ -0,0 +1,1027 @@
+//! ---------------------------------------------------------------------------
+//! File       : tcp.rs
+//! Module     : net::tcp
+//! Author     : DiTurr
+//! Description:
+//! Transmission Control Protocol (RFC 9293).
+//!
+//! Connections live in a fixed table of transmission control blocks (TCBs),
+//! each owning a send and a receive ring buffer. The implementation covers the
+//! full connection state machine, passive (listen/accept) and active opens,
+//! retransmission with an adaptive timeout (RFC 6298, Karn's algorithm,
+//! exponential backoff), the peer's sliding window with zero-window probing,
+//! and our own receive window. Out-of-order segments are dropped and
+//! re-requested with a duplicate ACK; there is no SACK, window scaling or
+//! congestion control.
+//!
+//! All functions are non-blocking and report [`NetError::WouldBlock`] when they
+//! cannot make progress; [`poll`] must be called regularly to run the timers.
+//!
+//! ## Example
+//! ```rust
+//! let listener = TcpListener::bind(80, 4)?;
+//! loop {
+//!     net::poll();
+//!     if let Ok(stream) = listener.accept() {
+//!         stream.send(b"hello\r\n")?;
+//!         stream.close();
+//!     }
+//! }
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::{checksum, elapsed_ms, interface, ipv4, read_u16, read_u32, write_u16, write_u32, NetError};
+use crate::random;
+use crate::time::instant::Instant;
+use crate::sync::spinlock::SpinLock;
+
+/// Length of the TCP header without options.
+const HEADER_LEN: usize = 20;
+
+/// Maximum number of connections (including listeners).
+const MAX_CONNECTIONS: usize = 8;
+
+/// Size of the send and receive buffers of each connection.
+const BUFFER_SIZE: usize = 4096;
+
+/// Maximum segment size we accept (MTU minus IPv4 and TCP headers).
+const OUR_MSS: usize = ipv4::MAX_PAYLOAD - HEADER_LEN;
+
+/// Segment size assumed when the peer does not send the MSS option (RFC 9293).
+const DEFAULT_MSS: usize = 536;
+
+/// Initial retransmission timeout in milliseconds (RFC 6298).
+const INITIAL_RTO_MS: u64 = 1_000;
+/// Lower bound of the retransmission timeout in milliseconds.
+const MIN_RTO_MS: u64 = 200;
+/// Upper bound of the retransmission timeout in milliseconds.
+const MAX_RTO_MS: u64 = 60_000;
+/// Number of consecutive timeouts after which the connection is aborted.
+const MAX_RETRIES: u32 = 8;
+
+/// Time spent in TIME-WAIT before the connection slot is reused, in milliseconds.
+/// Much shorter than 2*MSL: the kernel has few slots and talks to a local host.
+const TIME_WAIT_MS: u64 = 2_000;
+
+/// First port of the ephemeral range (RFC 6335).
+const EPHEMERAL_START: u16 = 49152;
+
+// Header flags.
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+/// TCP option: end of list.
+const OPT_END: u8 = 0;
+/// TCP option: no operation (padding).
+const OPT_NOP: u8 = 1;
+/// TCP option: maximum segment size.
+const OPT_MSS: u8 = 2;
+
+/// Connection states (RFC 9293, section 3.3.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// Returns `true` if sequence number `a` precedes `b` (modulo 2^32).
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// Returns `true` if sequence number `a` precedes or equals `b` (modulo 2^32).
+fn seq_le(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
+
+/// A fixed-size byte ring buffer.
+struct RingBuffer {
+    data: [u8; BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    const fn new() -> Self {
+        RingBuffer { data: [0; BUFFER_SIZE], head: 0, len: 0 }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn free(&self) -> usize {
+        BUFFER_SIZE - self.len
+    }
+
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Appends as much of `bytes` as fits, returning the number of bytes stored.
+    fn push(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(self.free());
+        for (i, &byte) in bytes[..count].iter().enumerate() {
+            self.data[(self.head + self.len + i) % BUFFER_SIZE] = byte;
+        }
+        self.len += count;
+        count
+    }
+
+    /// Copies bytes starting `offset` bytes after the head into `out` without consuming them.
+    fn peek(&self, offset: usize, out: &mut [u8]) -> usize {
+        let count = out.len().min(self.len.saturating_sub(offset));
+        for (i, byte) in out[..count].iter_mut().enumerate() {
+            *byte = self.data[(self.head + offset + i) % BUFFER_SIZE];
+        }
+        count
+    }
+
+    /// Drops up to `count` bytes from the head.
+    fn consume(&mut self, count: usize) {
+        let count = count.min(self.len);
+        self.head = (self.head + count) % BUFFER_SIZE;
+        self.len -= count;
+    }
+
+    /// Moves bytes from the head into `out`, returning the number of bytes read.
+    fn pop(&mut self, out: &mut [u8]) -> usize {
+        let count = self.peek(0, out);
+        self.consume(count);
+        count
+    }
+}
+
+/// Transmission control block: the state of one connection or listener.
+struct Tcb {
+    state: State,
+    local_port: u16,
+    remote_addr: Ipv4Addr,
+    remote_port: u16,
+    /// Listener that created this connection (passive open), if any.
+    parent: Option<usize>,
+    /// `true` once a user handle refers to this TCB (after accept/connect).
+    attached: bool,
+    /// `true` once the user handle has been dropped; the slot is freed when closed.
+    detached: bool,
+    /// Maximum number of pending connections (listeners only).
+    backlog: usize,
+    /// The connection was reset or refused by the peer, or timed out.
+    error: Option<NetError>,
+
+    // Send sequence variables.
+    iss: u32,
+    snd_una: u32,
+    snd_nxt: u32,
+    /// Highest sequence number sent so far (`snd_nxt` moves back on retransmission).
+    snd_max: u32,
+    snd_wnd: usize,
+    snd_mss: usize,
+    /// The user asked to close; a FIN follows the queued data.
+    fin_queued: bool,
+
+    // Receive sequence variables.
+    rcv_nxt: u32,
+    /// Window advertised in the last segment sent.
+    rcv_wnd_adv: usize,
+
+    // Retransmission state.
+    rto_ms: u64,
+    srtt_ms: Option<u64>,
+    rttvar_ms: u64,
+    retries: u32,
+    /// Start of the retransmission timer, if running.
+    timer: Option<Instant>,
+    /// Sequence number being timed for an RTT sample and the time it was sent.
+    rtt_probe: Option<(u32, Instant)>,
+    /// Time at which TIME-WAIT was entered.
+    time_wait_start: Instant,
+
+    /// Bytes queued by the user and not yet acknowledged (starts at `snd_una`).
+    tx: RingBuffer,
+    /// Bytes received in order and not yet read by the user.
+    rx: RingBuffer,
+}
+
+impl Tcb {
+    const fn new() -> Self {
+        Tcb {
+            state: State::Closed,
+            local_port: 0,
+            remote_addr: Ipv4Addr::UNSPECIFIED,
+            remote_port: 0,
+            parent: None,
+            attached: false,
+            detached: false,
+            backlog: 0,
+            error: None,
+            iss: 0,
+            snd_una: 0,
+            snd_nxt: 0,
+            snd_max: 0,
+            snd_wnd: 0,
+            snd_mss: DEFAULT_MSS,
+            fin_queued: false,
+            rcv_nxt: 0,
+            rcv_wnd_adv: 0,
+            rto_ms: INITIAL_RTO_MS,
+            srtt_ms: None,
+            rttvar_ms: 0,
+            retries: 0,
+            timer: None,
+            rtt_probe: None,
+            time_wait_start: Instant::BOOT,
+            tx: RingBuffer::new(),
+            rx: RingBuffer::new(),
+        }
+    }
+
+    /// Resets the block to a fresh, closed state.
+    fn reset(&mut self) {
+        self.state = State::Closed;
+        self.parent = None;
+        self.attached = false;
+        self.detached = false;
+        self.backlog = 0;
+        self.error = None;
+        self.snd_wnd = 0;
+        self.snd_mss = DEFAULT_MSS;
+        self.fin_queued = false;
+        self.rcv_wnd_adv = 0;
+        self.rto_ms = INITIAL_RTO_MS;
+        self.srtt_ms = None;
+        self.rttvar_ms = 0;
+        self.retries = 0;
+        self.timer = None;
+        self.rtt_probe = None;
+        self.tx.clear();
+        self.rx.clear();
+    }
+
+    /// Returns `true` if the slot can be handed out to a new connection.
+    fn is_free(&self) -> bool {
+        self.state == State::Closed && !self.attached && self.parent.is_none()
+    }
+
+    /// Returns `true` if queued user data may still be transmitted in this state.
+    fn can_send_data(&self) -> bool {
+        matches!(
+            self.state,
+            State::Established | State::CloseWait | State::FinWait1 | State::Closing | State::LastAck
+        )
+    }
+
+    /// Window to advertise: free space in the receive buffer.
+    fn rcv_wnd(&self) -> usize {
+        self.rx.free().min(u16::MAX as usize)
+    }
+
+    /// Sends a segment carrying `data` with the given flags and sequence number.
+    fn send(&mut self, flags: u8, seq: u32, data: &[u8]) {
+        let ack = if flags & ACK != 0 { self.rcv_nxt } else { 0 };
+        self.rcv_wnd_adv = self.rcv_wnd();
+        let _ = send_segment(
+            self.local_port,
+            self.remote_addr,
+            self.remote_port,
+            seq,
+            ack,
+            flags,
+            self.rcv_wnd_adv as u16,
+            data,
+        );
+    }
+
+    /// Sends a bare acknowledgment.
+    fn send_ack(&mut self) {
+        self.send(ACK, self.snd_nxt, &[]);
+    }
+
+    /// Sends a SYN (active open) or SYN-ACK (passive open).
+    fn send_syn(&mut self) {
+        let flags = if self.state == State::SynReceived { SYN | ACK } else { SYN };
+        self.send(flags, self.iss, &[]);
+    }
+
+    /// Sequence number following the last byte queued by the user.
+    fn data_end(&self) -> u32 {
+        self.snd_una.wrapping_add(self.tx.len() as u32)
+    }
+
+    /// Advances `snd_nxt` by `count` sequence numbers, tracking `snd_max`.
+    fn advance(&mut self, count: u32) {
+        self.snd_nxt = self.snd_nxt.wrapping_add(count);
+        if seq_lt(self.snd_max, self.snd_nxt) {
+            self.snd_max = self.snd_nxt;
+        }
+    }
+
+    /// Starts the retransmission timer if it is not already running.
+    fn arm_timer(&mut self) {
+        if self.timer.is_none() {
+            self.timer = Some(Instant::now());
+        }
+    }
+
+    /// Transmits as much queued data as the peer's window allows, then the FIN if due.
+    fn output(&mut self) {
+        if !self.can_send_data() {
+            return;
+        }
+        let mut segment = [0u8; OUR_MSS];
+        loop {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+            let unsent = self.tx.len().saturating_sub(in_flight);
+            if unsent == 0 {
+                break;
+            }
+            let usable = self.snd_wnd.saturating_sub(in_flight);
+            if usable == 0 {
+                // Zero window: the retransmission timer will send probes.
+                self.arm_timer();
+                break;
+            }
+            let count = unsent.min(usable).min(self.snd_mss);
+            let count = self.tx.peek(in_flight, &mut segment[..count]);
+            let seq = self.snd_nxt;
+            self.send(ACK | PSH, seq, &segment[..count]);
+            if self.rtt_probe.is_none() {
+                self.rtt_probe = Some((seq, Instant::now()));
+            }
+            self.advance(count as u32);
+            self.arm_timer();
+        }
+        // The FIN follows the last data byte, once everything before it was sent.
+        if self.fin_queued && self.snd_nxt == self.data_end() {
+            self.send(FIN | ACK, self.snd_nxt, &[]);
+            self.advance(1);
+            self.arm_timer();
+        }
+    }
+
+    /// Handles an expired retransmission timer.
+    fn on_timeout(&mut self) {
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.send(RST | ACK, self.snd_nxt, &[]);
+            self.abort(NetError::Timeout);
+            return;
+        }
+        // Exponential backoff; samples of retransmitted segments are ambiguous (Karn).
+        self.rto_ms = (self.rto_ms * 2).min(MAX_RTO_MS);
+        self.rtt_probe = None;
+        self.timer = Some(Instant::now());
+        match self.state {
+            State::SynSent | State::SynReceived => self.send_syn(),
+            _ if self.can_send_data() => {
+                if self.snd_wnd == 0 && self.tx.len() > 0 && self.snd_max == self.snd_una {
+                    // Zero-window probe: push one byte beyond the window.
+                    let mut byte = [0u8; 1];
+                    self.tx.peek(0, &mut byte);
+                    self.send(ACK, self.snd_una, &byte);
+                    self.snd_nxt = self.snd_una;
+                    self.advance(1);
+                    return;
+                }
+                // Go back N: resend everything from the oldest unacknowledged byte.
+                self.snd_nxt = self.snd_una;
+                self.output();
+            }
+            _ => self.timer = None,
+        }
+    }
+
+    /// Updates the RTT estimate and timeout with a new sample (RFC 6298, section 2).
+    fn update_rtt(&mut self, sample_ms: u64) {
+        match self.srtt_ms {
+            None => {
+                self.srtt_ms = Some(sample_ms);
+                self.rttvar_ms = sample_ms / 2;
+            }
+            Some(srtt) => {
+                self.rttvar_ms = (3 * self.rttvar_ms + srtt.abs_diff(sample_ms)) / 4;
+                self.srtt_ms = Some((7 * srtt + sample_ms) / 8);
+            }
+        }
+        let srtt = self.srtt_ms.unwrap_or(sample_ms);
+        self.rto_ms = (srtt + (4 * self.rttvar_ms).max(1)).clamp(MIN_RTO_MS, MAX_RTO_MS);
+    }
+
+    /// Terminates the connection after an error.
+    fn abort(&mut self, error: NetError) {
+        self.state = State::Closed;
+        self.error = Some(error);
+        self.timer = None;
+        self.tx.clear();
+    }
+
+    /// Processes the acknowledgment field of an incoming segment.
+    ///
+    /// # Returns
+    /// `false` if the segment must be dropped.
+    fn process_ack(&mut self, ack: u32, window: usize) -> bool {
+        if seq_lt(self.snd_max, ack) {
+            // Acknowledges something not yet sent.
+            self.send_ack();
+            return false;
+        }
+        if seq_lt(self.snd_una, ack) {
+            // The FIN is acknowledged together with everything before it.
+            let fin_acked = self.fin_queued && ack == self.data_end().wrapping_add(1);
+            let acked = (ack.wrapping_sub(self.snd_una) as usize).min(self.tx.len());
+            self.tx.consume(acked);
+            self.snd_una = ack;
+            if seq_lt(self.snd_nxt, ack) {
+                // An ACK for data sent before a go-back-N retransmission.
+                self.snd_nxt = ack;
+            }
+            if let Some((seq, sent)) = self.rtt_probe
+                && seq_lt(seq, ack)
+            {
+                self.update_rtt(elapsed_ms(sent));
+                self.rtt_probe = None;
+            }
+            self.retries = 0;
+            self.timer = if self.snd_una == self.snd_max { None } else { Some(Instant::now()) };
+            if fin_acked {
+                match self.state {
+                    State::FinWait1 => self.state = State::FinWait2,
+                    State::Closing => self.enter_time_wait(),
+                    State::LastAck => self.state = State::Closed,
+                    _ => {}
+                }
+            }
+        }
+        self.snd_wnd = window;
+        true
+    }
+
+    fn enter_time_wait(&mut self) {
+        self.state = State::TimeWait;
+        self.time_wait_start = Instant::now();
+        self.timer = None;
+    }
+}
+
+/// The connection table.
+struct Table {
+    tcbs: [Tcb; MAX_CONNECTIONS],
+    next_ephemeral: u16,
+}
+
+static TABLE: SpinLock<Table> = SpinLock::new(Table {
+    tcbs: [const { Tcb::new() }; MAX_CONNECTIONS],
+    next_ephemeral: EPHEMERAL_START,
+});
+
+impl Table {
+    fn allocate(&mut self) -> Result<usize, NetError> {
+        let index = self.tcbs.iter().position(Tcb::is_free).ok_or(NetError::NoSockets)?;
+        self.tcbs[index].reset();
+        Ok(index)
+    }
+
+    fn port_in_use(&self, port: u16) -> bool {
+        self.tcbs.iter().any(|tcb| !tcb.is_free() && tcb.local_port == port)
+    }
+
+    fn ephemeral_port(&mut self) -> Result<u16, NetError> {
+        for _ in 0..(u16::MAX - EPHEMERAL_START) {
+            let port = self.next_ephemeral;
+            self.next_ephemeral = if port == u16::MAX { EPHEMERAL_START } else { port + 1 };
+            if !self.port_in_use(port) {
+                return Ok(port);
+            }
+        }
+        Err(NetError::AddrInUse)
+    }
+
+    /// Finds the connection matching a segment, falling back to a listener on the port.
+    fn lookup(&self, local_port: u16, remote_addr: Ipv4Addr, remote_port: u16) -> Option<usize> {
+        let exact = self.tcbs.iter().position(|tcb| {
+            !matches!(tcb.state, State::Closed | State::Listen)
+                && tcb.local_port == local_port
+                && tcb.remote_addr == remote_addr
+                && tcb.remote_port == remote_port
+        });
+        exact.or_else(|| {
+            self.tcbs.iter().position(|tcb| tcb.state == State::Listen && tcb.local_port == local_port)
+        })
+    }
+
+    /// Releases slots of connections nobody refers to anymore.
+    fn collect(&mut self) {
+        for index in 0..MAX_CONNECTIONS {
+            let tcb = &self.tcbs[index];
+            if tcb.state != State::Closed {
+                continue;
+            }
+            let orphan = match tcb.parent {
+                // A passive connection that died before being accepted.
+                Some(_) if !tcb.attached => true,
+                _ => tcb.detached,
+            };
+            if orphan {
+                self.tcbs[index].reset();
+            }
+        }
+    }
+}
+
+/// Generates an initial sequence number.
+fn initial_sequence() -> u32 {
+    // Unpredictable ISNs defeat blind spoofing and injection (RFC 6528). There
+    // is no per-connection hash, so a fresh random value is used every time.
+    random::next_u32()
+}
+
+/// Builds and sends one segment.
+#[allow(clippy::too_many_arguments)]
+fn send_segment(
+    local_port: u16,
+    remote_addr: Ipv4Addr,
+    remote_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
+    data: &[u8],
+) -> Result<(), NetError> {
+    let src = interface::address();
+    // SYN segments carry our MSS option (4 bytes).
+    let options_len = if flags & SYN != 0 { 4 } else { 0 };
+    let header_len = HEADER_LEN + options_len;
+    let len = header_len + data.len();
+    ipv4::send(src, remote_addr, ipv4::PROTO_TCP, |p| {
+        write_u16(p, 0, local_port);
+        write_u16(p, 2, remote_port);
+        write_u32(p, 4, seq);
+        write_u32(p, 8, ack);
+        p[12] = ((header_len / 4) as u8) << 4;
+        p[13] = flags;
+        write_u16(p, 14, window);
+        write_u16(p, 16, 0);
+        write_u16(p, 18, 0);
+        if options_len != 0 {
+            p[20] = OPT_MSS;
+            p[21] = 4;
+            write_u16(p, 22, OUR_MSS as u16);
+        }
+        p[header_len..len].copy_from_slice(data);
+        let sum = checksum::finish(checksum::accumulate(
+            checksum::pseudo_header(src, remote_addr, ipv4::PROTO_TCP, len),
+            &p[..len],
+        ));
+        write_u16(p, 16, sum);
+        len
+    })
+}
+
+/// Answers a segment that belongs to no connection with a reset (RFC 9293, section 3.10.7.1).
+fn send_reset(local_port: u16, remote_addr: Ipv4Addr, remote_port: u16, seq: u32, ack: u32, flags: u8, seg_len: u32) {
+    if flags & RST != 0 {
+        return;
+    }
+    let _ = if flags & ACK != 0 {
+        send_segment(local_port, remote_addr, remote_port, ack, 0, RST, 0, &[])
+    } else {
+        send_segment(local_port, remote_addr, remote_port, 0, seq.wrapping_add(seg_len), RST | ACK, 0, &[])
+    };
+}
+
+/// Extracts the MSS option from a SYN segment's options.
+fn parse_mss(options: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            OPT_END => break,
+            OPT_NOP => i += 1,
+            kind => {
+                let len = *options.get(i + 1)? as usize;
+                if len < 2 {
+                    return None;
+                }
+                if kind == OPT_MSS && len == 4 {
+                    return Some(read_u16(options, i + 2) as usize);
+                }
+                i += len;
+            }
+        }
+    }
+    None
+}
+
+/// Handles a received TCP segment.
+pub fn handle(src: Ipv4Addr, dst: Ipv4Addr, segment: &[u8]) {
+    if segment.len() < HEADER_LEN {
+        return;
+    }
+    let sum = checksum::accumulate(checksum::pseudo_header(src, dst, ipv4::PROTO_TCP, segment.len()), segment);
+    if checksum::finish(sum) != 0 {
+        return;
+    }
+    let src_port = read_u16(segment, 0);
+    let dst_port = read_u16(segment, 2);
+    let seq = read_u32(segment, 4);
+    let ack = read_u32(segment, 8);
+    let header_len = ((segment[12] >> 4) as usize) * 4;
+    if header_len < HEADER_LEN || header_len > segment.len() {
+        return;
+    }
+    let flags = segment[13];
+    let window = read_u16(segment, 14) as usize;
+    let options = &segment[HEADER_LEN..header_len];
+    let payload = &segment[header_len..];
+    // SYN and FIN each occupy one sequence number.
+    let seg_len = payload.len() as u32 + (flags & SYN != 0) as u32 + (flags & FIN != 0) as u32;
+
+    let mut table = TABLE.lock();
+    let Some(index) = table.lookup(dst_port, src, src_port) else {
+        send_reset(dst_port, src, src_port, seq, ack, flags, seg_len);
+        return;
+    };
+    match table.tcbs[index].state {
+        State::Listen => {
+            if flags & RST != 0 {
+                return;
+            }
+            if flags & ACK != 0 || flags & SYN == 0 {
+                send_reset(dst_port, src, src_port, seq, ack, flags, seg_len);
+                return;
+            }
+            let pending = table.tcbs.iter().filter(|tcb| tcb.parent == Some(index) && !tcb.attached).count();
+            if pending >= table.tcbs[index].backlog {
+                return;
+            }
+            let Ok(child) = table.allocate() else {
+                return;
+            };
+            let tcb = &mut table.tcbs[child];
+            tcb.state = State::SynReceived;
+            tcb.parent = Some(index);
+            tcb.local_port = dst_port;
+            tcb.remote_addr = src;
+            tcb.remote_port = src_port;
+            tcb.rcv_nxt = seq.wrapping_add(1);
+            tcb.iss = initial_sequence();
+            tcb.snd_una = tcb.iss;
+            tcb.snd_nxt = tcb.iss.wrapping_add(1);
+            tcb.snd_max = tcb.snd_nxt;
+            tcb.snd_wnd = window;
+            tcb.snd_mss = parse_mss(options).unwrap_or(DEFAULT_MSS).min(OUR_MSS);
+            tcb.send_syn();
+            tcb.arm_timer();
+        }
+        State::SynSent => {
+            let tcb = &mut table.tcbs[index];
+            let ack_ok = flags & ACK != 0 && ack == tcb.snd_nxt;
+            if flags & ACK != 0 && !ack_ok {
+                send_reset(dst_port, src, src_port, seq, ack, flags, seg_len);
+                return;
+            }
+            if flags & RST != 0 {
+                if ack_ok {
+                    tcb.abort(NetError::ConnectionRefused);
+                }
+                return;
+            }
+            if flags & SYN == 0 {
+                return;
+            }
+            tcb.rcv_nxt = seq.wrapping_add(1);
+            tcb.snd_mss = parse_mss(options).unwrap_or(DEFAULT_MSS).min(OUR_MSS);
+            tcb.snd_wnd = window;
+            if ack_ok {
+                tcb.snd_una = ack;
+                tcb.state = State::Established;
+                tcb.timer = None;
+                tcb.retries = 0;
+                tcb.send_ack();
+                tcb.output();
+            } else {
+                // Simultaneous open.
+                tcb.state = State::SynReceived;
+                tcb.send_syn();
+            }
+        }
+        _ => {
+            let tcb = &mut table.tcbs[index];
+            handle_synchronized(tcb, seq, ack, flags, window, payload);
+        }
+    }
+    table.collect();
+}
+
+/// Segment processing for every state after the initial handshake started.
+fn handle_synchronized(tcb: &mut Tcb, seq: u32, ack: u32, flags: u8, window: usize, payload: &[u8]) {
+    // Only in-order segments are accepted; trim data we already have.
+    let mut payload = payload;
+    let mut seq = seq;
+    if seq_lt(seq, tcb.rcv_nxt) {
+        let duplicate = tcb.rcv_nxt.wrapping_sub(seq) as usize;
+        if duplicate > payload.len() || (duplicate == payload.len() && flags & FIN == 0) {
+            // Entirely old: re-acknowledge (it may be a retransmission after a lost ACK).
+            if flags & RST == 0 {
+                tcb.send_ack();
+            }
+            return;
+        }
+        payload = &payload[duplicate..];
+        seq = tcb.rcv_nxt;
+    }
+    if seq != tcb.rcv_nxt {
+        // Out of order: drop it and ask for the missing data again.
+        if flags & RST == 0 {
+            tcb.send_ack();
+        }
+        return;
+    }
+    if flags & RST != 0 {
+        if tcb.state == State::SynReceived && tcb.parent.is_some() {
+            // The handshake of a passive open failed; the listener stays.
+            tcb.state = State::Closed;
+        } else {
+            tcb.abort(NetError::ConnectionReset);
+        }
+        return;
+    }
+    if flags & SYN != 0 {
+        // A SYN in a synchronized state is answered with a challenge ACK (RFC 5961).
+        tcb.send_ack();
+        return;
+    }
+    if flags & ACK == 0 {
+        return;
+    }
+    if tcb.state == State::SynReceived {
+        if seq_lt(tcb.snd_una, ack) && seq_le(ack, tcb.snd_nxt) {
+            // A close requested during the handshake takes effect now.
+            tcb.state = if tcb.fin_queued { State::FinWait1 } else { State::Established };
+            tcb.snd_una = ack;
+            tcb.timer = None;
+            tcb.retries = 0;
+            tcb.snd_wnd = window;
+        } else {
+            let _ = send_segment(tcb.local_port, tcb.remote_addr, tcb.remote_port, ack, 0, RST, 0, &[]);
+            return;
+        }
+    } else if !tcb.process_ack(ack, window) {
+        return;
+    }
+    if tcb.state == State::Closed || tcb.state == State::TimeWait {
+        return;
+    }
+
+    let mut need_ack = false;
+    let mut fin = flags & FIN != 0;
+    if !payload.is_empty() && matches!(tcb.state, State::Established | State::FinWait1 | State::FinWait2) {
+        let stored = tcb.rx.push(payload);
+        tcb.rcv_nxt = tcb.rcv_nxt.wrapping_add(stored as u32);
+        // A FIN is only in order if all the data before it was stored.
+        fin &= stored == payload.len();
+        need_ack = true;
+    }
+    if fin {
+        tcb.rcv_nxt = tcb.rcv_nxt.wrapping_add(1);
+        need_ack = true;
+        match tcb.state {
+            State::SynReceived | State::Established => tcb.state = State::CloseWait,
+            State::FinWait1 => tcb.state = State::Closing,
+            State::FinWait2 => tcb.enter_time_wait(),
+            _ => {}
+        }
+    }
+    if need_ack {
+        tcb.send_ack();
+    }
+    tcb.output();
+}
+
+/// Runs the retransmission and TIME-WAIT timers of all connections.
+pub fn poll() {
+    let mut table = TABLE.lock();
+    for tcb in table.tcbs.iter_mut() {
+        match tcb.state {
+            State::Closed | State::Listen => {}
+            State::TimeWait => {
+                if elapsed_ms(tcb.time_wait_start) >= TIME_WAIT_MS {
+                    tcb.state = State::Closed;
+                }
+            }
+            _ => {
+                if let Some(start) = tcb.timer
+                    && elapsed_ms(start) >= tcb.rto_ms
+                {
+                    tcb.on_timeout();
+                }
+            }
+        }
+    }
+    table.collect();
+}
+
+/// A socket listening for incoming connections. Dropping it stops listening.
+pub struct TcpListener {
+    index: usize,
+}
+
+impl TcpListener {
+    /// Listens on `port`, keeping at most `backlog` connections waiting for [`accept`](Self::accept).
+    pub fn bind(port: u16, backlog: usize) -> Result<TcpListener, NetError> {
+        let mut table = TABLE.lock();
+        if table.port_in_use(port) {
+            return Err(NetError::AddrInUse);
+        }
+        let port = if port == 0 { table.ephemeral_port()? } else { port };
+        let index = table.allocate()?;
+        let tcb = &mut table.tcbs[index];
+        tcb.state = State::Listen;
+        tcb.local_port = port;
+        tcb.attached = true;
+        tcb.backlog = backlog.max(1);
+        Ok(TcpListener { index })
+    }
+
+    /// Takes an established connection from the backlog.
+    ///
+    /// # Returns
+    /// The connection, or [`NetError::WouldBlock`] if none is ready.
+    pub fn accept(&self) -> Result<TcpStream, NetError> {
+        let mut table = TABLE.lock();
+        let ready = table.tcbs.iter().position(|tcb| {
+            tcb.parent == Some(self.index)
+                && !tcb.attached
+                && !matches!(tcb.state, State::SynReceived | State::Closed)
+        });
+        let index = ready.ok_or(NetError::WouldBlock)?;
+        table.tcbs[index].attached = true;
+        Ok(TcpStream { index })
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        let mut table = TABLE.lock();
+        // Reset connections that were never accepted and orphan the rest.
+        for tcb in table.tcbs.iter_mut() {
+            if tcb.parent != Some(self.index) {
+                continue;
+            }
+            if !tcb.attached && tcb.state != State::Closed {
+                tcb.send(RST | ACK, tcb.snd_nxt, &[]);
+                tcb.state = State::Closed;
+            }
+            tcb.parent = None;
+            if !tcb.attached {
+                tcb.reset();
+            }
+        }
+        table.tcbs[self.index].reset();
+    }
+}
+
+/// An established (or establishing) connection. Dropping it closes the connection.
+pub struct TcpStream {
+    index: usize,
+}
+
+impl TcpStream {
+    /// Starts an active open towards `addr:port`.
+    ///
+    /// The SYN is sent immediately; use [`state`](Self::state) to wait for the
+    /// handshake to complete.
+    pub fn connect(addr: Ipv4Addr, port: u16) -> Result<TcpStream, NetError> {
+        if interface::config().is_none() {
+            return Err(NetError::NotConfigured);
+        }
+        let mut table = TABLE.lock();
+        let local_port = table.ephemeral_port()?;
+        let index = table.allocate()?;
+        let tcb = &mut table.tcbs[index];
+        tcb.state = State::SynSent;
+        tcb.attached = true;
+        tcb.local_port = local_port;
+        tcb.remote_addr = addr;
+        tcb.remote_port = port;
+        tcb.iss = initial_sequence();
+        tcb.snd_una = tcb.iss;
+        tcb.snd_nxt = tcb.iss.wrapping_add(1);
+        tcb.snd_max = tcb.snd_nxt;
+        tcb.send_syn();
+        tcb.arm_timer();
+        Ok(TcpStream { index })
+    }
+
+    /// Returns the connection state, or the error that terminated it.
+    pub fn state(&self) -> Result<State, NetError> {
+        let table = TABLE.lock();
+        let tcb = &table.tcbs[self.index];
+        match tcb.error {
+            Some(error) => Err(error),
+            None => Ok(tcb.state),
+        }
+    }
+
+    /// Returns the remote address and port.
+    pub fn peer(&self) -> (Ipv4Addr, u16) {
+        let table = TABLE.lock();
+        let tcb = &table.tcbs[self.index];
+        (tcb.remote_addr, tcb.remote_port)
+    }
+
+    /// Queues `data` for transmission.
+    ///
+    /// # Returns
+    /// The number of bytes queued (possibly fewer than `data.len()`), or
+    /// [`NetError::WouldBlock`] if the send buffer is full.
+    pub fn send(&self, data: &[u8]) -> Result<usize, NetError> {
+        let mut table = TABLE.lock();
+        let tcb = &mut table.tcbs[self.index];
+        if let Some(error) = tcb.error {
+            return Err(error);
+        }
+        match tcb.state {
+            State::SynSent | State::SynReceived => return Err(NetError::WouldBlock),
+            State::Established | State::CloseWait if !tcb.fin_queued => {}
+            _ => return Err(NetError::NotConnected),
+        }
+        let queued = tcb.tx.push(data);
+        if queued == 0 && !data.is_empty() {
+            return Err(NetError::WouldBlock);
+        }
+        tcb.output();
+        Ok(queued)
+    }
+
+    /// Reads received data into `buf`.
+    ///
+    /// # Returns
+    /// The number of bytes read, `0` once the peer closed its side and all data
+    /// was read, or [`NetError::WouldBlock`] if no data is available yet.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize, NetError> {
+        let mut table = TABLE.lock();
+        let tcb = &mut table.tcbs[self.index];
+        let count = tcb.rx.pop(buf);
+        if count > 0 {
+            // Tell the peer about the reopened window if it was nearly closed.
+            if tcb.rcv_wnd_adv < tcb.snd_mss && tcb.rcv_wnd() >= tcb.snd_mss {
+                tcb.send_ack();
+            }
+            return Ok(count);
+        }
+        if let Some(error) = tcb.error {
+            return Err(error);
+        }
+        match tcb.state {
+            // The peer sent its FIN: end of stream.
+            State::CloseWait | State::Closing | State::LastAck | State::TimeWait | State::Closed => Ok(0),
+            _ => Err(NetError::WouldBlock),
+        }
+    }
+
+    /// Closes our side of the connection once the queued data has been sent.
+    pub fn close(&self) {
+        let mut table = TABLE.lock();
+        let tcb = &mut table.tcbs[self.index];
+        match tcb.state {
+            State::SynSent => tcb.state = State::Closed,
+            State::SynReceived => tcb.fin_queued = true,
+            State::Established => {
+                tcb.fin_queued = true;
+                tcb.state = State::FinWait1;
+            }
+            State::CloseWait => {
+                tcb.fin_queued = true;
+                tcb.state = State::LastAck;
+            }
+            _ => return,
+        }
+        tcb.output();
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        self.close();
+        let mut table = TABLE.lock();
+        let tcb = &mut table.tcbs[self.index];
+        tcb.detached = true;
+        tcb.parent = None;
+        table.collect();
+    }
+}
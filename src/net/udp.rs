@@ -0,0 +1,198 @@
+//! ---------------------------------------------------------------------------
+//! File       : udp.rs
+//! Module     : net::udp
+//! Author     : DiTurr
+//! Description:
+//! User Datagram Protocol (RFC 768) and UDP sockets.
+//!
+//! The kernel has no heap, so sockets live in a fixed table and every socket
+//! owns a small ring of received datagrams. Datagrams arriving for a full ring
+//! or an unbound port are dropped.
+//!
+//! ## Example
+//! ```rust
+//! let socket = UdpSocket::bind(0)?;
+//! socket.send_to(b"hello\n", Ipv4Addr::new(10, 0, 2, 2), 6666)?;
+//! if let Some((len, addr, port)) = socket.recv_from(&mut buf) { ... }
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::{checksum, interface, ipv4, read_u16, write_u16, NetError};
+use crate::sync::spinlock::SpinLock;
+
+/// Length of the UDP header.
+pub const HEADER_LEN: usize = 8;
+
+/// Largest payload of a single datagram.
+pub const MAX_PAYLOAD: usize = ipv4::MAX_PAYLOAD - HEADER_LEN;
+
+/// Maximum number of simultaneously bound sockets.
+const MAX_SOCKETS: usize = 8;
+
+/// Number of datagrams buffered per socket.
+const QUEUE_LEN: usize = 4;
+
+/// First port of the ephemeral range (RFC 6335).
+const EPHEMERAL_START: u16 = 49152;
+
+/// A buffered datagram.
+#[derive(Clone, Copy)]
+struct Datagram {
+    src: Ipv4Addr,
+    src_port: u16,
+    len: usize,
+    data: [u8; MAX_PAYLOAD],
+}
+
+/// A bound socket slot.
+struct Slot {
+    port: u16,
+    queue: [Datagram; QUEUE_LEN],
+    head: usize,
+    count: usize,
+}
+
+/// The socket table.
+struct Table {
+    slots: [Option<Slot>; MAX_SOCKETS],
+    next_ephemeral: u16,
+}
+
+static SOCKETS: SpinLock<Table> = SpinLock::new(Table {
+    slots: [const { None }; MAX_SOCKETS],
+    next_ephemeral: EPHEMERAL_START,
+});
+
+impl Table {
+    fn is_bound(&self, port: u16) -> bool {
+        self.slots.iter().flatten().any(|slot| slot.port == port)
+    }
+
+    /// Picks an unused port from the ephemeral range.
+    fn ephemeral_port(&mut self) -> Option<u16> {
+        for _ in 0..(u16::MAX - EPHEMERAL_START) {
+            let port = self.next_ephemeral;
+            self.next_ephemeral = if port == u16::MAX { EPHEMERAL_START } else { port + 1 };
+            if !self.is_bound(port) {
+                return Some(port);
+            }
+        }
+        None
+    }
+}
+
+/// A bound UDP socket. The port is released when the socket is dropped.
+pub struct UdpSocket {
+    index: usize,
+    port: u16,
+}
+
+impl UdpSocket {
+    /// Binds a socket to `port`, or to a free ephemeral port if `port` is 0.
+    pub fn bind(port: u16) -> Result<UdpSocket, NetError> {
+        let mut table = SOCKETS.lock();
+        let port = match port {
+            0 => table.ephemeral_port().ok_or(NetError::AddrInUse)?,
+            port if table.is_bound(port) => return Err(NetError::AddrInUse),
+            port => port,
+        };
+        let index = table.slots.iter().position(Option::is_none).ok_or(NetError::NoSockets)?;
+        table.slots[index] = Some(Slot {
+            port,
+            queue: [Datagram { src: Ipv4Addr::UNSPECIFIED, src_port: 0, len: 0, data: [0; MAX_PAYLOAD] }; QUEUE_LEN],
+            head: 0,
+            count: 0,
+        });
+        Ok(UdpSocket { index, port })
+    }
+
+    /// Sends `data` to `addr:port`.
+    pub fn send_to(&self, data: &[u8], addr: Ipv4Addr, port: u16) -> Result<(), NetError> {
+        send(interface::address(), self.port, addr, port, data)
+    }
+
+    /// Takes the oldest buffered datagram, copying it into `buf` (truncating if needed).
+    ///
+    /// # Returns
+    /// The copied length and the sender address and port, or `None` if no datagram is queued.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Option<(usize, Ipv4Addr, u16)> {
+        let mut table = SOCKETS.lock();
+        let slot = table.slots[self.index].as_mut()?;
+        if slot.count == 0 {
+            return None;
+        }
+        let datagram = &slot.queue[slot.head];
+        let len = datagram.len.min(buf.len());
+        buf[..len].copy_from_slice(&datagram.data[..len]);
+        let from = (len, datagram.src, datagram.src_port);
+        slot.head = (slot.head + 1) % QUEUE_LEN;
+        slot.count -= 1;
+        Some(from)
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        SOCKETS.lock().slots[self.index] = None;
+    }
+}
+
+/// Sends a single datagram from `src:src_port` to `dst:dst_port`.
+pub fn send(src: Ipv4Addr, src_port: u16, dst: Ipv4Addr, dst_port: u16, data: &[u8]) -> Result<(), NetError> {
+    if data.len() > MAX_PAYLOAD {
+        return Err(NetError::TooLarge);
+    }
+    let len = HEADER_LEN + data.len();
+    ipv4::send(src, dst, ipv4::PROTO_UDP, |p| {
+        write_u16(p, 0, src_port);
+        write_u16(p, 2, dst_port);
+        write_u16(p, 4, len as u16);
+        write_u16(p, 6, 0);
+        p[HEADER_LEN..len].copy_from_slice(data);
+        let sum = checksum::finish(checksum::accumulate(
+            checksum::pseudo_header(src, dst, ipv4::PROTO_UDP, len),
+            &p[..len],
+        ));
+        // A computed checksum of zero is transmitted as all ones (RFC 768).
+        write_u16(p, 6, if sum == 0 { 0xffff } else { sum });
+        len
+    })
+}
+
+/// Handles a received UDP datagram.
+pub fn handle(src: Ipv4Addr, dst: Ipv4Addr, datagram: &[u8]) {
+    if datagram.len() < HEADER_LEN {
+        return;
+    }
+    let len = read_u16(datagram, 4) as usize;
+    if len < HEADER_LEN || len > datagram.len() {
+        return;
+    }
+    let datagram = &datagram[..len];
+    // A zero checksum means the sender did not compute one.
+    if read_u16(datagram, 6) != 0 {
+        let sum = checksum::accumulate(checksum::pseudo_header(src, dst, ipv4::PROTO_UDP, len), datagram);
+        if checksum::finish(sum) != 0 {
+            return;
+        }
+    }
+    let src_port = read_u16(datagram, 0);
+    let dst_port = read_u16(datagram, 2);
+    let payload = &datagram[HEADER_LEN..];
+    let mut table = SOCKETS.lock();
+    let Some(slot) = table.slots.iter_mut().flatten().find(|slot| slot.port == dst_port) else {
+        return;
+    };
+    if slot.count == QUEUE_LEN {
+        return;
+    }
+    let tail = (slot.head + slot.count) % QUEUE_LEN;
+    let entry = &mut slot.queue[tail];
+    entry.src = src;
+    entry.src_port = src_port;
+    entry.len = payload.len();
+    entry.data[..payload.len()].copy_from_slice(payload);
+    slot.count += 1;
+}
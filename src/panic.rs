@@ -0,0 +1,220 @@
+//! ---------------------------------------------------------------------------
+//! File       : panic.rs
+//! Module     : panic
+//! Author     : DiTurr
+//! Description:
+//! The panic path. After the panic message is logged, the report is written
+//! straight to the UART, which needs no driver state nor lock:
+//! - the registers of the faulting context: all of them for a fatal trap (see
+//!   [`trap_panic`]), the stack and frame pointers and the CSRs otherwise;
+//! - a backtrace, walking the frame-pointer chain (the kernel is built with
+//!   `-C force-frame-pointers=yes`), with symbols (see `ksyms`);
+//! - the kernel message buffer.
+//!
+//! The machine is then stopped according to the `panic=` command line option:
+//! `poweroff` (default; QEMU exits with status 1, so automated runs terminate),
+//! `reboot` or `halt` (e.g. to attach a debugger). A panic raised while
+//! handling a panic skips the report and stops the machine right away. The
+//! other harts never leave the boot parking loop, so there is nothing to stop
+//! on them.
+//! ---------------------------------------------------------------------------
+
+use core::fmt;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+
+use crate::cmdline;
+use crate::log_warn;
+use crate::traps::trap_frame::TrapFrame;
+// The report itself only runs in the kernel (see `handle`).
+#[cfg(target_os = "none")]
+use {
+    core::fmt::Write,
+    core::panic::PanicInfo,
+    core::ptr::read_volatile,
+    core::sync::atomic::AtomicBool,
+    crate::arch::decode::REG_NAMES,
+    crate::ksyms,
+    crate::logger::kmsg,
+    crate::peripherals::uart::UartWriter,
+    crate::power,
+    crate::registers::{mcause::MCAUSE, mepc::MEPC, mhartid::MHARTID, mie::MIE, mip::MIP, mstatus::MSTATUS, mtval::MTVAL},
+    crate::stack,
+    crate::traps::traps::Trap,
+    crate::{log_error, uart_println},
+};
+
+/// Maximum number of frames printed in a backtrace.
+#[cfg(target_os = "none")]
+const MAX_FRAMES: usize = 32;
+
+/// What to do once the panic was reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Policy {
+    Poweroff = 0,
+    Reboot = 1,
+    Halt = 2,
+}
+
+/// The policy selected by `panic=`.
+static POLICY: AtomicU8 = AtomicU8::new(Policy::Poweroff as u8);
+
+/// Set once a panic is being handled.
+#[cfg(target_os = "none")]
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// The context of the fatal trap being reported, if the panic comes from one.
+static TRAP_FRAME: AtomicPtr<TrapFrame> = AtomicPtr::new(null_mut());
+
+/// Applies the `panic=` command line option.
+pub fn init() {
+    let policy = match cmdline::get("panic") {
+        None | Some("poweroff") => Policy::Poweroff,
+        Some("reboot") => Policy::Reboot,
+        Some("halt") => Policy::Halt,
+        Some(other) => {
+            log_warn!("panic: invalid policy '{}'.", other);
+            return;
+        }
+    };
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Panics because of a trap that cannot be handled, reporting the interrupted
+/// context `frame` in the panic dump.
+pub fn trap_panic(frame: &TrapFrame, message: fmt::Arguments) -> ! {
+    // The frame lives on the stack of the trap handler, which never returns.
+    TRAP_FRAME.store(frame as *const TrapFrame as *mut TrapFrame, Ordering::Relaxed);
+    panic!("{}", message)
+}
+
+/// Describes `mcause`, e.g. `Load Access Fault`.
+#[cfg(target_os = "none")]
+fn cause_name(mcause: usize) -> &'static str {
+    Trap::from_mcause(mcause).map_or("unknown", Trap::name)
+}
+
+/// Prints the registers of a trapped context.
+#[cfg(target_os = "none")]
+fn dump_trap_frame(frame: &TrapFrame) {
+    uart_println!("trap context (hart {}):", MHARTID::read());
+    for (row, regs) in frame.regs.chunks(4).enumerate() {
+        for (column, value) in regs.iter().enumerate() {
+            let _ = write!(UartWriter, " {:>4}: {:#018x}", REG_NAMES[row * 4 + column], value);
+        }
+        uart_println!();
+    }
+    uart_println!(" mepc: {}", ksyms::symbolize(frame.mepc));
+    uart_println!("   ra: {}", ksyms::symbolize_return(frame.regs[1]));
+    uart_println!(" mtval: {:#018x}", frame.mtval);
+    uart_println!(" mstatus: {:#018x}  mcause: {:#018x} ({})", frame.mstatus, frame.mcause, cause_name(frame.mcause));
+}
+
+/// Prints the registers of the panicking code: only the stack and frame
+/// pointers are meaningful, plus the machine CSRs.
+#[cfg(target_os = "none")]
+fn dump_current(sp: usize, fp: usize) {
+    let mcause = MCAUSE::read();
+    uart_println!("panic context (hart {}):", MHARTID::read());
+    uart_println!("   sp: {:#018x}    s0: {:#018x}", sp, fp);
+    uart_println!(" mstatus: {:#018x}  mie: {:#018x}  mip: {:#018x}", MSTATUS::read(), MIE::read(), MIP::read());
+    uart_println!(" last trap: mepc {}", ksyms::symbolize(MEPC::read()));
+    uart_println!("            mtval {:#018x}  mcause {:#018x} ({})", MTVAL::read(), mcause, cause_name(mcause));
+}
+
+/// Prints the return addresses found by walking the frame-pointer chain.
+///
+/// With frame pointers, `s0` points just above the saved return address
+/// (`s0 - 8`) and the caller's `s0` (`s0 - 16`). The walk stops at the first
+/// frame pointer outside the stack it started on (see `stack`) or not above
+/// the previous one.
+///
+/// # Arguments
+/// * `pc` - The faulting instruction, printed first if known.
+/// * `fp` - The frame pointer of the faulting function.
+#[cfg(target_os = "none")]
+fn backtrace(pc: Option<usize>, mut fp: usize) {
+    let (low, high) = stack::find(fp.wrapping_sub(16)).map_or((0, 0), |stack| (stack.bottom, stack.top));
+    uart_println!("backtrace:");
+    let mut depth = 0;
+    if let Some(pc) = pc {
+        uart_println!("  #{:<2} {}", depth, ksyms::symbolize(pc));
+        depth += 1;
+    }
+    while depth < MAX_FRAMES && fp.is_multiple_of(8) && fp >= low + 16 && fp <= high {
+        // SAFETY: `fp` lies within the kernel stack.
+        let (ra, caller_fp) = unsafe { (read_volatile((fp - 8) as *const usize), read_volatile((fp - 16) as *const usize)) };
+        if ra == 0 {
+            break;
+        }
+        uart_println!("  #{:<2} {}", depth, ksyms::symbolize_return(ra));
+        depth += 1;
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+}
+
+/// Stops the machine according to the policy.
+#[cfg(target_os = "none")]
+fn finish() -> ! {
+    match POLICY.load(Ordering::Relaxed) {
+        p if p == Policy::Reboot as u8 => power::reboot(),
+        p if p == Policy::Halt as u8 => power::halt(),
+        _ => power::exit_with_code(1),
+    }
+}
+
+/// Reports a panic and stops the machine. Called by the `#[panic_handler]`.
+#[cfg(target_os = "none")]
+pub fn handle(info: &PanicInfo) -> ! {
+    // Nothing must interrupt the report.
+    unsafe { core::arch::asm!("csrci mstatus, 8") };
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        let _ = UartWriter.write_str("\nrecursive panic, stopping.\n");
+        finish();
+    }
+    // Where the panic handler was entered, for the backtrace.
+    let (sp, fp): (usize, usize);
+    unsafe {
+        core::arch::asm!("mv {0}, sp", out(reg) sp);
+        core::arch::asm!("mv {0}, s0", out(reg) fp);
+    }
+    // If the panic has location information, log it.
+    // Otherwise, log a generic panic message.
+    if let Some(location) = info.location() {
+        log_error!(
+            "Kernel panic at line {}, file {}: {}",
+            location.line(),
+            location.file(),
+            info.message()
+        );
+    } else {
+        log_error!("Kernel panic without additional information.");
+    }
+    // In the test kernel, the panic fails the running test.
+    #[cfg(test)]
+    crate::testing::fail();
+    let frame = TRAP_FRAME.load(Ordering::Relaxed);
+    // SAFETY: Set by `trap_panic` to a frame that outlives the panic.
+    match unsafe { frame.as_ref() } {
+        Some(frame) => {
+            dump_trap_frame(frame);
+            backtrace(Some(frame.mepc), frame.regs[8]);
+        }
+        None => {
+            dump_current(sp, fp);
+            backtrace(None, fp);
+        }
+    }
+    // Replay the kernel message buffer on the UART: the log may have gone to
+    // sinks nobody is watching.
+    uart_println!("---[ kernel log ]---");
+    kmsg::dump(|line| {
+        let _ = UartWriter.write_str(line);
+    });
+    uart_println!("---[ end of kernel log ]---");
+    finish()
+}
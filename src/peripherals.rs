@@ -4,4 +4,8 @@
 //! Author     : DiTurr
 //! Description: Common peripheral interfaces and shared functionality.
 //! ---------------------------------------------------------------------------
+pub mod clint;
+pub mod plic;
+pub mod rtc;
 pub mod uart;
+pub mod virtio;
@@ -0,0 +1,47 @@
+//! ---------------------------------------------------------------------------
+//! File       : clint.rs
+//! Module     : peripherals::clint
+//! Author     : DiTurr
+//! Description:
+//! Driver for the Core Local Interruptor (CLINT) of the QEMU `virt` machine,
+//! which raises the machine software and timer interrupts of each hart:
+//! - `msip`: writing 1 raises the software interrupt of the hart (an IPI,
+//!   possibly to itself), writing 0 clears it;
+//! - `mtimecmp`: the timer interrupt is pending while `mtime` (the `time` CSR)
+//!   is at or past it.
+//! ---------------------------------------------------------------------------
+
+use core::ptr::{read_volatile, write_volatile};
+
+/// Base address of the CLINT on the QEMU `virt` machine.
+const CLINT_BASE: usize = 0x200_0000;
+
+// Register offsets, for hart 0 (then one register per hart).
+const MSIP: usize     = 0x0000;
+const MTIMECMP: usize = 0x4000;
+
+/// Raises the software interrupt of `hart`.
+pub fn send_ipi(hart: usize) {
+    unsafe { write_volatile((CLINT_BASE + MSIP + 4 * hart) as *mut u32, 1) };
+}
+
+/// Clears the software interrupt of `hart`.
+pub fn clear_ipi(hart: usize) {
+    unsafe { write_volatile((CLINT_BASE + MSIP + 4 * hart) as *mut u32, 0) };
+}
+
+/// Raises the timer interrupt of `hart` once `mtime` reaches `deadline`
+/// (right away if it is past).
+pub fn set_timer(hart: usize, deadline: u64) {
+    unsafe { write_volatile((CLINT_BASE + MTIMECMP + 8 * hart) as *mut u64, deadline) };
+}
+
+/// Returns the deadline of the timer of `hart` (`u64::MAX` when disarmed).
+pub fn timer_deadline(hart: usize) -> u64 {
+    unsafe { read_volatile((CLINT_BASE + MTIMECMP + 8 * hart) as *const u64) }
+}
+
+/// Clears the timer interrupt of `hart`: it never fires.
+pub fn disarm_timer(hart: usize) {
+    set_timer(hart, u64::MAX);
+}
@@ -0,0 +1,262 @@
+//! ---------------------------------------------------------------------------
+//! File       : plic.rs
+//! Module     : peripherals::plic
+//! Author     : DiTurr
+//! Description:
+//! Driver for the Platform-Level Interrupt Controller (PLIC) of the QEMU
+//! `virt` machine, which routes the device interrupt lines to the machine
+//! external interrupt of the harts. Each hart has a context per privilege
+//! mode; the M-mode context of hart `n` is `2 * n`. A hart claims the highest
+//! priority pending line, serves it, then completes it.
+//!
+//! Each line has a priority (1-7, 0 never interrupts) and each context a
+//! threshold: only the enabled lines with a priority above the threshold
+//! interrupt it. Drivers [`register`] a [`Handler`] for their line, which sets
+//! its priority and enables it; `traps::interrupts` runs the handlers, and
+//! raises the threshold while a preemptible one runs.
+//!
+//! ## Example
+//! ```rust
+//! static HANDLER: Handler = Handler::new("virtio-net", 1, interrupt).preemptible();
+//!
+//! plic::register(transport.irq(), &HANDLER)?;
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::registers::mhartid::MHARTID;
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+use crate::traps::interrupts;
+
+/// Base address of the PLIC on the QEMU `virt` machine.
+const PLIC_BASE: usize = 0xc00_0000;
+
+// Register offsets: line priorities, then those of context 0 (the enable
+// bits 0x80 apart, the threshold and claim registers 0x1000 apart).
+const PRIORITY: usize  = 0x0000;
+const ENABLE: usize    = 0x2000;
+const THRESHOLD: usize = 0x20_0000;
+const CLAIM: usize     = 0x20_0004;
+
+/// Stride between the enable bits of two contexts.
+const ENABLE_STRIDE: usize = 0x80;
+
+/// Stride between the threshold and claim registers of two contexts.
+const CONTEXT_STRIDE: usize = 0x1000;
+
+/// Number of lines handled (line 0 does not exist). The `virt` devices use
+/// lines 1-8 (virtio), 10 (UART), 11 (RTC) and 32-35 (PCIe).
+pub const LINES: usize = 64;
+
+/// Highest line priority.
+pub const MAX_PRIORITY: u32 = 7;
+
+/// Handlers, per line.
+static HANDLERS: SpinLock<[Option<&'static Handler>; LINES]> = SpinLock::new([None; LINES]);
+
+fn reg(offset: usize) -> *mut u32 {
+    (PLIC_BASE + offset) as *mut u32
+}
+
+/// Returns the M-mode context of `hart`.
+pub fn context(hart: usize) -> usize {
+    2 * hart
+}
+
+/// Sets the priority of line `irq`.
+fn set_priority(irq: u32, priority: u32) {
+    unsafe { write_volatile(reg(PRIORITY + 4 * irq as usize), priority) };
+}
+
+/// Enables or disables line `irq` for `context`.
+fn set_enabled(context: usize, irq: u32, enabled: bool) {
+    let word = reg(ENABLE + ENABLE_STRIDE * context + 4 * (irq as usize / 32));
+    let bit = 1 << (irq % 32);
+    unsafe {
+        let bits = read_volatile(word);
+        write_volatile(word, if enabled { bits | bit } else { bits & !bit });
+    }
+}
+
+/// Returns the priority threshold of `context`.
+pub fn threshold(context: usize) -> u32 {
+    unsafe { read_volatile(reg(THRESHOLD + CONTEXT_STRIDE * context)) }
+}
+
+/// Sets the priority threshold of `context`: lines at or below it do not
+/// interrupt it.
+pub fn set_threshold(context: usize, threshold: u32) {
+    unsafe { write_volatile(reg(THRESHOLD + CONTEXT_STRIDE * context), threshold) };
+}
+
+/// Claims the highest priority pending line of `context`.
+///
+/// # Returns
+/// `None` if no line is pending.
+pub fn claim(context: usize) -> Option<u32> {
+    let irq = unsafe { read_volatile(reg(CLAIM + CONTEXT_STRIDE * context)) };
+    (irq != 0).then_some(irq)
+}
+
+/// Completes line `irq`, claimed by `context`: it can be raised again.
+pub fn complete(context: usize, irq: u32) {
+    unsafe { write_volatile(reg(CLAIM + CONTEXT_STRIDE * context), irq) };
+}
+
+/// The handler of one or more device lines.
+pub struct Handler {
+    /// Name shown by the monitor's `irqstat`.
+    pub name: &'static str,
+    /// Priority of its lines, 1 to [`MAX_PRIORITY`].
+    pub priority: u32,
+    /// Runs with interrupts enabled.
+    preemptible: bool,
+    /// May run again (from another line) before it returns.
+    reentrant: bool,
+    run: fn(u32),
+    /// Set while a non-reentrant handler runs.
+    running: AtomicBool,
+    /// Lines that were raised while a non-reentrant handler ran: bit `n` for line `n`.
+    deferred: AtomicU64,
+}
+
+impl Handler {
+    /// Creates a handler that calls `run` with the line, with interrupts
+    /// disabled.
+    pub const fn new(name: &'static str, priority: u32, run: fn(u32)) -> Self {
+        Handler {
+            name,
+            priority,
+            preemptible: false,
+            reentrant: true,
+            run,
+            running: AtomicBool::new(false),
+            deferred: AtomicU64::new(0),
+        }
+    }
+
+    /// Lets the timer, IPIs and lines of a higher priority preempt the
+    /// handler (within the nesting limit of `traps::interrupts`).
+    pub const fn preemptible(self) -> Self {
+        Handler { preemptible: true, ..self }
+    }
+
+    /// Keeps the handler from being entered again, from another of its
+    /// lines, while it is preempted: such lines wait until it returns.
+    pub const fn non_reentrant(self) -> Self {
+        Handler { reentrant: false, ..self }
+    }
+
+    /// Returns whether the handler runs with interrupts enabled.
+    pub fn is_preemptible(&self) -> bool {
+        self.preemptible
+    }
+
+    /// Returns whether the handler may be entered again while it is preempted.
+    pub fn is_reentrant(&self) -> bool {
+        self.reentrant
+    }
+
+    /// Starts serving line `irq`, claimed by `context`.
+    ///
+    /// # Returns
+    /// `false` if the handler is non-reentrant and already running: the line is
+    /// disabled until [`Handler::leave`].
+    pub fn enter(&self, context: usize, irq: u32) -> bool {
+        if self.reentrant || !self.running.swap(true, Ordering::Acquire) {
+            return true;
+        }
+        set_enabled(context, irq, false);
+        self.deferred.fetch_or(1 << irq, Ordering::Relaxed);
+        false
+    }
+
+    /// Calls the handler for line `irq`.
+    pub fn run(&self, irq: u32) {
+        (self.run)(irq);
+    }
+
+    /// Ends serving a line: enables again the lines deferred meanwhile (still
+    /// pending, they are raised again).
+    pub fn leave(&self, context: usize) {
+        if self.reentrant {
+            return;
+        }
+        // Lines deferred until the flag is cleared are collected below.
+        self.running.store(false, Ordering::Release);
+        let deferred = self.deferred.swap(0, Ordering::Relaxed);
+        for irq in (1..LINES as u32).filter(|irq| deferred & (1 << irq) != 0) {
+            set_enabled(context, irq, true);
+        }
+    }
+}
+
+/// Registers `handler` for line `irq`, sets the line priority and enables it
+/// on this hart.
+///
+/// # Returns
+/// `EINVAL` if the line or the priority is out of range, `EEXIST` if the line
+/// has a handler.
+pub fn register(irq: u32, handler: &'static Handler) -> Result<(), Errno> {
+    if irq == 0 || irq as usize >= LINES || !(1..=MAX_PRIORITY).contains(&handler.priority) {
+        return Err(Errno::EINVAL);
+    }
+    // The handlers are looked up in interrupt context.
+    interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        if handlers[irq as usize].is_some() {
+            return Err(Errno::EEXIST);
+        }
+        handlers[irq as usize] = Some(handler);
+        set_priority(irq, handler.priority);
+        set_enabled(context(MHARTID::read()), irq, true);
+        Ok(())
+    })
+}
+
+/// Returns the handler of line `irq`.
+pub fn handler(irq: u32) -> Option<&'static Handler> {
+    HANDLERS.lock().get(irq as usize).copied().flatten()
+}
+
+/// Returns the lines with a handler.
+pub fn handlers() -> impl Iterator<Item = (u32, &'static Handler)> {
+    let handlers = interrupts::without_interrupts(|| *HANDLERS.lock());
+    (0..LINES as u32).zip(handlers).filter_map(|(irq, handler)| Some((irq, handler?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignore(_: u32) {}
+
+    #[test_case]
+    fn invalid_registrations_are_rejected() {
+        static ZERO: Handler = Handler::new("zero", 0, ignore);
+        static HANDLER: Handler = Handler::new("test", 1, ignore);
+        assert_eq!(register(0, &HANDLER), Err(Errno::EINVAL));
+        assert_eq!(register(LINES as u32, &HANDLER), Err(Errno::EINVAL));
+        assert_eq!(register(1, &ZERO), Err(Errno::EINVAL));
+    }
+
+    // The PLIC registers only exist on the target.
+    #[cfg(target_os = "none")]
+    #[test_case]
+    fn non_reentrant_handlers_defer_their_lines() {
+        static HANDLER: Handler = Handler::new("test", 1, ignore).preemptible().non_reentrant();
+        // No device drives the last line.
+        let (context, irq) = (context(MHARTID::read()), LINES as u32 - 1);
+        assert!(HANDLER.enter(context, irq));
+        assert!(!HANDLER.enter(context, irq));
+        assert_eq!(HANDLER.deferred.load(Ordering::Relaxed), 1 << irq);
+        HANDLER.leave(context);
+        assert_eq!(HANDLER.deferred.load(Ordering::Relaxed), 0);
+        assert!(HANDLER.enter(context, irq));
+        HANDLER.leave(context);
+        set_enabled(context, irq, false);
+    }
+}
@@ -0,0 +1,54 @@
+//! ---------------------------------------------------------------------------
+//! File       : rtc.rs
+//! Module     : peripherals::rtc
+//! Author     : DiTurr
+//! Description:
+//! Driver for the Goldfish real-time clock of the QEMU `virt` machine, which
+//! reports the host's wall-clock time as nanoseconds since the Unix epoch.
+//! Only the time registers are used: the clock is read once at boot (see
+//! `time::init`) and the timebase counts from there.
+//! ---------------------------------------------------------------------------
+
+use core::ptr::read_volatile;
+
+use crate::fdt;
+
+/// Base address of the RTC on the QEMU `virt` machine.
+const RTC_BASE: usize = 0x10_1000;
+
+// Register offsets.
+const TIME_LOW: usize  = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+/// Device tree compatible string of the RTC.
+const COMPATIBLE: &[u8] = b"google,goldfish-rtc";
+
+/// The Goldfish RTC.
+pub struct GoldfishRtc;
+
+/// Global static RTC instance.
+pub static RTC: GoldfishRtc = GoldfishRtc;
+
+impl GoldfishRtc {
+    /// Returns `true` if the device tree describes the RTC at the expected
+    /// address. Without a device tree the RTC is assumed absent.
+    pub fn probe(&self) -> bool {
+        fdt::get()
+            .and_then(|fdt| fdt.find_node("/soc/rtc@101000"))
+            .and_then(|node| node.property("compatible"))
+            .is_some_and(|compatible| compatible.split(|&b| b == 0).any(|name| name == COMPATIBLE))
+    }
+
+    /// Reads the current time.
+    ///
+    /// # Returns
+    /// Nanoseconds since the Unix epoch.
+    pub fn read_ns(&self) -> u64 {
+        unsafe {
+            // Reading the low word latches the high word.
+            let low = read_volatile((RTC_BASE + TIME_LOW) as *const u32);
+            let high = read_volatile((RTC_BASE + TIME_HIGH) as *const u32);
+            (high as u64) << 32 | low as u64
+        }
+    }
+}
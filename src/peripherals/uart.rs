@@ -5,12 +5,15 @@
 //! Description:
 //! This module provides a minimal UART interface for sending characters and strings
 //! over a serial interface using memory-mapped I/O (MMIO). It is designed for use
-//! in `no_std` environments such as kernels or embedded systems.
+//! in `no_std` environments such as kernels or embedded systems. Host builds (unit tests)
+//! use a mock instead (see `peripherals::uart::mock`).
 //!
 //! ## Features
 //! - `Uart::putb`: Send a single byte.
 //! - `Uart::puts`: Send a string slice byte-by-byte.
+//! - `Uart::getb`: Receive a single byte, if one is pending.
 //! - `UART`: Global static UART instance.
+//! - `UartWriter`: `core::fmt::Write` adapter writing to `UART`.
 //! - `uart_println!`: `println!`-like macro that writes to UART with formatting.
 //!
 //! ## Example
@@ -23,9 +26,19 @@
 //! ```
 //! ---------------------------------------------------------------------------
 
+#[cfg(not(target_os = "none"))]
+pub mod mock;
+
+use core::fmt;
+#[cfg(target_os = "none")]
 use core::ptr::{read_volatile, write_volatile};
 
+use crate::fs::CharDevice;
+use crate::logger::sink::LogSink;
+use crate::syscalls::errno::Errno;
+
 /// Base address of the UART MMIO register block.
+#[cfg(target_os = "none")]
 /// This address must match the hardware or QEMU memory map.
 const UART_BASE: usize = 0x1000_0000;
 
@@ -61,10 +74,14 @@ impl Uart {
     /// Performs raw pointer access to MMIO registers, and should only
     /// be used when it is safe to access the UART hardware.
     pub fn putb(&self, byte: u8) {
+        #[cfg(not(target_os = "none"))]
+        mock::putb(byte);
+        #[cfg(target_os = "none")]
         unsafe {
             // Wait for Transmit Holding Register (THR) to be empty.
             while read_volatile((UART_BASE + 5) as *const u8) & (1 << 5) == 0 {}
-            write_volatile((UART_BASE + 0) as *mut u8, byte);
+            // THR is at offset 0.
+            write_volatile(UART_BASE as *mut u8, byte);
         }
     }
 
@@ -84,6 +101,59 @@ impl Uart {
             self.putb(b);
         }
     }
+
+    /// Receives a single byte over UART without waiting.
+    ///
+    /// # Returns
+    /// The byte in the Receiver Buffer Register (RBR), or `None` if no data is ready.
+    pub fn getb(&self) -> Option<u8> {
+        #[cfg(not(target_os = "none"))]
+        return mock::getb();
+        #[cfg(target_os = "none")]
+        unsafe {
+            // Data Ready (DR) bit of the Line Status Register (LSR).
+            if read_volatile((UART_BASE + 5) as *const u8) & 1 == 0 {
+                return None;
+            }
+            // RBR is at offset 0.
+            Some(read_volatile(UART_BASE as *const u8))
+        }
+    }
+}
+
+impl LogSink for Uart {
+    fn name(&self) -> &'static str {
+        "ttyS0"
+    }
+
+    fn write_str(&self, s: &str) {
+        self.puts(s);
+    }
+}
+
+impl CharDevice for Uart {
+    /// Reads the bytes already received; fails with `EAGAIN` if there are none.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let mut count = 0;
+        while count < buf.len() {
+            match self.getb() {
+                Some(byte) => buf[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        if count == 0 && !buf.is_empty() {
+            return Err(Errno::EAGAIN);
+        }
+        Ok(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        for &byte in buf {
+            self.putb(byte);
+        }
+        Ok(buf.len())
+    }
 }
 
 /// Global static UART instance.
@@ -92,10 +162,29 @@ impl Uart {
 /// peripherals are accessible.
 pub static UART: Uart = Uart::new();
 
+/// Formatted output to the global [`UART`], bypassing the log sinks: the
+/// writer of [`uart_println!`] and of the panic report.
+///
+/// # Examples
+/// ```
+/// let _ = write!(UartWriter, "{:#x}", 0x42);
+/// ```
+// Host builds have no panic report, and nothing else prints with it yet.
+#[cfg_attr(not(target_os = "none"), allow(dead_code))]
+pub struct UartWriter;
+
+impl fmt::Write for UartWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        UART.puts(s);
+        Ok(())
+    }
+}
+
 /// Macro for printing a formatted line to UART.
 ///
 /// This macro behaves similarly to `println!`, but writes directly to the
-/// UART serial interface using the global [`UART`] instance and `core::fmt::Write`.
+/// UART serial interface through [`UartWriter`], bypassing the log sinks (log
+/// messages should use the `log_*!` macros).
 ///
 /// It automatically appends a newline (`\n`) at the end of the message.
 ///
@@ -108,18 +197,6 @@ pub static UART: Uart = Uart::new();
 macro_rules! uart_println {
     ($($arg:tt)*) => {{
         use core::fmt::Write;
-
-        // Adapter implementing core::fmt::Write for the UART
-        struct UartWriter;
-
-        impl core::fmt::Write for UartWriter {
-            fn write_str(&mut self, s: &str) -> core::fmt::Result {
-                $crate::UART.puts(s);
-                Ok(())
-            }
-        }
-
-        // Use `writeln!` to print the formatted message + newline.
-        let _ = writeln!(UartWriter, $($arg)*);
+        let _ = writeln!($crate::peripherals::uart::UartWriter, $($arg)*);
     }};
 }
@@ -0,0 +1,51 @@
+//! ---------------------------------------------------------------------------
+//! File       : mock.rs
+//! Module     : peripherals::uart::mock
+//! Author     : DiTurr
+//! Description:
+//! Mock UART for host builds (unit tests): transmitted bytes go to the standard
+//! output, received bytes come from a queue filled by the tests.
+//!
+//! ## Example
+//! ```rust
+//! mock::push_input(b"ls\n");
+//! assert_eq!(UART.getb(), Some(b'l'));
+//! ```
+//! ---------------------------------------------------------------------------
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Bytes waiting to be received.
+static INPUT: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
+
+/// Transmits `byte`.
+pub fn putb(byte: u8) {
+    let _ = std::io::stdout().write_all(&[byte]);
+}
+
+/// Receives a byte, if one is queued.
+pub fn getb() -> Option<u8> {
+    INPUT.lock().unwrap().pop_front()
+}
+
+/// Queues `bytes` to be received.
+#[cfg(test)]
+pub fn push_input(bytes: &[u8]) {
+    INPUT.lock().unwrap().extend(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peripherals::uart::UART;
+
+    #[test_case]
+    fn queued_input_is_received_in_order() {
+        push_input(b"ls");
+        assert_eq!(UART.getb(), Some(b'l'));
+        assert_eq!(UART.getb(), Some(b's'));
+        assert_eq!(UART.getb(), None);
+    }
+}
@@ -0,0 +1,90 @@
+//! ---------------------------------------------------------------------------
+//! File       : virtio.rs
+//! Module     : peripherals::virtio
+//! Author     : DiTurr
+//! Description:
+//! Common VirtIO definitions and device discovery for the virtio-mmio transport
+//! (version 2, "modern") exposed by the QEMU `virt` machine.
+//!
+//! QEMU maps up to eight virtio-mmio slots starting at `0x1000_1000`, each one
+//! `0x1000` bytes apart. Slots without a backend report a device ID of 0.
+//! QEMU defaults to the legacy interface, so it must be started with
+//! `-global virtio-mmio.force-legacy=false`.
+//!
+//! ## Example
+//! ```rust
+//! if let Some(transport) = virtio::find_device(virtio::DeviceType::Network) {
+//!     VIRTIO_NET.init(transport)?;
+//! }
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod console;
+pub mod mmio;
+pub mod net;
+pub mod queue;
+pub mod rng;
+
+use mmio::VirtioMmio;
+
+/// Base address of the first virtio-mmio slot.
+const VIRTIO_MMIO_BASE: usize = 0x1000_1000;
+
+/// Distance in bytes between two consecutive virtio-mmio slots.
+const VIRTIO_MMIO_STRIDE: usize = 0x1000;
+
+/// Number of virtio-mmio slots on the QEMU `virt` machine.
+const VIRTIO_MMIO_SLOTS: usize = 8;
+
+/// PLIC line of the first virtio-mmio slot (the others follow).
+const VIRTIO_IRQ: u32 = 1;
+
+/// VirtIO device types (virtio spec 1.2, section 5).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DeviceType {
+    Network = 1,
+    Console = 3,
+    Entropy = 4,
+}
+
+/// Device status bits written by the driver during initialization.
+pub mod status {
+    pub const ACKNOWLEDGE: u32        = 1;
+    pub const DRIVER: u32             = 2;
+    pub const DRIVER_OK: u32          = 4;
+    pub const FEATURES_OK: u32        = 8;
+    pub const FAILED: u32             = 128;
+}
+
+/// Transport feature bits shared by all device types.
+pub mod features {
+    /// The device complies with virtio 1.0 or later (mandatory for modern devices).
+    pub const VERSION_1: u64 = 1 << 32;
+}
+
+/// Errors reported while bringing up a VirtIO device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtioError {
+    /// The slot does not contain a virtio-mmio device.
+    BadMagic,
+    /// The device uses an interface version this driver does not support.
+    UnsupportedVersion(u32),
+    /// The device rejected the negotiated feature set.
+    FeaturesRejected,
+    /// The requested queue does not exist or is too small.
+    QueueUnavailable(u16),
+    /// The queue is already in use by the device.
+    QueueInUse(u16),
+    /// Static driver memory was already handed out (driver initialized twice).
+    AlreadyInitialized,
+}
+
+/// Finds the first virtio-mmio slot holding a device of the given type.
+///
+/// # Returns
+/// The transport of the matching device, or `None` if no such device exists.
+pub fn find_device(device: DeviceType) -> Option<VirtioMmio> {
+    (0..VIRTIO_MMIO_SLOTS)
+        .map(|slot| VirtioMmio::new(VIRTIO_MMIO_BASE + slot * VIRTIO_MMIO_STRIDE))
+        .find(|transport| transport.is_valid() && transport.device_id() == device as u32)
+}
@@ -0,0 +1,436 @@
+//! ---------------------------------------------------------------------------
+//! File       : console.rs
+//! Module     : peripherals::virtio::console
+//! Author     : DiTurr
+//! Description:
+//! This module implements a polled virtio console driver (virtio spec 1.2,
+//! section 5.3) with multiport support.
+//!
+//! Port 0 uses queues 0 (receive) and 1 (transmit); with `MULTIPORT`, queues 2
+//! and 3 carry control messages and port `n > 0` uses queues `2(n+1)` and
+//! `2(n+1)+1`. Ports are announced by the device over the control queue
+//! (`DEVICE_ADD`), acknowledged by the driver (`PORT_READY`) and opened from the
+//! guest side right away (`PORT_OPEN`). The host side of a port is open while a
+//! client is connected to its character device; output to a closed port is
+//! discarded.
+//!
+//! Ports are exposed as `hvc<n>` handles usable as log sinks and character
+//! devices.
+//!
+//! ## Example
+//! ```rust
+//! VIRTIO_CONSOLE.init(transport)?;
+//! HVC[1].write_str("kernel log line\n");
+//! let count = HVC[0].read(&mut buf)?;
+//! ```
+//! ---------------------------------------------------------------------------
+
+use super::mmio::VirtioMmio;
+use super::queue::{Buffer, QueueMemory, VirtQueue, QUEUE_SIZE};
+use super::VirtioError;
+use crate::fs::CharDevice;
+use crate::logger::sink::LogSink;
+use crate::sync::spinlock::SpinLock;
+use crate::sync::static_cell::StaticCell;
+use crate::syscalls::errno::Errno;
+
+/// Feature bit: the device supports multiple ports and the control queues.
+const VIRTIO_CONSOLE_F_MULTIPORT: u64 = 1 << 1;
+
+/// Offset of `max_nr_ports` in the configuration space.
+const CONFIG_MAX_NR_PORTS: usize = 4;
+
+/// Number of ports handled by the driver.
+pub const MAX_PORTS: usize = 3;
+
+/// Control receive queue index.
+const CONTROL_RX_QUEUE: u16 = 2;
+/// Control transmit queue index.
+const CONTROL_TX_QUEUE: u16 = 3;
+
+/// Number of queues for `MAX_PORTS` ports plus the control queues.
+const QUEUE_COUNT: usize = 2 * MAX_PORTS + 2;
+
+/// Size of the receive buffer posted for every port.
+const RX_LEN: usize = 64;
+/// Size of the transmit buffer of every port.
+const TX_LEN: usize = 256;
+/// Size of the per-port input ring holding received, unread bytes.
+const INPUT_LEN: usize = 256;
+
+/// Number and size of the control receive buffers.
+const CONTROL_RX_BUFFERS: usize = 4;
+const CONTROL_LEN: usize = 64;
+
+/// Size of `struct virtio_console_control`.
+const CONTROL_MSG_LEN: usize = 8;
+
+/// Polls of the used ring before a transmission is given up.
+const TX_SPIN_LIMIT: usize = 1_000_000;
+
+/// Control message events.
+mod event {
+    pub const DEVICE_READY: u16  = 0;
+    pub const DEVICE_ADD: u16    = 1;
+    pub const DEVICE_REMOVE: u16 = 2;
+    pub const PORT_READY: u16    = 3;
+    pub const CONSOLE_PORT: u16  = 4;
+    pub const PORT_OPEN: u16     = 6;
+}
+
+/// Buffers shared with the device for one port.
+struct PortBuffers {
+    rx: [u8; RX_LEN],
+    tx: [u8; TX_LEN],
+}
+
+/// Buffers shared with the device for the control queues.
+struct ControlBuffers {
+    rx: [[u8; CONTROL_LEN]; CONTROL_RX_BUFFERS],
+    tx: [u8; CONTROL_MSG_LEN],
+}
+
+static QUEUE_MEM: StaticCell<[QueueMemory; QUEUE_COUNT]> =
+    StaticCell::new([const { QueueMemory::new() }; QUEUE_COUNT]);
+static PORT_BUFFERS: StaticCell<[PortBuffers; MAX_PORTS]> =
+    StaticCell::new([const { PortBuffers { rx: [0; RX_LEN], tx: [0; TX_LEN] } }; MAX_PORTS]);
+static CONTROL_BUFFERS: StaticCell<ControlBuffers> = StaticCell::new(ControlBuffers {
+    rx: [[0; CONTROL_LEN]; CONTROL_RX_BUFFERS],
+    tx: [0; CONTROL_MSG_LEN],
+});
+
+/// Returns the receive queue index of `port` (the transmit queue follows it).
+fn rx_queue(port: usize) -> u16 {
+    if port == 0 { 0 } else { 2 * (port as u16 + 1) }
+}
+
+/// Driver-side state of one port.
+struct Port {
+    /// The device announced the port.
+    present: bool,
+    /// The port is a console port (`virtconsole`) rather than a plain serial port.
+    console: bool,
+    /// A client is connected on the host side.
+    host_open: bool,
+    /// Received bytes not read yet.
+    input: [u8; INPUT_LEN],
+    input_head: usize,
+    input_len: usize,
+}
+
+impl Port {
+    const fn new() -> Self {
+        Port {
+            present: false,
+            console: false,
+            host_open: false,
+            input: [0; INPUT_LEN],
+            input_head: 0,
+            input_len: 0,
+        }
+    }
+
+    /// Appends received bytes to the input ring, dropping what does not fit.
+    fn push_input(&mut self, data: &[u8]) {
+        for &byte in data {
+            if self.input_len == INPUT_LEN {
+                break;
+            }
+            self.input[(self.input_head + self.input_len) % INPUT_LEN] = byte;
+            self.input_len += 1;
+        }
+    }
+
+    /// Moves buffered input into `buf`.
+    fn pop_input(&mut self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.input_len);
+        for byte in buf[..count].iter_mut() {
+            *byte = self.input[self.input_head];
+            self.input_head = (self.input_head + 1) % INPUT_LEN;
+        }
+        self.input_len -= count;
+        count
+    }
+}
+
+/// State of an initialized console device.
+struct Inner {
+    transport: VirtioMmio,
+    multiport: bool,
+    /// Number of ports whose queues were set up.
+    nr_ports: usize,
+    /// Virtqueues, indexed by queue number.
+    queues: [VirtQueue; QUEUE_COUNT],
+    ports: [Port; MAX_PORTS],
+    buffers: &'static mut [PortBuffers; MAX_PORTS],
+    control: &'static mut ControlBuffers,
+    /// Control buffer index posted under each descriptor ID.
+    control_slot: [u8; QUEUE_SIZE],
+}
+
+impl Inner {
+    /// Hands the receive buffer of `port` to the device.
+    fn post_rx(&mut self, port: usize) {
+        let queue = rx_queue(port);
+        self.queues[queue as usize].push(&[Buffer::writable(&mut self.buffers[port].rx)]);
+        self.transport.notify(queue);
+    }
+
+    /// Hands control receive buffer `slot` to the device.
+    fn post_control(&mut self, slot: usize) {
+        let buffer = Buffer::writable(&mut self.control.rx[slot]);
+        if let Some(id) = self.queues[CONTROL_RX_QUEUE as usize].push(&[buffer]) {
+            self.control_slot[id as usize] = slot as u8;
+        }
+        self.transport.notify(CONTROL_RX_QUEUE);
+    }
+
+    /// Sends a control message and waits for the device to consume it.
+    fn send_control(&mut self, id: u32, event: u16, value: u16) {
+        self.control.tx[0..4].copy_from_slice(&id.to_le_bytes());
+        self.control.tx[4..6].copy_from_slice(&event.to_le_bytes());
+        self.control.tx[6..8].copy_from_slice(&value.to_le_bytes());
+        let queue = &mut self.queues[CONTROL_TX_QUEUE as usize];
+        if queue.push(&[Buffer::readable(&self.control.tx)]).is_none() {
+            return;
+        }
+        self.transport.notify(CONTROL_TX_QUEUE);
+        for _ in 0..TX_SPIN_LIMIT {
+            if queue.pop_used().is_some() {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Processes control messages sent by the device.
+    fn process_control(&mut self) {
+        while let Some((id, len)) = self.queues[CONTROL_RX_QUEUE as usize].pop_used() {
+            let slot = self.control_slot[id as usize] as usize;
+            if len as usize >= CONTROL_MSG_LEN {
+                let msg = &self.control.rx[slot];
+                let port = u32::from_le_bytes([msg[0], msg[1], msg[2], msg[3]]);
+                let event = u16::from_le_bytes([msg[4], msg[5]]);
+                let value = u16::from_le_bytes([msg[6], msg[7]]);
+                self.handle_control(port, event, value);
+            }
+            self.post_control(slot);
+        }
+    }
+
+    /// Reacts to one control message about `port`.
+    fn handle_control(&mut self, port: u32, event: u16, value: u16) {
+        let index = port as usize;
+        if index >= self.nr_ports {
+            // Ports beyond those with queues are refused.
+            if event == event::DEVICE_ADD {
+                self.send_control(port, event::PORT_READY, 0);
+            }
+            return;
+        }
+        match event {
+            event::DEVICE_ADD => {
+                self.ports[index].present = true;
+                self.send_control(port, event::PORT_READY, 1);
+                // The kernel is always listening: open the guest side at once.
+                self.send_control(port, event::PORT_OPEN, 1);
+            }
+            event::DEVICE_REMOVE => {
+                self.ports[index].present = false;
+                self.ports[index].host_open = false;
+            }
+            event::CONSOLE_PORT => self.ports[index].console = true,
+            event::PORT_OPEN => self.ports[index].host_open = value != 0,
+            // Port names, resize and other notifications are not used.
+            _ => {}
+        }
+    }
+
+    /// Moves received data of every port into its input ring.
+    fn receive(&mut self) {
+        for port in 0..self.nr_ports {
+            let queue = rx_queue(port) as usize;
+            while let Some((_, len)) = self.queues[queue].pop_used() {
+                let len = (len as usize).min(RX_LEN);
+                let data = self.buffers[port].rx;
+                self.ports[port].push_input(&data[..len]);
+                self.post_rx(port);
+            }
+        }
+    }
+
+    /// Processes pending control messages and received data.
+    fn poll(&mut self) {
+        if self.multiport {
+            self.process_control();
+        }
+        self.receive();
+    }
+
+    /// Sends `data` on `port`, waiting for the device to consume every chunk.
+    ///
+    /// # Returns
+    /// The number of bytes consumed (data for a port nobody listens to is
+    /// discarded and counted as consumed).
+    fn transmit(&mut self, port: usize, data: &[u8]) -> usize {
+        // Pick up host connections and disconnections first.
+        if self.multiport {
+            self.process_control();
+        }
+        if port >= self.nr_ports || !self.ports[port].present {
+            return 0;
+        }
+        if !self.ports[port].host_open {
+            return data.len();
+        }
+        let queue = rx_queue(port) + 1;
+        let mut sent = 0;
+        for chunk in data.chunks(TX_LEN) {
+            let tx = &mut self.buffers[port].tx[..chunk.len()];
+            tx.copy_from_slice(chunk);
+            if self.queues[queue as usize].push(&[Buffer::readable(tx)]).is_none() {
+                break;
+            }
+            self.transport.notify(queue);
+            let mut done = false;
+            for _ in 0..TX_SPIN_LIMIT {
+                if self.queues[queue as usize].pop_used().is_some() {
+                    done = true;
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+            if !done {
+                break;
+            }
+            sent += chunk.len();
+        }
+        sent
+    }
+}
+
+/// Global virtio console device.
+pub struct VirtioConsole {
+    inner: SpinLock<Option<Inner>>,
+}
+
+/// The kernel's virtio console instance.
+pub static VIRTIO_CONSOLE: VirtioConsole = VirtioConsole::new();
+
+impl VirtioConsole {
+    const fn new() -> Self {
+        VirtioConsole { inner: SpinLock::new(None) }
+    }
+
+    /// Initializes the device behind `transport` and collects the ports it
+    /// announces.
+    pub fn init(&self, transport: VirtioMmio) -> Result<(), VirtioError> {
+        let (queue_mem, buffers, control) =
+            match (QUEUE_MEM.take(), PORT_BUFFERS.take(), CONTROL_BUFFERS.take()) {
+                (Some(a), Some(b), Some(c)) => (a, b, c),
+                _ => return Err(VirtioError::AlreadyInitialized),
+            };
+        let negotiated = transport.init(VIRTIO_CONSOLE_F_MULTIPORT)?;
+        let multiport = negotiated & VIRTIO_CONSOLE_F_MULTIPORT != 0;
+        let nr_ports = if multiport {
+            (transport.config_read_u32(CONFIG_MAX_NR_PORTS) as usize).clamp(1, MAX_PORTS)
+        } else {
+            1
+        };
+        let mut inner = Inner {
+            transport,
+            multiport,
+            nr_ports,
+            queues: queue_mem.each_mut().map(VirtQueue::new),
+            ports: [const { Port::new() }; MAX_PORTS],
+            buffers,
+            control,
+            control_slot: [0; QUEUE_SIZE],
+        };
+        for port in 0..nr_ports {
+            let queue = rx_queue(port);
+            transport.setup_queue(queue, &inner.queues[queue as usize])?;
+            transport.setup_queue(queue + 1, &inner.queues[queue as usize + 1])?;
+        }
+        if multiport {
+            transport.setup_queue(CONTROL_RX_QUEUE, &inner.queues[CONTROL_RX_QUEUE as usize])?;
+            transport.setup_queue(CONTROL_TX_QUEUE, &inner.queues[CONTROL_TX_QUEUE as usize])?;
+        }
+        transport.driver_ok();
+        for port in 0..nr_ports {
+            inner.post_rx(port);
+        }
+        if multiport {
+            for slot in 0..CONTROL_RX_BUFFERS {
+                inner.post_control(slot);
+            }
+            // The device answers with DEVICE_ADD for every port.
+            inner.send_control(0, event::DEVICE_READY, 1);
+            inner.process_control();
+        } else {
+            // Without control queues, port 0 is a console that is always open.
+            inner.ports[0] = Port { present: true, console: true, host_open: true, ..Port::new() };
+        }
+        *self.inner.lock() = Some(inner);
+        Ok(())
+    }
+
+    /// Returns `(present, console)` for `port`, or `None` if the device is not
+    /// initialized.
+    pub fn port_info(&self, port: usize) -> Option<(bool, bool)> {
+        let guard = self.inner.lock();
+        let port = guard.as_ref()?.ports.get(port)?;
+        Some((port.present, port.console))
+    }
+}
+
+/// Handle to one port of [`VIRTIO_CONSOLE`], named `hvc<index>`.
+pub struct ConsolePort {
+    index: usize,
+    name: &'static str,
+}
+
+/// The ports of [`VIRTIO_CONSOLE`].
+pub static HVC: [ConsolePort; MAX_PORTS] = [
+    ConsolePort { index: 0, name: "hvc0" },
+    ConsolePort { index: 1, name: "hvc1" },
+    ConsolePort { index: 2, name: "hvc2" },
+];
+
+impl LogSink for ConsolePort {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn write_str(&self, s: &str) {
+        // Never spin on the device lock from the logger: a log record emitted
+        // while the driver is busy (e.g. from a trap) is dropped instead.
+        if let Some(mut guard) = VIRTIO_CONSOLE.inner.try_lock()
+            && let Some(inner) = guard.as_mut()
+        {
+            inner.transmit(self.index, s.as_bytes());
+        }
+    }
+}
+
+impl CharDevice for ConsolePort {
+    /// Reads buffered input; fails with `EAGAIN` if there is none.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        let mut guard = VIRTIO_CONSOLE.inner.lock();
+        let inner = guard.as_mut().ok_or(Errno::ENODEV)?;
+        inner.poll();
+        match inner.ports[self.index].pop_input(buf) {
+            0 if !buf.is_empty() => Err(Errno::EAGAIN),
+            count => Ok(count),
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        let mut guard = VIRTIO_CONSOLE.inner.lock();
+        let inner = guard.as_mut().ok_or(Errno::ENODEV)?;
+        match inner.transmit(self.index, buf) {
+            0 if !buf.is_empty() => Err(Errno::EIO),
+            count => Ok(count),
+        }
+    }
+}
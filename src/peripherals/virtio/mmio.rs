@@ -0,0 +1,192 @@
+//! ---------------------------------------------------------------------------
+//! File       : mmio.rs
+//! Module     : peripherals::virtio::mmio
+//! Author     : DiTurr
+//! Description:
+//! This module implements the virtio-mmio transport (virtio spec 1.2, section 4.2):
+//! register access, the device initialization handshake, feature negotiation and
+//! virtqueue registration.
+//! ---------------------------------------------------------------------------
+
+use core::ptr::{read_volatile, write_volatile};
+
+use super::queue::VirtQueue;
+use super::{features, status, VirtioError, VIRTIO_IRQ, VIRTIO_MMIO_BASE, VIRTIO_MMIO_STRIDE};
+
+/// Magic value ("virt" in little endian) found at offset 0 of every device.
+const MAGIC: u32 = 0x7472_6976;
+
+// Register offsets of the modern (version 2) virtio-mmio layout.
+const REG_MAGIC: usize               = 0x000;
+const REG_VERSION: usize             = 0x004;
+const REG_DEVICE_ID: usize           = 0x008;
+const REG_DEVICE_FEATURES: usize     = 0x010;
+const REG_DEVICE_FEATURES_SEL: usize = 0x014;
+const REG_DRIVER_FEATURES: usize     = 0x020;
+const REG_DRIVER_FEATURES_SEL: usize = 0x024;
+const REG_QUEUE_SEL: usize           = 0x030;
+const REG_QUEUE_NUM_MAX: usize       = 0x034;
+const REG_QUEUE_NUM: usize           = 0x038;
+const REG_QUEUE_READY: usize         = 0x044;
+const REG_QUEUE_NOTIFY: usize        = 0x050;
+const REG_INTERRUPT_STATUS: usize    = 0x060;
+const REG_INTERRUPT_ACK: usize       = 0x064;
+const REG_STATUS: usize              = 0x070;
+const REG_QUEUE_DESC_LOW: usize      = 0x080;
+const REG_QUEUE_DESC_HIGH: usize     = 0x084;
+const REG_QUEUE_DRIVER_LOW: usize    = 0x090;
+const REG_QUEUE_DRIVER_HIGH: usize   = 0x094;
+const REG_QUEUE_DEVICE_LOW: usize    = 0x0a0;
+const REG_QUEUE_DEVICE_HIGH: usize   = 0x0a4;
+const REG_CONFIG: usize              = 0x100;
+
+/// Handle to a single virtio-mmio register block.
+#[derive(Debug, Clone, Copy)]
+pub struct VirtioMmio {
+    /// Base address of the register block.
+    base: usize,
+}
+
+impl VirtioMmio {
+    /// Creates a transport handle for the register block at `base`.
+    pub const fn new(base: usize) -> Self {
+        VirtioMmio { base }
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Returns `true` if the slot holds a virtio-mmio device with a backend.
+    pub fn is_valid(&self) -> bool {
+        self.read(REG_MAGIC) == MAGIC && self.read(REG_DEVICE_ID) != 0
+    }
+
+    /// Returns the virtio-mmio interface version (1 = legacy, 2 = modern).
+    pub fn version(&self) -> u32 {
+        self.read(REG_VERSION)
+    }
+
+    /// Returns the VirtIO device type ID.
+    pub fn device_id(&self) -> u32 {
+        self.read(REG_DEVICE_ID)
+    }
+
+    /// Runs the first half of the initialization handshake (spec 3.1.1).
+    ///
+    /// Resets the device, acknowledges it, and negotiates features: the accepted
+    /// set is `wanted` intersected with what the device offers, plus the mandatory
+    /// `VERSION_1` bit. Queues must be set up afterwards with [`setup_queue`],
+    /// followed by [`driver_ok`].
+    ///
+    /// # Returns
+    /// The negotiated feature bits.
+    pub fn init(&self, wanted: u64) -> Result<u64, VirtioError> {
+        if self.read(REG_MAGIC) != MAGIC {
+            return Err(VirtioError::BadMagic);
+        }
+        if self.version() != 2 {
+            return Err(VirtioError::UnsupportedVersion(self.version()));
+        }
+        // Reset, then announce that a driver has found the device.
+        self.write(REG_STATUS, 0);
+        self.write(REG_STATUS, status::ACKNOWLEDGE);
+        self.write(REG_STATUS, status::ACKNOWLEDGE | status::DRIVER);
+        // Read the 64-bit device feature set, 32 bits at a time.
+        self.write(REG_DEVICE_FEATURES_SEL, 0);
+        let low = self.read(REG_DEVICE_FEATURES) as u64;
+        self.write(REG_DEVICE_FEATURES_SEL, 1);
+        let high = self.read(REG_DEVICE_FEATURES) as u64;
+        let offered = (high << 32) | low;
+        let accepted = offered & (wanted | features::VERSION_1);
+        if accepted & features::VERSION_1 == 0 {
+            self.fail();
+            return Err(VirtioError::FeaturesRejected);
+        }
+        // Write back the accepted subset.
+        self.write(REG_DRIVER_FEATURES_SEL, 0);
+        self.write(REG_DRIVER_FEATURES, accepted as u32);
+        self.write(REG_DRIVER_FEATURES_SEL, 1);
+        self.write(REG_DRIVER_FEATURES, (accepted >> 32) as u32);
+        self.write(REG_STATUS, status::ACKNOWLEDGE | status::DRIVER | status::FEATURES_OK);
+        // The device clears FEATURES_OK if it cannot operate with this subset.
+        if self.read(REG_STATUS) & status::FEATURES_OK == 0 {
+            self.fail();
+            return Err(VirtioError::FeaturesRejected);
+        }
+        Ok(accepted)
+    }
+
+    /// Registers `queue` with the device as virtqueue number `index`.
+    pub fn setup_queue(&self, index: u16, queue: &VirtQueue) -> Result<(), VirtioError> {
+        self.write(REG_QUEUE_SEL, index as u32);
+        if self.read(REG_QUEUE_READY) != 0 {
+            return Err(VirtioError::QueueInUse(index));
+        }
+        let max = self.read(REG_QUEUE_NUM_MAX);
+        if max == 0 || max < queue.size() as u32 {
+            return Err(VirtioError::QueueUnavailable(index));
+        }
+        self.write(REG_QUEUE_NUM, queue.size() as u32);
+        let (desc, driver, device) = queue.addresses();
+        self.write(REG_QUEUE_DESC_LOW, desc as u32);
+        self.write(REG_QUEUE_DESC_HIGH, (desc >> 32) as u32);
+        self.write(REG_QUEUE_DRIVER_LOW, driver as u32);
+        self.write(REG_QUEUE_DRIVER_HIGH, (driver >> 32) as u32);
+        self.write(REG_QUEUE_DEVICE_LOW, device as u32);
+        self.write(REG_QUEUE_DEVICE_HIGH, (device >> 32) as u32);
+        self.write(REG_QUEUE_READY, 1);
+        Ok(())
+    }
+
+    /// Completes initialization; the device becomes live after this call.
+    pub fn driver_ok(&self) {
+        let current = self.read(REG_STATUS);
+        self.write(REG_STATUS, current | status::DRIVER_OK);
+    }
+
+    /// Marks the device as failed, telling it the driver gave up.
+    pub fn fail(&self) {
+        let current = self.read(REG_STATUS);
+        self.write(REG_STATUS, current | status::FAILED);
+    }
+
+    /// Notifies the device that new buffers are available in queue `index`.
+    pub fn notify(&self, index: u16) {
+        self.write(REG_QUEUE_NOTIFY, index as u32);
+    }
+
+    /// Returns the transport of the slot wired to PLIC line `irq`.
+    pub const fn from_irq(irq: u32) -> Self {
+        VirtioMmio::new(VIRTIO_MMIO_BASE + (irq - VIRTIO_IRQ) as usize * VIRTIO_MMIO_STRIDE)
+    }
+
+    /// Returns the PLIC line of the device (one per slot).
+    pub fn irq(&self) -> u32 {
+        VIRTIO_IRQ + ((self.base - VIRTIO_MMIO_BASE) / VIRTIO_MMIO_STRIDE) as u32
+    }
+
+    /// Acknowledges the pending interrupts of the device, which lowers its line.
+    ///
+    /// # Returns
+    /// The acknowledged causes: bit 0 for used buffers, bit 1 for a configuration change.
+    pub fn ack_interrupt(&self) -> u32 {
+        let causes = self.read(REG_INTERRUPT_STATUS);
+        self.write(REG_INTERRUPT_ACK, causes);
+        causes
+    }
+
+    /// Reads a byte from the device-specific configuration space.
+    pub fn config_read_u8(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.base + REG_CONFIG + offset) as *const u8) }
+    }
+
+    /// Reads a little-endian 32-bit field from the device-specific configuration space.
+    pub fn config_read_u32(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + REG_CONFIG + offset) as *const u32) }
+    }
+}
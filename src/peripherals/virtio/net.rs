@@ -0,0 +1,221 @@
+//! ---------------------------------------------------------------------------
+//! File       : net.rs
+//! Module     : peripherals::virtio::net
+//! Author     : DiTurr
+//! Description:
+//! This module implements an interrupt-driven virtio-net driver (virtio spec
+//! 1.2, section 5.1).
+//!
+//! The device interrupt acknowledges the device, then defers the rest (see
+//! `deferred`): a tasklet reclaims the transmitted buffers, and the `NetRx`
+//! softirq has the network stack process the received frames.
+//!
+//! The driver uses queue 0 for reception and queue 1 for transmission. Every
+//! descriptor points to a fixed-size static buffer holding the `virtio_net_hdr`
+//! followed by the Ethernet frame. The MAC address is read from the device
+//! configuration space.
+//!
+//! ## Example
+//! ```rust
+//! VIRTIO_NET.init(transport)?;
+//! VIRTIO_NET.transmit(&frame);
+//! ```
+//! ---------------------------------------------------------------------------
+
+use super::mmio::VirtioMmio;
+use super::queue::{Buffer, QueueMemory, VirtQueue, QUEUE_SIZE};
+use super::VirtioError;
+use crate::deferred::softirq::{self, Softirq};
+use crate::deferred::tasklet::Tasklet;
+use crate::log_warn;
+use crate::net::ethernet::MacAddr;
+use crate::net::interface::NetDevice;
+use crate::peripherals::plic::{self, Handler};
+use crate::sync::spinlock::SpinLock;
+use crate::sync::static_cell::StaticCell;
+
+/// Feature bit: the device has a MAC address in its configuration space.
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+/// Receive queue index.
+const RX_QUEUE: u16 = 0;
+/// Transmit queue index.
+const TX_QUEUE: u16 = 1;
+
+/// Size of `virtio_net_hdr` when `VERSION_1` is negotiated.
+const NET_HDR_LEN: usize = 12;
+
+/// Size of every packet buffer (header + maximum Ethernet frame, rounded up).
+const BUFFER_LEN: usize = 1536;
+
+/// Priority of the device line: the lowest, anything else may preempt it.
+const IRQ_PRIORITY: u32 = 1;
+
+/// A packet buffer shared with the device.
+type PacketBuffer = [u8; BUFFER_LEN];
+
+static RX_QUEUE_MEM: StaticCell<QueueMemory> = StaticCell::new(QueueMemory::new());
+static TX_QUEUE_MEM: StaticCell<QueueMemory> = StaticCell::new(QueueMemory::new());
+static RX_BUFFERS: StaticCell<[PacketBuffer; QUEUE_SIZE]> = StaticCell::new([[0; BUFFER_LEN]; QUEUE_SIZE]);
+static TX_BUFFERS: StaticCell<[PacketBuffer; QUEUE_SIZE]> = StaticCell::new([[0; BUFFER_LEN]; QUEUE_SIZE]);
+
+/// State of an initialized virtio-net device.
+struct Inner {
+    transport: VirtioMmio,
+    mac: MacAddr,
+    rx: VirtQueue,
+    tx: VirtQueue,
+    rx_buffers: &'static mut [PacketBuffer; QUEUE_SIZE],
+    tx_buffers: &'static mut [PacketBuffer; QUEUE_SIZE],
+    /// RX buffer index posted under each descriptor ID.
+    rx_slot: [u8; QUEUE_SIZE],
+    /// TX buffer index in flight under each descriptor ID.
+    tx_slot: [u8; QUEUE_SIZE],
+    /// Bitmask of TX buffers currently owned by the device.
+    tx_busy: u32,
+}
+
+impl Inner {
+    /// Hands RX buffer `slot` to the device.
+    fn post_rx(&mut self, slot: usize) {
+        let buffer = Buffer::writable(&mut self.rx_buffers[slot]);
+        if let Some(id) = self.rx.push(&[buffer]) {
+            self.rx_slot[id as usize] = slot as u8;
+        }
+    }
+
+    /// Returns completed TX buffers to the free pool.
+    fn reclaim_tx(&mut self) {
+        while let Some((id, _)) = self.tx.pop_used() {
+            self.tx_busy &= !(1 << self.tx_slot[id as usize]);
+        }
+    }
+}
+
+/// Interrupt handler of the device. Network work must not delay the timer,
+/// so it is preemptible, and a second interrupt of the device waits for the
+/// first one to be acknowledged.
+static INTERRUPT: Handler = Handler::new("virtio-net", IRQ_PRIORITY, interrupt).preemptible().non_reentrant();
+
+/// Reclaims the transmitted buffers, after an interrupt.
+static RECLAIM: Tasklet = Tasklet::new("virtio-net tx", reclaim);
+
+fn interrupt(irq: u32) {
+    VirtioMmio::from_irq(irq).ack_interrupt();
+    RECLAIM.schedule();
+    softirq::raise(Softirq::NetRx);
+}
+
+fn reclaim() {
+    // Busy, the network stack reclaims the buffers itself.
+    let Some(mut guard) = VIRTIO_NET.inner.try_lock() else {
+        return;
+    };
+    if let Some(inner) = guard.as_mut() {
+        inner.reclaim_tx();
+    }
+}
+
+/// Global virtio-net device, usable as a [`NetDevice`] once initialized.
+pub struct VirtioNet {
+    inner: SpinLock<Option<Inner>>,
+}
+
+/// The kernel's virtio-net device instance.
+pub static VIRTIO_NET: VirtioNet = VirtioNet::new();
+
+impl VirtioNet {
+    const fn new() -> Self {
+        VirtioNet { inner: SpinLock::new(None) }
+    }
+
+    /// Initializes the device behind `transport` and fills the RX queue.
+    pub fn init(&self, transport: VirtioMmio) -> Result<(), VirtioError> {
+        let (rx_mem, tx_mem, rx_buffers, tx_buffers) = match (
+            RX_QUEUE_MEM.take(),
+            TX_QUEUE_MEM.take(),
+            RX_BUFFERS.take(),
+            TX_BUFFERS.take(),
+        ) {
+            (Some(a), Some(b), Some(c), Some(d)) => (a, b, c, d),
+            _ => return Err(VirtioError::AlreadyInitialized),
+        };
+        let negotiated = transport.init(VIRTIO_NET_F_MAC)?;
+        let rx = VirtQueue::new(rx_mem);
+        let tx = VirtQueue::new(tx_mem);
+        transport.setup_queue(RX_QUEUE, &rx)?;
+        transport.setup_queue(TX_QUEUE, &tx)?;
+        // Without the MAC feature, fall back to a locally administered address.
+        let mac = if negotiated & VIRTIO_NET_F_MAC != 0 {
+            MacAddr(core::array::from_fn(|i| transport.config_read_u8(i)))
+        } else {
+            MacAddr([0x02, 0x00, 0x00, 0x00, 0x00, 0x01])
+        };
+        let mut inner = Inner {
+            transport,
+            mac,
+            rx,
+            tx,
+            rx_buffers,
+            tx_buffers,
+            rx_slot: [0; QUEUE_SIZE],
+            tx_slot: [0; QUEUE_SIZE],
+            tx_busy: 0,
+        };
+        for slot in 0..QUEUE_SIZE {
+            inner.post_rx(slot);
+        }
+        transport.driver_ok();
+        transport.notify(RX_QUEUE);
+        *self.inner.lock() = Some(inner);
+        if let Err(err) = plic::register(transport.irq(), &INTERRUPT) {
+            log_warn!("virtio-net: cannot register the interrupt handler: {:?}", err);
+        }
+        Ok(())
+    }
+}
+
+impl NetDevice for VirtioNet {
+    fn mac(&self) -> MacAddr {
+        self.inner.lock().as_ref().map_or(MacAddr::ZERO, |inner| inner.mac)
+    }
+
+    fn transmit(&self, frame: &[u8]) -> bool {
+        let mut guard = self.inner.lock();
+        let Some(inner) = guard.as_mut() else {
+            return false;
+        };
+        if frame.len() > BUFFER_LEN - NET_HDR_LEN {
+            return false;
+        }
+        inner.reclaim_tx();
+        let Some(slot) = (0..QUEUE_SIZE).find(|slot| inner.tx_busy & (1 << slot) == 0) else {
+            return false;
+        };
+        // A zeroed header requests no checksum offload and no segmentation.
+        let buffer = &mut inner.tx_buffers[slot];
+        buffer[..NET_HDR_LEN].fill(0);
+        buffer[NET_HDR_LEN..NET_HDR_LEN + frame.len()].copy_from_slice(frame);
+        let descriptor = Buffer::readable(&buffer[..NET_HDR_LEN + frame.len()]);
+        let Some(id) = inner.tx.push(&[descriptor]) else {
+            return false;
+        };
+        inner.tx_slot[id as usize] = slot as u8;
+        inner.tx_busy |= 1 << slot;
+        inner.transport.notify(TX_QUEUE);
+        true
+    }
+
+    fn receive(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut guard = self.inner.lock();
+        let inner = guard.as_mut()?;
+        let (id, written) = inner.rx.pop_used()?;
+        let slot = inner.rx_slot[id as usize] as usize;
+        let frame_len = (written as usize).saturating_sub(NET_HDR_LEN).min(buf.len());
+        buf[..frame_len].copy_from_slice(&inner.rx_buffers[slot][NET_HDR_LEN..NET_HDR_LEN + frame_len]);
+        // Give the buffer back to the device straight away.
+        inner.post_rx(slot);
+        inner.transport.notify(RX_QUEUE);
+        Some(frame_len)
+    }
+}
@@ -0,0 +1,220 @@
+//! ---------------------------------------------------------------------------
+//! File       : queue.rs
+//! Module     : peripherals::virtio::queue
+//! Author     : DiTurr
+//! Description:
+//! This module implements split virtqueues (virtio spec 1.2, section 2.7).
+//!
+//! A virtqueue consists of three areas shared with the device:
+//! - the descriptor table, describing guest buffers;
+//! - the available ring, where the driver publishes descriptor chains;
+//! - the used ring, where the device returns consumed chains.
+//!
+//! The kernel runs with the MMU disabled, so the addresses handed to the device
+//! are plain pointers into statically allocated memory (see [`QueueMemory`]).
+//! ---------------------------------------------------------------------------
+
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+/// Number of descriptors in every virtqueue used by the kernel drivers.
+pub const QUEUE_SIZE: usize = 16;
+
+/// Descriptor flag: the chain continues in the `next` field.
+const DESC_F_NEXT: u16 = 1;
+/// Descriptor flag: the buffer is write-only for the driver (device writes it).
+const DESC_F_WRITE: u16 = 2;
+
+/// One entry of the descriptor table.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// The driver-owned available ring.
+#[repr(C, align(2))]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+/// One entry of the used ring.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// The device-owned used ring.
+#[repr(C, align(4))]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/// Memory shared with the device for one virtqueue.
+///
+/// Instances are meant to live in a `static` [`StaticCell`](crate::sync::static_cell::StaticCell)
+/// so that their address stays fixed for the lifetime of the kernel.
+#[repr(C, align(4096))]
+pub struct QueueMemory {
+    desc: [Descriptor; QUEUE_SIZE],
+    avail: AvailRing,
+    used: UsedRing,
+}
+
+impl QueueMemory {
+    /// Creates zeroed queue memory.
+    pub const fn new() -> Self {
+        QueueMemory {
+            desc: [Descriptor { addr: 0, len: 0, flags: 0, next: 0 }; QUEUE_SIZE],
+            avail: AvailRing { flags: 0, idx: 0, ring: [0; QUEUE_SIZE], used_event: 0 },
+            used: UsedRing {
+                flags: 0,
+                idx: 0,
+                ring: [UsedElem { id: 0, len: 0 }; QUEUE_SIZE],
+                avail_event: 0,
+            },
+        }
+    }
+}
+
+/// A buffer handed to the device as part of a descriptor chain.
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    /// Physical address of the buffer.
+    pub addr: usize,
+    /// Length of the buffer in bytes.
+    pub len: usize,
+    /// `true` if the device writes into the buffer, `false` if it reads it.
+    pub device_writable: bool,
+}
+
+impl Buffer {
+    /// Describes a buffer the device reads from.
+    pub fn readable(data: &[u8]) -> Self {
+        Buffer { addr: data.as_ptr() as usize, len: data.len(), device_writable: false }
+    }
+
+    /// Describes a buffer the device writes into.
+    pub fn writable(data: &mut [u8]) -> Self {
+        Buffer { addr: data.as_mut_ptr() as usize, len: data.len(), device_writable: true }
+    }
+}
+
+/// Driver-side state of a split virtqueue.
+pub struct VirtQueue {
+    /// Memory shared with the device.
+    mem: &'static mut QueueMemory,
+    /// Head of the free descriptor list.
+    free_head: u16,
+    /// Number of free descriptors.
+    num_free: usize,
+    /// Index of the next used ring entry to process.
+    last_used: u16,
+}
+
+impl VirtQueue {
+    /// Initializes a virtqueue on top of `mem`, linking all descriptors into the free list.
+    pub fn new(mem: &'static mut QueueMemory) -> Self {
+        for (i, desc) in mem.desc.iter_mut().enumerate() {
+            desc.next = (i + 1) as u16;
+        }
+        VirtQueue { mem, free_head: 0, num_free: QUEUE_SIZE, last_used: 0 }
+    }
+
+    /// Returns the number of descriptors in the queue.
+    pub fn size(&self) -> usize {
+        QUEUE_SIZE
+    }
+
+    /// Returns the physical addresses of the descriptor table, available and used rings.
+    pub fn addresses(&self) -> (u64, u64, u64) {
+        (
+            addr_of!(self.mem.desc) as u64,
+            addr_of!(self.mem.avail) as u64,
+            addr_of!(self.mem.used) as u64,
+        )
+    }
+
+    /// Publishes a descriptor chain made of `buffers` to the device.
+    ///
+    /// The caller must keep the buffers alive and untouched until the chain is
+    /// returned by [`pop_used`](Self::pop_used), and must notify the device afterwards.
+    ///
+    /// # Returns
+    /// The head descriptor ID of the chain, or `None` if the queue is full.
+    pub fn push(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.num_free {
+            return None;
+        }
+        let head = self.free_head;
+        let mut id = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let desc = &mut self.mem.desc[id as usize];
+            desc.addr = buffer.addr as u64;
+            desc.len = buffer.len as u32;
+            desc.flags = if buffer.device_writable { DESC_F_WRITE } else { 0 };
+            if i + 1 < buffers.len() {
+                desc.flags |= DESC_F_NEXT;
+            }
+            let next = desc.next;
+            if i + 1 < buffers.len() {
+                id = next;
+            } else {
+                self.free_head = next;
+            }
+        }
+        self.num_free -= buffers.len();
+        // Publish the chain head, then make it visible by bumping `idx`.
+        let avail_idx = unsafe { read_volatile(addr_of!(self.mem.avail.idx)) };
+        self.mem.avail.ring[avail_idx as usize % QUEUE_SIZE] = head;
+        fence(Ordering::SeqCst);
+        unsafe { write_volatile(addr_of_mut!(self.mem.avail.idx), avail_idx.wrapping_add(1)) };
+        fence(Ordering::SeqCst);
+        Some(head)
+    }
+
+    /// Returns `true` if the device has returned chains not yet collected.
+    pub fn has_used(&self) -> bool {
+        let used_idx = unsafe { read_volatile(addr_of!(self.mem.used.idx)) };
+        used_idx != self.last_used
+    }
+
+    /// Collects the next chain returned by the device and frees its descriptors.
+    ///
+    /// # Returns
+    /// The head descriptor ID and the number of bytes the device wrote, or `None`
+    /// if no chain is pending.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.has_used() {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let elem = unsafe {
+            read_volatile(addr_of!(self.mem.used.ring[self.last_used as usize % QUEUE_SIZE]))
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+        // Walk the chain to its tail and return it to the free list.
+        let head = elem.id as u16;
+        let mut tail = head;
+        let mut count = 1;
+        while self.mem.desc[tail as usize].flags & DESC_F_NEXT != 0 {
+            tail = self.mem.desc[tail as usize].next;
+            count += 1;
+        }
+        self.mem.desc[tail as usize].next = self.free_head;
+        self.free_head = head;
+        self.num_free += count;
+        Some((head, elem.len))
+    }
+}
@@ -0,0 +1,96 @@
+//! ---------------------------------------------------------------------------
+//! File       : rng.rs
+//! Module     : peripherals::virtio::rng
+//! Author     : DiTurr
+//! Description:
+//! This module implements a polled virtio entropy device driver (virtio spec
+//! 1.2, section 5.4). The device has a single request queue: the driver posts a
+//! device-writable buffer and the device fills it with random bytes.
+//!
+//! ## Example
+//! ```rust
+//! VIRTIO_RNG.init(transport)?;
+//! let mut seed = [0u8; 32];
+//! let filled = VIRTIO_RNG.fill(&mut seed);
+//! ```
+//! ---------------------------------------------------------------------------
+
+use super::mmio::VirtioMmio;
+use super::queue::{Buffer, QueueMemory, VirtQueue};
+use super::VirtioError;
+use crate::sync::spinlock::SpinLock;
+use crate::sync::static_cell::StaticCell;
+
+/// Request queue index.
+const REQUEST_QUEUE: u16 = 0;
+
+/// Size of the buffer handed to the device per request.
+const BUFFER_LEN: usize = 64;
+
+static QUEUE_MEM: StaticCell<QueueMemory> = StaticCell::new(QueueMemory::new());
+static BUFFER: StaticCell<[u8; BUFFER_LEN]> = StaticCell::new([0; BUFFER_LEN]);
+
+/// State of an initialized entropy device.
+struct Inner {
+    transport: VirtioMmio,
+    queue: VirtQueue,
+    buffer: &'static mut [u8; BUFFER_LEN],
+}
+
+/// Global virtio entropy device.
+pub struct VirtioRng {
+    inner: SpinLock<Option<Inner>>,
+}
+
+/// The kernel's virtio-rng device instance.
+pub static VIRTIO_RNG: VirtioRng = VirtioRng::new();
+
+impl VirtioRng {
+    const fn new() -> Self {
+        VirtioRng { inner: SpinLock::new(None) }
+    }
+
+    /// Initializes the device behind `transport`.
+    pub fn init(&self, transport: VirtioMmio) -> Result<(), VirtioError> {
+        let (Some(mem), Some(buffer)) = (QUEUE_MEM.take(), BUFFER.take()) else {
+            return Err(VirtioError::AlreadyInitialized);
+        };
+        transport.init(0)?;
+        let queue = VirtQueue::new(mem);
+        transport.setup_queue(REQUEST_QUEUE, &queue)?;
+        transport.driver_ok();
+        *self.inner.lock() = Some(Inner { transport, queue, buffer });
+        Ok(())
+    }
+
+    /// Fills `out` with random bytes from the device, busy-waiting for each request.
+    ///
+    /// # Returns
+    /// The number of bytes written, 0 if the device is not initialized.
+    pub fn fill(&self, out: &mut [u8]) -> usize {
+        let mut guard = self.inner.lock();
+        let Some(inner) = guard.as_mut() else {
+            return 0;
+        };
+        let mut filled = 0;
+        while filled < out.len() {
+            let want = (out.len() - filled).min(BUFFER_LEN);
+            if inner.queue.push(&[Buffer::writable(&mut inner.buffer[..want])]).is_none() {
+                break;
+            }
+            inner.transport.notify(REQUEST_QUEUE);
+            let written = loop {
+                if let Some((_, len)) = inner.queue.pop_used() {
+                    break (len as usize).min(want);
+                }
+                core::hint::spin_loop();
+            };
+            if written == 0 {
+                break;
+            }
+            out[filled..filled + written].copy_from_slice(&inner.buffer[..written]);
+            filled += written;
+        }
+        filled
+    }
+}
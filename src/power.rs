@@ -0,0 +1,204 @@
+//! ---------------------------------------------------------------------------
+//! File       : power.rs
+//! Module     : power
+//! Author     : DiTurr
+//! Description:
+//! Power control: [`shutdown`], [`reboot`] and [`exit_with_code`], which stops
+//! QEMU with an exit status for scripted runs. The backend is chosen by
+//! [`init`]:
+//! - the `syscon-poweroff` and `syscon-reboot` device tree nodes, which name a
+//!   register (through the `regmap` phandle and `offset`) and the value to
+//!   write to it;
+//! - without a device tree, the SiFive test finisher of the QEMU `virt`
+//!   machine at `0x100000`, which is what the syscon nodes describe there;
+//! - with `power=sbi` on the command line, the SBI system reset extension, for
+//!   a kernel running under firmware (an M-mode kernel cannot detect firmware,
+//!   its `ecall`s trap to itself);
+//! - with `power=semihosting`, the semihosting exit request (QEMU started with
+//!   `-semihosting`), which carries exit statuses on any machine; there is no
+//!   semihosting reboot.
+//!
+//! Exit statuses need the test finisher; other backends just power off, after
+//! reporting a failure to the firmware if the status is not 0.
+//!
+//! ## Example
+//! ```rust
+//! power::init();
+//! power::exit_with_code(1); // QEMU exits with status 1
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod sbi;
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use crate::arch::semihosting;
+use crate::cmdline;
+use crate::fdt::{self, Fdt};
+use crate::{log_info, log_warn};
+
+/// Base address of the test finisher on the QEMU `virt` machine.
+const FINISHER_BASE: usize = 0x10_0000;
+
+/// Device tree compatible string of the test finisher.
+const FINISHER_COMPATIBLE: &str = "sifive,test0";
+
+// Finisher commands; a failure carries the exit status in the upper 16 bits.
+const FINISHER_FAIL: u32  = 0x3333;
+const FINISHER_PASS: u32  = 0x5555;
+const FINISHER_RESET: u32 = 0x7777;
+
+/// A power command: a value written to a syscon register.
+struct SysconAction {
+    /// Address of the register; 0 if the command is unavailable.
+    addr: AtomicUsize,
+    value: AtomicU32,
+    /// Bits of the register changed by the command.
+    mask: AtomicU32,
+}
+
+impl SysconAction {
+    const fn new(addr: usize, value: u32) -> Self {
+        SysconAction { addr: AtomicUsize::new(addr), value: AtomicU32::new(value), mask: AtomicU32::new(u32::MAX) }
+    }
+
+    /// Reads the command from a `syscon-poweroff`/`syscon-reboot` node.
+    fn configure(&self, fdt: &Fdt, compatible: &str) -> bool {
+        let Some((node, _)) = fdt.find_compatible(compatible) else {
+            return false;
+        };
+        let Some(base) = node
+            .property_u32("regmap")
+            .and_then(|phandle| fdt.find_phandle(phandle))
+            .and_then(|(regmap, cells)| regmap.reg_address(cells))
+        else {
+            log_warn!("power: {} node without a usable regmap.", compatible);
+            return false;
+        };
+        let offset = node.property_u32("offset").unwrap_or(0) as usize;
+        let mask = node.property_u32("mask").unwrap_or(u32::MAX);
+        // `value` is optional if `mask` is given (the value is then the mask).
+        let value = node.property_u32("value").unwrap_or(mask);
+        self.addr.store(base + offset, Ordering::Relaxed);
+        self.value.store(value, Ordering::Relaxed);
+        self.mask.store(mask, Ordering::Relaxed);
+        true
+    }
+
+    /// Writes the command, if available.
+    fn run(&self) {
+        let addr = self.addr.load(Ordering::Relaxed);
+        if addr == 0 {
+            return;
+        }
+        let (value, mask) = (self.value.load(Ordering::Relaxed), self.mask.load(Ordering::Relaxed));
+        unsafe {
+            let reg = addr as *mut u32;
+            let old = if mask == u32::MAX { 0 } else { read_volatile(reg) };
+            write_volatile(reg, (old & !mask) | (value & mask));
+        }
+    }
+}
+
+/// The poweroff command.
+static POWEROFF: SysconAction = SysconAction::new(FINISHER_BASE, FINISHER_PASS);
+
+/// The reboot command.
+static REBOOT: SysconAction = SysconAction::new(FINISHER_BASE, FINISHER_RESET);
+
+/// Base address of the test finisher, used for exit statuses; 0 if absent.
+static FINISHER: AtomicUsize = AtomicUsize::new(FINISHER_BASE);
+
+/// Whether to use the SBI system reset extension.
+static USE_SBI: AtomicBool = AtomicBool::new(false);
+
+/// Whether to exit through semihosting.
+static USE_SEMIHOSTING: AtomicBool = AtomicBool::new(false);
+
+/// Selects the power backend from the command line and the device tree.
+pub fn init() {
+    match cmdline::get("power") {
+        Some("sbi") => {
+            USE_SBI.store(true, Ordering::Relaxed);
+            log_info!("power: using the SBI system reset extension.");
+            return;
+        }
+        Some("semihosting") if semihosting::available() => {
+            USE_SEMIHOSTING.store(true, Ordering::Relaxed);
+            log_info!("power: exiting through semihosting.");
+        }
+        Some("semihosting") => {
+            log_warn!("power: semihosting is not available.");
+        }
+        _ => {}
+    }
+    let Some(fdt) = fdt::get() else {
+        return;
+    };
+    // Exit statuses are only possible with a test finisher.
+    let finisher = fdt
+        .find_compatible(FINISHER_COMPATIBLE)
+        .and_then(|(node, cells)| node.reg_address(cells));
+    FINISHER.store(finisher.unwrap_or(0), Ordering::Relaxed);
+    let poweroff = POWEROFF.configure(&fdt, "syscon-poweroff");
+    let reboot = REBOOT.configure(&fdt, "syscon-reboot");
+    log_info!(
+        "power: poweroff {}, reboot {}, exit status {}.",
+        if poweroff { "syscon" } else { "test finisher" },
+        if reboot { "syscon" } else { "test finisher" },
+        if finisher.is_some() { "test finisher" } else { "unavailable" }
+    );
+}
+
+/// Stops the hart: it waits for interrupts forever. Also used when a power
+/// command has no effect.
+pub fn halt() -> ! {
+    loop {
+        #[cfg(target_os = "none")]
+        unsafe { core::arch::asm!("wfi") };
+        #[cfg(not(target_os = "none"))]
+        core::hint::spin_loop();
+    }
+}
+
+/// Powers the machine off; QEMU exits with status 0.
+pub fn shutdown() -> ! {
+    if USE_SEMIHOSTING.load(Ordering::Relaxed) {
+        semihosting::exit(0);
+    }
+    if USE_SBI.load(Ordering::Relaxed) {
+        sbi::system_reset(sbi::RESET_SHUTDOWN, sbi::REASON_NONE);
+    }
+    POWEROFF.run();
+    halt()
+}
+
+/// Resets the machine.
+pub fn reboot() -> ! {
+    if USE_SBI.load(Ordering::Relaxed) {
+        sbi::system_reset(sbi::RESET_COLD_REBOOT, sbi::REASON_NONE);
+    }
+    REBOOT.run();
+    halt()
+}
+
+/// Powers the machine off; QEMU exits with status `code`.
+// Only the panic report and the test kernel exit with a status.
+#[cfg_attr(not(target_os = "none"), allow(dead_code))]
+pub fn exit_with_code(code: u16) -> ! {
+    if USE_SEMIHOSTING.load(Ordering::Relaxed) {
+        semihosting::exit(code as u32);
+    }
+    if code == 0 {
+        shutdown();
+    }
+    if USE_SBI.load(Ordering::Relaxed) {
+        sbi::system_reset(sbi::RESET_SHUTDOWN, sbi::REASON_SYSTEM_FAILURE);
+    }
+    let finisher = FINISHER.load(Ordering::Relaxed);
+    if finisher != 0 {
+        unsafe { write_volatile(finisher as *mut u32, (code as u32) << 16 | FINISHER_FAIL) };
+    }
+    // No way to report the status: power off anyway.
+    shutdown()
+}
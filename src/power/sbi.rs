@@ -0,0 +1,50 @@
+//! ---------------------------------------------------------------------------
+//! File       : sbi.rs
+//! Module     : power::sbi
+//! Author     : DiTurr
+//! Description:
+//! The System Reset extension (SRST) of the RISC-V Supervisor Binary
+//! Interface, implemented by firmware such as OpenSBI.
+//! ---------------------------------------------------------------------------
+
+/// Extension ID of SRST ("SRST").
+#[cfg(target_os = "none")]
+const EXT_SRST: usize = 0x5352_5354;
+
+/// Function ID of `sbi_system_reset`.
+#[cfg(target_os = "none")]
+const FID_SYSTEM_RESET: usize = 0;
+
+// Reset types.
+pub const RESET_SHUTDOWN: u32    = 0;
+pub const RESET_COLD_REBOOT: u32 = 1;
+
+// Reset reasons.
+pub const REASON_NONE: u32           = 0;
+pub const REASON_SYSTEM_FAILURE: u32 = 1;
+
+/// Asks the firmware to reset the system. Only returns if the firmware does
+/// not implement the request.
+///
+/// # Returns
+/// The SBI error code.
+pub fn system_reset(reset_type: u32, reason: u32) -> isize {
+    let error: isize;
+    #[cfg(not(target_os = "none"))]
+    {
+        // No firmware on the host: `SBI_ERR_NOT_SUPPORTED`.
+        let _ = (reset_type, reason);
+        error = -2;
+    }
+    #[cfg(target_os = "none")]
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            in("a7") EXT_SRST,
+            in("a6") FID_SYSTEM_RESET,
+            inlateout("a0") reset_type as usize => error,
+            inlateout("a1") reason as usize => _,
+        );
+    }
+    error
+}
@@ -0,0 +1,226 @@
+//! ---------------------------------------------------------------------------
+//! File       : random.rs
+//! Module     : random
+//! Author     : DiTurr
+//! Description:
+//! Kernel random number generator. Entropy from the virtio entropy device and
+//! from timer jitter is accumulated in a BLAKE2s input pool (see `pool`); once
+//! the pool holds 256 bits it seeds a ChaCha20 CRNG, which is reseeded from the
+//! pool at most every `RESEED_INTERVAL_MS`. Every request uses "fast key
+//! erasure": the first half of the first keystream block replaces the key, so a
+//! later compromise of the state does not reveal earlier output.
+//!
+//! ## Example
+//! ```rust
+//! random::init();
+//! let mut key = [0u8; 16];
+//! random::fill_bytes(&mut key);
+//! let isn = random::next_u32();
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod blake2s;
+pub mod chacha20;
+pub mod pool;
+pub mod urandom;
+
+use core::time::Duration;
+
+use crate::fs::devfs;
+use crate::peripherals::virtio::{self, rng::VIRTIO_RNG, DeviceType};
+use crate::registers::time::TIME;
+use crate::sync::spinlock::SpinLock;
+use crate::time::instant::Instant;
+use crate::{log_info, log_warn};
+use blake2s::Blake2s;
+use chacha20::{BLOCK_LEN, KEY_LEN};
+use pool::{FastPool, InputPool, SEED_BITS};
+
+/// Minimum time between two reseeds of the CRNG, in milliseconds.
+const RESEED_INTERVAL_MS: u64 = 60_000;
+
+/// Bytes requested from the entropy device at boot.
+const DEVICE_SEED_LEN: usize = 64;
+
+/// Jitter samples collected at boot before giving up on seeding the CRNG.
+const BOOT_JITTER_SAMPLES: usize = 4 * SEED_BITS * 64;
+
+/// The ChaCha20 CRNG.
+struct Crng {
+    key: [u8; KEY_LEN],
+    /// Per-request nonce, so that a key is never used twice with the same nonce.
+    generation: u64,
+    /// Time of the last reseed.
+    last_reseed: Instant,
+    seeded: bool,
+}
+
+/// The whole generator state, protected by a single lock.
+struct State {
+    input: InputPool,
+    fast: FastPool,
+    crng: Crng,
+}
+
+static STATE: SpinLock<State> = SpinLock::new(State {
+    input: InputPool::new(),
+    fast: FastPool::new(),
+    crng: Crng { key: [0; KEY_LEN], generation: 0, last_reseed: Instant::BOOT, seeded: false },
+});
+
+impl State {
+    /// Returns `true` if the CRNG is unseeded or was last reseeded more than
+    /// `RESEED_INTERVAL_MS` ago.
+    fn reseed_due(&self) -> bool {
+        !self.crng.seeded || self.crng.last_reseed.elapsed() >= Duration::from_millis(RESEED_INTERVAL_MS)
+    }
+
+    /// Mixes device entropy into the input pool, crediting every bit.
+    fn add_hw_entropy(&mut self, data: &[u8]) {
+        self.input.mix(data);
+        self.input.credit(8 * data.len());
+    }
+
+    /// Rekeys the CRNG from the input pool if the pool is full enough and a
+    /// reseed is due.
+    fn maybe_reseed(&mut self) {
+        if self.input.entropy_bits() < SEED_BITS || !self.reseed_due() {
+            return;
+        }
+        let mut hash = Blake2s::new();
+        hash.update(&self.crng.key);
+        hash.update(&self.input.extract());
+        self.crng.key = hash.finalize();
+        self.crng.last_reseed = Instant::now();
+        if !self.crng.seeded {
+            self.crng.seeded = true;
+            log_info!("random: CRNG seeded.");
+        }
+    }
+
+    /// Stirs one timer sample into the fast pool, folding it when full.
+    fn add_sample(&mut self, sample: u64) {
+        if self.fast.mix(sample) {
+            self.fast.fold_into(&mut self.input);
+            self.maybe_reseed();
+        }
+    }
+
+    /// Fills `out` with CRNG output.
+    fn generate(&mut self, out: &mut [u8]) {
+        self.maybe_reseed();
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&self.crng.generation.to_le_bytes());
+        self.crng.generation = self.crng.generation.wrapping_add(1);
+        // Fast key erasure: the first 32 bytes of block 0 become the next key.
+        let key = self.crng.key;
+        let first = chacha20::block(&key, 0, &nonce);
+        self.crng.key.copy_from_slice(&first[..KEY_LEN]);
+        let (head, rest) = out.split_at_mut(out.len().min(BLOCK_LEN - KEY_LEN));
+        head.copy_from_slice(&first[KEY_LEN..KEY_LEN + head.len()]);
+        for (counter, chunk) in rest.chunks_mut(BLOCK_LEN).enumerate() {
+            let block = chacha20::block(&key, counter as u32 + 1, &nonce);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+    }
+}
+
+/// Seeds the generator: pulls entropy from the virtio entropy device if QEMU
+/// provides one, collects timer jitter until the CRNG is seeded and exposes
+/// `/dev/urandom`.
+pub fn init() {
+    // Not secret, but it makes the pool differ between boots and machines.
+    add_device_randomness(&TIME::read().to_le_bytes());
+    match virtio::find_device(DeviceType::Entropy) {
+        Some(transport) => match VIRTIO_RNG.init(transport) {
+            Ok(()) => {
+                let mut seed = [0u8; DEVICE_SEED_LEN];
+                let filled = VIRTIO_RNG.fill(&mut seed);
+                let mut state = STATE.lock();
+                state.add_hw_entropy(&seed[..filled]);
+                state.maybe_reseed();
+                log_info!("random: {} bytes of entropy from virtio-rng.", filled);
+            }
+            Err(err) => {
+                log_warn!("virtio-rng initialization failed: {:?}", err);
+            }
+        },
+        None => {
+            log_warn!("No virtio-rng device found, relying on timer jitter.");
+        }
+    }
+    let mut samples = 0;
+    while !is_seeded() && samples < BOOT_JITTER_SAMPLES {
+        STATE.lock().add_sample(jitter_sample());
+        samples += 1;
+    }
+    if !is_seeded() {
+        log_warn!("random: CRNG not seeded yet, output is predictable.");
+    }
+    if let Err(err) = devfs::register("urandom", &urandom::URANDOM) {
+        log_warn!("random: cannot register /dev/urandom: {:?}", err);
+    }
+}
+
+/// Returns `true` once the CRNG has been seeded with 256 bits of entropy.
+pub fn is_seeded() -> bool {
+    STATE.lock().crng.seeded
+}
+
+/// Blocks until the CRNG is seeded, collecting timer jitter meanwhile.
+pub fn wait_for_seed() {
+    while !is_seeded() {
+        STATE.lock().add_sample(jitter_sample());
+    }
+}
+
+/// Fills `out` with cryptographically secure random bytes.
+///
+/// Never blocks: before the CRNG is seeded the output is only as good as the
+/// entropy collected so far (see [`is_seeded`]).
+pub fn fill_bytes(out: &mut [u8]) {
+    let mut state = STATE.lock();
+    // Top the pool up from the entropy device when a reseed is due.
+    if state.reseed_due() {
+        let mut seed = [0u8; KEY_LEN];
+        let filled = VIRTIO_RNG.fill(&mut seed);
+        state.add_hw_entropy(&seed[..filled]);
+    }
+    state.generate(out);
+}
+
+/// Returns a random `u32`.
+pub fn next_u32() -> u32 {
+    let mut bytes = [0u8; 4];
+    fill_bytes(&mut bytes);
+    u32::from_le_bytes(bytes)
+}
+
+/// Mixes `data` (e.g. a MAC address) into the input pool without crediting
+/// any entropy.
+pub fn add_device_randomness(data: &[u8]) {
+    STATE.lock().input.mix(data);
+}
+
+/// Mixes the current timer value into the pool. Meant for events arriving at
+/// unpredictable times (traps, received frames); it is skipped if the
+/// generator is busy, so it is safe to call from a trap handler.
+pub fn add_timer_jitter() {
+    if let Some(mut state) = STATE.try_lock() {
+        state.add_sample(TIME::read() as u64);
+    }
+}
+
+/// Measures how many loop iterations fit in one timer tick.
+///
+/// The count varies with cache, TLB and host scheduling effects, which makes
+/// it a (weak) entropy source even on an otherwise idle machine.
+fn jitter_sample() -> u64 {
+    let start = TIME::read();
+    let mut spins: u64 = 0;
+    let mut now = start;
+    while now == start {
+        spins += 1;
+        now = TIME::read();
+    }
+    (spins << 32) ^ now as u64
+}
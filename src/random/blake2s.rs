@@ -0,0 +1,159 @@
+//! ---------------------------------------------------------------------------
+//! File       : blake2s.rs
+//! Module     : random::blake2s
+//! Author     : DiTurr
+//! Description:
+//! The BLAKE2s-256 hash function (RFC 7693), used to accumulate and extract
+//! entropy in the input pool.
+//! ---------------------------------------------------------------------------
+
+/// Size of the digest in bytes.
+pub const HASH_LEN: usize = 32;
+
+/// Size of a message block in bytes.
+const BLOCK_LEN: usize = 64;
+
+/// Initialization vector (same as SHA-256).
+const IV: [u32; 8] = [
+    0x6a09_e667, 0xbb67_ae85, 0x3c6e_f372, 0xa54f_f53a,
+    0x510e_527f, 0x9b05_688c, 0x1f83_d9ab, 0x5be0_cd19,
+];
+
+/// Message word permutations of the ten rounds.
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// Incremental BLAKE2s-256 state.
+#[derive(Clone)]
+pub struct Blake2s {
+    h: [u32; 8],
+    /// Number of message bytes compressed so far.
+    t: u64,
+    buf: [u8; BLOCK_LEN],
+    buf_len: usize,
+}
+
+impl Blake2s {
+    /// Creates an unkeyed hasher with a 32-byte digest.
+    pub const fn new() -> Self {
+        let mut h = IV;
+        // Parameter block: digest length 32, key length 0, fanout 1, depth 1.
+        h[0] ^= 0x0101_0000 ^ HASH_LEN as u32;
+        Blake2s { h, t: 0, buf: [0; BLOCK_LEN], buf_len: 0 }
+    }
+
+    /// Compresses the buffered block.
+    fn compress(&mut self, last: bool) {
+        let mut m = [0u32; 16];
+        for (i, word) in m.iter_mut().enumerate() {
+            *word = u32::from_le_bytes(self.buf[4 * i..4 * i + 4].try_into().unwrap());
+        }
+        let mut v = [0u32; 16];
+        v[..8].copy_from_slice(&self.h);
+        v[8..].copy_from_slice(&IV);
+        v[12] ^= self.t as u32;
+        v[13] ^= (self.t >> 32) as u32;
+        if last {
+            v[14] = !v[14];
+        }
+        fn g(v: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+            v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+            v[d] = (v[d] ^ v[a]).rotate_right(16);
+            v[c] = v[c].wrapping_add(v[d]);
+            v[b] = (v[b] ^ v[c]).rotate_right(12);
+            v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+            v[d] = (v[d] ^ v[a]).rotate_right(8);
+            v[c] = v[c].wrapping_add(v[d]);
+            v[b] = (v[b] ^ v[c]).rotate_right(7);
+        }
+        for s in SIGMA.iter() {
+            g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+        for i in 0..8 {
+            self.h[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+
+    /// Absorbs `data`.
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The last block must be compressed by `finalize`, so only flush a
+            // full buffer when more input follows.
+            if self.buf_len == BLOCK_LEN {
+                self.t += BLOCK_LEN as u64;
+                self.compress(false);
+                self.buf_len = 0;
+            }
+            let count = (BLOCK_LEN - self.buf_len).min(data.len());
+            self.buf[self.buf_len..self.buf_len + count].copy_from_slice(&data[..count]);
+            self.buf_len += count;
+            data = &data[count..];
+        }
+    }
+
+    /// Produces the digest.
+    pub fn finalize(mut self) -> [u8; HASH_LEN] {
+        self.t += self.buf_len as u64;
+        self.buf[self.buf_len..].fill(0);
+        self.compress(true);
+        let mut out = [0u8; HASH_LEN];
+        for (i, word) in self.h.iter().enumerate() {
+            out[4 * i..4 * i + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::XorShift;
+
+    fn hash(data: &[u8]) -> [u8; HASH_LEN] {
+        let mut hasher = Blake2s::new();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    #[test_case]
+    fn rfc7693_abc() {
+        // RFC 7693, appendix B.
+        let expected = [
+            0x50, 0x8c, 0x5e, 0x8c, 0x32, 0x7c, 0x14, 0xe2, 0xe1, 0xa7, 0x2b, 0xa3, 0x4e, 0xeb, 0x45, 0x2f,
+            0x37, 0x45, 0x8b, 0x20, 0x9e, 0xd6, 0x3a, 0x29, 0x4d, 0x99, 0x9b, 0x4c, 0x86, 0x67, 0x59, 0x82,
+        ];
+        assert_eq!(hash(b"abc"), expected);
+    }
+
+    #[test_case]
+    fn split_updates_match_one_update() {
+        let mut rng = XorShift::new(7693);
+        let mut data = [0u8; 300];
+        rng.fill(&mut data);
+        let whole = hash(&data);
+        for _ in 0..64 {
+            let split = (rng.next_u64() % data.len() as u64) as usize;
+            let mut hasher = Blake2s::new();
+            hasher.update(&data[..split]);
+            hasher.update(&data[split..]);
+            assert_eq!(hasher.finalize(), whole, "split at {}", split);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! ---------------------------------------------------------------------------
+//! File       : pool.rs
+//! Module     : random::pool
+//! Author     : DiTurr
+//! Description:
+//! Entropy accumulation. Cheap samples (timer jitter) are first stirred into a
+//! small ARX "fast pool"; every `FOLD_SAMPLES` samples the fast pool is folded
+//! into the BLAKE2s input pool and credited with one bit of entropy. Device
+//! entropy goes straight into the input pool with the credit given by the
+//! caller. The input pool is only read through `extract`, which never reveals
+//! the pool state itself.
+//! ---------------------------------------------------------------------------
+
+use super::blake2s::{Blake2s, HASH_LEN};
+
+/// Number of entropy bits the input pool must hold before it seeds the CRNG.
+pub const SEED_BITS: usize = 256;
+
+/// Samples stirred into the fast pool before it is folded into the input pool.
+const FOLD_SAMPLES: u32 = 64;
+
+/// Upper bound for the entropy estimate of the input pool.
+const MAX_BITS: usize = 8 * HASH_LEN;
+
+/// The BLAKE2s input pool and its entropy estimate.
+pub struct InputPool {
+    hash: Blake2s,
+    entropy_bits: usize,
+}
+
+impl InputPool {
+    pub const fn new() -> Self {
+        InputPool { hash: Blake2s::new(), entropy_bits: 0 }
+    }
+
+    /// Mixes `data` into the pool without crediting entropy.
+    pub fn mix(&mut self, data: &[u8]) {
+        self.hash.update(data);
+    }
+
+    /// Credits `bits` bits of entropy to the pool.
+    pub fn credit(&mut self, bits: usize) {
+        self.entropy_bits = (self.entropy_bits + bits).min(MAX_BITS);
+    }
+
+    /// Returns the current entropy estimate in bits.
+    pub fn entropy_bits(&self) -> usize {
+        self.entropy_bits
+    }
+
+    /// Extracts a 32-byte seed and resets the entropy estimate.
+    ///
+    /// The pool digest is expanded into two independent hashes: one becomes
+    /// the new pool state, the other is returned, so the output cannot be used
+    /// to reconstruct past or future pool contents.
+    pub fn extract(&mut self) -> [u8; HASH_LEN] {
+        let digest = self.hash.clone().finalize();
+        let derive = |label: u8| {
+            let mut hash = Blake2s::new();
+            hash.update(&digest);
+            hash.update(&[label]);
+            hash.finalize()
+        };
+        self.hash = Blake2s::new();
+        self.hash.update(&derive(0));
+        self.entropy_bits = 0;
+        derive(1)
+    }
+}
+
+/// A small ARX pool absorbing high-rate, low-entropy samples.
+pub struct FastPool {
+    state: [u32; 4],
+    count: u32,
+}
+
+impl FastPool {
+    pub const fn new() -> Self {
+        FastPool { state: [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574], count: 0 }
+    }
+
+    /// Stirs a 64-bit sample into the pool.
+    ///
+    /// # Returns
+    /// `true` when enough samples were collected and the pool should be folded.
+    pub fn mix(&mut self, sample: u64) -> bool {
+        let s = &mut self.state;
+        s[0] ^= sample as u32;
+        s[1] ^= (sample >> 32) as u32;
+        // Two rounds of a SipHash-like ARX permutation on 32-bit words.
+        for _ in 0..2 {
+            s[0] = s[0].wrapping_add(s[1]);
+            s[1] = s[1].rotate_left(6) ^ s[0];
+            s[2] = s[2].wrapping_add(s[3]);
+            s[3] = s[3].rotate_left(27) ^ s[2];
+            s[0] = s[0].rotate_left(16).wrapping_add(s[3]);
+            s[3] = s[3].rotate_left(11) ^ s[0];
+            s[2] = s[2].wrapping_add(s[1]);
+            s[1] = s[1].rotate_left(7) ^ s[2];
+            s[2] = s[2].rotate_left(16);
+        }
+        self.count += 1;
+        self.count >= FOLD_SAMPLES
+    }
+
+    /// Folds the pool into `input`, crediting one bit of entropy.
+    pub fn fold_into(&mut self, input: &mut InputPool) {
+        let mut bytes = [0u8; 16];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        input.mix(&bytes);
+        input.credit(1);
+        self.count = 0;
+    }
+}
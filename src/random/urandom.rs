@@ -0,0 +1,29 @@
+//! ---------------------------------------------------------------------------
+//! File       : urandom.rs
+//! Module     : random::urandom
+//! Author     : DiTurr
+//! Description:
+//! The `/dev/urandom` character device. Reads return CRNG output and never
+//! block; writes are mixed into the input pool without crediting entropy.
+//! ---------------------------------------------------------------------------
+
+use crate::fs::CharDevice;
+use crate::syscalls::errno::Errno;
+
+/// The `/dev/urandom` device.
+pub struct Urandom;
+
+/// The kernel's `/dev/urandom` instance.
+pub static URANDOM: Urandom = Urandom;
+
+impl CharDevice for Urandom {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Errno> {
+        super::fill_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Errno> {
+        super::add_device_randomness(buf);
+        Ok(buf.len())
+    }
+}
@@ -5,6 +5,18 @@
 //! Description: Control and Status Registers (CSRs) utilities.
 //! ---------------------------------------------------------------------------
 pub mod macros;
-// pub mod mcause;
+pub mod mcause;
+pub mod mcycle;
 pub mod mepc;
+pub mod mhartid;
+pub mod mie;
+pub mod mip;
+#[cfg(not(target_os = "none"))]
+pub mod mock;
+pub mod mstatus;
+pub mod mtval;
+#[cfg(feature = "vectored-traps")]
+pub mod mtvec;
+pub mod pmpaddr;
+pub mod pmpcfg;
 pub mod time;
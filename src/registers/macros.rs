@@ -6,7 +6,9 @@
 //! Contains macros for reading and manipulating CSR registers. This macro simplifies the
 //! creation of types for reading Control and Status Registers (CSRs) in RISC-V architectures.
 //! It defines a public struct with a static `read()` method, which emits inline assembly to
-//! safely access the CSR and apply an optional bitmask.
+//! safely access the CSR and apply an optional bitmask. The `access` list adds `write()`,
+//! `set()` and `clear()` accessors. In host builds (unit tests), the accessors use the mock
+//! CSR file (see `registers::mock`) instead.
 //!
 //! ## Example
 //! ```rust
@@ -17,7 +19,16 @@
 //!     mask: 0xFFFF_FFFF_FFFF_FFFF
 //! );
 //!
+//! define_csr!(
+//!     /// Machine interrupt-enable register.
+//!     MIE,
+//!     address: 0x304,
+//!     mask: 0xffff_ffff_ffff_ffff,
+//!     access: set, clear
+//! );
+//!
 //! let ticks = MTime::read();
+//! MIE::set(1 << 7);
 //! ```
 //! ---------------------------------------------------------------------------
 
@@ -29,12 +40,22 @@ macro_rules! define_csr {
         $name:ident,
         address: $addr:literal,
         mask: $mask:expr
+        // Optional write accessors: `write`, `set`, `clear`
+        $(, access: $($access:ident),+)?
     ) => {
         // Apply the outer documentation, if provided
         $(#[$doc])*
         /// Auto-generated CSR accessor struct.
+        // Named as in the specification, e.g. `MSTATUS`.
+        #[allow(clippy::upper_case_acronyms)]
+        // Host builds only use the registers the unit tests exercise.
+        #[cfg_attr(not(target_os = "none"), allow(dead_code))]
         pub struct $name;
+        #[cfg_attr(not(target_os = "none"), allow(dead_code))]
         impl $name {
+            /// Address of the CSR.
+            pub const ADDRESS: usize = $addr;
+
             /// Reads the value of the CSR at the given address, masked with the provided bitmask.
             ///
             /// # Safety
@@ -46,13 +67,107 @@ macro_rules! define_csr {
             #[inline]
             pub fn read() -> usize {
                 let value: usize;
+                #[cfg(target_os = "none")]
                 unsafe {
                     // Emit a `csrr` instruction to read from a constant CSR address.
-                    core::arch::asm!("csrr {0}, {1}", out(reg) value, const $addr);
+                    core::arch::asm!("csrr {0}, {1}", out(reg) value, const Self::ADDRESS);
+                }
+                // Host builds read the mock CSR file instead.
+                #[cfg(not(target_os = "none"))]
+                {
+                    value = $crate::registers::mock::read(Self::ADDRESS);
                 }
                 // Apply the mask to filter relevant bits
                 value & $mask
             }
         }
+        $($($crate::define_csr!(@$access $name);)+)?
+    };
+    (@write $name:ident) => {
+        #[cfg_attr(not(target_os = "none"), allow(dead_code))]
+        impl $name {
+            /// Writes `value` to the CSR.
+            #[inline]
+            pub fn write(value: usize) {
+                #[cfg(target_os = "none")]
+                unsafe {
+                    // Emit a `csrw` instruction to write to a constant CSR address.
+                    core::arch::asm!("csrw {1}, {0}", in(reg) value, const Self::ADDRESS);
+                }
+                // Host builds write the mock CSR file instead.
+                #[cfg(not(target_os = "none"))]
+                $crate::registers::mock::write(Self::ADDRESS, value);
+            }
+        }
+    };
+    (@set $name:ident) => {
+        #[cfg_attr(not(target_os = "none"), allow(dead_code))]
+        impl $name {
+            /// Atomically sets `bits` in the CSR (`csrrs`).
+            ///
+            /// # Returns
+            /// The previous value of the CSR.
+            #[inline]
+            pub fn set(bits: usize) -> usize {
+                let previous: usize;
+                #[cfg(target_os = "none")]
+                unsafe {
+                    core::arch::asm!("csrrs {0}, {2}, {1}", out(reg) previous, in(reg) bits, const Self::ADDRESS);
+                }
+                #[cfg(not(target_os = "none"))]
+                {
+                    previous = $crate::registers::mock::read(Self::ADDRESS);
+                    $crate::registers::mock::write(Self::ADDRESS, previous | bits);
+                }
+                previous
+            }
+        }
+    };
+    (@clear $name:ident) => {
+        #[cfg_attr(not(target_os = "none"), allow(dead_code))]
+        impl $name {
+            /// Atomically clears `bits` in the CSR (`csrrc`).
+            ///
+            /// # Returns
+            /// The previous value of the CSR.
+            #[inline]
+            pub fn clear(bits: usize) -> usize {
+                let previous: usize;
+                #[cfg(target_os = "none")]
+                unsafe {
+                    core::arch::asm!("csrrc {0}, {2}, {1}", out(reg) previous, in(reg) bits, const Self::ADDRESS);
+                }
+                #[cfg(not(target_os = "none"))]
+                {
+                    previous = $crate::registers::mock::read(Self::ADDRESS);
+                    $crate::registers::mock::write(Self::ADDRESS, previous & !bits);
+                }
+                previous
+            }
+        }
     };
 }
+
+// The mock CSR file only exists on the host.
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use crate::registers::mepc::MEPC;
+    use crate::registers::mock;
+    use crate::registers::mstatus::MSTATUS;
+
+    #[test_case]
+    fn read_applies_the_mask() {
+        mock::write(0x341, 0x1_2345_6789);
+        assert_eq!(MEPC::read(), 0x2345_6789);
+    }
+
+    #[test_case]
+    fn set_and_clear_return_the_previous_value() {
+        let saved = mock::read(MSTATUS::ADDRESS);
+        mock::write(MSTATUS::ADDRESS, 0b0101);
+        assert_eq!(MSTATUS::set(0b0010), 0b0101);
+        assert_eq!(MSTATUS::clear(0b0001), 0b0111);
+        assert_eq!(MSTATUS::read(), 0b0110);
+        mock::write(MSTATUS::ADDRESS, saved);
+    }
+}
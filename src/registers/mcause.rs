@@ -12,5 +12,5 @@ define_csr!(
     /// Machine trap cause.
     MCAUSE,
     address: 0x342,
-    mask: 0xffff_ffff
+    mask: 0xffff_ffff_ffff_ffff
 );
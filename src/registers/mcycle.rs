@@ -0,0 +1,16 @@
+//! ---------------------------------------------------------------------------
+//! File       : mcycle.rs
+//! Module     : registers::mcycle
+//! Author     : DiTurr
+//! Description:
+//! Defines the mcycle CSR register abstraction and accessors.
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// Machine cycle counter: clock cycles executed by the hart.
+    MCYCLE,
+    address: 0xB00,
+    mask: 0xffff_ffff_ffff_ffff
+);
@@ -0,0 +1,16 @@
+//! ---------------------------------------------------------------------------
+//! File       : mhartid.rs
+//! Module     : registers::mhartid
+//! Author     : DiTurr
+//! Description:
+//! Defines the mhartid CSR register abstraction and accessors.
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// Hardware thread ID.
+    MHARTID,
+    address: 0xF14,
+    mask: 0xffff_ffff_ffff_ffff
+);
@@ -0,0 +1,17 @@
+//! ---------------------------------------------------------------------------
+//! File       : mie.rs
+//! Module     : registers::mie
+//! Author     : DiTurr
+//! Description:
+//! Defines the mie CSR register abstraction and accessors.
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// Machine interrupt-enable register.
+    MIE,
+    address: 0x304,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: set
+);
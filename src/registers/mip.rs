@@ -0,0 +1,16 @@
+//! ---------------------------------------------------------------------------
+//! File       : mip.rs
+//! Module     : registers::mip
+//! Author     : DiTurr
+//! Description:
+//! Defines the mip CSR register abstraction and accessors.
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// Machine interrupt-pending register.
+    MIP,
+    address: 0x344,
+    mask: 0xffff_ffff_ffff_ffff
+);
@@ -0,0 +1,36 @@
+//! ---------------------------------------------------------------------------
+//! File       : mock.rs
+//! Module     : registers::mock
+//! Author     : DiTurr
+//! Description:
+//! Mock CSR file for host builds (unit tests): the registers defined with
+//! `define_csr!` read their value from here instead of executing `csrr`.
+//! Every CSR reads 0 until a test stores a value.
+//!
+//! The file is shared by all tests; tests storing into the same CSR must not
+//! expect to be alone.
+//!
+//! ## Example
+//! ```rust
+//! mock::write(0x342, 0x8000_0000_0000_0007); // mcause
+//! assert_eq!(MCAUSE::read(), 0x8000_0000_0000_0007);
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of CSR addresses (12 bits).
+const CSR_COUNT: usize = 4096;
+
+/// The mock CSR values, indexed by address.
+static CSRS: [AtomicUsize; CSR_COUNT] = [const { AtomicUsize::new(0) }; CSR_COUNT];
+
+/// Returns the value of the CSR at `addr`.
+pub fn read(addr: usize) -> usize {
+    CSRS[addr % CSR_COUNT].load(Ordering::Relaxed)
+}
+
+/// Sets the value of the CSR at `addr`.
+pub fn write(addr: usize, value: usize) {
+    CSRS[addr % CSR_COUNT].store(value, Ordering::Relaxed);
+}
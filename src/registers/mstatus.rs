@@ -0,0 +1,17 @@
+//! ---------------------------------------------------------------------------
+//! File       : mstatus.rs
+//! Module     : registers::mstatus
+//! Author     : DiTurr
+//! Description:
+//! Defines the mstatus CSR register abstraction and accessors.
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// Machine status register.
+    MSTATUS,
+    address: 0x300,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: set, clear
+);
@@ -0,0 +1,16 @@
+//! ---------------------------------------------------------------------------
+//! File       : mtval.rs
+//! Module     : registers::mtval
+//! Author     : DiTurr
+//! Description:
+//! Defines the mtval CSR register abstraction and accessors.
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// Machine trap value (e.g., faulting address).
+    MTVAL,
+    address: 0x343,
+    mask: 0xffff_ffff_ffff_ffff
+);
@@ -0,0 +1,17 @@
+//! ---------------------------------------------------------------------------
+//! File       : mtvec.rs
+//! Module     : registers::mtvec
+//! Author     : DiTurr
+//! Description:
+//! Defines the mtvec CSR register abstraction and accessors.
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// Machine trap-vector base address register.
+    MTVEC,
+    address: 0x305,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
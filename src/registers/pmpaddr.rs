@@ -0,0 +1,138 @@
+//! ---------------------------------------------------------------------------
+//! File       : pmpaddr.rs
+//! Module     : registers::pmpaddr
+//! Author     : DiTurr
+//! Description:
+//! Defines the pmpaddr CSR register abstractions and accessors, one per PMP
+//! entry (the address divided by 4).
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// PMP address of entry 0.
+    PMPADDR0,
+    address: 0x3b0,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 1.
+    PMPADDR1,
+    address: 0x3b1,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 2.
+    PMPADDR2,
+    address: 0x3b2,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 3.
+    PMPADDR3,
+    address: 0x3b3,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 4.
+    PMPADDR4,
+    address: 0x3b4,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 5.
+    PMPADDR5,
+    address: 0x3b5,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 6.
+    PMPADDR6,
+    address: 0x3b6,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 7.
+    PMPADDR7,
+    address: 0x3b7,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 8.
+    PMPADDR8,
+    address: 0x3b8,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 9.
+    PMPADDR9,
+    address: 0x3b9,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 10.
+    PMPADDR10,
+    address: 0x3ba,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 11.
+    PMPADDR11,
+    address: 0x3bb,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 12.
+    PMPADDR12,
+    address: 0x3bc,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 13.
+    PMPADDR13,
+    address: 0x3bd,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 14.
+    PMPADDR14,
+    address: 0x3be,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP address of entry 15.
+    PMPADDR15,
+    address: 0x3bf,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
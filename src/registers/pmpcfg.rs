@@ -0,0 +1,26 @@
+//! ---------------------------------------------------------------------------
+//! File       : pmpcfg.rs
+//! Module     : registers::pmpcfg
+//! Author     : DiTurr
+//! Description:
+//! Defines the pmpcfg CSR register abstractions and accessors. Only the even
+//! registers exist on RV64, each holding the configuration of 8 entries.
+//! ---------------------------------------------------------------------------
+
+use crate::define_csr;
+
+define_csr!(
+    /// PMP configuration of entries 0 to 7.
+    PMPCFG0,
+    address: 0x3a0,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
+
+define_csr!(
+    /// PMP configuration of entries 8 to 15.
+    PMPCFG2,
+    address: 0x3a2,
+    mask: 0xffff_ffff_ffff_ffff,
+    access: write
+);
@@ -9,8 +9,8 @@
 use crate::define_csr;
 
 define_csr!(
-    /// Timer value.
+    /// Timer value: ticks of the platform timebase since reset (see `time`).
     TIME,
     address: 0xC01,
-    mask: 0xffff_ffff
+    mask: 0xffff_ffff_ffff_ffff
 );
@@ -0,0 +1,403 @@
+//! ---------------------------------------------------------------------------
+//! File       : stack.rs
+//! Module     : stack
+//! Author     : DiTurr
+//! Description:
+//! Kernel stacks and stack overflow detection. The linker script reserves,
+//! above the BSS, a slot for each of `MAX_HARTS` harts:
+//! ```text
+//! | guard | kernel stack (512 KiB) | guard | emergency trap stack (16 KiB) |
+//! ```
+//! then a slot for each of `MAX_THREAD_STACKS` kernel threads, handed out by
+//! [`allocate`]:
+//! ```text
+//! | guard | thread stack (16 KiB) |
+//! ```
+//! Stacks grow down, towards the guard region below them, which `pmp::init`
+//! makes inaccessible (with locked entries, which also bind M-mode): an
+//! overflow faults instead of corrupting what lies below.
+//!
+//! The trap vector (`asm/trap.S`) finds the hart's [`TrapState`] in
+//! `mscratch`. If the trap frame would land in the guard region below the
+//! current stack (see [`set_current`]), it is pushed on the hart's emergency
+//! trap stack instead, so
+//! that the trap handler can still run and report the overflow: it names the
+//! stack whose guard region holds the faulting address ("stack overflow in
+//! hart0").
+//!
+//! Stacks have a name and are registered for the diagnostics (and the panic
+//! backtrace) with [`register`].
+//!
+//! Stacks are painted with a known pattern when created ([`paint`]): the
+//! words still holding it were never used, which gives the high-water mark of
+//! each stack ([`stack_usage`], listed by the monitor's `ps`). [`poll`] warns
+//! once about each stack whose high-water mark exceeds `stackwarn=<percent>`
+//! of its size (75 by default), to size stacks from measurements.
+//! ---------------------------------------------------------------------------
+
+use core::ops::Range;
+use core::ptr::addr_of;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::cmdline;
+use crate::registers::mhartid::MHARTID;
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+use crate::time;
+use crate::{log_error, log_warn};
+
+// Layout of the stacks; must match `lds/virt.lds` (and `asm/trap.S` for the
+// guard size). Per-hart tables are indexed by `mhartid`: only hart 0 runs the
+// kernel, `asm/boot.S` parks the others before they reach any of them.
+pub const MAX_HARTS: usize       = 4;
+pub const GUARD_SIZE: usize      = 0x1000;
+pub const STACK_SIZE: usize      = 0x8_0000;
+pub const TRAP_STACK_SIZE: usize = 0x4000;
+const SLOT_SIZE: usize           = GUARD_SIZE + STACK_SIZE + GUARD_SIZE + TRAP_STACK_SIZE;
+pub const MAX_THREAD_STACKS: usize = 2;
+pub const THREAD_STACK_SIZE: usize = 0x4000;
+const THREAD_SLOT_SIZE: usize      = GUARD_SIZE + THREAD_STACK_SIZE;
+
+/// Maximum number of registered stacks.
+const MAX_STACKS: usize = 16;
+
+/// Pattern of the unused words of a stack.
+const PAINT: usize = 0xa5a5_a5a5_a5a5_a5a5;
+
+/// Room left unpainted below the stack pointer when painting the stack in use.
+const PAINT_MARGIN: usize = 1024;
+
+/// Default `stackwarn`, in percent of the stack size.
+const DEFAULT_WARN_PERCENT: usize = 75;
+
+/// Interval between two checks of the high-water marks, in milliseconds.
+const CHECK_INTERVAL_MS: u64 = 1000;
+
+/// Names of the stacks of each hart: kernel stack, emergency trap stack.
+const HART_STACKS: [(&str, &str); MAX_HARTS] = [
+    ("hart0", "hart0 trap"),
+    ("hart1", "hart1 trap"),
+    ("hart2", "hart2 trap"),
+    ("hart3", "hart3 trap"),
+];
+
+unsafe extern "C" {
+    static _stacks_start: u8;
+    static _stacks_end: u8;
+    static _stack: u8;
+}
+
+/// What the trap vector needs to pick a stack; `mscratch` points to the
+/// hart's. The layout must match the offsets used in `asm/trap.S`.
+#[repr(C)]
+pub struct TrapState {
+    /// Top of the emergency trap stack.
+    emergency_sp: AtomicUsize,
+    /// Bottom of the current stack, above its guard region (0: unknown).
+    limit: AtomicUsize,
+    /// Registers saved while the trap vector picks the stack.
+    scratch: [AtomicUsize; 2],
+}
+
+impl TrapState {
+    const fn new() -> TrapState {
+        TrapState {
+            emergency_sp: AtomicUsize::new(0),
+            limit: AtomicUsize::new(0),
+            scratch: [AtomicUsize::new(0), AtomicUsize::new(0)],
+        }
+    }
+}
+
+/// The trap state of each hart, used by `asm/boot.S` and `asm/trap.S`.
+#[unsafe(no_mangle)]
+static TRAP_STATES: [TrapState; MAX_HARTS] = [const { TrapState::new() }; MAX_HARTS];
+
+/// A kernel stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stack {
+    /// Who runs on it, e.g. `hart0`.
+    pub name: &'static str,
+    /// Lowest address; the guard region lies below.
+    pub bottom: usize,
+    /// First address past the stack, the initial stack pointer.
+    pub top: usize,
+}
+
+impl Stack {
+    /// Returns the guard region below the stack.
+    pub fn guard(&self) -> Range<usize> {
+        self.bottom - GUARD_SIZE..self.bottom
+    }
+
+    /// Returns whether `addr` lies within the stack.
+    pub fn contains(&self, addr: usize) -> bool {
+        (self.bottom..self.top).contains(&addr)
+    }
+}
+
+/// How much of a stack was used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// High-water mark, in bytes from the top.
+    pub used: usize,
+    /// Size of the stack, in bytes.
+    pub size: usize,
+}
+
+impl Usage {
+    /// Returns the high-water mark in percent of the size.
+    pub fn percent(&self) -> usize {
+        self.used * 100 / self.size
+    }
+}
+
+/// A registered stack.
+#[derive(Clone, Copy)]
+struct Entry {
+    stack: Stack,
+    /// The usage warning was logged.
+    warned: bool,
+}
+
+/// The registered stacks.
+static STACKS: SpinLock<[Option<Entry>; MAX_STACKS]> = SpinLock::new([None; MAX_STACKS]);
+
+/// Uptime of the last high-water mark check, in milliseconds.
+static LAST_CHECK_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Start of the stack slots, 0 until [`init`] checked the layout.
+static SLOTS_START: AtomicUsize = AtomicUsize::new(0);
+
+/// Thread stacks handed out by [`allocate`].
+static THREAD_STACKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers `stack` for the diagnostics. Its guard region must be reserved,
+/// and made inaccessible to catch overflows.
+///
+/// # Returns
+/// `EINVAL` if the stack is empty or its guard region not page-aligned,
+/// `EEXIST` if the name is taken, `ENOSPC` if the table is full.
+pub fn register(stack: Stack) -> Result<(), Errno> {
+    if stack.bottom >= stack.top || !stack.bottom.is_multiple_of(GUARD_SIZE) || stack.bottom < GUARD_SIZE {
+        return Err(Errno::EINVAL);
+    }
+    let mut stacks = STACKS.lock();
+    if stacks.iter().flatten().any(|entry| entry.stack.name == stack.name) {
+        return Err(Errno::EEXIST);
+    }
+    let slot = stacks.iter_mut().find(|slot| slot.is_none()).ok_or(Errno::ENOSPC)?;
+    *slot = Some(Entry { stack, warned: false });
+    Ok(())
+}
+
+/// Returns the registered stacks.
+pub fn stacks() -> impl Iterator<Item = Stack> {
+    let stacks = *STACKS.lock();
+    stacks.into_iter().flatten().map(|entry| entry.stack)
+}
+
+/// Returns the stack holding `addr`.
+pub fn find(addr: usize) -> Option<Stack> {
+    stacks().find(|stack| stack.contains(addr))
+}
+
+/// Returns the stack whose guard region holds `addr`: an access there is an
+/// overflow of that stack.
+pub fn overflowed(addr: usize) -> Option<Stack> {
+    stacks().find(|stack| stack.guard().contains(&addr))
+}
+
+/// Returns the guard regions of every stack slot, in use or not (none if the
+/// layout does not match the linker script).
+pub fn guards() -> impl Iterator<Item = Range<usize>> {
+    let start = SLOTS_START.load(Ordering::Relaxed);
+    let harts = (0..MAX_HARTS).flat_map(move |hart| {
+        let kernel = start + hart * SLOT_SIZE;
+        let trap = kernel + GUARD_SIZE + STACK_SIZE;
+        [kernel..kernel + GUARD_SIZE, trap..trap + GUARD_SIZE]
+    });
+    let threads = (0..MAX_THREAD_STACKS).map(move |thread| {
+        let guard = start + MAX_HARTS * SLOT_SIZE + thread * THREAD_SLOT_SIZE;
+        guard..guard + GUARD_SIZE
+    });
+    harts.chain(threads).filter(move |_| start != 0)
+}
+
+/// Hands out a thread stack, painted and registered as `name`.
+///
+/// # Returns
+/// `ENOSPC` if every thread stack is taken, `ENODEV` if the layout does not
+/// match the linker script.
+pub fn allocate(name: &'static str) -> Result<Stack, Errno> {
+    let start = SLOTS_START.load(Ordering::Relaxed);
+    if start == 0 {
+        return Err(Errno::ENODEV);
+    }
+    let index = THREAD_STACKS
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| (count < MAX_THREAD_STACKS).then_some(count + 1))
+        .map_err(|_| Errno::ENOSPC)?;
+    let bottom = start + MAX_HARTS * SLOT_SIZE + index * THREAD_SLOT_SIZE + GUARD_SIZE;
+    let stack = Stack { name, bottom, top: bottom + THREAD_STACK_SIZE };
+    // SAFETY: The slot was just handed out, nothing runs on it.
+    unsafe { paint(&stack) };
+    register(stack)?;
+    Ok(stack)
+}
+
+/// Makes `stack` the current stack of this hart, for the overflow check of
+/// the trap vector. Called when switching to a thread.
+pub fn set_current(stack: &Stack) {
+    TRAP_STATES[MHARTID::read()].limit.store(stack.bottom, Ordering::Relaxed);
+}
+
+/// Fills `words` with the paint pattern.
+fn paint_words(words: &mut [usize]) {
+    for word in words {
+        // SAFETY: `word` is a valid reference; volatile keeps the compiler
+        // from assuming anything about memory it does not see used.
+        unsafe { write_volatile(word, PAINT) };
+    }
+}
+
+/// Returns the number of words, from the start of `words`, still holding the
+/// paint pattern.
+fn painted_words(words: &[usize]) -> usize {
+    words.iter().take_while(|&word| unsafe { read_volatile(word) } == PAINT).count()
+}
+
+/// Paints `stack` up to `end` with the pattern.
+///
+/// # Safety
+/// Nothing may use `[stack.bottom, end)`.
+unsafe fn paint_until(stack: &Stack, end: usize) {
+    let len = end.saturating_sub(stack.bottom) / size_of::<usize>();
+    // SAFETY: The range lies within the stack and is unused (see above).
+    paint_words(unsafe { core::slice::from_raw_parts_mut(stack.bottom as *mut usize, len) });
+}
+
+/// Paints the new stack `stack`, for [`stack_usage`].
+///
+/// # Safety
+/// Nothing may run on `stack` yet.
+pub unsafe fn paint(stack: &Stack) {
+    unsafe { paint_until(stack, stack.top) };
+}
+
+/// Returns the high-water mark of `stack`, assuming it was painted.
+fn usage(stack: &Stack) -> Usage {
+    let size = stack.top - stack.bottom;
+    // SAFETY: The stack is mapped; concurrent writes only lower the mark.
+    let words = unsafe { core::slice::from_raw_parts(stack.bottom as *const usize, size / size_of::<usize>()) };
+    Usage { used: size - painted_words(words) * size_of::<usize>(), size }
+}
+
+/// Returns the high-water mark of the stack named `task`.
+pub fn stack_usage(task: &str) -> Option<Usage> {
+    stacks().find(|stack| stack.name == task).map(|stack| usage(&stack))
+}
+
+/// Checks the high-water marks against `stackwarn`, once a second, and warns
+/// about the stacks above it. Called from the main loop.
+pub fn poll() {
+    let now = time::uptime().as_millis() as u64;
+    if now.saturating_sub(LAST_CHECK_MS.load(Ordering::Relaxed)) < CHECK_INTERVAL_MS {
+        return;
+    }
+    LAST_CHECK_MS.store(now, Ordering::Relaxed);
+    let limit = cmdline::get("stackwarn").and_then(|value| value.parse().ok()).unwrap_or(DEFAULT_WARN_PERCENT);
+    let mut stacks = STACKS.lock();
+    for entry in stacks.iter_mut().flatten().filter(|entry| !entry.warned) {
+        let usage = usage(&entry.stack);
+        if usage.percent() >= limit {
+            entry.warned = true;
+            log_warn!(
+                "stack: '{}' used {} of {} bytes ({}%, stackwarn={}).",
+                entry.stack.name, usage.used, usage.size, usage.percent(), limit
+            );
+        }
+    }
+}
+
+/// Registers the stacks of the harts, paints them and points their trap
+/// states at them. Runs first: until then, the trap vector does not detect
+/// overflows.
+pub fn init() {
+    let start = addr_of!(_stacks_start) as usize;
+    if addr_of!(_stacks_end) as usize != start + MAX_HARTS * SLOT_SIZE + MAX_THREAD_STACKS * THREAD_SLOT_SIZE
+        || addr_of!(_stack) as usize != start + GUARD_SIZE + STACK_SIZE
+    {
+        log_error!("stack: the layout does not match the linker script, no overflow detection.");
+        return;
+    }
+    SLOTS_START.store(start, Ordering::Relaxed);
+    // This code runs on the boot hart's kernel stack, which is painted below
+    // the current stack pointer (approximated by a local variable).
+    let here = 0u8;
+    let sp = addr_of!(here) as usize;
+    for (hart, (name, trap_name)) in HART_STACKS.into_iter().enumerate() {
+        let bottom = start + hart * SLOT_SIZE + GUARD_SIZE;
+        let kernel = Stack { name, bottom, top: bottom + STACK_SIZE };
+        let trap = Stack { name: trap_name, bottom: kernel.top + GUARD_SIZE, top: kernel.top + GUARD_SIZE + TRAP_STACK_SIZE };
+        TRAP_STATES[hart].emergency_sp.store(trap.top, Ordering::Relaxed);
+        TRAP_STATES[hart].limit.store(kernel.bottom, Ordering::Relaxed);
+        // SAFETY: Only the boot hart runs, on its kernel stack above `sp`.
+        unsafe {
+            if kernel.contains(sp) {
+                paint_until(&kernel, sp - PAINT_MARGIN);
+            } else {
+                paint(&kernel);
+            }
+            paint(&trap);
+        }
+        for stack in [kernel, trap] {
+            if let Err(err) = register(stack) {
+                log_error!("stack: cannot register '{}': {:?}", stack.name, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn guards_lie_below_stacks() {
+        let stack = Stack { name: "test", bottom: 0x9000_1000, top: 0x9000_3000 };
+        assert_eq!(stack.guard(), 0x9000_0000..0x9000_1000);
+        assert!(stack.contains(0x9000_1000) && stack.contains(0x9000_2fff) && !stack.contains(0x9000_3000));
+    }
+
+    #[test_case]
+    fn registered_stacks_are_found() {
+        /// A guard page and a two-page stack, in memory that is mapped.
+        #[repr(C, align(4096))]
+        struct Pages([u8; 3 * GUARD_SIZE]);
+        let pages = Pages([0; 3 * GUARD_SIZE]);
+        let base = pages.0.as_ptr() as usize;
+        let stack = Stack { name: "test", bottom: base + GUARD_SIZE, top: base + 3 * GUARD_SIZE };
+        // The table is restored afterwards: the scans must not see this stack.
+        let saved = *STACKS.lock();
+        assert_eq!(register(Stack { bottom: stack.bottom + 0x800, ..stack }), Err(Errno::EINVAL));
+        assert_eq!(register(stack), Ok(()));
+        assert_eq!(register(stack), Err(Errno::EEXIST));
+        assert_eq!(overflowed(stack.bottom - 8), Some(stack));
+        assert_eq!(find(stack.bottom + GUARD_SIZE), Some(stack));
+        assert_eq!(overflowed(stack.bottom), None);
+        *STACKS.lock() = saved;
+    }
+
+    #[test_case]
+    fn watermark_is_the_deepest_write() {
+        let mut words = [0usize; 64];
+        paint_words(&mut words);
+        assert_eq!(painted_words(&words), 64);
+        // The stack grows down: the top words were used.
+        words[40] = 0;
+        assert_eq!(painted_words(&words), 40);
+        let usage = Usage { used: (64 - 40) * size_of::<usize>(), size: 64 * size_of::<usize>() };
+        assert_eq!(usage.percent(), 37);
+    }
+}
@@ -0,0 +1,8 @@
+//! ---------------------------------------------------------------------------
+//! File       : sync.rs
+//! Module     : sync
+//! Author     : DiTurr
+//! Description: Synchronization primitives usable without a heap or an OS.
+//! ---------------------------------------------------------------------------
+pub mod spinlock;
+pub mod static_cell;
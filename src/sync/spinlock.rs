@@ -0,0 +1,103 @@
+//! ---------------------------------------------------------------------------
+//! File       : spinlock.rs
+//! Module     : sync::spinlock
+//! Author     : DiTurr
+//! Description:
+//! This module provides a minimal busy-waiting mutual exclusion lock for `no_std`
+//! environments. It is used to protect global driver and subsystem state that is
+//! shared between harts (and, later, between normal code and trap handlers).
+//!
+//! ## Example
+//! ```rust
+//! static COUNTER: SpinLock<u32> = SpinLock::new(0);
+//!
+//! *COUNTER.lock() += 1;
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A spinning mutual exclusion lock protecting a value of type `T`.
+///
+/// The lock is acquired with [`SpinLock::lock`], which returns a guard that
+/// releases the lock when dropped.
+pub struct SpinLock<T> {
+    /// `true` while a guard is alive.
+    locked: AtomicBool,
+    /// The protected value.
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: Access to `value` is serialized through `locked`.
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    /// Creates a new unlocked `SpinLock` holding `value`.
+    ///
+    /// This is a `const fn`, allowing usage in `static` initializations.
+    pub const fn new(value: T) -> Self {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Acquires the lock, spinning until it becomes available.
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            // Spin on a plain load to avoid hammering the cache line with writes.
+            while self.locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Attempts to acquire the lock without spinning.
+    ///
+    /// # Returns
+    /// `Some(guard)` if the lock was free, `None` otherwise.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(SpinLockGuard { lock: self })
+        } else {
+            None
+        }
+    }
+}
+
+/// RAII guard giving access to the value protected by a [`SpinLock`].
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The guard proves exclusive ownership of the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The guard proves exclusive ownership of the lock.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,58 @@
+//! ---------------------------------------------------------------------------
+//! File       : static_cell.rs
+//! Module     : sync::static_cell
+//! Author     : DiTurr
+//! Description:
+//! This module provides `StaticCell`, a statically allocated value that can be
+//! handed out exactly once as a `&'static mut` reference. Drivers use it for
+//! memory that is shared with devices (virtqueues, DMA buffers): the memory needs
+//! a fixed physical address for the whole lifetime of the kernel, and there is no
+//! heap to allocate it from.
+//!
+//! ## Example
+//! ```rust
+//! static BUFFER: StaticCell<[u8; 4096]> = StaticCell::new([0; 4096]);
+//!
+//! let buf: &'static mut [u8; 4096] = BUFFER.take().expect("buffer already in use");
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A value in static storage that can be mutably borrowed only once.
+pub struct StaticCell<T> {
+    /// Set once the value has been handed out.
+    taken: AtomicBool,
+    /// The stored value.
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The value is handed out at most once, guarded by `taken`.
+unsafe impl<T: Send> Sync for StaticCell<T> {}
+
+impl<T> StaticCell<T> {
+    /// Creates a new `StaticCell` holding `value`.
+    pub const fn new(value: T) -> Self {
+        StaticCell {
+            taken: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Takes the unique mutable reference to the stored value.
+    ///
+    /// # Returns
+    /// `Some(&mut T)` the first time it is called, `None` afterwards.
+    // The mutable borrow comes from a shared one, but `taken` is set at most
+    // once: no other reference to the value ever exists.
+    #[allow(clippy::mut_from_ref)]
+    pub fn take(&'static self) -> Option<&'static mut T> {
+        if self.taken.swap(true, Ordering::AcqRel) {
+            None
+        } else {
+            // SAFETY: `taken` guarantees this is the only reference ever created.
+            Some(unsafe { &mut *self.value.get() })
+        }
+    }
+}
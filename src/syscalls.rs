@@ -0,0 +1,134 @@
+//! ---------------------------------------------------------------------------
+//! File       : syscalls.rs
+//! Module     : syscalls
+//! Author     : DiTurr
+//! Description:
+//! System call entry point. Programs issue `ecall` with the system call number
+//! in `a7` and up to six arguments in `a0`-`a5`; the result is returned in `a0`,
+//! negative values being `-errno`. Numbers follow the Linux RISC-V ABI.
+//!
+//! ## Example
+//! ```rust
+//! let fd: isize;
+//! unsafe {
+//!     core::arch::asm!("ecall", in("a7") nr::SOCKET, inlateout("a0") AF_INET => fd,
+//!                      in("a1") SOCK_STREAM, in("a2") 0);
+//! }
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod errno;
+pub mod fd;
+pub mod fs;
+pub mod net;
+pub mod random;
+pub mod syslog;
+pub mod time;
+
+use core::ptr::addr_of;
+
+use crate::arch::pmp::{self, Access};
+
+use crate::traps::trap_frame::{reg, TrapFrame};
+use errno::Errno;
+
+/// Result of a system call: a non-negative value or an error number.
+pub type SyscallResult = Result<usize, Errno>;
+
+/// System call numbers.
+pub mod nr {
+    pub const OPENAT: usize        = 56;
+    pub const CLOSE: usize         = 57;
+    pub const READ: usize          = 63;
+    pub const WRITE: usize         = 64;
+    pub const CLOCK_GETTIME: usize = 113;
+    pub const SYSLOG: usize        = 116;
+    pub const SOCKET: usize        = 198;
+    pub const BIND: usize          = 200;
+    pub const LISTEN: usize        = 201;
+    pub const ACCEPT: usize        = 202;
+    pub const CONNECT: usize       = 203;
+    pub const SENDTO: usize        = 206;
+    pub const RECVFROM: usize      = 207;
+    pub const GETRANDOM: usize     = 278;
+}
+
+// Memory boundaries defined by the linker script.
+unsafe extern "C" {
+    static _memory_start: u8;
+    static _memory_end: u8;
+}
+
+/// Serves the system call described by `frame`, storing the result in `a0`.
+pub fn dispatch(frame: &mut TrapFrame) {
+    let [a0, a1, a2, a3, a4, a5] = frame.syscall_args();
+    let result = match frame.regs[reg::A7] {
+        nr::OPENAT => fs::openat(a0, a1, a2, a3),
+        nr::CLOSE => fd::close(a0),
+        nr::READ => fs::read(a0, a1, a2),
+        nr::WRITE => fs::write(a0, a1, a2),
+        nr::CLOCK_GETTIME => time::clock_gettime(a0, a1),
+        nr::SYSLOG => syslog::syslog(a0, a1, a2),
+        nr::SOCKET => net::socket(a0, a1, a2),
+        nr::BIND => net::bind(a0, a1, a2),
+        nr::LISTEN => net::listen(a0, a1),
+        nr::ACCEPT => net::accept(a0, a1, a2),
+        nr::CONNECT => net::connect(a0, a1, a2),
+        nr::SENDTO => net::sendto(a0, a1, a2, a3, a4, a5),
+        nr::RECVFROM => net::recvfrom(a0, a1, a2, a3, a4, a5),
+        nr::GETRANDOM => random::getrandom(a0, a1, a2),
+        _ => Err(Errno::ENOSYS),
+    };
+    frame.regs[reg::A0] = match result {
+        Ok(value) => value,
+        Err(errno) => (-(errno as isize)) as usize,
+    };
+}
+
+/// Checks that `[ptr, ptr + len)` lies within RAM.
+fn check_range(ptr: usize, len: usize) -> Result<(), Errno> {
+    let start = addr_of!(_memory_start) as usize;
+    let end = addr_of!(_memory_end) as usize;
+    match ptr.checked_add(len) {
+        Some(limit) if ptr >= start && limit <= end => Ok(()),
+        _ => Err(Errno::EFAULT),
+    }
+}
+
+/// Borrows a caller buffer for reading.
+pub fn user_slice<'a>(ptr: usize, len: usize) -> Result<&'a [u8], Errno> {
+    check_range(ptr, len)?;
+    // SAFETY: The range lies within RAM; the kernel runs without memory protection.
+    Ok(unsafe { core::slice::from_raw_parts(ptr as *const u8, len) })
+}
+
+/// Borrows a caller buffer for writing.
+///
+/// # Returns
+/// `EFAULT` also if a locked PMP entry makes the range read-only (kernel text
+/// with `pmp=lock`).
+pub fn user_slice_mut<'a>(ptr: usize, len: usize) -> Result<&'a mut [u8], Errno> {
+    check_range(ptr, len)?;
+    if !pmp::machine_permits(ptr, len, Access::Write) {
+        return Err(Errno::EFAULT);
+    }
+    // SAFETY: The range lies within RAM and M-mode may write it.
+    Ok(unsafe { core::slice::from_raw_parts_mut(ptr as *mut u8, len) })
+}
+
+/// Borrows a NUL-terminated UTF-8 string from caller memory.
+///
+/// # Arguments
+/// * `max` - Maximum length, including the terminating NUL.
+pub fn user_cstr<'a>(ptr: usize, max: usize) -> Result<&'a str, Errno> {
+    let mut len = 0;
+    loop {
+        if len == max {
+            return Err(Errno::ENAMETOOLONG);
+        }
+        if user_slice(ptr.wrapping_add(len), 1)?[0] == 0 {
+            break;
+        }
+        len += 1;
+    }
+    core::str::from_utf8(user_slice(ptr, len)?).map_err(|_| Errno::EINVAL)
+}
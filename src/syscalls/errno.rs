@@ -0,0 +1,58 @@
+//! ---------------------------------------------------------------------------
+//! File       : errno.rs
+//! Module     : syscalls::errno
+//! Author     : DiTurr
+//! Description:
+//! Error numbers returned by system calls (Linux values).
+//! ---------------------------------------------------------------------------
+
+use crate::net::NetError;
+
+/// System call error numbers.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(isize)]
+pub enum Errno {
+    ENOENT          = 2,
+    EIO             = 5,
+    EBADF           = 9,
+    EAGAIN          = 11,
+    EFAULT          = 14,
+    EBUSY           = 16,
+    EEXIST          = 17,
+    ENODEV          = 19,
+    EINVAL          = 22,
+    EMFILE          = 24,
+    ENOSPC          = 28,
+    EPIPE           = 32,
+    ENAMETOOLONG    = 36,
+    ENOSYS          = 38,
+    ENOTSOCK        = 88,
+    EMSGSIZE        = 90,
+    EPROTONOSUPPORT = 93,
+    EAFNOSUPPORT    = 97,
+    EADDRINUSE      = 98,
+    ENETUNREACH     = 101,
+    ECONNRESET      = 104,
+    ENOBUFS         = 105,
+    ENOTCONN        = 107,
+    ETIMEDOUT       = 110,
+    ECONNREFUSED    = 111,
+}
+
+impl From<NetError> for Errno {
+    fn from(error: NetError) -> Self {
+        match error {
+            NetError::NoDevice | NetError::NotConfigured => Errno::ENETUNREACH,
+            NetError::ArpPending | NetError::WouldBlock => Errno::EAGAIN,
+            NetError::TxFailed | NetError::NoSockets => Errno::ENOBUFS,
+            NetError::TooLarge => Errno::EMSGSIZE,
+            NetError::AddrInUse => Errno::EADDRINUSE,
+            NetError::Timeout => Errno::ETIMEDOUT,
+            NetError::InvalidState => Errno::EINVAL,
+            NetError::NotConnected => Errno::ENOTCONN,
+            NetError::ConnectionRefused => Errno::ECONNREFUSED,
+            NetError::ConnectionReset => Errno::ECONNRESET,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! ---------------------------------------------------------------------------
+//! File       : fd.rs
+//! Module     : syscalls::fd
+//! Author     : DiTurr
+//! Description:
+//! The file descriptor table. There are no processes yet, so a single global
+//! table is shared by every caller. A descriptor refers either to a socket or to
+//! a device opened through `/dev`. Descriptors 0-2 are the standard streams,
+//! bound to the console at boot.
+//! ---------------------------------------------------------------------------
+
+use core::mem;
+
+use super::errno::Errno;
+use super::SyscallResult;
+use crate::fs::CharDevice;
+use crate::net::socket::Socket;
+use crate::sync::spinlock::SpinLock;
+
+/// Maximum number of open descriptors.
+const MAX_FILES: usize = 16;
+
+/// First descriptor handed out (after stdin, stdout and stderr).
+const FIRST_FD: usize = 3;
+
+/// An object referred to by a file descriptor.
+pub enum File {
+    Socket(Socket),
+    Device(&'static dyn CharDevice),
+}
+
+/// A descriptor table entry.
+enum Slot {
+    Free,
+    Open(File),
+    /// Moved out by [`with`] while it runs.
+    InUse,
+}
+
+static FILES: SpinLock<[Slot; MAX_FILES]> = SpinLock::new([const { Slot::Free }; MAX_FILES]);
+
+/// Stores `file` in the lowest free descriptor.
+///
+/// # Returns
+/// The new descriptor.
+pub fn install(file: File) -> SyscallResult {
+    let mut files = FILES.lock();
+    let fd = (FIRST_FD..MAX_FILES).find(|&fd| matches!(files[fd], Slot::Free)).ok_or(Errno::EMFILE)?;
+    files[fd] = Slot::Open(file);
+    Ok(fd)
+}
+
+/// Binds the standard streams (descriptors 0-2) to `device` where not already open.
+pub fn open_std_streams(device: &'static dyn CharDevice) {
+    let mut files = FILES.lock();
+    for slot in files[..FIRST_FD].iter_mut().filter(|slot| matches!(slot, Slot::Free)) {
+        *slot = Slot::Open(File::Device(device));
+    }
+}
+
+/// Runs `f` on the file behind `fd`.
+///
+/// The file is moved out of the table while `f` runs, so that a blocking
+/// socket call does not hold the table lock while it waits: other threads
+/// may open and close descriptors meanwhile, but see `fd` as busy.
+pub fn with<R>(fd: usize, f: impl FnOnce(&mut File) -> Result<R, Errno>) -> Result<R, Errno> {
+    let mut file = {
+        let mut files = FILES.lock();
+        let slot = files.get_mut(fd).ok_or(Errno::EBADF)?;
+        match mem::replace(slot, Slot::InUse) {
+            Slot::Open(file) => file,
+            Slot::Free => {
+                *slot = Slot::Free;
+                return Err(Errno::EBADF);
+            }
+            // In use by a blocking call on another thread.
+            Slot::InUse => return Err(Errno::EBUSY),
+        }
+    };
+    let result = f(&mut file);
+    FILES.lock()[fd] = Slot::Open(file);
+    result
+}
+
+/// Runs `f` on the socket behind `fd`.
+pub fn with_socket<R>(fd: usize, f: impl FnOnce(&mut Socket) -> Result<R, Errno>) -> Result<R, Errno> {
+    with(fd, |file| match file {
+        File::Socket(socket) => f(socket),
+        File::Device(_) => Err(Errno::ENOTSOCK),
+    })
+}
+
+/// `close(fd)`: releases a descriptor and the object behind it.
+pub fn close(fd: usize) -> SyscallResult {
+    let file = {
+        let mut files = FILES.lock();
+        let slot = files.get_mut(fd).ok_or(Errno::EBADF)?;
+        match mem::replace(slot, Slot::Free) {
+            Slot::Open(file) => file,
+            Slot::Free => return Err(Errno::EBADF),
+            // In use by a blocking call on another thread.
+            Slot::InUse => {
+                *slot = Slot::InUse;
+                return Err(Errno::EBUSY);
+            }
+        }
+    };
+    // Dropping the object (e.g. a TCP stream) may send packets: do it unlocked.
+    drop(file);
+    Ok(0)
+}
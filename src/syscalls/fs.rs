@@ -0,0 +1,42 @@
+//! ---------------------------------------------------------------------------
+//! File       : fs.rs
+//! Module     : syscalls::fs
+//! Author     : DiTurr
+//! Description:
+//! File system calls: `openat`, `read` and `write`. Only `/dev` and `/proc`
+//! paths can be opened; `read`/`write` on a socket behave like `recv`/`send`.
+//! ---------------------------------------------------------------------------
+
+use super::errno::Errno;
+use super::fd::{self, File};
+use super::{user_cstr, user_slice, user_slice_mut, SyscallResult};
+use crate::fs::{devfs, procfs};
+
+/// Longest path accepted by `openat`, including the terminating NUL.
+const PATH_MAX: usize = 64;
+
+/// `openat(dirfd, path, flags, mode)`: opens a device or a `/proc` file. Paths
+/// must be absolute, so `dirfd`, `flags` and `mode` are ignored.
+pub fn openat(_dirfd: usize, path: usize, _flags: usize, _mode: usize) -> SyscallResult {
+    let path = user_cstr(path, PATH_MAX)?;
+    let device = devfs::lookup(path).or_else(|| procfs::lookup(path)).ok_or(Errno::ENOENT)?;
+    fd::install(File::Device(device))
+}
+
+/// `read(fd, buf, count)`.
+pub fn read(fd: usize, buf: usize, count: usize) -> SyscallResult {
+    let data = user_slice_mut(buf, count)?;
+    fd::with(fd, |file| match file {
+        File::Socket(socket) => Ok(socket.recv_from(data)?.0),
+        File::Device(device) => device.read(data),
+    })
+}
+
+/// `write(fd, buf, count)`.
+pub fn write(fd: usize, buf: usize, count: usize) -> SyscallResult {
+    let data = user_slice(buf, count)?;
+    fd::with(fd, |file| match file {
+        File::Socket(socket) => Ok(socket.send_to(data, None)?),
+        File::Device(device) => device.write(data),
+    })
+}
@@ -0,0 +1,119 @@
+//! ---------------------------------------------------------------------------
+//! File       : net.rs
+//! Module     : syscalls::net
+//! Author     : DiTurr
+//! Description:
+//! Socket system calls: `socket`, `bind`, `listen`, `accept`, `connect`,
+//! `sendto` and `recvfrom` (`send`/`recv` are `sendto`/`recvfrom` without an
+//! address). Only IPv4 (`AF_INET`) stream and datagram sockets are supported.
+//! ---------------------------------------------------------------------------
+
+use core::net::Ipv4Addr;
+
+use super::errno::Errno;
+use super::fd::{self, File};
+use super::{user_slice, user_slice_mut, SyscallResult};
+use crate::net::socket::{Socket, SocketKind};
+
+/// Address family: IPv4.
+const AF_INET: usize = 2;
+/// Socket type: stream.
+const SOCK_STREAM: usize = 1;
+/// Socket type: datagram.
+const SOCK_DGRAM: usize = 2;
+/// Socket type flag: non-blocking operations.
+const SOCK_NONBLOCK: usize = 0o4000;
+/// Protocol numbers accepted by `socket` (0 selects the default).
+const IPPROTO_TCP: usize = 6;
+const IPPROTO_UDP: usize = 17;
+
+/// Size of `struct sockaddr_in`.
+const SOCKADDR_IN_LEN: usize = 16;
+
+/// Decodes a `struct sockaddr_in` from caller memory.
+fn read_sockaddr(ptr: usize, len: usize) -> Result<(Ipv4Addr, u16), Errno> {
+    if len < SOCKADDR_IN_LEN {
+        return Err(Errno::EINVAL);
+    }
+    let raw = user_slice(ptr, SOCKADDR_IN_LEN)?;
+    if u16::from_ne_bytes([raw[0], raw[1]]) as usize != AF_INET {
+        return Err(Errno::EAFNOSUPPORT);
+    }
+    let port = u16::from_be_bytes([raw[2], raw[3]]);
+    let addr = Ipv4Addr::new(raw[4], raw[5], raw[6], raw[7]);
+    Ok((addr, port))
+}
+
+/// Encodes `addr:port` as a `struct sockaddr_in` into caller memory.
+///
+/// `len_ptr` points to a `u32` holding the buffer size; it is updated with the
+/// address size. Null pointers are ignored.
+fn write_sockaddr(ptr: usize, len_ptr: usize, (addr, port): (Ipv4Addr, u16)) -> Result<(), Errno> {
+    if ptr == 0 || len_ptr == 0 {
+        return Ok(());
+    }
+    let len_bytes = user_slice_mut(len_ptr, 4)?;
+    let capacity = u32::from_ne_bytes(len_bytes[..4].try_into().unwrap()) as usize;
+    let mut raw = [0u8; SOCKADDR_IN_LEN];
+    raw[0..2].copy_from_slice(&(AF_INET as u16).to_ne_bytes());
+    raw[2..4].copy_from_slice(&port.to_be_bytes());
+    raw[4..8].copy_from_slice(&addr.octets());
+    let count = capacity.min(SOCKADDR_IN_LEN);
+    user_slice_mut(ptr, count)?.copy_from_slice(&raw[..count]);
+    len_bytes.copy_from_slice(&(SOCKADDR_IN_LEN as u32).to_ne_bytes());
+    Ok(())
+}
+
+/// `socket(domain, type, protocol)`: creates an unbound socket.
+pub fn socket(domain: usize, kind: usize, protocol: usize) -> SyscallResult {
+    if domain != AF_INET {
+        return Err(Errno::EAFNOSUPPORT);
+    }
+    let nonblocking = kind & SOCK_NONBLOCK != 0;
+    let kind = match (kind & !SOCK_NONBLOCK, protocol) {
+        (SOCK_STREAM, 0 | IPPROTO_TCP) => SocketKind::Stream,
+        (SOCK_DGRAM, 0 | IPPROTO_UDP) => SocketKind::Datagram,
+        _ => return Err(Errno::EPROTONOSUPPORT),
+    };
+    fd::install(File::Socket(Socket::new(kind, nonblocking)))
+}
+
+/// `bind(fd, addr, addrlen)`: assigns the local port (the address is ignored,
+/// the kernel has a single interface).
+pub fn bind(fd: usize, addr: usize, addrlen: usize) -> SyscallResult {
+    let (_, port) = read_sockaddr(addr, addrlen)?;
+    fd::with_socket(fd, |socket| Ok(socket.bind(port).map(|_| 0)?))
+}
+
+/// `listen(fd, backlog)`: starts accepting TCP connections.
+pub fn listen(fd: usize, backlog: usize) -> SyscallResult {
+    fd::with_socket(fd, |socket| Ok(socket.listen(backlog).map(|_| 0)?))
+}
+
+/// `accept(fd, addr, addrlen)`: waits for a connection and returns its descriptor.
+pub fn accept(fd: usize, addr: usize, addrlen: usize) -> SyscallResult {
+    let (connection, peer) = fd::with_socket(fd, |socket| Ok(socket.accept()?))?;
+    write_sockaddr(addr, addrlen, peer)?;
+    fd::install(File::Socket(connection))
+}
+
+/// `connect(fd, addr, addrlen)`: connects a stream or sets a datagram peer.
+pub fn connect(fd: usize, addr: usize, addrlen: usize) -> SyscallResult {
+    let (addr, port) = read_sockaddr(addr, addrlen)?;
+    fd::with_socket(fd, |socket| Ok(socket.connect(addr, port).map(|_| 0)?))
+}
+
+/// `sendto(fd, buf, len, flags, dest_addr, addrlen)`.
+pub fn sendto(fd: usize, buf: usize, len: usize, _flags: usize, dest: usize, addrlen: usize) -> SyscallResult {
+    let data = user_slice(buf, len)?;
+    let dest = if dest == 0 { None } else { Some(read_sockaddr(dest, addrlen)?) };
+    fd::with_socket(fd, |socket| Ok(socket.send_to(data, dest)?))
+}
+
+/// `recvfrom(fd, buf, len, flags, src_addr, addrlen)`.
+pub fn recvfrom(fd: usize, buf: usize, len: usize, _flags: usize, src: usize, addrlen: usize) -> SyscallResult {
+    let data = user_slice_mut(buf, len)?;
+    let (count, peer) = fd::with_socket(fd, |socket| Ok(socket.recv_from(data)?))?;
+    write_sockaddr(src, addrlen, peer)?;
+    Ok(count)
+}
@@ -0,0 +1,37 @@
+//! ---------------------------------------------------------------------------
+//! File       : random.rs
+//! Module     : syscalls::random
+//! Author     : DiTurr
+//! Description:
+//! The `getrandom` system call.
+//! ---------------------------------------------------------------------------
+
+use super::errno::Errno;
+use super::{user_slice_mut, SyscallResult};
+use crate::random;
+
+/// Fail with `EAGAIN` instead of blocking while the CRNG is unseeded.
+const GRND_NONBLOCK: usize = 1;
+/// Historical "blocking pool" flag; the kernel has a single pool, so it only
+/// implies waiting for the seed.
+const GRND_RANDOM: usize = 2;
+/// Return output even if the CRNG is unseeded.
+const GRND_INSECURE: usize = 4;
+
+/// `getrandom(buf, buflen, flags)`: fills `buf` with random bytes.
+pub fn getrandom(buf: usize, len: usize, flags: usize) -> SyscallResult {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+        || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+    {
+        return Err(Errno::EINVAL);
+    }
+    let out = user_slice_mut(buf, len)?;
+    if flags & GRND_INSECURE == 0 && !random::is_seeded() {
+        if flags & GRND_NONBLOCK != 0 {
+            return Err(Errno::EAGAIN);
+        }
+        random::wait_for_seed();
+    }
+    random::fill_bytes(out);
+    Ok(len)
+}
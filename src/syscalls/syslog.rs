@@ -0,0 +1,76 @@
+//! ---------------------------------------------------------------------------
+//! File       : syslog.rs
+//! Module     : syscalls::syslog
+//! Author     : DiTurr
+//! Description:
+//! The `syslog` system call (`klogctl` in glibc), reading the kernel message
+//! buffer. Records are returned as `<priority>[secs.micros] text\n` lines.
+//! ---------------------------------------------------------------------------
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::errno::Errno;
+use super::{user_slice_mut, SyscallResult};
+use crate::logger::filter;
+use crate::logger::kmsg::{self, Format};
+use crate::logger::level::Level;
+
+// Actions.
+const SYSLOG_ACTION_CLOSE: usize         = 0;
+const SYSLOG_ACTION_OPEN: usize          = 1;
+const SYSLOG_ACTION_READ: usize          = 2;
+const SYSLOG_ACTION_READ_ALL: usize      = 3;
+const SYSLOG_ACTION_READ_CLEAR: usize     = 4;
+const SYSLOG_ACTION_CLEAR: usize         = 5;
+const SYSLOG_ACTION_CONSOLE_LEVEL: usize = 8;
+const SYSLOG_ACTION_SIZE_UNREAD: usize   = 9;
+const SYSLOG_ACTION_SIZE_BUFFER: usize   = 10;
+
+/// Sequence number of the next record returned by `SYSLOG_ACTION_READ`.
+static READ_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Sequence number of the first record returned by `SYSLOG_ACTION_READ_ALL`,
+/// moved by `SYSLOG_ACTION_CLEAR`.
+static CLEAR_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// `syslog(action, buf, len)`:
+/// - `READ` consumes the unread records (without waiting for new ones);
+/// - `READ_ALL` returns the most recent records since the last clear that fit
+///   in `buf`; `READ_CLEAR` also clears them, `CLEAR` only clears;
+/// - `CONSOLE_LEVEL` sets the global log threshold: records with a priority
+///   below `len` (1 to 8) are logged;
+/// - `SIZE_UNREAD` and `SIZE_BUFFER` return the size of the unread records and
+///   of the buffer.
+pub fn syslog(action: usize, buf: usize, len: usize) -> SyscallResult {
+    match action {
+        SYSLOG_ACTION_CLOSE | SYSLOG_ACTION_OPEN => Ok(0),
+        SYSLOG_ACTION_READ => {
+            let out = user_slice_mut(buf, len)?;
+            let mut seq = READ_SEQ.load(Ordering::Relaxed);
+            let count = kmsg::read_lines(&mut seq, out, Format::Syslog);
+            READ_SEQ.store(seq, Ordering::Relaxed);
+            Ok(count)
+        }
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+            let out = user_slice_mut(buf, len)?;
+            let count = kmsg::read_tail(CLEAR_SEQ.load(Ordering::Relaxed), out, Format::Syslog);
+            if action == SYSLOG_ACTION_READ_CLEAR {
+                CLEAR_SEQ.store(kmsg::next_seq(), Ordering::Relaxed);
+            }
+            Ok(count)
+        }
+        SYSLOG_ACTION_CLEAR => {
+            CLEAR_SEQ.store(kmsg::next_seq(), Ordering::Relaxed);
+            Ok(0)
+        }
+        SYSLOG_ACTION_CONSOLE_LEVEL => {
+            let priority = len.checked_sub(1).filter(|&priority| priority < 8).ok_or(Errno::EINVAL)?;
+            let level = Level::from_syslog_priority(priority as u8).ok_or(Errno::EINVAL)?;
+            filter::set_max_level(level);
+            Ok(0)
+        }
+        SYSLOG_ACTION_SIZE_UNREAD => Ok(kmsg::pending_len(READ_SEQ.load(Ordering::Relaxed), Format::Syslog)),
+        SYSLOG_ACTION_SIZE_BUFFER => Ok(kmsg::SLOTS * kmsg::TEXT_LEN),
+        _ => Err(Errno::EINVAL),
+    }
+}
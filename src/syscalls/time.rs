@@ -0,0 +1,35 @@
+//! ---------------------------------------------------------------------------
+//! File       : time.rs
+//! Module     : syscalls::time
+//! Author     : DiTurr
+//! Description:
+//! The `clock_gettime` system call.
+//! ---------------------------------------------------------------------------
+
+use super::errno::Errno;
+use super::{user_slice_mut, SyscallResult};
+use crate::time;
+use crate::time::system::SystemTime;
+
+// Clock identifiers.
+const CLOCK_REALTIME: usize  = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const CLOCK_BOOTTIME: usize  = 7;
+
+/// Size of `struct timespec` (`tv_sec` and `tv_nsec`, 64 bits each).
+const TIMESPEC_LEN: usize = 16;
+
+/// `clock_gettime(clock, tp)`: stores the time of `clock` in `*tp`.
+/// `CLOCK_REALTIME` is the wall-clock time; `CLOCK_MONOTONIC` and
+/// `CLOCK_BOOTTIME` both count from boot.
+pub fn clock_gettime(clock: usize, tp: usize) -> SyscallResult {
+    let now = match clock {
+        CLOCK_REALTIME => SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default(),
+        CLOCK_MONOTONIC | CLOCK_BOOTTIME => time::uptime(),
+        _ => return Err(Errno::EINVAL),
+    };
+    let out = user_slice_mut(tp, TIMESPEC_LEN)?;
+    out[..8].copy_from_slice(&now.as_secs().to_le_bytes());
+    out[8..].copy_from_slice(&(now.subsec_nanos() as u64).to_le_bytes());
+    Ok(0)
+}
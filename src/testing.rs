@@ -0,0 +1,156 @@
+//! ---------------------------------------------------------------------------
+//! File       : testing.rs
+//! Module     : testing
+//! Author     : DiTurr
+//! Description:
+//! Test framework (`custom_test_frameworks`). The `#[test_case]` functions run
+//! in one of two ways:
+//! - `make test` builds a test kernel in which `kmain` runs them once the
+//!   kernel is initialized, then boots it in QEMU: the exit status of QEMU is
+//!   the result of the run (0 if every test passed). A failed assertion (or
+//!   any panic, including a fatal trap) fails the running test: the kernel
+//!   cannot unwind, so the panic report follows and the remaining tests are
+//!   not run;
+//! - `make test-host` builds the crate for the host, where only the pure logic
+//!   is tested (CSRs and the UART are mocks, see `registers::mock` and
+//!   `peripherals::uart::mock`). A panic fails its test and the run goes on.
+//!
+//! Results are written straight to the UART, like the panic report. With
+//! `testlog=<path>` on the command line of a test kernel run with semihosting
+//! (`make test SEMIHOSTING=1`), they are also written to the host file `path`.
+//!
+//! ## Example
+//! ```rust
+//! #[test_case]
+//! fn unknown_syscall_fails() {
+//!     assert_eq!(syscall(usize::MAX, 0, 0), -(Errno::ENOSYS as isize));
+//! }
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::semihosting::{self, File, Mode};
+use crate::cmdline;
+use crate::peripherals::uart::UART;
+use crate::sync::spinlock::SpinLock;
+
+/// A test: a function, reported under its path.
+pub trait Testable {
+    /// Runs the test.
+    ///
+    /// # Returns
+    /// `true` if it passed.
+    fn run(&self) -> bool;
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) -> bool {
+        let name = core::any::type_name::<T>();
+        *CURRENT.lock() = Some(name);
+        report(format_args!("test {} ... ", name));
+        #[cfg(target_os = "none")]
+        self();
+        #[cfg(not(target_os = "none"))]
+        if std::panic::catch_unwind(std::panic::AssertUnwindSafe(self)).is_err() {
+            report(format_args!("FAILED\n"));
+            return false;
+        }
+        *CURRENT.lock() = None;
+        PASSED.fetch_add(1, Ordering::Relaxed);
+        report(format_args!("ok\n"));
+        true
+    }
+}
+
+/// The test being run.
+static CURRENT: SpinLock<Option<&'static str>> = SpinLock::new(None);
+
+/// Number of tests run and passed.
+static PASSED: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of registered tests.
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+
+/// The host file named by `testlog=`.
+static LOG: SpinLock<Option<File>> = SpinLock::new(None);
+
+/// Writes results to the UART and to the log file.
+struct Report;
+
+impl Write for Report {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        UART.puts(s);
+        // The panic may have interrupted a write.
+        if let Some(Some(file)) = LOG.try_lock().as_deref() {
+            let _ = file.write(s.as_bytes());
+        }
+        Ok(())
+    }
+}
+
+fn report(args: fmt::Arguments) {
+    let _ = Report.write_fmt(args);
+}
+
+/// Runs the tests, then exits: the test kernel powers the machine off, the
+/// host process exits with status 1 if a test failed. Called by the generated
+/// test harness.
+pub fn runner(tests: &[&dyn Testable]) {
+    TOTAL.store(tests.len(), Ordering::Relaxed);
+    if let Some(path) = cmdline::get("testlog").filter(|_| semihosting::available()) {
+        *LOG.lock() = File::open(path, Mode::Write).ok();
+    }
+    report(format_args!("running {} tests\n", tests.len()));
+    let failed = tests.iter().filter(|test| !test.run()).count();
+    let passed = tests.len() - failed;
+    if failed == 0 {
+        report(format_args!("test result: ok. {} passed; 0 failed\n", passed));
+    } else {
+        report(format_args!("test result: FAILED. {} passed; {} failed\n", passed, failed));
+    }
+    // Flush and close the log before exiting.
+    LOG.lock().take();
+    #[cfg(target_os = "none")]
+    crate::power::exit_with_code(0);
+    #[cfg(not(target_os = "none"))]
+    std::process::exit((failed > 0) as i32);
+}
+
+/// Reports the running test, if any, as failed. Called by the panic handler of
+/// the test kernel, which then stops the machine (with exit status 1 by default).
+#[cfg(target_os = "none")]
+pub fn fail() {
+    // The panic may have interrupted the runner holding the lock.
+    let Some(Some(name)) = CURRENT.try_lock().map(|current| *current) else {
+        return;
+    };
+    let passed = PASSED.load(Ordering::Relaxed);
+    let not_run = TOTAL.load(Ordering::Relaxed) - passed - 1;
+    report(format_args!("FAILED\ntest {} failed\n", name));
+    report(format_args!("test result: FAILED. {} passed; 1 failed; {} not run\n", passed, not_run));
+}
+
+/// A xorshift generator for property tests: reproducible, not random.
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        XorShift(seed | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Fills `buf` with pseudo-random bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for byte in buf {
+            *byte = self.next_u64() as u8;
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! ---------------------------------------------------------------------------
+//! File       : thread.rs
+//! Module     : thread
+//! Author     : DiTurr
+//! Description:
+//! Cooperative kernel threads, on the boot hart. The boot code runs as the
+//! `main` thread (on the hart's kernel stack); [`spawn`] starts others on the
+//! thread stacks of `stack::allocate`. A thread runs until it calls
+//! [`yield_now`] (the main loop does, once per round) or [`park`]s itself;
+//! the next runnable thread then runs, in turn. [`unpark`] makes a parked
+//! thread runnable again, from any context (interrupt handlers included).
+//!
+//! Threads are never preempted, so a thread switch happens only where its
+//! code yields: a thread must not yield while holding a lock. The switch
+//! itself (`asm/switch.S`) saves and restores the callee-saved registers,
+//! with interrupts disabled; it also tells the trap vector which stack is
+//! current, for the stack overflow check.
+//!
+//! ## Example
+//! ```rust
+//! fn worker() -> ! {
+//!     loop {
+//!         // ...
+//!         thread::park();
+//!     }
+//! }
+//!
+//! let worker = thread::spawn("worker", worker)?;
+//! thread::unpark(worker);
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::cell::UnsafeCell;
+use core::ptr::addr_of;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::log_error;
+use crate::stack::{self, Stack, MAX_THREAD_STACKS};
+use crate::sync::spinlock::SpinLock;
+use crate::syscalls::errno::Errno;
+use crate::traps::interrupts;
+
+/// Maximum number of threads: `main` and one per thread stack.
+pub const MAX_THREADS: usize = 1 + MAX_THREAD_STACKS;
+
+/// Identifies a thread: its index in the thread table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadId(pub usize);
+
+/// Registers preserved across a call, saved by `switch_context`. The layout
+/// must match `asm/switch.S`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Context {
+    ra: usize,
+    sp: usize,
+    s: [usize; 12],
+}
+
+impl Context {
+    const fn new() -> Context {
+        Context { ra: 0, sp: 0, s: [0; 12] }
+    }
+}
+
+#[cfg(target_os = "none")]
+unsafe extern "C" {
+    fn switch_context(from: *mut Context, to: *const Context);
+}
+
+/// Host builds (unit tests) have a single thread and never switch.
+#[cfg(not(target_os = "none"))]
+unsafe fn switch_context(_: *mut Context, _: *const Context) {
+    unreachable!("no thread switch on the host");
+}
+
+/// The saved contexts, per thread.
+struct Contexts(UnsafeCell<[Context; MAX_THREADS]>);
+
+// SAFETY: A context is only accessed while switching threads, by the boot
+// hart with interrupts disabled.
+unsafe impl Sync for Contexts {}
+
+static CONTEXTS: Contexts = Contexts(UnsafeCell::new([const { Context::new() }; MAX_THREADS]));
+
+/// A thread.
+#[derive(Clone, Copy)]
+struct Thread {
+    name: &'static str,
+    /// What it runs; `None` for `main`.
+    entry: Option<fn() -> !>,
+    stack: Stack,
+}
+
+static THREADS: SpinLock<[Option<Thread>; MAX_THREADS]> = SpinLock::new([None; MAX_THREADS]);
+
+/// Index of the running thread.
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+/// Threads waiting in [`park`].
+static PARKED: [AtomicBool; MAX_THREADS] = [const { AtomicBool::new(false) }; MAX_THREADS];
+
+/// Wake-ups not consumed by [`park`] yet.
+static TOKENS: [AtomicBool; MAX_THREADS] = [const { AtomicBool::new(false) }; MAX_THREADS];
+
+/// Times each thread was switched to.
+static SWITCHES: [AtomicUsize; MAX_THREADS] = [const { AtomicUsize::new(0) }; MAX_THREADS];
+
+/// State of a thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    Runnable,
+    Parked,
+}
+
+impl State {
+    /// Returns the name of the state, e.g. `parked`.
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Running => "running",
+            State::Runnable => "runnable",
+            State::Parked => "parked",
+        }
+    }
+}
+
+/// A thread, as listed by [`threads`].
+#[derive(Debug, Clone, Copy)]
+pub struct Info {
+    pub name: &'static str,
+    /// Name of its stack.
+    pub stack: &'static str,
+    pub state: State,
+    /// Times it was switched to.
+    pub switches: usize,
+}
+
+/// Makes the running code the `main` thread. Runs on the boot hart's kernel
+/// stack, after `stack::init`.
+pub fn init() {
+    let here = 0u8;
+    let Some(stack) = stack::find(addr_of!(here) as usize) else {
+        log_error!("thread: not running on a known stack, no threads.");
+        return;
+    };
+    THREADS.lock()[0] = Some(Thread { name: "main", entry: None, stack });
+}
+
+/// Starts a thread running `entry`, on a new thread stack.
+///
+/// # Returns
+/// `ENOSPC` if there is no thread stack left, `ENODEV` before [`init`].
+pub fn spawn(name: &'static str, entry: fn() -> !) -> Result<ThreadId, Errno> {
+    if THREADS.lock()[0].is_none() {
+        return Err(Errno::ENODEV);
+    }
+    let stack = stack::allocate(name)?;
+    let mut threads = THREADS.lock();
+    let index = threads.iter().position(Option::is_none).ok_or(Errno::ENOSPC)?;
+    // SAFETY: The thread does not run yet, nothing else uses its context.
+    let context = Context { ra: thread_start as *const () as usize, sp: stack.top, s: [0; 12] };
+    unsafe { (*CONTEXTS.0.get())[index] = context };
+    threads[index] = Some(Thread { name, entry: Some(entry), stack });
+    Ok(ThreadId(index))
+}
+
+/// First code of a new thread, returned into by `switch_context`.
+extern "C" fn thread_start() -> ! {
+    // The switch disabled the interrupts, and it is not returning here.
+    interrupts::set_interrupts(true);
+    let entry = THREADS.lock()[CURRENT.load(Ordering::Relaxed)].and_then(|thread| thread.entry);
+    match entry {
+        Some(entry) => entry(),
+        None => unreachable!("thread started without an entry"),
+    }
+}
+
+/// Lets the next runnable thread, if any, run; returns when this one is
+/// switched to again.
+pub fn yield_now() {
+    let current = CURRENT.load(Ordering::Relaxed);
+    let threads = *THREADS.lock();
+    let next = (1..MAX_THREADS)
+        .map(|offset| (current + offset) % MAX_THREADS)
+        .find(|&index| threads[index].is_some() && !PARKED[index].load(Ordering::Acquire));
+    let (Some(next), Some(thread)) = (next, next.and_then(|next| threads[next])) else {
+        return;
+    };
+    interrupts::without_interrupts(|| {
+        CURRENT.store(next, Ordering::Relaxed);
+        SWITCHES[next].fetch_add(1, Ordering::Relaxed);
+        stack::set_current(&thread.stack);
+        let contexts = CONTEXTS.0.get() as *mut Context;
+        // SAFETY: Interrupts are disabled and only this hart switches threads;
+        // `next` is a started thread or a new one set up by `spawn`.
+        unsafe { switch_context(contexts.add(current), contexts.add(next)) };
+    });
+}
+
+/// Waits until another thread or an interrupt handler calls [`unpark`] (at
+/// once if it already did since the last `park`). The other threads run
+/// meanwhile; `main` must not park, it is the one left to run.
+pub fn park() {
+    let current = CURRENT.load(Ordering::Relaxed);
+    PARKED[current].store(true, Ordering::Release);
+    if TOKENS[current].swap(false, Ordering::Acquire) {
+        PARKED[current].store(false, Ordering::Release);
+        return;
+    }
+    // With no other thread runnable, this spins until an interrupt unparks it.
+    while PARKED[current].load(Ordering::Acquire) {
+        yield_now();
+        core::hint::spin_loop();
+    }
+    TOKENS[current].store(false, Ordering::Relaxed);
+}
+
+/// Wakes `thread` up if it is parked, or makes its next [`park`] return.
+pub fn unpark(thread: ThreadId) {
+    TOKENS[thread.0].store(true, Ordering::Release);
+    PARKED[thread.0].store(false, Ordering::Release);
+}
+
+/// Returns the threads.
+pub fn threads() -> impl Iterator<Item = Info> {
+    let threads = *THREADS.lock();
+    let current = CURRENT.load(Ordering::Relaxed);
+    threads.into_iter().enumerate().filter_map(move |(index, thread)| {
+        let thread = thread?;
+        let state = match (index == current, PARKED[index].load(Ordering::Relaxed)) {
+            (true, _) => State::Running,
+            (false, true) => State::Parked,
+            (false, false) => State::Runnable,
+        };
+        let switches = SWITCHES[index].load(Ordering::Relaxed);
+        Some(Info { name: thread.name, stack: thread.stack.name, state, switches })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn park_consumes_an_earlier_unpark() {
+        // The running thread (main) is not switched away from: the wake-up
+        // token makes `park` return at once.
+        let current = CURRENT.load(Ordering::Relaxed);
+        unpark(ThreadId(current));
+        park();
+        assert!(!PARKED[current].load(Ordering::Relaxed));
+        assert!(!TOKENS[current].load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,76 @@
+//! ---------------------------------------------------------------------------
+//! File       : time.rs
+//! Module     : time
+//! Author     : DiTurr
+//! Description:
+//! Time keeping. The monotonic clock is the `TIME` CSR, which counts ticks of the
+//! platform timebase since reset; its frequency is read from the device tree
+//! (`timebase-frequency` of `/cpus`, 10 MHz on QEMU `virt`). Wall-clock time
+//! comes from the Goldfish RTC, read once at boot and advanced with the
+//! monotonic clock afterwards.
+//!
+//! - [`Instant`]: a point on the monotonic clock, for timeouts and intervals.
+//! - [`SystemTime`]: wall-clock time, for log lines and timestamps.
+//! - `core::time::Duration` measures the difference between two of them.
+//!
+//! ## Example
+//! ```rust
+//! let start = Instant::now();
+//! while start.elapsed() < Duration::from_millis(100) {}
+//! log_info!("{} s since boot, it is {}", time::uptime().as_secs(), SystemTime::now());
+//! ```
+//! ---------------------------------------------------------------------------
+pub mod instant;
+pub mod system;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use instant::Instant;
+use system::SystemTime;
+
+use crate::fdt;
+use crate::peripherals::rtc::RTC;
+use crate::{log_info, log_warn};
+
+/// Timebase frequency assumed until [`init`] reads it from the device tree.
+const DEFAULT_TIMEBASE_HZ: u64 = 10_000_000;
+
+/// Frequency of the `TIME` counter, in Hz.
+static TIMEBASE_HZ: AtomicU64 = AtomicU64::new(DEFAULT_TIMEBASE_HZ);
+
+/// Returns the frequency of the `TIME` counter, in Hz.
+pub fn timebase_hz() -> u64 {
+    TIMEBASE_HZ.load(Ordering::Relaxed)
+}
+
+/// Returns the time elapsed since reset.
+pub fn uptime() -> Duration {
+    Instant::now().duration_since(Instant::BOOT)
+}
+
+/// Reads the timebase frequency from the device tree.
+fn fdt_timebase() -> Option<u64> {
+    let value = fdt::get()?.find_node("/cpus")?.property("timebase-frequency")?;
+    // A single cell on every known platform, but two are allowed.
+    match *value {
+        [a, b, c, d] => Some(u32::from_be_bytes([a, b, c, d]) as u64),
+        [a, b, c, d, e, f, g, h] => Some(u64::from_be_bytes([a, b, c, d, e, f, g, h])),
+        _ => None,
+    }
+}
+
+/// Sets the timebase frequency from the device tree and the wall clock from the
+/// RTC.
+pub fn init() {
+    match fdt_timebase().filter(|&hz| hz > 0) {
+        Some(hz) => TIMEBASE_HZ.store(hz, Ordering::Relaxed),
+        None => log_warn!("time: no timebase frequency, assuming {} Hz.", DEFAULT_TIMEBASE_HZ),
+    }
+    if RTC.probe() {
+        system::set(Duration::from_nanos(RTC.read_ns()));
+        log_info!("time: timebase {} Hz, wall clock {}.", timebase_hz(), SystemTime::now());
+    } else {
+        log_warn!("time: no RTC, wall clock counts from the epoch.");
+    }
+}
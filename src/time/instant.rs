@@ -0,0 +1,74 @@
+//! ---------------------------------------------------------------------------
+//! File       : instant.rs
+//! Module     : time::instant
+//! Author     : DiTurr
+//! Description:
+//! Monotonic instants, in ticks of the `TIME` counter. At 10 MHz the 64-bit
+//! counter wraps after more than 58000 years, so instants are simply compared.
+//! ---------------------------------------------------------------------------
+
+use core::ops::{Add, Sub};
+use core::time::Duration;
+
+use super::timebase_hz;
+use crate::registers::time::TIME;
+
+/// A point on the monotonic clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+/// Converts timebase ticks to a duration.
+fn ticks_to_duration(ticks: u64) -> Duration {
+    let hz = timebase_hz();
+    let nanos = (ticks % hz) as u128 * 1_000_000_000 / hz as u128;
+    Duration::new(ticks / hz, nanos as u32)
+}
+
+/// Converts a duration to timebase ticks, rounding up and saturating.
+fn duration_to_ticks(duration: Duration) -> u64 {
+    let ticks = (duration.as_nanos() * timebase_hz() as u128).div_ceil(1_000_000_000);
+    u64::try_from(ticks).unwrap_or(u64::MAX)
+}
+
+impl Instant {
+    /// The instant the timer was reset.
+    pub const BOOT: Instant = Instant(0);
+
+    /// Returns the instant the `TIME` counter reads `ticks` (e.g. a timer
+    /// deadline).
+    pub const fn from_ticks(ticks: u64) -> Instant {
+        Instant(ticks)
+    }
+
+    /// Returns the current instant.
+    pub fn now() -> Instant {
+        Instant(TIME::read() as u64)
+    }
+
+    /// Returns the time elapsed from `earlier` to `self`, or zero if `earlier`
+    /// is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        ticks_to_duration(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Returns the time elapsed since `self`.
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(self.0.saturating_add(duration_to_ticks(duration)))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
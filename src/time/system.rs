@@ -0,0 +1,120 @@
+//! ---------------------------------------------------------------------------
+//! File       : system.rs
+//! Module     : time::system
+//! Author     : DiTurr
+//! Description:
+//! Wall-clock time. The RTC is read once at boot: the wall-clock time of the
+//! timer reset is recorded, and the current time is that plus the uptime, so
+//! reading it costs no device access. Times are shown in UTC, e.g.
+//! `2026-10-18 14:03:27.512034`.
+//! ---------------------------------------------------------------------------
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use super::uptime;
+
+/// Wall-clock time of the timer reset, in nanoseconds since the Unix epoch
+/// (0 until the RTC was read).
+static BOOT_TIME_NS: AtomicU64 = AtomicU64::new(0);
+
+/// A wall-clock time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemTime(Duration);
+
+impl SystemTime {
+    /// 1970-01-01 00:00:00 UTC.
+    pub const UNIX_EPOCH: SystemTime = SystemTime(Duration::ZERO);
+
+    /// Returns the current time. Before the RTC is read, or without one, this
+    /// is the uptime counted from the epoch.
+    pub fn now() -> SystemTime {
+        SystemTime(Duration::from_nanos(BOOT_TIME_NS.load(Ordering::Relaxed)) + uptime())
+    }
+
+    /// Returns the time elapsed from `earlier` to `self`, or `None` if
+    /// `earlier` is later.
+    pub fn duration_since(&self, earlier: SystemTime) -> Option<Duration> {
+        self.0.checked_sub(earlier.0)
+    }
+}
+
+/// Records that the current wall-clock time is `now` (since the epoch).
+pub(super) fn set(now: Duration) {
+    let boot = now.saturating_sub(uptime());
+    BOOT_TIME_NS.store(boot.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Converts a number of days since the epoch to a `(year, month, day)` date of
+/// the proleptic Gregorian calendar (algorithm from Howard Hinnant's
+/// `civil_from_days`).
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Count from 0000-03-01, so that leap days end the (400-year) era.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
+
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let (year, month, day) = civil_from_days(secs / 86_400);
+        let time_of_day = secs % 86_400;
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+            year,
+            month,
+            day,
+            time_of_day / 3600,
+            time_of_day / 60 % 60,
+            time_of_day % 60,
+            self.0.subsec_micros()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn known_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+    }
+
+    #[test_case]
+    fn days_follow_each_other() {
+        let days_in_month = |year: u64, month: u64| match month {
+            2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        let mut previous = civil_from_days(0);
+        // Up to year 2517, across several leap centuries.
+        for days in 1..200_000 {
+            let (year, month, day) = previous;
+            let expected = if day < days_in_month(year, month) {
+                (year, month, day + 1)
+            } else if month < 12 {
+                (year, month + 1, 1)
+            } else {
+                (year + 1, 1, 1)
+            };
+            let date = civil_from_days(days);
+            assert_eq!(date, expected, "day {}", days);
+            previous = date;
+        }
+    }
+}
@@ -4,5 +4,10 @@
 //! Author     : DiTurr
 //! Description: Trap handlers and utilities.
 //! ---------------------------------------------------------------------------
+pub mod interrupts;
 pub mod machine_traps;
-pub mod traps;
\ No newline at end of file
+pub mod misaligned;
+pub mod stats;
+pub mod trap_frame;
+#[allow(clippy::module_inception)]
+pub mod traps;
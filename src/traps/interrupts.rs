@@ -0,0 +1,411 @@
+//! ---------------------------------------------------------------------------
+//! File       : interrupts.rs
+//! Module     : traps::interrupts
+//! Author     : DiTurr
+//! Description:
+//! Machine interrupts: software (IPIs, from the CLINT), timer (CLINT) and
+//! external (device lines, from the PLIC). Their handlers are entered in one
+//! of two ways:
+//! - direct mode (default): `mtvec` points to `asm_trap_vector`, which saves
+//!   the full trap frame, and `machine_trap` hands interrupts to [`dispatch`];
+//! - vectored mode (cargo feature `vectored-traps`): `mtvec` points to
+//!   `asm_trap_table` (see `asm/trap.S`). Exceptions still take
+//!   `asm_trap_vector`, but the machine interrupts jump to fast entries that
+//!   save only the caller-saved registers and call the handler.
+//!
+//! Both save `mepc` and `mstatus` before calling the handler, so interrupts can
+//! nest: a preemptible device handler (see `peripherals::plic::Handler`) runs
+//! with `mstatus.MIE` set again and the PLIC threshold raised to its priority,
+//! so that the timer, IPIs and lines of a higher priority preempt it. The
+//! nested trap saves `MIE` in `MPIE` and restores it on `mret`; the handler
+//! clears `MIE` before returning, and the outer `mret` restores the state of
+//! the interrupted context. Handlers are at most `irqnest=<depth>` deep (4 by
+//! default, 1 disables nesting): at the limit, preemptible handlers run with
+//! interrupts disabled.
+//!
+//! When the outermost handler returns, the softirqs its handlers raised run
+//! (see `deferred`), also with interrupts enabled.
+//!
+//! [`benchmark`] measures the interrupt latency, from raising an interrupt to
+//! entering its handler, to compare the two modes (the monitor's `irqbench`).
+//!
+//! ## Example
+//! ```rust
+//! let latency = interrupts::benchmark(Source::Software, 1000)?;
+//! log_info!("{} mode: {} cycles on average", interrupts::mode(), latency.mean);
+//! ```
+//! ---------------------------------------------------------------------------
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::cmdline;
+use crate::deferred::softirq;
+use crate::monitor::commands::parse_number;
+use crate::monitor::{self, Command, CommandError};
+use crate::peripherals::clint;
+use crate::peripherals::plic::{self, Handler};
+use crate::random;
+use crate::registers::{mcycle::MCYCLE, mhartid::MHARTID, mie::MIE, mstatus::MSTATUS, time::TIME};
+#[cfg(feature = "vectored-traps")]
+use crate::registers::mtvec::MTVEC;
+use crate::stack::MAX_HARTS;
+use crate::time::instant::Instant;
+use crate::traps::stats;
+use crate::traps::traps::Trap;
+use crate::{log_info, log_warn};
+
+// Bits of `mie`.
+const MSIE: usize = 1 << 3;
+const MTIE: usize = 1 << 7;
+const MEIE: usize = 1 << 11;
+
+/// Interrupt enable bit of `mstatus`.
+const MSTATUS_MIE: usize = 1 << 3;
+
+/// Default nesting limit: handlers running at once on a hart.
+const DEFAULT_NESTING: usize = 4;
+
+/// `mtvec` mode: interrupt `n` jumps to `BASE + 4 * n`.
+#[cfg(feature = "vectored-traps")]
+const MODE_VECTORED: usize = 1;
+
+/// Iterations waiting for a benchmark interrupt before giving up.
+const MAX_SPINS: u32 = 1_000_000;
+
+/// Default number of `irqbench` iterations.
+const BENCH_ITERATIONS: usize = 1000;
+
+#[cfg(feature = "vectored-traps")]
+unsafe extern "C" {
+    fn asm_trap_table();
+}
+
+/// `mcycle` when the last software or timer interrupt handler was entered.
+static ENTERED: AtomicU64 = AtomicU64::new(0);
+
+/// Handlers that may run at once on a hart (`irqnest=<depth>`).
+static NESTING_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_NESTING);
+
+/// Interrupt nesting of a hart.
+struct HartNesting {
+    /// Handlers running.
+    depth: AtomicUsize,
+    /// Deepest nesting seen.
+    max_depth: AtomicUsize,
+    /// Interrupts taken while a handler ran.
+    preemptions: AtomicUsize,
+    /// Preemptible handlers run with interrupts disabled, at the limit.
+    capped: AtomicUsize,
+}
+
+static NESTING: [HartNesting; MAX_HARTS] = [const {
+    HartNesting {
+        depth: AtomicUsize::new(0),
+        max_depth: AtomicUsize::new(0),
+        preemptions: AtomicUsize::new(0),
+        capped: AtomicUsize::new(0),
+    }
+}; MAX_HARTS];
+
+/// Interrupt nesting statistics of a hart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nesting {
+    pub depth: usize,
+    pub max_depth: usize,
+    pub preemptions: usize,
+    pub capped: usize,
+}
+
+/// Returns the interrupt nesting statistics of `hart`.
+pub fn nesting(hart: usize) -> Option<Nesting> {
+    let state = NESTING.get(hart)?;
+    Some(Nesting {
+        depth: state.depth.load(Ordering::Relaxed),
+        max_depth: state.max_depth.load(Ordering::Relaxed),
+        preemptions: state.preemptions.load(Ordering::Relaxed),
+        capped: state.capped.load(Ordering::Relaxed),
+    })
+}
+
+/// Returns the nesting limit.
+pub fn nesting_limit() -> usize {
+    NESTING_LIMIT.load(Ordering::Relaxed)
+}
+
+/// Counts a handler entered on this hart.
+///
+/// # Returns
+/// The nesting state of the hart and the new depth.
+fn nest() -> (&'static HartNesting, usize) {
+    let state = &NESTING[MHARTID::read()];
+    let depth = state.depth.fetch_add(1, Ordering::Relaxed) + 1;
+    if depth > 1 {
+        state.preemptions.fetch_add(1, Ordering::Relaxed);
+    }
+    state.max_depth.fetch_max(depth, Ordering::Relaxed);
+    (state, depth)
+}
+
+/// Counts a handler returning on this hart. The outermost one then runs the
+/// pending softirqs, with interrupts enabled (`mepc` and `mstatus` are saved).
+fn unnest(state: &HartNesting) {
+    if state.depth.fetch_sub(1, Ordering::Relaxed) == 1 && softirq::pending() {
+        set_interrupts(true);
+        softirq::run();
+        set_interrupts(false);
+    }
+}
+
+/// Sets (`true`) or clears `mstatus.MIE`, which enables the interrupts of
+/// this hart.
+///
+/// # Returns
+/// Whether it was set.
+pub fn set_interrupts(enabled: bool) -> bool {
+    let previous = if enabled { MSTATUS::set(MSTATUS_MIE) } else { MSTATUS::clear(MSTATUS_MIE) };
+    previous & MSTATUS_MIE != 0
+}
+
+/// Runs `f` with the interrupts of this hart disabled, e.g. to take a lock
+/// also taken by an interrupt handler.
+pub fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    let enabled = set_interrupts(false);
+    let result = f();
+    if enabled {
+        set_interrupts(true);
+    }
+    result
+}
+
+/// Installs the vector table.
+#[cfg(feature = "vectored-traps")]
+fn install_table() {
+    MTVEC::write(asm_trap_table as *const () as usize | MODE_VECTORED);
+    // The mode field is WARL: a hart without vectored mode keeps `direct`.
+    if MTVEC::read() & 0b11 != MODE_VECTORED {
+        log_warn!("traps: vectored mode not supported, mtvec is {:#x}.", MTVEC::read());
+    }
+}
+
+/// Returns the trap mode, `direct` or `vectored`.
+pub fn mode() -> &'static str {
+    if cfg!(feature = "vectored-traps") { "vectored" } else { "direct" }
+}
+
+/// Quiesces the interrupt sources, installs the vector table (in vectored
+/// mode) and enables the machine interrupts of this hart.
+pub fn init() {
+    let hart = MHARTID::read();
+    clint::disarm_timer(hart);
+    clint::clear_ipi(hart);
+    #[cfg(feature = "vectored-traps")]
+    install_table();
+    MIE::set(MSIE | MTIE | MEIE);
+    if let Some(depth) = cmdline::get("irqnest") {
+        match depth.parse::<usize>() {
+            Ok(depth) if depth > 0 => NESTING_LIMIT.store(depth, Ordering::Relaxed),
+            _ => log_warn!("interrupts: invalid irqnest={}, keeping {}.", depth, DEFAULT_NESTING),
+        }
+    }
+    log_info!("interrupts: {} mode, nesting up to {}.", mode(), nesting_limit());
+    if let Err(err) = monitor::register(&IRQBENCH) {
+        log_warn!("interrupts: cannot register the monitor command: {:?}", err);
+    }
+}
+
+/// Serves a machine interrupt taken through the full trap path.
+///
+/// # Returns
+/// `false` if `mcause` is not a machine interrupt.
+pub fn dispatch(mcause: usize) -> bool {
+    match mcause {
+        cause if cause == Trap::MachineSoftInterrupt as usize => machine_software_interrupt(),
+        cause if cause == Trap::MachineTimerInterrupt as usize => machine_timer_interrupt(),
+        cause if cause == Trap::MachineExternalInterrupt as usize => machine_external_interrupt(),
+        _ => return false,
+    }
+    true
+}
+
+/// Handles the machine software interrupt: acknowledges the IPI.
+#[unsafe(no_mangle)]
+pub extern "C" fn machine_software_interrupt() {
+    let start = stats::start();
+    ENTERED.store(start, Ordering::Relaxed);
+    let (state, _) = nest();
+    clint::clear_ipi(MHARTID::read());
+    stats::record(Trap::MachineSoftInterrupt, start);
+    unnest(state);
+}
+
+/// Handles the machine timer interrupt: the timer is one-shot, disarm it.
+#[unsafe(no_mangle)]
+pub extern "C" fn machine_timer_interrupt() {
+    let start = stats::start();
+    ENTERED.store(start, Ordering::Relaxed);
+    let (state, _) = nest();
+    let hart = MHARTID::read();
+    let deadline = Instant::from_ticks(clint::timer_deadline(hart));
+    stats::record_latency(Trap::MachineTimerInterrupt, deadline.elapsed());
+    clint::disarm_timer(hart);
+    stats::record(Trap::MachineTimerInterrupt, start);
+    unnest(state);
+}
+
+/// Handles the machine external interrupt: claims the pending lines and runs
+/// their handlers.
+#[unsafe(no_mangle)]
+pub extern "C" fn machine_external_interrupt() {
+    let (start, taken) = (stats::start(), Instant::now());
+    let (state, depth) = nest();
+    // Device interrupt timing is a (weak) entropy source.
+    random::add_timer_jitter();
+    let context = plic::context(MHARTID::read());
+    let mut claimed = false;
+    while let Some(irq) = plic::claim(context) {
+        claimed = true;
+        // Lines are only enabled once they have a handler: one without is spurious.
+        match plic::handler(irq) {
+            Some(handler) => {
+                // Latency ends where the handler starts, before it (or a
+                // nested interrupt) runs.
+                let (latency, line_start) = (taken.elapsed(), stats::start());
+                serve(handler, context, irq, state, depth);
+                stats::record_line(irq, latency, line_start);
+            }
+            None => stats::record_spurious(),
+        }
+        plic::complete(context, irq);
+    }
+    if !claimed {
+        // Another context claimed the line first, or it was lowered.
+        stats::record_spurious();
+    }
+    stats::record(Trap::MachineExternalInterrupt, start);
+    unnest(state);
+}
+
+/// Runs `handler` for line `irq`, claimed by `context`, at nesting `depth`.
+fn serve(handler: &Handler, context: usize, irq: u32, state: &HartNesting, depth: usize) {
+    // A non-reentrant handler already running gets the line when it returns.
+    if !handler.enter(context, irq) {
+        return;
+    }
+    if !handler.is_preemptible() {
+        handler.run(irq);
+    } else if depth < nesting_limit() {
+        // Only lines of a higher priority interrupt the handler.
+        let threshold = plic::threshold(context);
+        plic::set_threshold(context, threshold.max(handler.priority));
+        set_interrupts(true);
+        handler.run(irq);
+        set_interrupts(false);
+        plic::set_threshold(context, threshold);
+    } else {
+        state.capped.fetch_add(1, Ordering::Relaxed);
+        handler.run(irq);
+    }
+    handler.leave(context);
+}
+
+/// Interrupt raised by [`benchmark`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// An IPI to this hart.
+    Software,
+    /// A timer deadline of now (its latency is accounted, so not in the past).
+    Timer,
+}
+
+/// Interrupt latency, in `mcycle` cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    pub min: u64,
+    pub mean: u64,
+    pub max: u64,
+}
+
+/// Measures the latency of `source`: the cycles from raising the interrupt
+/// to entering its handler, `iterations` times.
+///
+/// # Returns
+/// `None` if the interrupt is not taken (interrupts disabled).
+pub fn benchmark(source: Source, iterations: usize) -> Option<Latency> {
+    let hart = MHARTID::read();
+    let iterations = iterations.max(1);
+    let (mut min, mut max, mut total) = (u64::MAX, 0, 0);
+    for _ in 0..iterations {
+        ENTERED.store(0, Ordering::Relaxed);
+        let start = MCYCLE::read() as u64;
+        match source {
+            Source::Software => clint::send_ipi(hart),
+            Source::Timer => clint::set_timer(hart, TIME::read() as u64),
+        }
+        let mut spins = 0;
+        while ENTERED.load(Ordering::Relaxed) == 0 {
+            spins += 1;
+            if spins == MAX_SPINS {
+                clint::clear_ipi(hart);
+                clint::disarm_timer(hart);
+                return None;
+            }
+            core::hint::spin_loop();
+        }
+        let latency = ENTERED.load(Ordering::Relaxed).wrapping_sub(start);
+        min = min.min(latency);
+        max = max.max(latency);
+        total += latency;
+    }
+    Some(Latency { min, mean: total / iterations as u64, max })
+}
+
+static IRQBENCH: Command = Command {
+    name: "irqbench",
+    args: "[iterations]",
+    help: "measure the interrupt latency",
+    run: irqbench,
+};
+
+fn irqbench(args: &[&str], out: &mut dyn Write) -> Result<(), CommandError> {
+    let iterations = match args {
+        [] => BENCH_ITERATIONS,
+        [count] => parse_number(count)?,
+        _ => return Err(CommandError::Usage),
+    };
+    let _ = writeln!(out, "{} mode, {} iterations (mcycle cycles):", mode(), iterations);
+    for (name, source) in [("software", Source::Software), ("timer", Source::Timer)] {
+        let latency = benchmark(source, iterations).ok_or(CommandError::Failed("interrupts are disabled"))?;
+        let _ = writeln!(out, "{:<8} min {:>6}  mean {:>6}  max {:>6}", name, latency.min, latency.mean, latency.max);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn without_interrupts_restores_mie() {
+        let enabled = set_interrupts(true);
+        // Disabled inside, enabled again after.
+        assert!(!without_interrupts(|| set_interrupts(false)));
+        assert!(set_interrupts(false));
+        // Left disabled if they were.
+        without_interrupts(|| ());
+        assert!(!set_interrupts(enabled));
+    }
+
+    // Interrupts are taken through the trap vector: test kernel only.
+    #[cfg(target_os = "none")]
+    #[test_case]
+    fn interrupts_are_taken() {
+        let hart = MHARTID::read();
+        let ipis = stats::trap(hart, Trap::MachineSoftInterrupt).count;
+        for source in [Source::Software, Source::Timer] {
+            let latency = benchmark(source, 10).expect("interrupt not taken");
+            assert!(latency.min <= latency.mean && latency.mean <= latency.max);
+        }
+        assert_eq!(stats::trap(hart, Trap::MachineSoftInterrupt).count, ipis + 10);
+    }
+}
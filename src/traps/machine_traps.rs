@@ -7,126 +7,220 @@
 //! (exception or interrupt) occurs in RISC-V Machine mode.
 //! ---------------------------------------------------------------------------
 
-use crate::log_info;
+use core::fmt;
+
+use crate::arch::decode::{self, Instruction};
+use crate::arch::pmp::{self, Access};
+use crate::arch::semihosting;
+use crate::gdbstub;
+use crate::ksyms;
+use crate::log_debug;
+use crate::panic;
+use crate::random;
+use crate::registers::mhartid::MHARTID;
+use crate::stack;
+use crate::syscalls;
+use crate::traps::{interrupts, misaligned, stats};
+use crate::traps::trap_frame::TrapFrame;
 use crate::traps::traps::Trap;
 
+/// Size in bytes of the `ecall` instruction, skipped when returning from a system call.
+const ECALL_SIZE: usize = 4;
+
+/// Returns the instruction that raised the exception of `frame`, unless
+/// fetching it is what faulted (or `frame` is an interrupt).
+fn faulting_instruction(frame: &TrapFrame) -> Option<Instruction> {
+    match Trap::from_mcause(frame.mcause) {
+        Some(Trap::InstructionMisaligned | Trap::InstructionAccessFault | Trap::InstructionPageFault) | None => None,
+        Some(_) if (frame.mcause as isize) < 0 => None,
+        // SAFETY: `mepc` is the instruction that raised the exception.
+        Some(_) => Some(decode::decode(unsafe { decode::fetch(frame.mepc) })),
+    }
+}
+
+/// Where a trap was taken: `mepc`, then the instruction if known, e.g.
+/// `0x80000124 (csrw satp, t0)`.
+struct Location {
+    pc: usize,
+    insn: Option<Instruction>,
+}
+
+impl Location {
+    /// Locates the trap of `frame`, decoding the instruction once.
+    fn of(frame: &TrapFrame) -> Location {
+        Location { pc: frame.mepc, insn: faulting_instruction(frame) }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.pc)?;
+        match self.insn {
+            Some(insn) => write!(f, " ({})", insn),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Trap handler for exceptions and interrupts occurring in Machine mode.
 /// This function is called directly from the trap vector (typically via `mtvec`)
 /// when an exception or interrupt is taken while the CPU is in **Machine mode**.
 ///
 /// # Context
-/// - Runs in **Machine mode (M-mode)**
-/// - **Interrupts and MMU are disabled**
+/// - Runs in **Machine mode (M-mode)**, with the MMU disabled
+/// - Entered with **interrupts disabled** (the trap clears `mstatus.MIE`);
+///   exceptions are served that way, while interrupt handlers may enable
+///   them again to nest (see `interrupts`). An exception can therefore also
+///   be taken inside an interrupt handler.
+/// - Runs on the stack picked by the trap vector from the hart's
+///   `stack::TrapState` (in `mscratch`): the interrupted one, or the
+///   emergency trap stack when it overflowed
 /// - Full privileged access to hardware is available
 ///
 /// # Responsibilities
+/// - Serve system calls (`ecall`) and resume the caller after the instruction
+/// - Emulate misaligned loads and stores (see `misaligned`)
 /// - Print diagnostic information (register values at time of trap)
-/// - Provide a single point for debugging trap causes
-/// - Halt the system to avoid undefined behavior in the absence of recovery
+/// - Report traps that cannot be recovered from, with the interrupted context,
+///   through the panic handler
 ///
 /// # Future Extensions
-/// - Decode and handle specific trap causes (`mcause`)
 /// - Delegate to Supervisor mode (`sret`) if MMU and traps are initialized
-/// - Implement per-hart context management via `mscratch`
 ///
 /// # Parameters:
-/// - `mepc`: Machine Exception Program Counter (where the exception occurred)
-/// - `mtval`: Trap value (e.g., faulting address)
-/// - `mcause`: Trap cause (interrupt or exception ID)
-/// - `mhartid`: Current hardware thread ID
-/// - `mstatus`: Machine status register at time of trap
-/// - `mscratch`: Value of the scratch register (can be used for context switch)
+/// - `frame`: Registers of the interrupted context, saved by `asm_trap_vector`.
+///   Changes made to it (e.g. `a0` or `mepc`) are restored on return.
 ///
 /// # Safety:
 /// - Marked `unsafe` because it's called directly by the trap vector and must adhere
 ///   to the ABI and calling conventions of the hardware.
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn machine_trap(
-    mepc: usize,
-    mtval: usize,
-    mcause: usize,
-    mhartid: usize,
-    mstatus: usize,
-    mscratch: usize,
-) -> ! {
-    // Log full trap state for debugging purposes
-    log_info!(
+pub unsafe extern "C" fn machine_trap(frame: &mut TrapFrame) {
+    let mcause = frame.mcause;
+    // Machine interrupts are accounted by their handlers (also entered
+    // directly in vectored mode).
+    if interrupts::dispatch(mcause) {
+        return;
+    }
+    let start = stats::start();
+    handle_exception(frame);
+    // Fatal traps do not return here, and are not counted.
+    if let Some(trap) = Trap::from_mcause(mcause) {
+        stats::record(trap, start);
+    }
+}
+
+/// Serves the exception (or unexpected interrupt) described by `frame`.
+fn handle_exception(frame: &mut TrapFrame) {
+    let mcause = frame.mcause;
+    // Trap timing is a (weak) entropy source.
+    random::add_timer_jitter();
+    // System calls are regular control flow: serve them and return to the caller.
+    if mcause == Trap::UserEnvCall as usize
+        || mcause == Trap::SupervisorEnvCall as usize
+        || mcause == Trap::MachineEnvCall as usize
+    {
+        syscalls::dispatch(frame);
+        frame.mepc += ECALL_SIZE;
+        return;
+    }
+    // Misaligned loads and stores are emulated, unless the policy faults them.
+    if (mcause == Trap::LoadMisaligned as usize || mcause == Trap::StoreMisaligned as usize) && misaligned::handle(frame) {
+        return;
+    }
+    // `ebreak` is either a semihosting request nobody served (failed here) or
+    // a breakpoint, which stops in the debugger if one is attached.
+    if mcause == Trap::Breakpoint as usize && (semihosting::handle_trap(frame) || gdbstub::handle_trap(frame)) {
+        return;
+    }
+    // Log full trap state for debugging purposes (every interrupt gets here,
+    // so this is off unless enabled with e.g. `loglevel=traps=debug`).
+    let location = Location::of(frame);
+    let insn: &dyn fmt::Display = match &location.insn {
+        Some(insn) => insn,
+        None => &"-",
+    };
+    log_debug!(
         "Machine trap. \
-        MEPC: 0x{:08x} - \
+        MEPC: {} - \
+        INSN: {} - \
         MTVAL: 0x{:08x} - \
         MCAUSE: 0x{:08x} - \
         MHARTID: 0x{:08x} - \
-        MSTATUS: 0x{:08x} - \
-        MSCRATCH: 0x{:08x}",
-        mepc, mtval, mcause, mhartid, mstatus, mscratch
+        MSTATUS: 0x{:08x}",
+        ksyms::symbolize(frame.mepc), insn, frame.mtval, mcause, MHARTID::read(), frame.mstatus
     );
-    // Select reaction depending on trap type
-    match mcause {
-        // === Exception Codes (synchronous traps) ===
-        val if val == Trap::InstructionMisaligned as usize => {
-            panic!("Unhandled Instruction Misaligned Trap.");
-        }
-        val if val == Trap::InstructionAccessFault as usize => {
-            panic!("Unhandled Instruction Access Fault Trap.");
-        }
-        val if val == Trap::IllegalInstruction as usize => {
-            panic!("Unhandled Illegal Instruction Trap.");
-        }
-        val if val == Trap::Breakpoint as usize => {
-            panic!("Unhandled Breakpoint Trap.");
-        }
-        val if val == Trap::LoadMisaligned as usize => {
-            panic!("Unhandled Load Misaligned Trap.");
-        }
-        val if val == Trap::LoadAccessFault as usize => {
-            panic!("Unhandled Load Access Fault Trap.");
-        }
-        val if val == Trap::StoreMisaligned as usize => {
-            panic!("Unhandled Store Misaligned Trap.");
-        }
-        val if val == Trap::StoreAccessFault as usize => {
-            panic!("Unhandled Store Access Fault Trap.");
-        }
-        val if val == Trap::UserEnvCall as usize => {
-            panic!("Unhandled User Environment Call Trap.");
-        }
-        val if val == Trap::SupervisorEnvCall as usize => {
-            panic!("Unhandled Supervisor Environment Call Trap.");
-        }
-        val if val == Trap::MachineEnvCall as usize => {
-            panic!("Unhandled Machine Environment Call Trap.");
-        }
-        val if val == Trap::InstructionPageFault as usize => {
-            panic!("Unhandled Instruction Page Fault Trap.");
-        }
-        val if val == Trap::LoadPageFault as usize => {
-            panic!("Unhandled Load Page Fault Trap.");
-        }
-        val if val == Trap::StorePageFault as usize => {
-            panic!("Unhandled Store Page Fault Trap.");
-        }
-        // === Interrupt Codes (asynchronous traps) ===
-        val if val == Trap::SupervisorSoftInterrupt as usize => {
-            panic!("Unhandled Supervisor Software Interrupt.");
-        }
-        val if val == Trap::MachineSoftInterrupt as usize => {
-            panic!("Unhandled Machine Software Interrupt.");
-        }
-        val if val == Trap::SupervisorTimerInterrupt as usize => {
-            panic!("Unhandled Supervisor Timer Interrupt.");
-        }
-        val if val == Trap::MachineTimerInterrupt as usize => {
-            panic!("Unhandled Machine Timer Interrupt.");
-        }
-        val if val == Trap::SupervisorExternalInterrupt as usize => {
-            panic!("Unhandled Supervisor External Interrupt.");
-        }
-        val if val == Trap::MachineExternalInterrupt as usize => {
-            panic!("Unhandled Machine External Interrupt.");
-        }
-        // === Unknown or unhandled trap code ===
-        _ => {
-            panic!("Unhandled unknown machine trap: 0x{:x}", mcause);
-        }
+    // Every other trap is fatal for now: stop with the interrupted context.
+    match Trap::from_mcause(mcause) {
+        // Interrupts have the MSB set.
+        Some(trap) if (mcause as isize) < 0 => {
+            panic::trap_panic(frame, format_args!("Unhandled {}.", trap.name()))
+        }
+        // Access faults say which PMP entry (if any) covers the address; in
+        // the guard region below a stack, that stack overflowed.
+        Some(trap) => match (Access::of(trap), stack::overflowed(frame.mtval)) {
+            (Some(access), Some(stack)) => {
+                let fault = pmp::Fault { access, addr: frame.mtval };
+                panic::trap_panic(frame, format_args!("stack overflow in {} at {}: {}.", stack.name, location, fault))
+            }
+            (Some(access), None) => {
+                let fault = pmp::Fault { access, addr: frame.mtval };
+                panic::trap_panic(frame, format_args!("Unhandled {} Trap at {}: {}.", trap.name(), location, fault))
+            }
+            (None, _) => panic::trap_panic(frame, format_args!("Unhandled {} Trap at {}.", trap.name(), location)),
+        },
+        None => panic::trap_panic(frame, format_args!("Unhandled unknown machine trap: 0x{:x}", mcause)),
+    }
+}
+
+// These go through the trap vector: test kernel only.
+#[cfg(all(test, target_os = "none"))]
+mod tests {
+    use crate::syscalls::errno::Errno;
+    use crate::syscalls::nr;
+
+    /// Issues system call `nr` with arguments `a0` and `a1` through the trap path.
+    fn syscall(nr: usize, a0: usize, a1: usize) -> isize {
+        let ret: isize;
+        unsafe { core::arch::asm!("ecall", in("a7") nr, inlateout("a0") a0 => ret, in("a1") a1) };
+        ret
+    }
+
+    #[test_case]
+    fn unknown_syscall_returns_enosys() {
+        assert_eq!(syscall(usize::MAX, 0, 0), -(Errno::ENOSYS as isize));
+    }
+
+    #[test_case]
+    fn ecall_preserves_registers() {
+        let (t0, t6, s1): (usize, usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "ecall",
+                in("a7") usize::MAX,
+                inlateout("t0") 0x1234_5678usize => t0,
+                inlateout("t6") usize::MAX => t6,
+                inlateout("s1") 0x8000_0000usize => s1,
+                lateout("a0") _,
+            );
+        }
+        assert_eq!((t0, t6, s1), (0x1234_5678, usize::MAX, 0x8000_0000));
+    }
+
+    #[test_case]
+    fn clock_gettime_fills_timespec() {
+        const CLOCK_MONOTONIC: usize = 1;
+        let mut first = [0u64; 2];
+        let mut second = [0u64; 2];
+        assert_eq!(syscall(nr::CLOCK_GETTIME, CLOCK_MONOTONIC, first.as_mut_ptr() as usize), 0);
+        assert_eq!(syscall(nr::CLOCK_GETTIME, CLOCK_MONOTONIC, second.as_mut_ptr() as usize), 0);
+        assert!(first[1] < 1_000_000_000);
+        assert!(second >= first);
+    }
+
+    #[test_case]
+    fn syscall_rejects_bad_pointer() {
+        const CLOCK_MONOTONIC: usize = 1;
+        assert_eq!(syscall(nr::CLOCK_GETTIME, CLOCK_MONOTONIC, 0), -(Errno::EFAULT as isize));
     }
 }
@@ -0,0 +1,198 @@
+//! ---------------------------------------------------------------------------
+//! File       : misaligned.rs
+//! Module     : traps::misaligned
+//! Author     : DiTurr
+//! Description:
+//! Emulation of misaligned loads and stores. QEMU performs them in hardware,
+//! but other cores (and QEMU configurations) raise `LoadMisaligned` and
+//! `StoreMisaligned` instead; the handler then decodes the instruction at
+//! `mepc` (32-bit or compressed), performs the access one byte at a time at
+//! `mtval`, writes the loaded value back to the destination register of the
+//! trap frame, and resumes after the instruction.
+//!
+//! Integer loads and stores are emulated: `lb`-`ld`, `lbu`-`lwu`, `sb`-`sd`,
+//! and their compressed forms (`c.lw`, `c.ld`, `c.sw`, `c.sd` and the
+//! `sp`-relative ones). Floating-point accesses and atomics are not: they
+//! remain fatal.
+//!
+//! The `misaligned=` command line option selects the policy:
+//! - `emulate` (default): every misaligned access is emulated;
+//! - `fault`: only those of the kernel (M-mode) are; the others fault, as a
+//!   user process would be sent (there are no user processes yet, so the
+//!   fault is reported like any other fatal trap).
+//! ---------------------------------------------------------------------------
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::arch::decode::{self, Instruction};
+use crate::cmdline;
+use crate::traps::trap_frame::TrapFrame;
+use crate::traps::traps::Trap;
+use crate::{log_debug, log_warn};
+
+// `mstatus.MPP`: privilege mode of the interrupted code.
+const MPP_SHIFT: usize   = 11;
+const MPP_MASK: usize    = 0b11;
+const MPP_MACHINE: usize = 0b11;
+
+/// Set when lower privilege modes are sent the fault (`misaligned=fault`).
+static FAULT: AtomicBool = AtomicBool::new(false);
+
+/// Loads emulated.
+static LOADS: AtomicU64 = AtomicU64::new(0);
+
+/// Stores emulated.
+static STORES: AtomicU64 = AtomicU64::new(0);
+
+/// A decoded load or store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Access {
+    /// `true` for a store.
+    store: bool,
+    /// Access size in bytes.
+    width: usize,
+    /// Loads: sign-extend the value.
+    signed: bool,
+    /// Destination (loads) or source (stores) register.
+    reg: usize,
+    /// Size of the instruction in bytes.
+    len: usize,
+}
+
+impl Access {
+    const fn load(width: usize, signed: bool, reg: usize, len: usize) -> Option<Access> {
+        Some(Access { store: false, width, signed, reg, len })
+    }
+
+    const fn store(width: usize, reg: usize, len: usize) -> Option<Access> {
+        Some(Access { store: true, width, signed: false, reg, len })
+    }
+}
+
+/// Decodes the integer load or store `insn` (see `arch::decode`).
+fn access(insn: u32) -> Option<Access> {
+    let len = decode::length(insn);
+    match decode::decode(insn) {
+        Instruction::Load { op, rd, .. } => Access::load(op.width(), op.signed(), rd.0 as usize, len),
+        Instruction::Store { op, rs2, .. } => Access::store(op.width(), rs2.0 as usize, len),
+        _ => None,
+    }
+}
+
+/// Applies the `misaligned=` command line option.
+pub fn init() {
+    match cmdline::get("misaligned") {
+        None | Some("emulate") => {}
+        Some("fault") => FAULT.store(true, Ordering::Relaxed),
+        Some(other) => log_warn!("misaligned: invalid policy '{}'.", other),
+    }
+}
+
+/// Returns the policy, `emulate` or `fault`.
+pub fn policy() -> &'static str {
+    if FAULT.load(Ordering::Relaxed) { "fault" } else { "emulate" }
+}
+
+/// Returns the number of loads and stores emulated.
+pub fn emulated() -> (u64, u64) {
+    (LOADS.load(Ordering::Relaxed), STORES.load(Ordering::Relaxed))
+}
+
+/// Emulates the misaligned access that trapped with `frame`.
+///
+/// # Returns
+/// `false` if it is not emulated (policy, or not an integer load or store
+/// matching the trap): the trap is then fatal.
+pub fn handle(frame: &mut TrapFrame) -> bool {
+    let machine = (frame.mstatus >> MPP_SHIFT) & MPP_MASK == MPP_MACHINE;
+    if !machine && FAULT.load(Ordering::Relaxed) {
+        return false;
+    }
+    // SAFETY: `mepc` is the instruction that trapped.
+    let insn = unsafe { decode::fetch(frame.mepc) };
+    let Some(access) = access(insn) else {
+        return false;
+    };
+    if access.store != (frame.mcause == Trap::StoreMisaligned as usize) {
+        return false;
+    }
+    let addr = frame.mtval;
+    // Each byte is aligned; an access fault on one is reported as usual.
+    if access.store {
+        let value = frame.regs[access.reg];
+        for i in 0..access.width {
+            unsafe { write_volatile((addr + i) as *mut u8, (value >> (8 * i)) as u8) };
+        }
+        STORES.fetch_add(1, Ordering::Relaxed);
+    } else {
+        let mut value = 0usize;
+        for i in 0..access.width {
+            value |= (unsafe { read_volatile((addr + i) as *const u8) } as usize) << (8 * i);
+        }
+        if access.signed && access.width < 8 {
+            let shift = 64 - 8 * access.width;
+            value = ((value << shift) as isize >> shift) as usize;
+        }
+        // `x0` stays zero.
+        if access.reg != 0 {
+            frame.regs[access.reg] = value;
+        }
+        LOADS.fetch_add(1, Ordering::Relaxed);
+    }
+    log_debug!("misaligned: emulated {}-byte access to {:#x} at {:#x}", access.width, addr, frame.mepc);
+    frame.mepc += access.len;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trap frame for `insn` raising `trap` at `addr`, interrupted in M-mode.
+    fn frame(insn: &[u16; 2], trap: Trap, addr: usize) -> TrapFrame {
+        TrapFrame {
+            regs: [0; 32],
+            mepc: insn.as_ptr() as usize,
+            mstatus: MPP_MACHINE << MPP_SHIFT,
+            mcause: trap as usize,
+            mtval: addr,
+        }
+    }
+
+    #[test_case]
+    fn accesses_decode() {
+        // lw a0, 0(a1); lhu t0, 1(a2); sd a1, 3(sp)
+        assert_eq!(access(0x0005_a503), Access::load(4, true, 10, 4));
+        assert_eq!(access(0x0016_5283), Access::load(2, false, 5, 4));
+        assert_eq!(access(0x00b1_31a3), Access::store(8, 11, 4));
+        // c.lw a0, 0(a1); c.sdsp ra, 8(sp); c.fld is not emulated.
+        assert_eq!(access(0x4188), Access::load(4, true, 10, 2));
+        assert_eq!(access(0xe406), Access::store(8, 1, 2));
+        assert_eq!(access(0x2188), None);
+    }
+
+    #[test_case]
+    fn loads_and_stores_are_emulated() {
+        let mut data = [0u8; 16];
+        data[1..5].copy_from_slice(&0x8765_4321u32.to_le_bytes());
+        let addr = data.as_mut_ptr() as usize + 1;
+        // lw a0, 0(a1): sign-extended, `mepc` advanced.
+        let lw = [0xa503, 0x0005];
+        let mut trap = frame(&lw, Trap::LoadMisaligned, addr);
+        let (loads, stores) = emulated();
+        assert!(handle(&mut trap));
+        assert_eq!(trap.regs[10], 0xffff_ffff_8765_4321);
+        assert_eq!(trap.mepc, lw.as_ptr() as usize + 4);
+        // c.sd a0, 0(a1)
+        let csd = [0xe188, 0];
+        let mut trap = frame(&csd, Trap::StoreMisaligned, addr + 4);
+        trap.regs[10] = 0x0102_0304_0506_0708;
+        assert!(handle(&mut trap));
+        assert_eq!(data[5..13], 0x0102_0304_0506_0708u64.to_le_bytes());
+        assert_eq!(trap.mepc, csd.as_ptr() as usize + 2);
+        assert_eq!(emulated(), (loads + 1, stores + 1));
+        // A load cannot raise a store fault.
+        assert!(!handle(&mut frame(&lw, Trap::StoreMisaligned, addr)));
+    }
+}
@@ -0,0 +1,245 @@
+//! ---------------------------------------------------------------------------
+//! File       : stats.rs
+//! Module     : traps::stats
+//! Author     : DiTurr
+//! Description:
+//! Trap and interrupt accounting, per hart: for every `Trap` cause and every
+//! PLIC line, the number of times it was taken and the `mcycle` cycles spent
+//! in its handler (in total and at most, nested handlers included), and the
+//! worst latency seen, on the `time` clock:
+//! - the timer interrupt: from the `mtimecmp` deadline to its handler;
+//! - a PLIC line: from the external interrupt to the handler of the line
+//!   (claiming it, and serving the lines claimed before).
+//!
+//! Spurious interrupts are counted too: external interrupts with no line to
+//! claim, and claimed lines without a handler.
+//!
+//! `/proc/interrupts` lists it all, like its Linux namesake: one row per
+//! device line with a handler (by number), per trap cause (`I<code>` for
+//! interrupts, `E<code>` for exceptions) and for the spurious interrupts
+//! (`ERR`), with a count column per hart. The monitor's `irqstat` and
+//! `trapstat` show the same statistics.
+//! ---------------------------------------------------------------------------
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::fs::procfs::{self, ProcFile};
+use crate::log_warn;
+use crate::peripherals::plic;
+use crate::registers::{mcycle::MCYCLE, mhartid::MHARTID};
+use crate::stack::MAX_HARTS;
+use crate::traps::traps::Trap;
+
+/// Accounting of one trap cause or line, on one hart.
+struct Counter {
+    count: AtomicU64,
+    /// `mcycle` cycles in the handler, in total.
+    cycles: AtomicU64,
+    /// Longest run of the handler, in `mcycle` cycles.
+    max_cycles: AtomicU64,
+    /// Worst latency, in nanoseconds.
+    max_latency: AtomicU64,
+}
+
+impl Counter {
+    const fn new() -> Counter {
+        Counter {
+            count: AtomicU64::new(0),
+            cycles: AtomicU64::new(0),
+            max_cycles: AtomicU64::new(0),
+            max_latency: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a handler run that started at `mcycle` `start`.
+    fn record(&self, start: u64) {
+        let cycles = (MCYCLE::read() as u64).wrapping_sub(start);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.cycles.fetch_add(cycles, Ordering::Relaxed);
+        self.max_cycles.fetch_max(cycles, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> Stats {
+        Stats {
+            count: self.count.load(Ordering::Relaxed),
+            cycles: self.cycles.load(Ordering::Relaxed),
+            max_cycles: self.max_cycles.load(Ordering::Relaxed),
+            max_latency_ns: self.max_latency.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static TRAPS: [[Counter; Trap::ALL.len()]; MAX_HARTS] = [const { [const { Counter::new() }; Trap::ALL.len()] }; MAX_HARTS];
+
+static LINES: [[Counter; plic::LINES]; MAX_HARTS] = [const { [const { Counter::new() }; plic::LINES] }; MAX_HARTS];
+
+static SPURIOUS: [AtomicU64; MAX_HARTS] = [const { AtomicU64::new(0) }; MAX_HARTS];
+
+/// The `/proc/interrupts` file.
+static INTERRUPTS: ProcFile = ProcFile::new("interrupts", show);
+
+/// Statistics of a trap cause or line, on a hart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    pub count: u64,
+    /// `mcycle` cycles in the handler, in total.
+    pub cycles: u64,
+    /// Longest run of the handler, in `mcycle` cycles.
+    pub max_cycles: u64,
+    /// Worst latency, in nanoseconds (0 if not measured).
+    pub max_latency_ns: u64,
+}
+
+impl Stats {
+    /// Adds the statistics of another hart.
+    fn merge(self, other: Stats) -> Stats {
+        Stats {
+            count: self.count + other.count,
+            cycles: self.cycles + other.cycles,
+            max_cycles: self.max_cycles.max(other.max_cycles),
+            max_latency_ns: self.max_latency_ns.max(other.max_latency_ns),
+        }
+    }
+
+    /// Returns the mean cycles per run of the handler.
+    pub fn mean_cycles(&self) -> u64 {
+        self.cycles.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+fn hart() -> usize {
+    MHARTID::read()
+}
+
+/// Registers `/proc/interrupts`.
+pub fn init() {
+    if let Err(err) = procfs::register(&INTERRUPTS) {
+        log_warn!("traps: cannot register /proc/interrupts: {:?}", err);
+    }
+}
+
+/// Returns the `mcycle` value to pass to [`record`] or [`record_line`] when
+/// a handler returns.
+pub fn start() -> u64 {
+    MCYCLE::read() as u64
+}
+
+/// Counts a `trap` taken on this hart, whose handler started at `start`.
+pub fn record(trap: Trap, start: u64) {
+    TRAPS[hart()][trap.index()].record(start);
+}
+
+/// Notes that `trap` was taken `latency` after it was due (the timer).
+pub fn record_latency(trap: Trap, latency: Duration) {
+    TRAPS[hart()][trap.index()].max_latency.fetch_max(latency.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Counts line `irq`, whose handler started at `start`, `latency` after the
+/// external interrupt was taken.
+pub fn record_line(irq: u32, latency: Duration, start: u64) {
+    let Some(counter) = LINES[hart()].get(irq as usize) else {
+        return;
+    };
+    counter.max_latency.fetch_max(latency.as_nanos() as u64, Ordering::Relaxed);
+    counter.record(start);
+}
+
+/// Counts a spurious interrupt on this hart.
+pub fn record_spurious() {
+    SPURIOUS[hart()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the statistics of `trap` on `hart`.
+pub fn trap(hart: usize, trap: Trap) -> Stats {
+    TRAPS.get(hart).map_or(Stats::default(), |traps| traps[trap.index()].get())
+}
+
+/// Returns the statistics of line `irq` on `hart`.
+pub fn line(hart: usize, irq: u32) -> Stats {
+    LINES.get(hart).and_then(|lines| lines.get(irq as usize)).map_or(Stats::default(), Counter::get)
+}
+
+/// Returns the number of spurious interrupts on `hart`.
+pub fn spurious(hart: usize) -> u64 {
+    SPURIOUS.get(hart).map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Returns the statistics of every hart, merged.
+fn total(stats: impl Fn(usize) -> Stats) -> Stats {
+    (0..MAX_HARTS).map(stats).fold(Stats::default(), Stats::merge)
+}
+
+/// Returns the harts that took a trap, and this one.
+fn harts() -> impl Iterator<Item = usize> {
+    let current = hart();
+    (0..MAX_HARTS).filter(move |&hart| hart == current || Trap::ALL.into_iter().any(|trap| self::trap(hart, trap).count > 0))
+}
+
+/// Writes `prefix` then `number`, right-aligned in `width` columns.
+fn write_padded(out: &mut dyn Write, prefix: &str, number: usize, width: usize) {
+    let digits = number.checked_ilog10().unwrap_or(0) as usize + 1;
+    let _ = write!(out, "{:>pad$}{}", prefix, number, pad = width.saturating_sub(digits));
+}
+
+/// Writes one row of `/proc/interrupts`: the counts per hart, then the mean
+/// and max cycles and the max latency of all harts.
+fn row(out: &mut dyn Write, prefix: &str, number: usize, stats: impl Fn(usize) -> Stats, name: &str) {
+    write_padded(out, prefix, number, 4);
+    let _ = write!(out, ":");
+    for hart in harts() {
+        let _ = write!(out, " {:>10}", stats(hart).count);
+    }
+    let total = total(stats);
+    let _ = writeln!(out, " {:>10} {:>10} {:>10}  {}", total.mean_cycles(), total.max_cycles, total.max_latency_ns, name);
+}
+
+/// Writes the text of `/proc/interrupts`.
+pub fn show(out: &mut dyn Write) {
+    let _ = write!(out, "     ");
+    for hart in harts() {
+        let _ = write!(out, " ");
+        write_padded(out, "hart", hart, 10);
+    }
+    let _ = writeln!(out, " {:>10} {:>10} {:>10}", "mean cyc", "max cyc", "max lat ns");
+    for (irq, handler) in plic::handlers() {
+        row(out, "", irq as usize, |hart| line(hart, irq), handler.name);
+    }
+    for trap in Trap::ALL {
+        let prefix = if (trap as isize) < 0 { "I" } else { "E" };
+        row(out, prefix, trap as usize & 0x3f, |hart| self::trap(hart, trap), trap.name());
+    }
+    let _ = write!(out, "{:>4}:", "ERR");
+    for hart in harts() {
+        let _ = write!(out, " {:>10}", spurious(hart));
+    }
+    let _ = writeln!(out, "  spurious");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::CharDevice;
+
+    #[test_case]
+    fn records_add_up() {
+        let hart = hart();
+        let before = trap(hart, Trap::Breakpoint);
+        record(Trap::Breakpoint, start());
+        record(Trap::Breakpoint, start());
+        let after = trap(hart, Trap::Breakpoint);
+        assert_eq!(after.count, before.count + 2);
+        assert!(after.cycles >= before.cycles && after.max_cycles >= before.max_cycles);
+    }
+
+    #[test_case]
+    fn interrupts_file_lists_every_cause() {
+        let mut buf = [0u8; 8192];
+        let len = INTERRUPTS.read(&mut buf).unwrap();
+        let text = core::str::from_utf8(&buf[..len]).unwrap();
+        assert!(text.ends_with("  spurious\n"));
+        assert!(text.contains("  I7:") && text.contains("  E2:"));
+        assert!(text.lines().count() >= 2 + Trap::ALL.len());
+    }
+}
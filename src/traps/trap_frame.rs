@@ -0,0 +1,52 @@
+//! ---------------------------------------------------------------------------
+//! File       : trap_frame.rs
+//! Module     : traps::trap_frame
+//! Author     : DiTurr
+//! Description:
+//! Defines the `TrapFrame` saved by `asm_trap_vector` (see `asm/trap.S`) on the
+//! stack of the interrupted context. The handler receives a mutable reference
+//! to it, and any change made to the frame is restored on `mret`.
+//! ---------------------------------------------------------------------------
+
+/// Register state of the interrupted context.
+///
+/// The layout must match the offsets used in `asm/trap.S`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TrapFrame {
+    /// General purpose registers `x0`-`x31` (`x0` is always zero, `x2` is the pre-trap `sp`).
+    pub regs: [usize; 32],
+    /// Machine Exception Program Counter; `mret` resumes execution here.
+    pub mepc: usize,
+    /// Machine status register at the time of the trap.
+    pub mstatus: usize,
+    /// Trap cause (interrupt or exception ID).
+    pub mcause: usize,
+    /// Trap value (e.g., faulting address).
+    pub mtval: usize,
+}
+
+/// Register indices following the RISC-V calling convention.
+pub mod reg {
+    pub const A0: usize = 10;
+    pub const A1: usize = 11;
+    pub const A2: usize = 12;
+    pub const A3: usize = 13;
+    pub const A4: usize = 14;
+    pub const A5: usize = 15;
+    pub const A7: usize = 17;
+}
+
+impl TrapFrame {
+    /// Returns the six system call arguments (`a0`-`a5`).
+    pub fn syscall_args(&self) -> [usize; 6] {
+        [
+            self.regs[reg::A0],
+            self.regs[reg::A1],
+            self.regs[reg::A2],
+            self.regs[reg::A3],
+            self.regs[reg::A4],
+            self.regs[reg::A5],
+        ]
+    }
+}
@@ -2,6 +2,9 @@
 ///
 /// The upper bit (bit XLEN-1) of mcause/scause distinguishes
 /// between interrupts and exceptions.
+// The interrupt bit is computed from the width of `usize`: the values fit on
+// 32-bit targets too.
+#[allow(clippy::enum_clike_unportable_variant)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(usize)]
 pub enum Trap {
@@ -28,3 +31,106 @@ pub enum Trap {
     SupervisorExternalInterrupt   = 9 | (1 << (core::mem::size_of::<usize>() * 8 - 1)),
     MachineExternalInterrupt      = 11 | (1 << (core::mem::size_of::<usize>() * 8 - 1)),
 }
+
+impl Trap {
+    /// All trap causes, used to decode `mcause`.
+    pub const ALL: [Trap; 20] = [
+        Trap::InstructionMisaligned,
+        Trap::InstructionAccessFault,
+        Trap::IllegalInstruction,
+        Trap::Breakpoint,
+        Trap::LoadMisaligned,
+        Trap::LoadAccessFault,
+        Trap::StoreMisaligned,
+        Trap::StoreAccessFault,
+        Trap::UserEnvCall,
+        Trap::SupervisorEnvCall,
+        Trap::MachineEnvCall,
+        Trap::InstructionPageFault,
+        Trap::LoadPageFault,
+        Trap::StorePageFault,
+        Trap::SupervisorSoftInterrupt,
+        Trap::MachineSoftInterrupt,
+        Trap::SupervisorTimerInterrupt,
+        Trap::MachineTimerInterrupt,
+        Trap::SupervisorExternalInterrupt,
+        Trap::MachineExternalInterrupt,
+    ];
+
+    /// Decodes an `mcause` value.
+    ///
+    /// # Returns
+    /// `None` for reserved or platform-specific causes.
+    pub fn from_mcause(mcause: usize) -> Option<Trap> {
+        Trap::ALL.into_iter().find(|&trap| trap as usize == mcause)
+    }
+
+    /// Returns the position of the cause in [`Trap::ALL`], e.g. to index a table.
+    pub fn index(self) -> usize {
+        Trap::ALL.iter().position(|&trap| trap == self).unwrap_or(0)
+    }
+
+    /// Returns a human-readable name of the cause, e.g. `Load Access Fault`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Trap::InstructionMisaligned => "Instruction Misaligned",
+            Trap::InstructionAccessFault => "Instruction Access Fault",
+            Trap::IllegalInstruction => "Illegal Instruction",
+            Trap::Breakpoint => "Breakpoint",
+            Trap::LoadMisaligned => "Load Misaligned",
+            Trap::LoadAccessFault => "Load Access Fault",
+            Trap::StoreMisaligned => "Store Misaligned",
+            Trap::StoreAccessFault => "Store Access Fault",
+            Trap::UserEnvCall => "User Environment Call",
+            Trap::SupervisorEnvCall => "Supervisor Environment Call",
+            Trap::MachineEnvCall => "Machine Environment Call",
+            Trap::InstructionPageFault => "Instruction Page Fault",
+            Trap::LoadPageFault => "Load Page Fault",
+            Trap::StorePageFault => "Store Page Fault",
+            Trap::SupervisorSoftInterrupt => "Supervisor Software Interrupt",
+            Trap::MachineSoftInterrupt => "Machine Software Interrupt",
+            Trap::SupervisorTimerInterrupt => "Supervisor Timer Interrupt",
+            Trap::MachineTimerInterrupt => "Machine Timer Interrupt",
+            Trap::SupervisorExternalInterrupt => "Supervisor External Interrupt",
+            Trap::MachineExternalInterrupt => "Machine External Interrupt",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn every_cause_decodes_to_itself() {
+        for trap in Trap::ALL {
+            assert_eq!(Trap::from_mcause(trap as usize), Some(trap));
+            assert_eq!(Trap::ALL[trap.index()], trap);
+        }
+    }
+
+    #[test_case]
+    fn interrupts_have_the_msb_set() {
+        for trap in Trap::ALL {
+            let interrupt = (trap as usize as isize) < 0;
+            assert_eq!(interrupt, trap.name().ends_with("Interrupt"), "{:?}", trap);
+        }
+    }
+
+    #[test_case]
+    fn reserved_causes_do_not_decode() {
+        for mcause in [10, 14, 16, 24, 1 << 63, (1 << 63) | 13] {
+            assert_eq!(Trap::from_mcause(mcause), None);
+        }
+    }
+
+    // The mock CSR file only exists on the host.
+    #[cfg(not(target_os = "none"))]
+    #[test_case]
+    fn mcause_csr_decodes() {
+        use crate::registers::{mcause::MCAUSE, mock};
+
+        mock::write(0x342, Trap::MachineTimerInterrupt as usize);
+        assert_eq!(Trap::from_mcause(MCAUSE::read()), Some(Trap::MachineTimerInterrupt));
+    }
+}
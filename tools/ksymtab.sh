@@ -0,0 +1,43 @@
+#!/bin/sh
+# ---------------------------------------------------------------------------
+# File       : ksymtab.sh
+# Author     : DiTurr
+# Description:
+# Generates the kernel symbol table (see `src/ksyms.rs`) from a linked kernel.
+# The output is an assembly file defining the `.ksymtab` section:
+#   - the number of symbols (u64);
+#   - one entry per function, sorted by address: the address (u64), then the
+#     offset and length of its name in the string block (u32 each);
+#   - the string block (demangled names, not NUL-terminated).
+#
+# Usage: ksymtab.sh <nm> <elf> > ksymtab.S
+# ---------------------------------------------------------------------------
+set -e
+
+"$1" --numeric-sort --demangle --defined-only "$2" | awk '
+BEGIN { n = 0 }
+# Functions only; skip mapping symbols ($x, $d) and aliases of a previous address.
+$2 ~ /^[tT]$/ {
+    name = substr($0, length($1) + length($2) + 3)
+    if (name ~ /^\$/ || $1 == last) next
+    last = $1
+    addr[n] = $1
+    names[n] = name
+    n++
+}
+END {
+    print "\t.section .ksymtab, \"a\""
+    print "\t.balign 8"
+    printf "\t.8byte %d\n", n
+    offset = 0
+    for (i = 0; i < n; i++) {
+        printf "\t.8byte 0x%s\n\t.4byte %d, %d\n", addr[i], offset, length(names[i])
+        offset += length(names[i])
+    }
+    for (i = 0; i < n; i++) {
+        name = names[i]
+        gsub(/\\/, "\\\\", name)
+        gsub(/"/, "\\\"", name)
+        printf "\t.ascii \"%s\"\n", name
+    }
+}'
@@ -0,0 +1,10 @@
+#!/bin/sh
+# Cargo runner for the test kernel (see `make test`).
+#
+# Usage: test-runner.sh <test binary>
+#
+# Built with `--emit=obj`, the test kernel comes as an object file next to the
+# binary cargo passes; it is linked like the kernel and booted in QEMU, whose
+# exit status is the result of the tests.
+set -e
+exec make --no-print-directory -C "$(dirname "$0")/.." test-run TEST_OBJ="$(realpath "$1").o"